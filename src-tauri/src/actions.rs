@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tauri::{async_runtime, Manager, State, Window};
+
+use crate::jobs::{emit_event, JobId, JobMessage, JobRegistry};
+use crate::queue::ExportQueue;
+
+// Time left to call `cancel_shutdown` once the last job ended
+const SHUTDOWN_DELAY: Duration = Duration::from_secs(60);
+
+/// Done with the outputs of a job after it finished.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PostAction {
+    /// Opens the first output with its default app
+    Open,
+    /// Shows the first output in the file manager
+    Reveal,
+    /// Emits `copy-output-paths` for the frontend to put on the clipboard
+    CopyPath,
+    /// Runs in the system shell, with the outputs in `VIDEO_CROPPER_OUTPUT`
+    /// (the first) and `VIDEO_CROPPER_OUTPUTS` (one per line)
+    Command { command: String },
+    /// Shuts the machine down once no job is running or queued
+    Shutdown,
+}
+
+/// Payload of `copy-output-paths`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CopyOutputPaths {
+    pub job_id: JobId,
+    /// The output paths, one per line
+    pub text: String,
+}
+
+/// Payload of `shutdown-scheduled`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShutdownScheduled {
+    pub delay_seconds: u64,
+}
+
+impl PostAction {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            PostAction::Command { command } if command.trim().is_empty() => {
+                Err("The command to run after the export is empty".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The platform's way of running `command` through its shell.
+fn shell(command: &str) -> tokio::process::Command {
+    let (program, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut shell = tokio::process::Command::new(program);
+    shell.arg(flag).arg(command);
+    shell
+}
+
+/// Starts `program` with `args` without waiting for it, as file managers
+/// and players keep running.
+fn launch(program: &str, args: &[&str]) -> Result<(), String> {
+    tokio::process::Command::new(program)
+        .args(args)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Cannot run {}: {}", program, e))
+}
+
+fn open(path: &str) -> Result<(), String> {
+    if cfg!(windows) {
+        launch("explorer", &[path])
+    } else if cfg!(target_os = "macos") {
+        launch("open", &[path])
+    } else {
+        launch("xdg-open", &[path])
+    }
+}
+
+/// Selects the file in a file manager window. Linux has no common way to
+/// select it, so its folder is opened instead.
+fn reveal(path: &str) -> Result<(), String> {
+    if cfg!(windows) {
+        launch("explorer", &[&format!("/select,{}", path)])
+    } else if cfg!(target_os = "macos") {
+        launch("open", &["-R", path])
+    } else {
+        let folder = Path::new(path).parent().unwrap_or(Path::new("."));
+        launch("xdg-open", &[&folder.to_string_lossy()])
+    }
+}
+
+/// Runs the command line, adding what it printed to the job log.
+async fn run_command(
+    window: &Window,
+    job_id: JobId,
+    command: &str,
+    outputs: &[String],
+) -> Result<(), String> {
+    let output = shell(command)
+        .env(
+            "VIDEO_CROPPER_OUTPUT",
+            outputs.first().unwrap_or(&String::new()),
+        )
+        .env("VIDEO_CROPPER_OUTPUTS", outputs.join("\n"))
+        .env("VIDEO_CROPPER_JOB_ID", job_id.to_string())
+        .output()
+        .await
+        .map_err(|e| format!("Cannot run {}: {}", command, e))?;
+
+    let jobs = window.state::<JobRegistry>();
+    jobs.append_log(job_id, &format!("$ {}", command));
+    for text in [&output.stdout, &output.stderr] {
+        for line in String::from_utf8_lossy(text).lines() {
+            jobs.append_log(job_id, line);
+        }
+    }
+    if !output.status.success() {
+        return Err(format!("{} exited with {}", command, output.status));
+    }
+    Ok(())
+}
+
+/// Runs the action of a finished job. Failures are reported with
+/// `post-action-failed`, the export itself stays finished.
+pub async fn run_post_action(
+    window: &Window,
+    job_id: JobId,
+    action: &PostAction,
+    outputs: &[String],
+) {
+    let first = outputs.first().map(String::as_str).unwrap_or_default();
+    let result = match action {
+        PostAction::Open => open(first),
+        PostAction::Reveal => reveal(first),
+        PostAction::CopyPath => {
+            let text = outputs.join("\n");
+            emit_event(
+                window,
+                "copy-output-paths",
+                CopyOutputPaths { job_id, text },
+            );
+            Ok(())
+        }
+        PostAction::Command { command } => run_command(window, job_id, command, outputs).await,
+        PostAction::Shutdown => {
+            window.state::<JobRegistry>().set_shutdown_when_idle(true);
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        println!("Post-export action of {} failed: {}", job_id, e);
+        emit_event(window, "post-action-failed", JobMessage::new(job_id, e));
+    }
+}
+
+/// Called whenever a job ended. Once a `Shutdown` action was asked for and
+/// nothing is left running or queued, shuts down after `SHUTDOWN_DELAY`
+/// unless `cancel_shutdown` comes first or another job starts.
+pub fn shutdown_if_idle(window: &Window) {
+    let jobs = window.state::<JobRegistry>();
+    if !jobs.shutdown_when_idle() || jobs.has_running_jobs() {
+        return;
+    }
+    if window.state::<ExportQueue>().has_queued_jobs() {
+        return;
+    }
+
+    println!("Shutting down in {}s", SHUTDOWN_DELAY.as_secs());
+    emit_event(
+        window,
+        "shutdown-scheduled",
+        ShutdownScheduled {
+            delay_seconds: SHUTDOWN_DELAY.as_secs(),
+        },
+    );
+
+    let window = window.clone();
+    async_runtime::spawn(async move {
+        tokio::time::sleep(SHUTDOWN_DELAY).await;
+        let jobs = window.state::<JobRegistry>();
+        if !jobs.shutdown_when_idle() || jobs.has_running_jobs() {
+            return;
+        }
+        let command = if cfg!(windows) {
+            "shutdown /s /t 0"
+        } else {
+            "shutdown -h now"
+        };
+        if let Err(e) = shell(command).status().await {
+            println!("Could not shut down: {}", e);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn open_path(path: String) -> Result<(), String> {
+    open(&path)
+}
+
+#[tauri::command]
+pub fn reveal_path(path: String) -> Result<(), String> {
+    reveal(&path)
+}
+
+/// Keeps the machine running after the queue empties.
+#[tauri::command]
+pub fn cancel_shutdown(jobs: State<'_, JobRegistry>) {
+    jobs.set_shutdown_when_idle(false);
+}
@@ -0,0 +1,473 @@
+use serde::Serialize;
+use std::process::Stdio;
+use tokio::io::AsyncReadExt;
+
+use crate::binaries;
+use crate::ffmpeg::CropArea;
+use crate::probe::{probe, probe_duration};
+
+// Seconds of video sampled when the caller does not choose a range
+const DEFAULT_SAMPLE_DURATION: f64 = 10.0;
+
+/// Which stream an analysis filter runs on.
+#[derive(Clone, Copy, PartialEq)]
+enum Stream {
+    Video,
+    Audio,
+}
+
+/// Runs FFmpeg with `filter` on one stream of `input_path` and returns its
+/// stderr, where analysis filters log their results.
+async fn run_analysis(
+    input_path: &str,
+    start: f64,
+    duration: Option<f64>,
+    stream: Stream,
+    filter: &str,
+) -> Result<String, String> {
+    let mut args = vec![
+        "-hide_banner".to_string(),
+        "-nostats".into(),
+        "-ss".into(),
+        start.to_string(),
+        "-i".into(),
+        input_path.to_string(),
+    ];
+    if let Some(duration) = duration {
+        args.extend(["-t".into(), duration.to_string()]);
+    }
+    let (filter_flag, drop_other) = match stream {
+        Stream::Video => ("-vf", "-an"),
+        Stream::Audio => ("-af", "-vn"),
+    };
+    args.extend([
+        filter_flag.into(),
+        filter.to_string(),
+        drop_other.into(),
+        "-f".into(),
+        "null".into(),
+        "-".into(),
+    ]);
+
+    let output = tokio::process::Command::new(binaries::ffmpeg())
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    if !output.status.success() {
+        return Err(format!(
+            "FFmpeg analysis failed (code: {:?}): {}",
+            output.status.code(),
+            stderr.lines().last().unwrap_or_default()
+        ));
+    }
+
+    Ok(stderr)
+}
+
+/// Parses the `crop=W:H:X:Y` that `cropdetect` appends to its log lines.
+fn parse_cropdetect_line(line: &str) -> Option<CropArea> {
+    let (_, crop) = line.rsplit_once("crop=")?;
+    let mut values = crop.trim().split(':').map(|value| value.parse::<u32>());
+
+    Some(CropArea {
+        width: values.next()?.ok()?,
+        height: values.next()?.ok()?,
+        x: values.next()?.ok()?,
+        y: values.next()?.ok()?,
+    })
+}
+
+/// Suggests a crop that removes black bars, by running `cropdetect` over
+/// `duration` seconds from `start`. Without a range, 10 seconds from a tenth
+/// into the video are sampled, which skips most black intros.
+#[tauri::command]
+pub async fn detect_crop(
+    input_path: String,
+    start: Option<f64>,
+    duration: Option<f64>,
+) -> Result<CropArea, String> {
+    let start = match start {
+        Some(start) => start,
+        None => probe_duration(&input_path).await.unwrap_or(0.0) * 0.1,
+    };
+    let duration = duration.unwrap_or(DEFAULT_SAMPLE_DURATION);
+
+    // Crops are drawn on square pixels, as the export filters see the frame
+    let mut filters: Vec<String> = probe(&input_path)
+        .await?
+        .square_pixels_filter()
+        .into_iter()
+        .collect();
+    // reset=0 makes every line cover all frames so far, the last one wins
+    filters.push("cropdetect=limit=24:round=2:reset=0".to_string());
+    let log = run_analysis(
+        &input_path,
+        start,
+        Some(duration),
+        Stream::Video,
+        &filters.join(","),
+    )
+    .await?;
+
+    log.lines()
+        .rev()
+        .filter(|line| line.contains("Parsed_cropdetect"))
+        .find_map(parse_cropdetect_line)
+        .ok_or("No frames were analysed, try another range".to_string())
+}
+
+// Audio is decoded to mono at this rate, plenty for drawing peaks
+const WAVEFORM_SAMPLE_RATE: u32 = 8000;
+// More peaks than any timeline is wide, even zoomed in
+const MAX_WAVEFORM_BUCKETS: usize = 100_000;
+
+/// Peak amplitudes returned by `get_waveform`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Waveform {
+    /// One peak per bucket, 0.0 - 1.0
+    pub peaks: Vec<f32>,
+    /// Seconds of audio covered by each bucket
+    pub bucket_duration: f64,
+}
+
+/// Decodes the audio between `start` and `end` (the whole file by default)
+/// and reduces it to `buckets` peak values. Samples are streamed from FFmpeg,
+/// so long files do not have to fit in memory.
+#[tauri::command]
+pub async fn get_waveform(
+    input_path: String,
+    buckets: usize,
+    start: Option<f64>,
+    end: Option<f64>,
+) -> Result<Waveform, String> {
+    if buckets == 0 {
+        return Err("A waveform needs at least one bucket".to_string());
+    }
+    if buckets > MAX_WAVEFORM_BUCKETS {
+        return Err(format!(
+            "A waveform has at most {} buckets, not {}",
+            MAX_WAVEFORM_BUCKETS, buckets
+        ));
+    }
+    let start = start.unwrap_or(0.0);
+    let end = match end {
+        Some(end) => end,
+        None => probe_duration(&input_path).await?,
+    };
+    if end <= start {
+        return Err("The waveform range is empty".to_string());
+    }
+
+    let bucket_duration = (end - start) / buckets as f64;
+    let samples_per_bucket =
+        ((bucket_duration * WAVEFORM_SAMPLE_RATE as f64).ceil() as usize).max(1);
+
+    let mut child = tokio::process::Command::new(binaries::ffmpeg())
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-ss",
+            &start.to_string(),
+            "-t",
+            &(end - start).to_string(),
+            "-i",
+            &input_path,
+            "-vn",
+            "-ac",
+            "1",
+            "-ar",
+            &WAVEFORM_SAMPLE_RATE.to_string(),
+            "-f",
+            "s16le",
+            "-",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+    let mut stdout = child.stdout.take().ok_or("FFmpeg has no stdout")?;
+
+    let mut peaks = vec![0.0f32; buckets];
+    let mut sample_index = 0usize;
+    let mut buffer = vec![0u8; 64 * 1024];
+    // A sample can be split across two reads
+    let mut pending: Option<u8> = None;
+
+    loop {
+        let read = stdout
+            .read(&mut buffer)
+            .await
+            .map_err(|e| format!("Cannot read decoded audio: {}", e))?;
+        if read == 0 {
+            break;
+        }
+
+        let mut bytes = buffer[..read].iter().copied();
+        while let Some(byte) = pending.take().or_else(|| bytes.next()) {
+            let Some(high) = bytes.next() else {
+                pending = Some(byte);
+                break;
+            };
+            let sample = i16::from_le_bytes([byte, high]);
+            let amplitude = (sample as f32 / i16::MAX as f32).abs().min(1.0);
+            let bucket = (sample_index / samples_per_bucket).min(buckets - 1);
+            peaks[bucket] = peaks[bucket].max(amplitude);
+            sample_index += 1;
+        }
+    }
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| format!("FFmpeg did not exit: {}", e))?;
+    if !status.success() {
+        return Err(format!(
+            "FFmpeg could not decode the audio (code: {:?})",
+            status.code()
+        ));
+    }
+
+    Ok(Waveform {
+        peaks,
+        bucket_duration,
+    })
+}
+
+// Default `scene` score above which a frame counts as a cut
+const DEFAULT_SCENE_THRESHOLD: f64 = 0.3;
+
+/// Reads the number after `key` in a filter log line, e.g. `pts_time:`.
+fn parse_log_value(line: &str, key: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(key)?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+/// Timestamps (in seconds) of scene changes between `start` and `end`, whole
+/// file by default. `threshold` is the `scene` score from 0 to 1 above which
+/// a frame starts a new scene.
+#[tauri::command]
+pub async fn detect_scenes(
+    input_path: String,
+    threshold: Option<f64>,
+    start: Option<f64>,
+    end: Option<f64>,
+) -> Result<Vec<f64>, String> {
+    let threshold = threshold.unwrap_or(DEFAULT_SCENE_THRESHOLD);
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(format!(
+            "Scene threshold {} is out of range (0-1)",
+            threshold
+        ));
+    }
+    let start = start.unwrap_or(0.0);
+    let duration = end.map(|end| end - start);
+
+    let log = run_analysis(
+        &input_path,
+        start,
+        duration,
+        Stream::Video,
+        &format!("select='gt(scene,{})',showinfo", threshold),
+    )
+    .await?;
+
+    Ok(parse_scenes(&log, start))
+}
+
+/// Times of the frames `showinfo` logged, in seconds of the source.
+fn parse_scenes(log: &str, start: f64) -> Vec<f64> {
+    // Input seeking restarts the timestamps at 0
+    log.lines()
+        .filter(|line| line.contains("Parsed_showinfo"))
+        .filter_map(|line| parse_log_value(line, "pts_time:"))
+        .map(|time| start + time)
+        .collect()
+}
+
+// Defaults of `detect_silence`
+const DEFAULT_SILENCE_THRESHOLD_DB: f64 = -30.0;
+const DEFAULT_MIN_SILENCE: f64 = 0.5;
+
+/// A silent stretch of audio, in seconds of the source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SilentRange {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Finds stretches quieter than `threshold_db` (dBFS) lasting at least
+/// `min_duration` seconds, between `start` and `end` (whole file by default).
+#[tauri::command]
+pub async fn detect_silence(
+    input_path: String,
+    threshold_db: Option<f64>,
+    min_duration: Option<f64>,
+    start: Option<f64>,
+    end: Option<f64>,
+) -> Result<Vec<SilentRange>, String> {
+    let threshold_db = threshold_db.unwrap_or(DEFAULT_SILENCE_THRESHOLD_DB);
+    let min_duration = min_duration.unwrap_or(DEFAULT_MIN_SILENCE);
+    if threshold_db > 0.0 || min_duration <= 0.0 {
+        return Err("The threshold must be at most 0 dB and the duration positive".to_string());
+    }
+    let start = start.unwrap_or(0.0);
+    let end = match end {
+        Some(end) => end,
+        None => probe_duration(&input_path).await?,
+    };
+
+    let log = run_analysis(
+        &input_path,
+        start,
+        Some(end - start),
+        Stream::Audio,
+        &format!("silencedetect=noise={}dB:d={}", threshold_db, min_duration),
+    )
+    .await?;
+
+    Ok(parse_silence(&log, start, end))
+}
+
+/// Pairs the `silence_start` and `silence_end` lines of `silencedetect` for
+/// the range `start..end`.
+fn parse_silence(log: &str, start: f64, end: f64) -> Vec<SilentRange> {
+    // Input seeking restarts the timestamps at 0
+    let mut ranges = vec![];
+    let mut silence_start = None;
+    for line in log.lines().filter(|line| line.contains("silencedetect")) {
+        if let Some(time) = parse_log_value(line, "silence_start:") {
+            silence_start = Some(start + time.max(0.0));
+        } else if let Some(time) = parse_log_value(line, "silence_end:") {
+            if let Some(silence_start) = silence_start.take() {
+                ranges.push(SilentRange {
+                    start: silence_start,
+                    end: start + time,
+                });
+            }
+        }
+    }
+    // Silence running into the end of the range is never closed in the log
+    if let Some(silence_start) = silence_start {
+        ranges.push(SilentRange {
+            start: silence_start,
+            end,
+        });
+    }
+    ranges
+}
+
+/// Frame counts of `idet`, returned by `detect_interlacing`.
+#[derive(Debug, Clone, Serialize)]
+pub struct InterlaceReport {
+    /// Most frames show combing from either field order
+    pub interlaced: bool,
+    pub top_field_first: u64,
+    pub bottom_field_first: u64,
+    pub progressive: u64,
+    pub undetermined: u64,
+}
+
+/// Runs `idet` over `duration` seconds from `start`, 10 seconds from the
+/// beginning by default, to tell whether the source is interlaced.
+#[tauri::command]
+pub async fn detect_interlacing(
+    input_path: String,
+    start: Option<f64>,
+    duration: Option<f64>,
+) -> Result<InterlaceReport, String> {
+    let log = run_analysis(
+        &input_path,
+        start.unwrap_or(0.0),
+        Some(duration.unwrap_or(DEFAULT_SAMPLE_DURATION)),
+        Stream::Video,
+        "idet",
+    )
+    .await?;
+
+    // Multi frame detection looks at neighbouring frames too and is the more
+    // reliable of the two summaries
+    let line = log
+        .lines()
+        .find(|line| line.contains("Multi frame detection:"))
+        .ok_or("No frames were analysed, try another range")?;
+    let count = |key| parse_log_value(line, key).unwrap_or(0.0) as u64;
+    let (top_field_first, bottom_field_first) = (count("TFF:"), count("BFF:"));
+    let progressive = count("Progressive:");
+
+    Ok(InterlaceReport {
+        interlaced: top_field_first + bottom_field_first > progressive,
+        top_field_first,
+        bottom_field_first,
+        progressive,
+        undetermined: count("Undetermined:"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cropdetect_lines_give_the_crop() {
+        let line = "[Parsed_cropdetect_1 @ 0x600002c58000] x1:0 x2:1919 y1:138 y2:941 \
+                    w:1920 h:800 x:0 y:140 pts:250250 t:10.427083 limit:0.094118 \
+                    crop=1920:800:0:140";
+        assert_eq!(
+            parse_cropdetect_line(line),
+            Some(CropArea {
+                x: 0,
+                y: 140,
+                width: 1920,
+                height: 800,
+            })
+        );
+        assert_eq!(parse_cropdetect_line("crop=1920:800:0"), None);
+    }
+
+    #[test]
+    fn log_values_are_read_after_their_key() {
+        let line = "Multi frame detection: TFF:   412 BFF:     0 Progressive:    11 \
+                    Undetermined:    77";
+        assert_eq!(parse_log_value(line, "TFF:"), Some(412.0));
+        assert_eq!(parse_log_value(line, "Progressive:"), Some(11.0));
+        assert_eq!(parse_log_value(line, "RFF:"), None);
+    }
+
+    #[test]
+    fn showinfo_frames_are_scene_times() {
+        let log = "[Parsed_showinfo_1 @ 0x7f8b4c0] config in time_base: 1/12800, \
+                   frame_rate: 25/1\n\
+                   [Parsed_showinfo_1 @ 0x7f8b4c0] n:   0 pts: 57600 pts_time:4.5     \
+                   duration:    512 duration_time:0.04    fmt:yuv420p sar:1/1 s:1920x1080\n\
+                   [Parsed_showinfo_1 @ 0x7f8b4c0] n:   1 pts:156800 pts_time:12.25   \
+                   duration:    512 duration_time:0.04    fmt:yuv420p sar:1/1 s:1920x1080\n";
+        assert_eq!(parse_scenes(log, 30.0), [34.5, 42.25]);
+    }
+
+    #[test]
+    fn silence_starts_and_ends_are_paired() {
+        let log = "[silencedetect @ 0x6000013f8000] silence_start: -0.0213\n\
+                   [silencedetect @ 0x6000013f8000] silence_end: 1.5 | silence_duration: 1.5213\n\
+                   size=N/A time=00:00:05.00 bitrate=N/A speed= 250x\n\
+                   [silencedetect @ 0x6000013f8000] silence_start: 8.25\n";
+        assert_eq!(
+            parse_silence(log, 10.0, 20.0),
+            [
+                SilentRange {
+                    start: 10.0,
+                    end: 11.5,
+                },
+                // Still silent at the end of the range
+                SilentRange {
+                    start: 18.25,
+                    end: 20.0,
+                },
+            ]
+        );
+    }
+}
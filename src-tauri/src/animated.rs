@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimatedFormat {
+    #[default]
+    Gif,
+    Webp,
+    Apng,
+}
+
+/// Dithering algorithms of FFmpeg's `paletteuse` filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    Bayer,
+    Heckbert,
+    FloydSteinberg,
+    Sierra2,
+    #[default]
+    Sierra2_4a,
+    Sierra3,
+    Burkes,
+    Atkinson,
+    None,
+}
+
+impl Dither {
+    fn filter_value(self) -> &'static str {
+        match self {
+            Dither::Bayer => "bayer",
+            Dither::Heckbert => "heckbert",
+            Dither::FloydSteinberg => "floyd_steinberg",
+            Dither::Sierra2 => "sierra2",
+            Dither::Sierra2_4a => "sierra2_4a",
+            Dither::Sierra3 => "sierra3",
+            Dither::Burkes => "burkes",
+            Dither::Atkinson => "atkinson",
+            Dither::None => "none",
+        }
+    }
+}
+
+impl AnimatedFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimatedFormat::Gif => "gif",
+            AnimatedFormat::Webp => "webp",
+            AnimatedFormat::Apng => "apng",
+        }
+    }
+}
+
+const DEFAULT_FPS: f64 = 15.0;
+const DEFAULT_WEBP_QUALITY: u8 = 75;
+
+/// Settings for exporting the clip as an animated image instead of a video.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnimatedOptions {
+    #[serde(default)]
+    pub format: AnimatedFormat,
+    /// Frame rate of the animation, 15 by default
+    pub fps: Option<f64>,
+    /// Downscale wider frames to this width, keeping the aspect ratio
+    pub max_width: Option<u32>,
+    /// GIF only
+    #[serde(default)]
+    pub dither: Dither,
+    /// WebP only, 0-100 where higher is better
+    pub quality: Option<u8>,
+    /// How many times the animation plays, 0 (the default) loops forever
+    pub loop_count: Option<u32>,
+}
+
+impl AnimatedOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.fps {
+            Some(fps) if fps <= 0.0 || fps > 60.0 => Err(format!(
+                "Animation frame rate {} is out of range (0-60)",
+                fps
+            )),
+            _ => Ok(()),
+        }?;
+
+        match self.quality {
+            Some(_) if self.format != AnimatedFormat::Webp => {
+                Err("Quality is only supported for WebP animations".to_string())
+            }
+            Some(quality) if quality > 100 => {
+                Err(format!("WebP quality {} is out of range (0-100)", quality))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Filters that run after the crop and decide which frames end up in the
+    /// animation and at what size.
+    pub fn frame_filters(&self) -> Vec<String> {
+        let mut filters = vec![format!("fps={}", self.fps.unwrap_or(DEFAULT_FPS))];
+        if let Some(max_width) = self.max_width {
+            filters.push(format!("scale='min({},iw)':-1:flags=lanczos", max_width));
+        }
+        filters
+    }
+
+    /// `stats_mode=diff` favours the moving parts of the frame, which is what
+    /// cropped clips are mostly about.
+    pub fn palettegen_filter(&self) -> &'static str {
+        "palettegen=stats_mode=diff"
+    }
+
+    pub fn paletteuse_filter(&self) -> String {
+        format!("paletteuse=dither={}", self.dither.filter_value())
+    }
+
+    /// Encoder and muxer flags of the final pass, including the loop count.
+    pub fn output_args(&self) -> Vec<String> {
+        let plays = self.loop_count.unwrap_or(0);
+
+        match self.format {
+            // The GIF muxer counts repeats after the first play, with 0 for
+            // forever and -1 for playing once
+            AnimatedFormat::Gif => vec![
+                "-loop".into(),
+                match plays {
+                    0 => "0".to_string(),
+                    1 => "-1".to_string(),
+                    plays => (plays - 1).to_string(),
+                },
+                "-f".into(),
+                "gif".into(),
+            ],
+            AnimatedFormat::Webp => vec![
+                "-c:v".into(),
+                "libwebp_anim".into(),
+                "-quality".into(),
+                self.quality.unwrap_or(DEFAULT_WEBP_QUALITY).to_string(),
+                "-loop".into(),
+                plays.to_string(),
+                "-f".into(),
+                "webp".into(),
+            ],
+            AnimatedFormat::Apng => vec![
+                "-c:v".into(),
+                "apng".into(),
+                "-plays".into(),
+                plays.to_string(),
+                "-f".into(),
+                "apng".into(),
+            ],
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// Where an FFmpeg tool is run from.
+struct Binary {
+    program: String,
+    bundled: bool,
+}
+
+/// Prefers the sidecar shipped next to the app executable and falls back to
+/// whatever is on PATH, e.g. in dev builds without bundled binaries.
+fn resolve(name: &str) -> Binary {
+    let file_name = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    let sidecar = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(&file_name)))
+        .filter(|path| path.is_file());
+
+    match sidecar {
+        Some(path) => Binary {
+            program: path.to_string_lossy().to_string(),
+            bundled: true,
+        },
+        None => Binary {
+            program: name.to_string(),
+            bundled: false,
+        },
+    }
+}
+
+fn ffmpeg_binary() -> &'static Binary {
+    static FFMPEG: OnceLock<Binary> = OnceLock::new();
+    FFMPEG.get_or_init(|| resolve("ffmpeg"))
+}
+
+fn ffprobe_binary() -> &'static Binary {
+    static FFPROBE: OnceLock<Binary> = OnceLock::new();
+    FFPROBE.get_or_init(|| resolve("ffprobe"))
+}
+
+/// Program to run for `ffmpeg`.
+pub fn ffmpeg() -> &'static str {
+    &ffmpeg_binary().program
+}
+
+/// Program to run for `ffprobe`.
+pub fn ffprobe() -> &'static str {
+    &ffprobe_binary().program
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BinaryInfo {
+    pub path: String,
+    /// Whether this is the sidecar shipped with the app
+    pub bundled: bool,
+    /// e.g. `6.1.1`, as reported by `-version`
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FfmpegInfo {
+    pub ffmpeg: BinaryInfo,
+    pub ffprobe: BinaryInfo,
+}
+
+async fn binary_info(name: &str, binary: &Binary) -> Result<BinaryInfo, String> {
+    let output = tokio::process::Command::new(&binary.program)
+        .arg("-version")
+        .output()
+        .await
+        .map_err(|e| {
+            format!(
+                "{} was not found: {}. Install FFmpeg and make sure it is in PATH.",
+                name, e
+            )
+        })?;
+
+    // First line looks like `ffmpeg version 6.1.1 Copyright (c) ...`
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(2))
+        .ok_or(format!("Unexpected `{} -version` output", name))?;
+
+    Ok(BinaryInfo {
+        path: binary.program.clone(),
+        bundled: binary.bundled,
+        version: version.to_string(),
+    })
+}
+
+/// Reports which FFmpeg and ffprobe binaries exports use, failing if either
+/// cannot be run.
+#[tauri::command]
+pub async fn check_ffmpeg() -> Result<FfmpegInfo, String> {
+    Ok(FfmpegInfo {
+        ffmpeg: binary_info("ffmpeg", ffmpeg_binary()).await?,
+        ffprobe: binary_info("ffprobe", ffprobe_binary()).await?,
+    })
+}
@@ -0,0 +1,97 @@
+use serde::Serialize;
+use tokio::sync::OnceCell;
+
+use crate::binaries;
+
+/// What the FFmpeg build in use has compiled in.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub video_encoders: Vec<String>,
+    pub audio_encoders: Vec<String>,
+    pub filters: Vec<String>,
+    /// Hardware decoding methods accepted by `-hwaccel`
+    pub hwaccels: Vec<String>,
+}
+
+static CAPABILITIES: OnceCell<Capabilities> = OnceCell::const_new();
+
+async fn run_ffmpeg(arg: &str) -> Result<String, String> {
+    let output = tokio::process::Command::new(binaries::ffmpeg())
+        .args(["-hide_banner", arg])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Encoders of the given type from `ffmpeg -encoders`, whose lines look like
+/// ` V....D h264_nvenc           NVIDIA NVENC H.264 encoder`.
+fn parse_encoders(output: &str, kind: char) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let flags = columns.next()?;
+            let name = columns.next()?;
+            (flags.len() == 6 && flags.starts_with(kind)).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Filter names from `ffmpeg -filters`, whose lines look like
+/// ` ..C scale             V->V       Scale the input video size`.
+fn parse_filters(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let flags = columns.next()?;
+            let name = columns.next()?;
+            let pads = columns.next()?;
+            (flags.len() == 3 && pads.contains("->")).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Methods from `ffmpeg -hwaccels`, one per line after the heading.
+fn parse_hwaccels(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Hardware acceleration methods"))
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Capabilities of the FFmpeg build, detected once per run. A failed detection
+/// is not cached, so it is retried on the next call.
+pub async fn capabilities() -> Result<&'static Capabilities, String> {
+    CAPABILITIES
+        .get_or_try_init(|| async {
+            let encoders = run_ffmpeg("-encoders").await?;
+            let capabilities = Capabilities {
+                video_encoders: parse_encoders(&encoders, 'V'),
+                audio_encoders: parse_encoders(&encoders, 'A'),
+                filters: parse_filters(&run_ffmpeg("-filters").await?),
+                hwaccels: parse_hwaccels(&run_ffmpeg("-hwaccels").await?),
+            };
+
+            println!(
+                "FFmpeg capabilities: {} video encoders, {} audio encoders, {} filters, hwaccels {:?}",
+                capabilities.video_encoders.len(),
+                capabilities.audio_encoders.len(),
+                capabilities.filters.len(),
+                capabilities.hwaccels
+            );
+            Ok(capabilities)
+        })
+        .await
+}
+
+#[tauri::command]
+pub async fn get_ffmpeg_capabilities() -> Result<Capabilities, String> {
+    capabilities().await.cloned()
+}
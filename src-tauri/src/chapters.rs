@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::binaries;
+
+#[derive(Debug, Deserialize)]
+struct FfprobeChapters {
+    #[serde(default)]
+    chapters: Vec<FfprobeChapter>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeChapter {
+    start_time: String,
+    end_time: String,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// Chapter marker of a file, in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+/// Reads the chapter markers of the input, in order.
+pub async fn read_chapters(input_path: &str) -> Result<Vec<Chapter>, String> {
+    let output = tokio::process::Command::new(binaries::ffprobe())
+        .args(["-v", "error", "-print_format", "json", "-show_chapters"])
+        .arg(input_path)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let probed: FfprobeChapters = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Invalid ffprobe output: {}", e))?;
+    let mut chapters: Vec<Chapter> = probed
+        .chapters
+        .into_iter()
+        .filter_map(|chapter| {
+            Some(Chapter {
+                start: chapter.start_time.parse().ok()?,
+                end: chapter.end_time.parse().ok()?,
+                title: chapter.tags.get("title").cloned(),
+            })
+        })
+        .filter(|chapter| chapter.end > chapter.start)
+        .collect();
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(chapters)
+}
+
+#[tauri::command]
+pub async fn get_chapters(input_path: String) -> Result<Vec<Chapter>, String> {
+    read_chapters(&input_path).await
+}
+
+/// One chapter per range of an output joining ranges that end at
+/// `range_ends` (output times).
+pub fn joined_ranges(range_ends: &[f64]) -> Vec<Chapter> {
+    let mut start = 0.0;
+    range_ends
+        .iter()
+        .enumerate()
+        .map(|(index, &end)| {
+            let chapter = Chapter {
+                start,
+                end,
+                title: Some(format!("Chapter {}", index + 1)),
+            };
+            start = end;
+            chapter
+        })
+        .collect()
+}
+
+/// FFmpeg metadata file holding the chapters, read with `-map_chapters`.
+pub fn ffmetadata(chapters: &[Chapter]) -> String {
+    // `=`, `;`, `#`, `\` and line breaks are escaped with a backslash
+    let escape = |value: &str| {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    };
+    let millis = |time: f64| (time * 1000.0).round() as u64;
+
+    let mut contents = ";FFMETADATA1\n".to_string();
+    for chapter in chapters {
+        contents.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\n",
+            millis(chapter.start),
+            millis(chapter.end)
+        ));
+        if let Some(title) = &chapter.title {
+            contents.push_str(&format!("title={}\n", escape(title)));
+        }
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joined_ranges_become_chapters() {
+        let mut chapters = joined_ranges(&[12.5, 30.0]);
+        chapters[1].title = Some("Round 2; final".to_string());
+        assert_eq!(
+            ffmetadata(&chapters),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=12500\ntitle=Chapter 1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=12500\nEND=30000\ntitle=Round 2\\; final\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::filters::escape_value;
+
+const DEFAULT_SIMILARITY: f64 = 0.1;
+const DEFAULT_BLEND: f64 = 0.05;
+
+/// What shows through where the key color was.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChromaBackground {
+    /// "black", "#202020", ...
+    Color {
+        color: String,
+    },
+    Image {
+        path: String,
+    },
+    /// Looped if shorter than the clip
+    Video {
+        path: String,
+    },
+}
+
+/// Keys a color out of the cropped clip with `chromakey` and lays the rest
+/// over a background, for green screen footage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChromaKey {
+    /// Color to key out, e.g. "0x00ff00" or "green"
+    pub color: String,
+    /// How far from the key color a pixel may be and still be removed, 0.01
+    /// to 1
+    pub similarity: Option<f64>,
+    /// Softness of the edge, 0 for a hard cut
+    pub blend: Option<f64>,
+    pub background: ChromaBackground,
+}
+
+impl ChromaKey {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.color.trim().is_empty() {
+            return Err("Choose a color to key out".to_string());
+        }
+        if !(0.01..=1.0).contains(&self.similarity()) {
+            return Err("The key similarity must be between 0.01 and 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.blend()) {
+            return Err("The key blend must be between 0 and 1".to_string());
+        }
+        match &self.background {
+            ChromaBackground::Color { color } if color.trim().is_empty() => {
+                Err("Choose a background color".to_string())
+            }
+            ChromaBackground::Image { path } | ChromaBackground::Video { path }
+                if !Path::new(path).is_file() =>
+            {
+                Err(format!("Background {} not found", path))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn similarity(&self) -> f64 {
+        self.similarity.unwrap_or(DEFAULT_SIMILARITY)
+    }
+
+    fn blend(&self) -> f64 {
+        self.blend.unwrap_or(DEFAULT_BLEND)
+    }
+
+    /// Background filling `width` x `height` at `frame_rate`, the size and
+    /// rate of the keyed clip, so the overlay outputs frames in step with it.
+    fn background_filter(&self, width: u32, height: u32, frame_rate: f64) -> String {
+        // Covers the clip and crops what sticks out
+        let fill = format!(
+            "scale={w}:{h}:force_original_aspect_ratio=increase,crop={w}:{h},setsar=1",
+            w = width,
+            h = height
+        );
+        match &self.background {
+            ChromaBackground::Color { color } => format!(
+                "color=c={}:s={}x{}:r={}",
+                escape_value(color),
+                width,
+                height,
+                frame_rate
+            ),
+            ChromaBackground::Image { path } => format!(
+                "movie=filename={},loop=loop=-1:size=1,setpts=N/({}*TB),{}",
+                escape_value(path),
+                frame_rate,
+                fill
+            ),
+            ChromaBackground::Video { path } => format!(
+                "movie=filename={}:loop=0,fps={},{}",
+                escape_value(path),
+                frame_rate,
+                fill
+            ),
+        }
+    }
+
+    /// Sub-graph keying the picture coming into it and laying it over the
+    /// background. It fits into a filter chain like a single filter.
+    pub fn filter(&self, width: u32, height: u32, frame_rate: f64) -> String {
+        format!(
+            "chromakey=color={}:similarity={}:blend={}[chroma_fg];\
+             {}[chroma_bg];[chroma_bg][chroma_fg]overlay=shortest=1",
+            escape_value(&self.color),
+            self.similarity(),
+            self.blend(),
+            self.background_filter(width, height, frame_rate)
+        )
+    }
+}
@@ -0,0 +1,162 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use tauri::{async_runtime, AppHandle, Manager, Window};
+
+use crate::errors::JobError;
+use crate::ffmpeg::{spawn_export, ExportArgs};
+use crate::jobs::{JobRegistry, JobStatus};
+
+const USAGE: &str = "\
+Usage: video-cropper --input FILE [options]
+
+Exports one clip without the editor, printing each event as a JSON line.
+
+Options:
+  --input FILE      Video to export
+  --output FILE     Output path, may use {stem}, {ext}, ... ({stem}-cropped.{ext}
+                    next to the input by default)
+  --start SECONDS   Start of the clip, the start of the video by default
+  --end SECONDS     End of the clip, the end of the video by default
+  --crop WxH+X+Y    Crop in video pixels, the whole frame by default
+  --preset NAME     Saved preset to apply
+  --overwrite       Replace an existing output instead of failing
+  --help            Show this text";
+
+/// Managed when the app runs from the command line, see `emit_event`.
+pub struct Headless;
+
+/// Export arguments from the command line, or `None` when the app was
+/// started without `--input` and opens the editor.
+pub fn parse(args: &[String]) -> Result<Option<ExportArgs>, String> {
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        std::process::exit(0);
+    }
+    // Other arguments, like the ones macOS passes to apps it launches, are
+    // not meant for us
+    if !args.iter().any(|arg| arg == "--input") {
+        return Ok(None);
+    }
+
+    let mut input = None;
+    let mut export = json!({});
+    let (mut start, mut end) = (None, None);
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--overwrite" {
+            export["on_conflict"] = json!("overwrite");
+            continue;
+        }
+        let value = match flag.as_str() {
+            "--input" | "--output" | "--start" | "--end" | "--crop" | "--preset" => {
+                args.next().ok_or(format!("{} needs a value", flag))?
+            }
+            _ => return Err(format!("Unknown option {}\n\n{}", flag, USAGE)),
+        };
+        match flag.as_str() {
+            "--input" => input = Some(value.clone()),
+            "--output" => export["output_path"] = json!(value),
+            "--start" => start = Some(parse_seconds(flag, value)?),
+            "--end" => end = Some(parse_seconds(flag, value)?),
+            "--crop" => export["crop"] = parse_crop(value)?,
+            _ => export["preset"] = json!(value),
+        }
+    }
+
+    let input = input.ok_or("--input needs a value")?;
+    export["input_path"] = json!(input);
+    if export.get("output_path").is_none() {
+        let dir = Path::new(&input).parent().unwrap_or(Path::new(""));
+        export["output_path"] = json!(dir.join("{stem}-cropped.{ext}").to_string_lossy());
+    }
+    if export.get("crop").is_none() {
+        export["crop_fraction"] = json!({ "x": 0.0, "y": 0.0, "width": 1.0, "height": 1.0 });
+    }
+    // Open ends are taken as fractions of the duration, which forces both
+    // ends to be fractions
+    match (start, end) {
+        (Some(start), Some(end)) => export["selection"] = json!([{ "start": start, "end": end }]),
+        (None, None) => {
+            export["selection"] = json!([{ "start": 0.0, "end": 1.0 }]);
+            export["relative_selection"] = json!(true);
+        }
+        _ => return Err("Give both --start and --end, or neither".to_string()),
+    }
+
+    serde_json::from_value(export)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+fn parse_seconds(flag: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} takes seconds, not {}", flag, value))
+}
+
+/// `1080x1920+420+0` as a `CropArea`.
+fn parse_crop(value: &str) -> Result<Value, String> {
+    let invalid = || format!("--crop takes WxH+X+Y, not {}", value);
+    let (size, position) = value.split_once('+').ok_or_else(invalid)?;
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let (x, y) = position.split_once('+').ok_or_else(invalid)?;
+    let number = |part: &str| part.trim().parse::<u32>().map_err(|_| invalid());
+    Ok(json!({
+        "x": number(x)?,
+        "y": number(y)?,
+        "width": number(width)?,
+        "height": number(height)?,
+    }))
+}
+
+/// Stands in for `Window::emit` while headless.
+pub fn print_event<S: Serialize>(event: &str, payload: &S) {
+    match serde_json::to_string(payload) {
+        Ok(payload) => println!("{} {}", event, payload),
+        Err(e) => println!("Could not print {}: {}", event, e),
+    }
+}
+
+/// Runs the export with the editor's own job code in the hidden `window`,
+/// then quits with a non-zero code unless it finished.
+pub fn run(app_handle: AppHandle, window: Window, args: ExportArgs) {
+    app_handle.manage(Headless);
+    if let Err(e) = window.hide() {
+        println!("Could not hide the window: {}", e);
+    }
+
+    async_runtime::spawn(async move {
+        let job_id = app_handle.state::<JobRegistry>().next_id();
+        let status = match spawn_export(window, job_id, args).await {
+            Ok(handle) => handle.await.unwrap_or(JobStatus::Failed),
+            Err(e) => {
+                print_event("ffmpeg-error", &JobError::new(job_id, e));
+                JobStatus::Failed
+            }
+        };
+        app_handle.exit(if status == JobStatus::Finished { 0 } else { 1 });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_fill_in_the_export_args() {
+        let flags =
+            |flags: &str| -> Vec<String> { flags.split_whitespace().map(String::from).collect() };
+        let args = parse(&flags(
+            "--input in.mp4 --start 1 --end 4.5 --crop 608x1080+656+0 --preset discord",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(args.input_path(), "in.mp4");
+
+        assert!(parse(&[]).unwrap().is_none());
+        assert!(parse(&flags("--input in.mp4 --start 1")).is_err());
+        assert!(parse(&flags("--input in.mp4 --crop 608x1080")).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+    Vp9,
+    Av1,
+    /// Intra-frame editing codecs, profiles are set by `EditingPreset`
+    Prores,
+    Dnxhr,
+}
+
+impl VideoCodec {
+    pub const ALL: [VideoCodec; 6] = [
+        VideoCodec::H264,
+        VideoCodec::Hevc,
+        VideoCodec::Vp9,
+        VideoCodec::Av1,
+        VideoCodec::Prores,
+        VideoCodec::Dnxhr,
+    ];
+
+    /// Maps an ffprobe `codec_name` to the codec, if it is one we can encode.
+    pub fn from_ffprobe_name(name: &str) -> Option<Self> {
+        match name {
+            "h264" => Some(VideoCodec::H264),
+            "hevc" => Some(VideoCodec::Hevc),
+            "vp9" => Some(VideoCodec::Vp9),
+            "av1" => Some(VideoCodec::Av1),
+            _ => None,
+        }
+    }
+
+    /// Software encoder used when no hardware encoder is selected.
+    pub fn cpu_encoder(self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::Hevc => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1 => "libsvtav1",
+            VideoCodec::Prores => "prores_ks",
+            VideoCodec::Dnxhr => "dnxhd",
+        }
+    }
+
+    /// Codecs made for editing rather than delivery.
+    pub fn is_editing(self) -> bool {
+        matches!(self, VideoCodec::Prores | VideoCodec::Dnxhr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    /// Keep the source audio stream as is
+    Copy,
+    Aac,
+    Opus,
+    Mp3,
+    Flac,
+    /// 24-bit PCM, what editors import without decoding
+    Pcm,
+}
+
+impl AudioCodec {
+    pub fn encoder(self) -> &'static str {
+        match self {
+            AudioCodec::Copy => "copy",
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Mp3 => "libmp3lame",
+            AudioCodec::Flac => "flac",
+            AudioCodec::Pcm => "pcm_s24le",
+        }
+    }
+}
+
+/// Output file types of audio-only exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Mp3,
+    /// Raw ADTS stream (`.aac`)
+    Aac,
+    Flac,
+    Wav,
+}
+
+impl AudioFormat {
+    pub fn encoder(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "libmp3lame",
+            AudioFormat::Aac => "aac",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Wav => "pcm_s16le",
+        }
+    }
+
+    /// Muxer name passed to `-f`.
+    pub fn format_name(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Aac => "adts",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Wav => "wav",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Aac => "aac",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Wav => "wav",
+        }
+    }
+
+    /// Lossless formats have no bitrate to choose.
+    pub fn is_lossy(self) -> bool {
+        matches!(self, AudioFormat::Mp3 | AudioFormat::Aac)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    Mov,
+    Mkv,
+    Webm,
+}
+
+impl Container {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "mp4" | "m4v" => Some(Container::Mp4),
+            "mov" => Some(Container::Mov),
+            "mkv" => Some(Container::Mkv),
+            "webm" => Some(Container::Webm),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mov => "mov",
+            Container::Mkv => "mkv",
+            Container::Webm => "webm",
+        }
+    }
+
+    /// Muxer name passed to `-f`.
+    pub fn format_name(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mov => "mov",
+            Container::Mkv => "matroska",
+            Container::Webm => "webm",
+        }
+    }
+
+    /// Codec keeping the alpha channel of the source by default, none for MP4
+    /// which has no codec that stores one.
+    pub fn alpha_codec(self) -> Option<VideoCodec> {
+        match self {
+            Container::Mp4 => None,
+            Container::Mov => Some(VideoCodec::Prores),
+            Container::Mkv | Container::Webm => Some(VideoCodec::Vp9),
+        }
+    }
+
+    fn default_video_codec(self) -> VideoCodec {
+        match self {
+            Container::Webm => VideoCodec::Vp9,
+            _ => VideoCodec::H264,
+        }
+    }
+
+    fn default_audio_codec(self) -> AudioCodec {
+        match self {
+            // WebM only takes Opus/Vorbis, which the source rarely is
+            Container::Webm => AudioCodec::Opus,
+            _ => AudioCodec::Copy,
+        }
+    }
+
+    fn supports_video(self, codec: VideoCodec) -> bool {
+        match self {
+            Container::Mp4 => !codec.is_editing(),
+            Container::Mkv => true,
+            Container::Mov => matches!(
+                codec,
+                VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Prores | VideoCodec::Dnxhr
+            ),
+            Container::Webm => matches!(codec, VideoCodec::Vp9 | VideoCodec::Av1),
+        }
+    }
+
+    fn supports_audio(self, codec: AudioCodec) -> bool {
+        match self {
+            Container::Mp4 => codec != AudioCodec::Pcm,
+            Container::Mkv => true,
+            Container::Mov => matches!(
+                codec,
+                AudioCodec::Copy | AudioCodec::Aac | AudioCodec::Mp3 | AudioCodec::Pcm
+            ),
+            Container::Webm => codec == AudioCodec::Opus,
+        }
+    }
+}
+
+/// Fully resolved output format of an export.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputFormat {
+    pub container: Container,
+    pub video_codec: VideoCodec,
+    pub audio_codec: AudioCodec,
+}
+
+/// Fills in defaults for anything not chosen explicitly and rejects codec and
+/// container combinations FFmpeg cannot mux.
+pub fn resolve_output_format(
+    container: Option<Container>,
+    video_codec: Option<VideoCodec>,
+    audio_codec: Option<AudioCodec>,
+    output_path: &str,
+) -> Result<OutputFormat, String> {
+    let container = container
+        .or_else(|| Container::from_path(output_path))
+        .ok_or(format!(
+            "Cannot infer the container from {}, choose one explicitly",
+            output_path
+        ))?;
+    let video_codec = video_codec.unwrap_or(container.default_video_codec());
+    let audio_codec = match audio_codec {
+        Some(codec) => codec,
+        // Editors import PCM without decoding it again
+        None if video_codec.is_editing() => AudioCodec::Pcm,
+        None => container.default_audio_codec(),
+    };
+
+    if !container.supports_video(video_codec) {
+        return Err(format!(
+            "{:?} video cannot be stored in a {:?} container",
+            video_codec, container
+        ));
+    }
+    if !container.supports_audio(audio_codec) {
+        return Err(format!(
+            "{:?} audio cannot be stored in a {:?} container",
+            audio_codec, container
+        ));
+    }
+
+    Ok(OutputFormat {
+        container,
+        video_codec,
+        audio_codec,
+    })
+}
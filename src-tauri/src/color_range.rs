@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+/// Levels the YUV values of a picture use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorRange {
+    /// 16 - 235, what players expect from video
+    Limited,
+    /// 0 - 255, from phones, screen recorders and webcams
+    Full,
+}
+
+impl ColorRange {
+    /// The range of a source from ffprobe `color_range` (`tv` or `pc`), or
+    /// from its pixel format for the JPEG formats that imply full range.
+    pub fn probed(color_range: Option<&str>, pix_fmt: Option<&str>) -> Option<Self> {
+        match color_range {
+            Some("tv") => Some(ColorRange::Limited),
+            Some("pc") => Some(ColorRange::Full),
+            _ if pix_fmt.is_some_and(|pix_fmt| pix_fmt.starts_with("yuvj")) => {
+                Some(ColorRange::Full)
+            }
+            _ => None,
+        }
+    }
+
+    /// Name taken by `-color_range` and the `scale` and `setparams` filters.
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            ColorRange::Limited => "tv",
+            ColorRange::Full => "pc",
+        }
+    }
+}
+
+/// Overrides of the color range of an export.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RangeOptions {
+    /// Range the source is read as, for files whose tag is missing or wrong
+    pub input: Option<ColorRange>,
+    /// Range of video outputs, limited by default
+    pub output: Option<ColorRange>,
+}
+
+/// Range of the source and of a re-encoded video output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangePlan {
+    /// Set when the source is read in another range than its tag says
+    pub input_override: Option<ColorRange>,
+    /// Range of the source as probed or overridden, if known
+    pub input: Option<ColorRange>,
+    /// None for animated outputs, which end up in RGB
+    pub output: Option<ColorRange>,
+    /// Pixel format of a JPEG (`yuvj`) source, which carries its range
+    pub jpeg_pix_fmt: Option<String>,
+}
+
+impl RangePlan {
+    pub fn new(
+        options: RangeOptions,
+        probed: Option<ColorRange>,
+        pix_fmt: Option<&str>,
+        video: bool,
+    ) -> Self {
+        RangePlan {
+            input_override: options.input.filter(|&range| Some(range) != probed),
+            input: options.input.or(probed),
+            output: video.then(|| options.output.unwrap_or(ColorRange::Limited)),
+            jpeg_pix_fmt: pix_fmt
+                .filter(|pix_fmt| pix_fmt.starts_with("yuvj"))
+                .map(String::from),
+        }
+    }
+
+    /// Retags the decoded frames, before any filter converts them.
+    pub fn input_filter(&self) -> Option<String> {
+        self.input_override
+            .map(|range| format!("setparams=range={}", range.ffmpeg_name()))
+    }
+
+    /// Converts the filtered picture to the output range. Tonemapping
+    /// already leaves it in limited range.
+    pub fn output_filters(&self, tonemapped: bool) -> Vec<String> {
+        let Some(output) = self.output else {
+            return vec![];
+        };
+        let input = if tonemapped {
+            ColorRange::Limited
+        } else {
+            self.input.unwrap_or(ColorRange::Limited)
+        };
+        if input == output {
+            return vec![];
+        }
+        // `scale` reads the range of the frames it gets
+        let mut filters = vec![format!("scale=out_range={}", output.ffmpeg_name())];
+        // Otherwise the JPEG format of the source would be kept with the new
+        // range
+        if let (ColorRange::Limited, Some(pix_fmt)) = (output, &self.jpeg_pix_fmt) {
+            if !tonemapped {
+                filters.push(format!("format={}", pix_fmt.replacen("yuvj", "yuv", 1)));
+            }
+        }
+        filters
+    }
+
+    /// Tags the output with its range.
+    pub fn output_args(&self) -> Vec<String> {
+        match self.output {
+            Some(range) => vec!["-color_range".to_string(), range.ffmpeg_name().to_string()],
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_range_sources_are_brought_to_limited_range() {
+        let probed = ColorRange::probed(None, Some("yuvj420p"));
+        assert_eq!(probed, Some(ColorRange::Full));
+
+        let plan = RangePlan::new(RangeOptions::default(), probed, Some("yuvj420p"), true);
+        assert_eq!(plan.input_filter(), None);
+        assert_eq!(
+            plan.output_filters(false),
+            ["scale=out_range=tv", "format=yuv420p"]
+        );
+        assert_eq!(plan.output_args(), ["-color_range", "tv"]);
+
+        // A mistagged source is read as full range and kept that way
+        let options = RangeOptions {
+            input: Some(ColorRange::Full),
+            output: Some(ColorRange::Full),
+        };
+        let plan = RangePlan::new(options, Some(ColorRange::Limited), Some("yuv420p"), true);
+        assert_eq!(plan.input_filter().unwrap(), "setparams=range=pc");
+        assert!(plan.output_filters(false).is_empty());
+        assert_eq!(plan.output_filters(true), ["scale=out_range=pc"]);
+    }
+}
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::binaries;
+use crate::capabilities::capabilities;
+use crate::codecs::VideoCodec;
+
+/// Video encoder requested for an export.
+///
+/// `Auto` picks the first working hardware encoder and falls back to the CPU
+/// when none is usable; the hardware variants fall back the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoder {
+    #[default]
+    Cpu,
+    Auto,
+    Nvenc,
+    Qsv,
+    Amf,
+    VideoToolbox,
+    Vaapi,
+}
+
+const HARDWARE: [Encoder; 5] = [
+    Encoder::Nvenc,
+    Encoder::Qsv,
+    Encoder::Amf,
+    Encoder::VideoToolbox,
+    Encoder::Vaapi,
+];
+
+// VAAPI needs an explicit render node to upload frames to
+const VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+impl Encoder {
+    fn is_supported_on_platform(self) -> bool {
+        match self {
+            Encoder::Cpu | Encoder::Auto => true,
+            Encoder::Nvenc | Encoder::Qsv => cfg!(any(windows, target_os = "linux")),
+            Encoder::Amf => cfg!(windows),
+            Encoder::VideoToolbox => cfg!(target_os = "macos"),
+            Encoder::Vaapi => cfg!(target_os = "linux"),
+        }
+    }
+
+    /// FFmpeg encoder for `codec`, `None` if this hardware cannot encode it.
+    pub fn encoder_name(self, codec: VideoCodec) -> Option<&'static str> {
+        use VideoCodec::*;
+
+        match (self, codec) {
+            (Encoder::Cpu | Encoder::Auto, _) => Some(codec.cpu_encoder()),
+            (Encoder::Nvenc, H264) => Some("h264_nvenc"),
+            (Encoder::Nvenc, Hevc) => Some("hevc_nvenc"),
+            (Encoder::Nvenc, Av1) => Some("av1_nvenc"),
+            (Encoder::Qsv, H264) => Some("h264_qsv"),
+            (Encoder::Qsv, Hevc) => Some("hevc_qsv"),
+            (Encoder::Qsv, Vp9) => Some("vp9_qsv"),
+            (Encoder::Qsv, Av1) => Some("av1_qsv"),
+            (Encoder::Amf, H264) => Some("h264_amf"),
+            (Encoder::Amf, Hevc) => Some("hevc_amf"),
+            (Encoder::Amf, Av1) => Some("av1_amf"),
+            (Encoder::VideoToolbox, H264) => Some("h264_videotoolbox"),
+            (Encoder::VideoToolbox, Hevc) => Some("hevc_videotoolbox"),
+            (Encoder::Vaapi, H264) => Some("h264_vaapi"),
+            (Encoder::Vaapi, Hevc) => Some("hevc_vaapi"),
+            (Encoder::Vaapi, Vp9) => Some("vp9_vaapi"),
+            (Encoder::Vaapi, Av1) => Some("av1_vaapi"),
+            _ => None,
+        }
+    }
+
+    /// Arguments that go before `-i` to enable hardware decoding.
+    pub fn input_args(self) -> Vec<&'static str> {
+        match self {
+            Encoder::Cpu | Encoder::Auto => vec![],
+            Encoder::Nvenc => vec!["-hwaccel", "cuda"],
+            Encoder::Qsv => vec!["-hwaccel", "qsv"],
+            Encoder::Amf => vec!["-hwaccel", "d3d11va"],
+            Encoder::VideoToolbox => vec!["-hwaccel", "videotoolbox"],
+            Encoder::Vaapi => vec!["-vaapi_device", VAAPI_DEVICE],
+        }
+    }
+
+    /// Filter appended after the software filters, for encoders that only
+    /// accept frames in GPU memory.
+    pub fn filter_suffix(self) -> Option<&'static str> {
+        match self {
+            Encoder::Vaapi => Some("format=nv12,hwupload"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HwEncoder {
+    pub encoder: Encoder,
+    pub codec: VideoCodec,
+    pub name: &'static str,
+}
+
+static HW_ENCODERS: OnceCell<Vec<HwEncoder>> = OnceCell::const_new();
+
+/// Being listed by `-encoders` does not mean a usable GPU or driver exists, so
+/// each candidate has to encode a single test frame first.
+async fn encodes_test_frame(encoder: Encoder, name: &str) -> bool {
+    let mut args = vec!["-hide_banner", "-v", "error"];
+    args.extend(encoder.input_args());
+    args.extend([
+        "-f",
+        "lavfi",
+        "-i",
+        "color=black:s=256x256",
+        "-frames:v",
+        "1",
+    ]);
+    if let Some(filter) = encoder.filter_suffix() {
+        args.extend(["-vf", filter]);
+    }
+    args.extend(["-c:v", name, "-f", "null", "-"]);
+
+    tokio::process::Command::new(binaries::ffmpeg())
+        .args(args)
+        .output()
+        .await
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Hardware encoders that work on this machine, detected once per run.
+pub async fn available_hw_encoders() -> &'static [HwEncoder] {
+    HW_ENCODERS
+        .get_or_init(|| async {
+            let compiled = match capabilities().await {
+                Ok(capabilities) => capabilities.video_encoders.as_slice(),
+                Err(e) => {
+                    println!("Could not list FFmpeg encoders: {}", e);
+                    &[]
+                }
+            };
+
+            let mut available = vec![];
+            for encoder in HARDWARE {
+                if !encoder.is_supported_on_platform() {
+                    continue;
+                }
+                for codec in VideoCodec::ALL {
+                    let Some(name) = encoder.encoder_name(codec) else {
+                        continue;
+                    };
+                    if compiled.iter().any(|compiled| compiled == name)
+                        && encodes_test_frame(encoder, name).await
+                    {
+                        available.push(HwEncoder {
+                            encoder,
+                            codec,
+                            name,
+                        });
+                    }
+                }
+            }
+
+            println!("Hardware encoders: {:?}", available);
+            available
+        })
+        .await
+}
+
+/// Turns the requested encoder into one that will actually run `codec`,
+/// falling back to the CPU encoder when the hardware is missing.
+pub async fn resolve_encoder(requested: Encoder, codec: VideoCodec) -> Encoder {
+    if requested == Encoder::Cpu {
+        return Encoder::Cpu;
+    }
+
+    available_hw_encoders()
+        .await
+        .iter()
+        .find(|hw| hw.codec == codec && (requested == Encoder::Auto || hw.encoder == requested))
+        .map(|hw| hw.encoder)
+        .unwrap_or_else(|| {
+            println!(
+                "Encoder {:?} unavailable for {:?}, falling back to CPU",
+                requested, codec
+            );
+            Encoder::Cpu
+        })
+}
+
+#[tauri::command]
+pub async fn list_hw_encoders() -> Vec<HwEncoder> {
+    available_hw_encoders().await.to_vec()
+}
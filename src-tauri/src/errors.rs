@@ -0,0 +1,143 @@
+use serde::Serialize;
+use std::fmt;
+
+use crate::jobs::JobId;
+
+/// Why an export could not be started or did not finish.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportError {
+    /// The arguments cannot be exported as given
+    InvalidInput {
+        message: String,
+    },
+    /// ffprobe could not read the source
+    ProbeFailed {
+        message: String,
+    },
+    /// FFmpeg does not have the encoder the export needs
+    EncoderMissing {
+        encoder: String,
+    },
+    /// FFmpeg could not be started
+    SpawnFailed {
+        message: String,
+    },
+    /// FFmpeg exited with an error
+    NonZeroExit {
+        code: Option<i32>,
+        /// Last lines FFmpeg wrote to stderr
+        stderr_tail: Vec<String>,
+    },
+    /// Reading or writing files around the export failed
+    Io {
+        message: String,
+    },
+    /// The output is expected to need more space than the volume has left
+    InsufficientDiskSpace {
+        path: String,
+        required_bytes: u64,
+        available_bytes: u64,
+    },
+    JobNotFound {
+        job_id: JobId,
+    },
+    Cancelled,
+}
+
+impl ExportError {
+    pub fn probe(message: String) -> Self {
+        ExportError::ProbeFailed { message }
+    }
+
+    pub fn io(context: &str, error: impl fmt::Display) -> Self {
+        ExportError::Io {
+            message: format!("{}: {}", context, error),
+        }
+    }
+
+    /// Picks the most specific error for an FFmpeg run that failed.
+    pub fn from_exit(code: Option<i32>, stderr_tail: Vec<String>) -> Self {
+        let missing_encoder = stderr_tail.iter().find_map(|line| {
+            let (_, rest) = line.split_once("Unknown encoder '")?;
+            Some(rest.split('\'').next()?.to_string())
+        });
+
+        match missing_encoder {
+            Some(encoder) => ExportError::EncoderMissing { encoder },
+            None => ExportError::NonZeroExit { code, stderr_tail },
+        }
+    }
+}
+
+/// Messages from the validation helpers, which return plain strings.
+impl From<String> for ExportError {
+    fn from(message: String) -> Self {
+        ExportError::InvalidInput { message }
+    }
+}
+
+impl From<&str> for ExportError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::InvalidInput { message }
+            | ExportError::ProbeFailed { message }
+            | ExportError::Io { message } => write!(f, "{}", message),
+            ExportError::EncoderMissing { encoder } => {
+                write!(f, "FFmpeg was built without the {} encoder", encoder)
+            }
+            ExportError::SpawnFailed { message } => {
+                write!(f, "Could not start FFmpeg: {}", message)
+            }
+            ExportError::NonZeroExit { code, stderr_tail } => {
+                match code {
+                    Some(code) => write!(f, "FFmpeg exited with error code: {}", code)?,
+                    None => write!(f, "FFmpeg process finished without explicit status code.")?,
+                }
+                match stderr_tail.last() {
+                    Some(line) => write!(f, " ({})", line),
+                    None => Ok(()),
+                }
+            }
+            ExportError::InsufficientDiskSpace {
+                path,
+                required_bytes,
+                available_bytes,
+            } => write!(
+                f,
+                "Not enough disk space in {}: about {} MB needed, {} MB free",
+                path,
+                required_bytes / 1_000_000,
+                available_bytes / 1_000_000
+            ),
+            ExportError::JobNotFound { job_id } => {
+                write!(f, "No running export with id {}", job_id)
+            }
+            ExportError::Cancelled => write!(f, "Export cancelled"),
+        }
+    }
+}
+
+/// Payload of `ffmpeg-error`. `message` is the error ready for display.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobError {
+    pub job_id: JobId,
+    pub message: String,
+    pub error: ExportError,
+}
+
+impl JobError {
+    pub fn new(job_id: JobId, error: ExportError) -> Self {
+        Self {
+            job_id,
+            message: error.to_string(),
+            error,
+        }
+    }
+}
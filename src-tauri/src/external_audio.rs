@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+// Ducking lowers the external audio by up to `DUCK_RATIO` while the original
+// audio is louder than `DUCK_THRESHOLD` (about -30 dBFS)
+const DUCK_THRESHOLD: f64 = 0.03;
+const DUCK_RATIO: u32 = 8;
+const DUCK_ATTACK_MS: u32 = 20;
+const DUCK_RELEASE_MS: u32 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExternalAudioMode {
+    /// Drop the original audio and use the external file instead
+    Replace,
+    /// Play the external file under the original audio
+    Mix,
+}
+
+/// Audio file laid under or in place of the audio of the clip, such as a music
+/// bed or a re-recorded voiceover. It starts with the clip and is cut to its
+/// length.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalAudio {
+    pub path: String,
+    pub mode: ExternalAudioMode,
+    /// Level of the external audio in dB, 0 keeps it as is
+    pub gain_db: Option<f64>,
+    /// Lower the external audio while the original audio is loud. Mix only.
+    #[serde(default)]
+    pub duck: bool,
+    /// Seconds into the file the clip starts at
+    pub start: Option<f64>,
+}
+
+impl ExternalAudio {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.gain_db.is_some_and(|gain| !gain.is_finite()) {
+            return Err("The external audio gain must be a finite number of dB".to_string());
+        }
+        if self
+            .start
+            .is_some_and(|start| !start.is_finite() || start < 0.0)
+        {
+            return Err("The external audio cannot start before its beginning".to_string());
+        }
+        if self.duck && self.mode == ExternalAudioMode::Replace {
+            return Err("Ducking needs the original audio, mix the external audio instead".into());
+        }
+        Ok(())
+    }
+
+    /// Options opening the file as an FFmpeg input.
+    pub fn input_args(&self) -> Vec<String> {
+        let mut input_args = vec![];
+        if let Some(start) = self.start.filter(|start| *start > 0.0) {
+            input_args.extend(["-ss".into(), start.to_string()]);
+        }
+        input_args.extend(["-i".into(), self.path.clone()]);
+        input_args
+    }
+
+    /// Chain taking the file from input `input`, cut to `duration` seconds and
+    /// delayed to start at `time_offset`, the start of the clip in the
+    /// filtered stream. Has no output label.
+    pub fn source_filter(&self, input: usize, time_offset: f64, duration: f64) -> String {
+        let mut filters = vec![format!("atrim=duration={}", duration)];
+        if time_offset > 0.0 {
+            filters.push(format!(
+                "adelay=delays={}:all=1",
+                (time_offset * 1000.0).round() as u64
+            ));
+        }
+        if let Some(gain) = self.gain_db.filter(|gain| *gain != 0.0) {
+            filters.push(format!("volume={}dB", gain));
+        }
+        format!("[{}:a]{}", input, filters.join(","))
+    }
+
+    /// Part of a filter chain mixing the file under the audio coming into it,
+    /// see `source_filter` for the arguments. Keeps the length of the
+    /// original audio.
+    pub fn mix_filter(&self, input: usize, time_offset: f64, duration: f64) -> String {
+        let source = self.source_filter(input, time_offset, duration);
+        let mix = "[ext_orig][ext_audio]amix=inputs=2:duration=first:normalize=0";
+        if self.duck {
+            format!(
+                "asplit=2[ext_orig][ext_key];{}[ext_bed];\
+                 [ext_bed][ext_key]sidechaincompress=threshold={}:ratio={}:attack={}:release={}[ext_audio];{}",
+                source, DUCK_THRESHOLD, DUCK_RATIO, DUCK_ATTACK_MS, DUCK_RELEASE_MS, mix
+            )
+        } else {
+            format!("anull[ext_orig];{}[ext_audio];{}", source, mix)
+        }
+    }
+}
@@ -1,264 +1,4660 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::process::Stdio;
-use tauri::api::process::Command;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use tauri::api::process::{Command, CommandEvent};
 use tauri::async_runtime;
-use tauri::{AppHandle, Window};
+use tauri::async_runtime::Receiver;
+use tauri::{AppHandle, Manager, State, Window};
 use tokio::fs;
 
+use crate::actions::{run_post_action, shutdown_if_idle, PostAction};
+use crate::analysis::detect_interlacing;
+use crate::animated::{AnimatedFormat, AnimatedOptions};
+use crate::binaries;
+use crate::capabilities::capabilities;
+use crate::chapters::{ffmetadata, joined_ranges, read_chapters};
+use crate::chroma::ChromaKey;
+use crate::codecs::{
+    resolve_output_format, AudioCodec, AudioFormat, Container, OutputFormat, VideoCodec,
+};
+use crate::color_range::{RangeOptions, RangePlan};
+use crate::encoders::{resolve_encoder, Encoder};
+use crate::errors::{ExportError, JobError};
+use crate::external_audio::{ExternalAudio, ExternalAudioMode};
+use crate::filters::{
+    atempo_filters, escape_value, fade_filters, frame_rate_value, interpolation_expr,
+    transform_filters, validate_fades, validate_frame_rate, validate_rotation, AspectFit,
+    AspectRatio, BlurFill, ColorAdjustment, Deinterlace, Denoise, Flip, Interpolation,
+    ScaleOptions, ScaleSize, Stabilization, TonemapOperator, Watermark,
+};
+use crate::hdr::{merge_x265_params, HdrSignal};
+use crate::history::{record as record_history, PendingEntry};
+use crate::intermediate::EditingPreset;
+use crate::jobs::{emit_event, JobFinished, JobId, JobMessage, JobRegistry, JobStatus, RunningJob};
+use crate::ladder::{add_rungs, LadderRung};
+use crate::layout::{CropOutput, DualCrop};
+use crate::loudness::{measured_options, Loudness, MEASUREMENT_PLACEHOLDER};
+use crate::lut::{lut_filter, validate_lut};
+use crate::masks::MaskRegion;
+use crate::metadata::{keep_modified_time, MetadataOptions};
+use crate::presets::{load_preset, PresetSettings};
+use crate::probe::{get_keyframes, probe, VideoMetadata};
+use crate::progress::ProgressParser;
+use crate::quality::{lookahead_frames, Quality, SpeedPreset};
+use crate::queue::ExportQueue;
+use crate::scratch::{check_budget, job_temp_dir, remove_temp_dir, scratch_file};
+use crate::settings::{Settings, SettingsStore};
+use crate::smartcut::{plan_segments, Segment};
+use crate::streaming::StreamingOptions;
+use crate::subtitles::{retime, RetimedRange, SubtitleFormat};
+use crate::taskbar::{job_ended, show_progress};
+use crate::text::{resolve_font, TextOverlay};
+use crate::thumbnails::get_thumbnail;
+use crate::timecode::{to_selection, FrameRange};
+use crate::web::{merge_movflags, WebOptions};
+
 // Data Structures matching Plan Section 4.4
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipSelection {
-    start: f64,
-    end: f64,
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CropArea {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Crop as fractions (0 - 1) of the displayed frame, which fits sources of
+/// any size.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FractionalCrop {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl FractionalCrop {
+    pub fn validate(&self) -> Result<(), String> {
+        let fractions = [self.x, self.y, self.width, self.height];
+        if fractions.iter().any(|value| !(0.0..=1.0).contains(value)) {
+            return Err("Crop fractions must be between 0 and 1".to_string());
+        }
+        if self.width == 0.0 || self.height == 0.0 {
+            return Err("The crop area is too small to export".to_string());
+        }
+        // A little rounding slack for fractions written as 1/3 + 2/3
+        if self.x + self.width > 1.0 + 1e-6 || self.y + self.height > 1.0 + 1e-6 {
+            return Err("The crop fractions reach past the frame".to_string());
+        }
+        Ok(())
+    }
+
+    /// The crop in pixels of a `width` x `height` frame.
+    pub fn resolve(&self, width: u32, height: u32) -> CropArea {
+        let pixels = |fraction: f64, side: u32| (fraction * side as f64).round() as u32;
+        CropArea {
+            x: pixels(self.x, width),
+            y: pixels(self.y, height),
+            width: pixels(self.width, width),
+            height: pixels(self.height, height),
+        }
+    }
+}
+
+/// Size of a video frame in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Position of the crop window at `time` (seconds in the source). The window
+/// moves linearly between keyframes and keeps the size of `ExportArgs::crop`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CropKeyframe {
+    time: f64,
+    x: u32,
+    y: u32,
+}
+
+/// What to do when an output file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    #[default]
+    Fail,
+    Overwrite,
+    /// Write to `name_1.ext`, `name_2.ext`, ... instead
+    Rename,
+}
+
+/// How re-encoded cuts get to their first frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrimAccuracy {
+    /// Jump to shortly before the cut on the input, then decode up to the
+    /// exact frame
+    #[default]
+    Seek,
+    /// Decode everything before the cut. Slow on long files, for sources
+    /// whose index seeks to the wrong place.
+    Decode,
+}
+
+/// Most each file of a split export may hold, for platforms capping the
+/// length or size of uploads.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum SplitLimit {
+    /// Seconds of output
+    Duration { seconds: f64 },
+    /// MiB, from the estimated bitrate with some headroom
+    Size { megabytes: f64 },
+}
+
+/// Order the selected clip is played in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Playback {
+    #[default]
+    Forward,
+    Reverse,
+    /// Forward, then reversed back to the start
+    Boomerang,
+}
+
+impl Playback {
+    /// Length of the output in lengths of the selection.
+    fn copies(self) -> f64 {
+        match self {
+            Playback::Boomerang => 2.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportArgs {
+    /// Left out of the shared settings of a batch, which fills it in per input
+    #[serde(default)]
+    input_path: String,
+    /// May contain the placeholders of `fill_placeholders`, e.g.
+    /// `{stem}_crop_{width}x{height}_{start}-{end}.{ext}`
+    output_path: String,
+    /// Ranges to keep, joined in this order
+    selection: Vec<ClipSelection>,
+    /// Treat `selection` as the ranges to cut out and keep everything else
+    #[serde(default)]
+    remove_selection: bool,
+    /// Treat `selection` as fractions (0 - 1) of the source duration, so one
+    /// selection fits inputs of any length
+    #[serde(default)]
+    relative_selection: bool,
+    /// Ranges in frame numbers or SMPTE timecodes instead of seconds,
+    /// converted into `selection` at the probed frame rate
+    #[serde(default)]
+    frame_selection: Vec<FrameRange>,
+    /// Export each chapter of the input to a file of its own, named by
+    /// `output_template`. Replaces `selection`.
+    #[serde(default)]
+    split_chapters: bool,
+    /// Chop the selection into files of at most this length or size,
+    /// numbered by `output_template`. Stream copies are cut on keyframes.
+    split: Option<SplitLimit>,
+    /// Mark where each range starts in the joined output with a chapter
+    #[serde(default)]
+    chapter_markers: bool,
+    /// How re-encodes seek to the selection. Both cut on the exact frame.
+    #[serde(default)]
+    trim_accuracy: TrimAccuracy,
+    /// Write each range to its own file instead of joining them. Placed next
+    /// to `output_path`, with the placeholders of `fill_placeholders` replaced
+    /// per range, e.g. `{stem}_{index}_{start}.{ext}`.
+    output_template: Option<String>,
+    #[serde(default)]
+    crop: CropArea,
+    /// Replaces `crop` with fractions of the frame, resolved against the
+    /// probed size
+    crop_fraction: Option<FractionalCrop>,
+    /// Pans the crop window over time when set; `crop` then only gives its size
+    #[serde(default)]
+    crop_keyframes: Vec<CropKeyframe>,
+    /// Size of the frame the crop was drawn on, the video size of the
+    /// preview. Previews showing the frame as stored, without its rotation
+    /// tag or pixel aspect ratio, get their crops turned and scaled to the
+    /// frame as it displays.
+    crop_frame: Option<FrameSize>,
+    /// The one of the settings, or the CPU, when left out
+    encoder: Option<Encoder>,
+    video_codec: Option<VideoCodec>,
+    audio_codec: Option<AudioCodec>,
+    container: Option<Container>,
+    #[serde(default)]
+    quality: Quality,
+    /// Audio bitrate in kbit/s, used whenever the audio is re-encoded
+    audio_bitrate: Option<u32>,
+    /// Audio streams to export, counted among the audio streams (see
+    /// `VideoMetadata::audio_streams`). Several are mixed down to one track,
+    /// none keeps FFmpeg's default pick.
+    #[serde(default)]
+    audio_tracks: Vec<usize>,
+    /// Raises or lowers the audio by this many dB, re-encoding it
+    audio_gain_db: Option<f64>,
+    /// Drop the audio altogether
+    #[serde(default)]
+    mute: bool,
+    /// Normalize the loudness of the audio
+    loudness: Option<Loudness>,
+    /// Seconds to fade the picture in from black and the audio from silence
+    fade_in: Option<f64>,
+    /// Seconds to fade out to black and silence at the end of the clip
+    fade_out: Option<f64>,
+    /// Audio file replacing or mixed into the audio of the clip
+    external_audio: Option<ExternalAudio>,
+    /// Playback speed of the clip, below 1 for slow motion
+    speed: Option<f64>,
+    /// Make up frames in between for smoother slow motion
+    interpolation: Option<Interpolation>,
+    /// Convert to this frame rate by dropping or repeating frames, e.g. 25
+    /// for PAL delivery
+    fps: Option<f64>,
+    /// Second crop of the source, stacked above the main one or laid over it
+    second_crop: Option<DualCrop>,
+    /// More crops of the source, each written to a file of its own by the
+    /// same FFmpeg run
+    #[serde(default)]
+    extra_crops: Vec<CropOutput>,
+    /// Smaller copies of the export, scaled from its picture in the same run
+    #[serde(default)]
+    ladder: Vec<LadderRung>,
+    /// Regions of the source to blur or pixelate, before the crop
+    #[serde(default)]
+    masks: Vec<MaskRegion>,
+    /// Remove the combing of interlaced sources before anything else
+    deinterlace: Option<Deinterlace>,
+    /// Remove noise right after the crop
+    denoise: Option<Denoise>,
+    /// Steady shaky footage, analysed in a pass of its own first
+    stabilize: Option<Stabilization>,
+    /// Key out a green screen and lay the clip over another background
+    chroma_key: Option<ChromaKey>,
+    /// Fill another aspect ratio, 9:16 by default, with a blurred copy of the
+    /// picture behind it
+    blur_fill: Option<BlurFill>,
+    /// Exact aspect ratio of the output, reached by narrowing the crop or by
+    /// padding the picture
+    aspect_ratio: Option<AspectRatio>,
+    /// Curve HDR sources are tonemapped to SDR with, for H.264 and animations
+    #[serde(default)]
+    tonemap: TonemapOperator,
+    /// Keep HDR sources 10-bit HDR, with their colors and mastering metadata,
+    /// in HEVC and AV1 re-encodes
+    #[serde(default)]
+    preserve_hdr: bool,
+    /// Limited or full range of the source and the output, for files whose
+    /// blacks come out crushed or washed out
+    #[serde(default)]
+    color_range: RangeOptions,
+    /// `.cube` LUT applied after the crop, e.g. to bring log footage to Rec.709
+    lut_path: Option<String>,
+    /// Brightness, contrast, saturation, gamma and white balance, after the LUT
+    color: Option<ColorAdjustment>,
+    /// Reverse the clip or bounce it back and forth. The reversed frames are
+    /// held in memory.
+    #[serde(default)]
+    playback: Playback,
+    /// Aim for an output of this many MiB using a bitrate-targeted encode
+    target_size_mb: Option<f64>,
+    /// Encode the video at this bitrate in kbit/s, in two passes on the CPU
+    video_bitrate: Option<u32>,
+    /// Cut with stream copy instead of re-encoding. Only possible without a
+    /// crop, and cuts land on the nearest keyframes (see `get_keyframes`).
+    #[serde(default)]
+    lossless: bool,
+    /// Like `lossless`, but cuts exactly at the selection by re-encoding only
+    /// the partial GOPs at both ends
+    #[serde(default)]
+    smart_cut: bool,
+    /// Export an animated image (GIF, WebP or APNG) instead of a video
+    animated: Option<AnimatedOptions>,
+    /// Export only the audio of the selection, ignoring the crop
+    audio_only: Option<AudioFormat>,
+    /// Clockwise rotation of the cropped picture: 90, 180 or 270
+    rotate: Option<u32>,
+    /// Mirroring applied after the rotation
+    flip: Option<Flip>,
+    /// Output size
+    scale: Option<ScaleOptions>,
+    /// Logo laid over the picture once it is cropped and scaled
+    watermark: Option<Watermark>,
+    /// Captions burned into the picture, drawn in this order below the watermark
+    #[serde(default)]
+    text_overlays: Vec<TextOverlay>,
+    /// SRT or ASS file burned into the picture, timed against the source
+    subtitle_path: Option<String>,
+    /// Raw FFmpeg flags added to every pass after the codec and quality flags,
+    /// right before the output format and path, e.g. `["-tune", "film"]`
+    #[serde(default)]
+    extra_args: Vec<String>,
+    /// Tags of the outputs and their modification time
+    #[serde(default)]
+    metadata: MetadataOptions,
+    /// Faststart MP4 that any browser plays, for uploads
+    web: Option<WebOptions>,
+    /// ProRes or DNxHR profile, which also picks the video codec
+    editing: Option<EditingPreset>,
+    /// Keep the alpha channel of the source, in ProRes 4444 or VP9
+    #[serde(default)]
+    keep_alpha: bool,
+    /// HLS or DASH package written into the folder `output_path` names
+    streaming: Option<StreamingOptions>,
+    /// Name of a saved preset whose settings replace the matching fields
+    preset: Option<String>,
+    #[serde(default)]
+    on_conflict: OnConflict,
+    /// Done with the outputs once the export finished
+    post_action: Option<PostAction>,
+    /// Decoder of the source, set by `resolve_args` for the VP8 and VP9
+    /// alpha that FFmpeg's own decoders leave behind
+    #[serde(skip)]
+    source_decoder: Option<String>,
+}
+
+impl ExportArgs {
+    /// Export of the whole frame and duration of `input_path` with the
+    /// settings of the preset `preset`, which may still narrow the crop.
+    pub fn whole_source(input_path: &str, output_path: &str, preset: &str) -> ExportArgs {
+        serde_json::from_value(serde_json::json!({
+            "input_path": input_path,
+            "output_path": output_path,
+            "selection": [{ "start": 0.0, "end": 1.0 }],
+            "relative_selection": true,
+            "crop_fraction": { "x": 0.0, "y": 0.0, "width": 1.0, "height": 1.0 },
+            "preset": preset,
+        }))
+        .expect("export args of a whole source")
+    }
+
+    pub fn input_path(&self) -> &str {
+        &self.input_path
+    }
+
+    /// The same export of another input.
+    pub fn for_input(&self, input_path: &str) -> ExportArgs {
+        ExportArgs {
+            input_path: input_path.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the output is named after the input, so that exports of
+    /// several inputs do not write to the same file.
+    pub fn names_output_after_input(&self) -> bool {
+        let template = self.output_template.as_ref().unwrap_or(&self.output_path);
+        ["{stem}", "{basename}"]
+            .iter()
+            .any(|placeholder| template.contains(placeholder))
+    }
+}
+
+/// Payload of `crop-adjusted`, emitted when the requested crop had to be
+/// changed to be encodable.
+#[derive(Clone, Serialize)]
+struct CropAdjusted {
+    job_id: JobId,
+    requested: CropArea,
+    adjusted: CropArea,
+    /// Human readable description of each change
+    reasons: Vec<String>,
+}
+
+// Share of the target size reserved for container overhead
+const MUX_OVERHEAD: f64 = 0.02;
+const DEFAULT_AUDIO_BITRATE: u32 = 128;
+// Below this the result is not worth watching
+const MIN_VIDEO_BITRATE: u32 = 100;
+
+// The first of two passes only analyses the video and takes about half as long
+const FIRST_PASS_COST: f64 = 0.5;
+// Playback speeds that keep a clip watchable
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 100.0;
+// Measuring the loudness only decodes the audio
+const LOUDNESS_MEASURE_COST: f64 = 0.1;
+// Motion detection decodes the picture but encodes nothing
+const STABILIZE_DETECT_COST: f64 = 0.4;
+// Re-encoded smart cut edges should be indistinguishable from the source
+const SMART_CUT_CRF: u8 = 18;
+// Relative cost of stream copying a second of video compared to encoding it
+const COPY_COST: f64 = 0.05;
+// Seconds automatic deinterlacing checks for combing
+const INTERLACE_SAMPLE_DURATION: f64 = 10.0;
+// Tags of tonemapped outputs, so players do not assume the colors of the source
+const SDR_COLOR_ARGS: [&str; 6] = [
+    "-color_primaries",
+    "bt709",
+    "-color_trc",
+    "bt709",
+    "-colorspace",
+    "bt709",
+];
+
+/// Everything about an export that has to be resolved before building the
+/// FFmpeg arguments.
+struct ExportPlan {
+    /// Seconds of output, used for percentages and bitrate targets
+    clip_duration: f64,
+    /// Output time at which each selection range ends
+    range_ends: Vec<f64>,
+    /// Scratch directory for pass logs, segments and palettes, removed afterwards
+    temp_dir: Option<PathBuf>,
+    subtitles: Option<SubtitlePlan>,
+    /// Set when an HDR source is converted to SDR
+    tonemap: Option<TonemapOperator>,
+    /// Deinterlacing filter, if the source needs one
+    deinterlace: Option<&'static str>,
+    /// Stretches non-square pixels square before anything else sees them
+    square_pixels: Option<String>,
+    /// Keying and background sub-graph of `ExportArgs::chroma_key`
+    chroma_key: Option<String>,
+    /// Range of the source and of the output, for re-encodes
+    color_range: Option<RangePlan>,
+    /// The source as probed once for the whole job
+    source: VideoMetadata,
+    output: PlannedOutput,
+}
+
+/// Subtitles burned into the picture.
+struct SubtitlePlan {
+    /// File the `subtitles` filter reads
+    path: PathBuf,
+    /// Contents to write to `path` first, when the cues had to be retimed
+    retimed: Option<String>,
+}
+
+enum PlannedOutput {
+    Video(VideoPlan),
+    Animated(AnimatedOptions),
+    Audio(AudioFormat),
+}
+
+struct VideoPlan {
+    format: OutputFormat,
+    encoder: Encoder,
+    /// Fixed video bitrate in kbit/s, replaces the CRF when set
+    video_bitrate: Option<u32>,
+    audio_bitrate: Option<u32>,
+    two_pass: bool,
+    /// Whether the source has audio and it is kept. Only probed when it
+    /// matters: to join the audio of several ranges, or to map or filter
+    /// picked `audio_tracks`.
+    has_audio: bool,
+    segmented: Option<SegmentedCut>,
+    /// FFMETADATA file marking each joined range as a chapter
+    chapters: Option<PathBuf>,
+    /// GoPro telemetry streams of the source copied along
+    telemetry_streams: Vec<usize>,
+    spherical: Option<SphericalPlan>,
+    streaming: Option<StreamingPlan>,
+    /// Profile of ProRes and DNxHR outputs
+    editing: Option<EditingPreset>,
+    /// Whether the alpha channel of the source is kept
+    alpha: bool,
+    /// Colors of an HDR source kept by `preserve_hdr`
+    hdr: Option<Box<HdrSignal>>,
+}
+
+/// Where a streaming package goes: the export is written to `mezzanine`
+/// first and then cut into segments in `dir`.
+struct StreamingPlan {
+    dir: PathBuf,
+    mezzanine: PathBuf,
+}
+
+// Encoding a rendition reads the export but skips its filters
+const RENDITION_COST: f64 = 0.5;
+
+/// What becomes of the projection of a 360° source.
+#[derive(Debug, Clone, PartialEq)]
+enum SphericalPlan {
+    /// Written to the output, which shows the whole frame
+    Keep,
+    /// Dropped, as the crop or transforms leave no valid projection
+    Lost { projection: String },
+}
+
+/// A cut made of pieces exported one by one and joined with the concat
+/// demuxer: smart cuts, and lossless cuts of several ranges.
+struct SegmentedCut {
+    /// Index of the selection range each piece belongs to, and the piece
+    pieces: Vec<(usize, Segment)>,
+    /// What re-encoded pieces have to match, only known for smart cuts
+    source: Option<SourceFormat>,
+}
+
+/// Source stream details the re-encoded smart cut edges have to match.
+struct SourceFormat {
+    codec: VideoCodec,
+    pix_fmt: Option<String>,
+}
+
+/// One FFmpeg invocation of an export.
+struct Pass {
+    args: Vec<String>,
+    /// Seconds of media this pass processes
+    duration: f64,
+    /// Share of the overall progress; the weights of all passes add up to 1
+    weight: f64,
+    /// Selection range this pass is limited to, `None` if it covers them all
+    range: Option<usize>,
+    /// Whether this pass measures the loudness for the passes after it
+    measures_loudness: bool,
+    /// Whether this pass runs filters much slower than encoding
+    slow: bool,
+    /// Frames its encoder reads ahead before writing one
+    lookahead_frames: u32,
+}
+
+/// Turns (args, relative cost) pairs into passes with normalized weights.
+fn weighted_passes(passes: Vec<(Vec<String>, f64, f64)>) -> Vec<Pass> {
+    let total_cost: f64 = passes.iter().map(|(_, _, cost)| cost).sum();
+
+    passes
+        .into_iter()
+        .map(|(args, duration, cost)| Pass {
+            args,
+            duration,
+            weight: if total_cost > 0.0 {
+                cost / total_cost
+            } else {
+                1.0
+            },
+            range: None,
+            measures_loudness: false,
+            slow: false,
+            lookahead_frames: 0,
+        })
+        .collect()
+}
+
+/// Computes the video bitrate needed to fit `duration` seconds into
+/// `target_mb` MiB next to an audio track of `audio_kbps`.
+fn bitrate_for_target_size(target_mb: f64, duration: f64, audio_kbps: u32) -> Result<u32, String> {
+    if target_mb <= 0.0 || duration <= 0.0 {
+        return Err("Target size and clip duration must be positive".to_string());
+    }
+
+    let total_kbps = target_mb * 1024.0 * 1024.0 * 8.0 * (1.0 - MUX_OVERHEAD) / duration / 1000.0;
+    let video_kbps = total_kbps - audio_kbps as f64;
+
+    if video_kbps < MIN_VIDEO_BITRATE as f64 {
+        return Err(format!(
+            "{} MB is too small for {:.1}s of video, the video bitrate would be {:.0} kbit/s",
+            target_mb, duration, video_kbps
+        ));
+    }
+
+    Ok(video_kbps as u32)
+}
+
+fn progress_args() -> Vec<String> {
+    vec![
+        "-progress".into(),
+        "pipe:1".into(), // Machine-readable progress on stdout
+        "-nostats".into(),
+    ]
+}
+
+/// Stream copy cut. Seeking on the input makes FFmpeg start at the keyframe
+/// before `start` instead of emitting undecodable frames.
+fn build_lossless_args(args: &ExportArgs, plan: &ExportPlan, video: &VideoPlan) -> Vec<String> {
+    let range = &args.selection[0];
+    let mut ffmpeg_args = progress_args();
+    ffmpeg_args.extend([
+        "-ss".into(),
+        range.start.to_string(),
+        "-to".into(),
+        range.end.to_string(),
+        "-i".into(),
+        args.input_path.clone(),
+    ]);
+    // Stream copies take at most one track, mixing needs a re-encode
+    if let Some(track) = args.audio_tracks.first() {
+        ffmpeg_args.extend([
+            "-map".into(),
+            "0:v:0".into(),
+            "-map".into(),
+            format!("0:a:{}", track),
+        ]);
+    }
+    ffmpeg_args.extend(source_extras_args(&ffmpeg_args, video));
+    ffmpeg_args.extend(["-c".into(), "copy".into()]);
+    ffmpeg_args.extend(stream_copy_audio_args(args, plan, video));
+    ffmpeg_args.extend([
+        "-avoid_negative_ts".into(),
+        "make_zero".into(),
+        "-f".into(),
+        video.format.container.format_name().into(),
+        "-y".into(),
+        args.output_path.clone(),
+    ]);
+
+    ffmpeg_args
+}
+
+/// Expression for the source timestamp of the frame at output time `t`.
+/// Over a single range the filters see source timestamps already, after
+/// joining several ranges the timestamps restart at 0.
+fn source_time_expr(ranges: &[ClipSelection]) -> String {
+    let mut range_starts = vec![];
+    let mut output_time = 0.0;
+    for range in ranges {
+        range_starts.push((output_time, range.start));
+        output_time += range.end - range.start;
+    }
+
+    match range_starts.as_slice() {
+        [] | [_] => "t".to_string(),
+        [.., (last_output, last_start)] => {
+            let mut expr = format!("{}+t-{}", last_start, last_output);
+            for pair in range_starts.windows(2).rev() {
+                let ((output, start), (next_output, _)) = (pair[0], pair[1]);
+                expr = format!("if(lt(t,{}),{}+t-{},{})", next_output, start, output, expr);
+            }
+            expr
+        }
+    }
+}
+
+fn crop_filter(args: &ExportArgs) -> String {
+    let crop = &args.crop;
+    if args.crop_keyframes.is_empty() {
+        return format!("crop={}:{}:{}:{}", crop.width, crop.height, crop.x, crop.y);
+    }
+
+    // x and y are evaluated per frame, FFmpeg keeps them inside the frame
+    let mut keyframes = args.crop_keyframes.clone();
+    keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let time = format!("({})", source_time_expr(&args.selection));
+    let position = |value: fn(&CropKeyframe) -> u32| {
+        let points: Vec<(f64, f64)> = keyframes
+            .iter()
+            .map(|keyframe| (keyframe.time, value(keyframe) as f64))
+            .collect();
+        interpolation_expr(&points, &time)
+    };
+
+    format!(
+        "crop={}:{}:x='{}':y='{}'",
+        crop.width,
+        crop.height,
+        position(|keyframe| keyframe.x),
+        position(|keyframe| keyframe.y)
+    )
+}
+
+/// Deinterlacing, masks and the crop, which stabilization analyses the output
+/// of.
+fn source_filters(args: &ExportArgs, plan: &ExportPlan) -> Vec<String> {
+    // Corrected before any filter converts the pixels
+    let mut filters: Vec<String> = plan
+        .color_range
+        .iter()
+        .flat_map(RangePlan::input_filter)
+        .collect();
+    // Fields have to be rebuilt before the crop splits them unevenly
+    filters.extend(plan.deinterlace.map(String::from));
+    // Crop coordinates and every size after them are in square pixels
+    filters.extend(plan.square_pixels.clone());
+    let time = format!("({})", source_time_expr(&args.selection));
+    filters.extend(
+        args.masks
+            .iter()
+            .enumerate()
+            .map(|(index, mask)| mask.filter(index, &time)),
+    );
+    filters.push(match &args.second_crop {
+        Some(second) => second.filter(&crop_filter(args), args.crop.width),
+        None => crop_filter(args),
+    });
+    filters
+}
+
+/// Where the detection pass of stabilization writes the camera motion.
+fn transforms_path(plan: &ExportPlan) -> Option<String> {
+    let temp_dir = plan.temp_dir.as_ref()?;
+    Some(
+        temp_dir
+            .join("transforms.trf")
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// Crop, picture transforms, scaling, subtitles and captions, shared by every
+/// re-encoding mode.
+fn picture_filters(args: &ExportArgs, plan: &ExportPlan) -> Vec<String> {
+    let mut filters = source_filters(args, plan);
+    if let (Some(stabilize), Some(transforms)) = (args.stabilize, transforms_path(plan)) {
+        filters.extend(stabilize.transform_filters(&transforms));
+    }
+    filters.extend(args.denoise.map(Denoise::filter));
+    filters.extend(plan.chroma_key.clone());
+    filters.extend(plan.tonemap.iter().flat_map(|operator| operator.filters()));
+    filters.extend(args.lut_path.as_deref().map(lut_filter));
+    filters.extend(args.color.iter().flat_map(ColorAdjustment::filters));
+    filters.extend(transform_filters(args.rotate, args.flip));
+    if let Some(fill) = &args.blur_fill {
+        let (width, height) = rotated_size(args);
+        filters.push(fill.filter(width, height));
+    }
+    if let Some(aspect) = &args.aspect_ratio {
+        let (width, height) = rotated_size(args);
+        filters.extend(aspect.filter(width, height));
+    }
+    filters.extend(args.scale.map(|scale| scale.filter()));
+    // Interpolated before anything is drawn on the picture
+    filters.extend(args.interpolation.as_ref().map(Interpolation::filter));
+    filters.extend(args.fps.map(|fps| format!("fps={}", frame_rate_value(fps))));
+    if let Some(subtitles) = &plan.subtitles {
+        filters.push(format!(
+            "subtitles=filename={}",
+            escape_value(&subtitles.path.to_string_lossy())
+        ));
+    }
+    let time_offset = filter_time_offset(args);
+    filters.extend(
+        args.text_overlays
+            .iter()
+            .map(|overlay| overlay.filter(time_offset)),
+    );
+    if let Some(range) = &plan.color_range {
+        filters.extend(range.output_filters(plan.tonemap.is_some()));
+    }
+    filters
+}
+
+/// Fades of the picture from and to black.
+fn picture_fades(args: &ExportArgs, plan: &ExportPlan) -> Vec<String> {
+    fade_filters(
+        "fade",
+        args.fade_in,
+        args.fade_out,
+        filter_time_offset(args),
+        plan.clip_duration,
+    )
+}
+
+/// Timestamp the clip starts at in the filters. A single range is cut on the
+/// output side, so the filters see source time, joined ranges start at 0.
+fn filter_time_offset(args: &ExportArgs) -> f64 {
+    single_cut(args).map_or(0.0, |range| range.start)
+}
+
+fn playback_speed(args: &ExportArgs) -> f64 {
+    args.speed.unwrap_or(1.0)
+}
+
+/// The range cut with output-side seeking, if the selection is a single range
+/// played forward at normal speed. Anything else is trimmed and joined in the filter
+/// graph, see `concat_graph`.
+fn single_cut(args: &ExportArgs) -> Option<&ClipSelection> {
+    match args.selection.as_slice() {
+        [range] if playback_speed(args) == 1.0 && args.playback == Playback::Forward => Some(range),
+        _ => None,
+    }
+}
+
+/// Audio output options of a re-encode: the codec and its bitrate, or `-an`
+/// when muted.
+fn audio_codec_args(args: &ExportArgs, video: &VideoPlan) -> Vec<String> {
+    if args.mute {
+        return vec!["-an".into()];
+    }
+    let mut audio_args = vec!["-c:a".into(), video.format.audio_codec.encoder().into()];
+    if let (Some(kbps), true) = (
+        video.audio_bitrate,
+        !matches!(video.format.audio_codec, AudioCodec::Copy | AudioCodec::Pcm),
+    ) {
+        audio_args.extend(["-b:a".into(), format!("{}k", kbps)]);
+    }
+    audio_args
+}
+
+/// Audio options following `-c copy`: nothing when the audio is copied too,
+/// otherwise the gain filter and the codec re-encoding it.
+fn stream_copy_audio_args(args: &ExportArgs, plan: &ExportPlan, video: &VideoPlan) -> Vec<String> {
+    if args.mute {
+        return vec!["-an".into()];
+    }
+    let filters = AudioChain::new(args, plan.clip_duration).filters;
+    if !video.has_audio || filters.is_empty() {
+        return vec![];
+    }
+    let mut audio_args = vec!["-filter:a".into(), filters.join(",")];
+    audio_args.extend(audio_codec_args(args, video));
+    audio_args
+}
+
+fn gain_filter(args: &ExportArgs) -> Option<String> {
+    args.audio_gain_db
+        .filter(|gain| *gain != 0.0)
+        .map(|gain| format!("volume={}dB", gain))
+}
+
+/// Input the external audio is read from. It follows the source, the
+/// watermark image comes after it.
+const EXTERNAL_AUDIO_INPUT: usize = 1;
+
+/// Opens the external audio as input `EXTERNAL_AUDIO_INPUT`, if there is any.
+fn external_audio_input_args(args: &ExportArgs) -> Vec<String> {
+    args.external_audio
+        .iter()
+        .flat_map(ExternalAudio::input_args)
+        .collect()
+}
+
+/// Input the watermark image is read from.
+fn watermark_input(args: &ExportArgs) -> usize {
+    EXTERNAL_AUDIO_INPUT + args.external_audio.is_some() as usize
+}
+
+/// The audio an export keeps: the picked tracks of input 0, mixed down when
+/// there are several, or the external audio replacing them, and the filters
+/// run on the result.
+struct AudioChain<'a> {
+    tracks: &'a [usize],
+    /// Graph producing the audio in place of the source tracks
+    replacement: Option<String>,
+    filters: Vec<String>,
+}
+
+impl<'a> AudioChain<'a> {
+    /// The audio of the export, for a clip of `duration` seconds.
+    fn new(args: &'a ExportArgs, duration: f64) -> Self {
+        Self::with_tracks(args, &args.audio_tracks, filter_time_offset(args), duration)
+    }
+
+    /// The audio of `tracks` for a clip starting at `time_offset` in the
+    /// filtered stream and lasting `duration` seconds.
+    fn with_tracks(
+        args: &ExportArgs,
+        tracks: &'a [usize],
+        time_offset: f64,
+        duration: f64,
+    ) -> Self {
+        let mut chain = Self::mixing_external(args, tracks, time_offset, duration);
+        if let Some(loudness) = &args.loudness {
+            chain.filters.extend(loudness.filters());
+        }
+        chain.filters.extend(fade_filters(
+            "afade",
+            args.fade_in,
+            args.fade_out,
+            time_offset,
+            duration,
+        ));
+        chain
+    }
+
+    /// The chain of a loudness measurement: everything up to `loudnorm`,
+    /// which only measures what it would have to do. Measured clips start at
+    /// 0 in the filtered stream.
+    fn measuring(
+        args: &ExportArgs,
+        tracks: &'a [usize],
+        loudness: &Loudness,
+        duration: f64,
+    ) -> Self {
+        let mut chain = Self::mixing_external(args, tracks, 0.0, duration);
+        chain.filters.push(loudness.measure_filter());
+        chain
+    }
+
+    /// The gain and the external audio of `args`.
+    fn mixing_external(
+        args: &ExportArgs,
+        tracks: &'a [usize],
+        time_offset: f64,
+        duration: f64,
+    ) -> Self {
+        let mut chain = Self {
+            tracks,
+            replacement: None,
+            filters: gain_filter(args).into_iter().collect(),
+        };
+        match &args.external_audio {
+            Some(external) if external.mode == ExternalAudioMode::Replace => {
+                chain.replacement =
+                    Some(external.source_filter(EXTERNAL_AUDIO_INPUT, time_offset, duration));
+            }
+            Some(external) => {
+                chain
+                    .filters
+                    .push(external.mix_filter(EXTERNAL_AUDIO_INPUT, time_offset, duration))
+            }
+            None => {}
+        }
+        chain
+    }
+
+    /// Whether FFmpeg's default audio stream passes through as is.
+    fn is_default(&self) -> bool {
+        self.tracks.is_empty() && self.replacement.is_none() && self.filters.is_empty()
+    }
+
+    /// The stream to `-map` when no filter is needed.
+    fn stream(&self) -> String {
+        match self.tracks {
+            [] => "0:a?".to_string(),
+            [track, ..] => format!("0:a:{}", track),
+        }
+    }
+
+    /// Filter graph label of the single track taken as is.
+    fn input_label(&self) -> String {
+        match self.tracks {
+            [] => "[0:a]".to_string(),
+            [track, ..] => format!("[0:a:{}]", track),
+        }
+    }
+
+    /// Graph mixing the picked tracks down to one, without an output label,
+    /// or `None` for a single track.
+    fn mix(&self) -> Option<String> {
+        let [_, _, ..] = self.tracks else {
+            return None;
+        };
+        let inputs: String = self
+            .tracks
+            .iter()
+            .map(|track| format!("[0:a:{}]", track))
+            .collect();
+        // Keep the levels of each track instead of dividing by the track count
+        Some(format!(
+            "{}amix=inputs={}:duration=longest:normalize=0",
+            inputs,
+            self.tracks.len()
+        ))
+    }
+
+    /// Graph producing the audio, without an output label, or `None` when
+    /// `stream` is used as is.
+    fn graph(&self) -> Option<String> {
+        let source = match (self.replacement.clone().or_else(|| self.mix()), self.tracks) {
+            (Some(source), _) if self.filters.is_empty() => return Some(source),
+            (Some(source), _) => format!("{},", source),
+            (None, _) if self.filters.is_empty() => return None,
+            (None, _) => self.input_label(),
+        };
+        Some(format!("{}{}", source, self.filters.join(",")))
+    }
+
+    /// What to `-map` as the audio of a single range, and the graph it comes
+    /// from when it is filtered.
+    fn single_range(&self) -> (String, Option<String>) {
+        match self.graph() {
+            Some(graph) => ("[audio]".to_string(), Some(format!("{}[audio]", graph))),
+            None => (self.stream(), None),
+        }
+    }
+}
+
+/// Filter graph that trims every range out of input `input` and joins them,
+/// leaving the result in `[joined_v]` and/or `[joined_a]`.
+fn concat_graph(
+    args: &ExportArgs,
+    input: usize,
+    video: bool,
+    audio: Option<&AudioChain>,
+) -> String {
+    let ranges = &args.selection;
+    let speed = playback_speed(args);
+    let (pts, tempo) = if speed == 1.0 {
+        ("PTS-STARTPTS".to_string(), String::new())
+    } else {
+        let tempo: String = atempo_filters(speed)
+            .iter()
+            .map(|filter| format!(",{}", filter))
+            .collect();
+        (format!("(PTS-STARTPTS)/{}", speed), tempo)
+    };
+    let mut graph = vec![];
+    let mut joined_inputs = String::new();
+    // Audio replacing the source is added after the concat, already in one piece
+    let joined_audio = audio.filter(|audio| audio.replacement.is_none());
+
+    // A mixdown is split into one copy per range
+    let audio_inputs: Vec<String> = match joined_audio
+        .map(|audio| (audio.input_label(), audio.mix()))
+    {
+        None => vec![],
+        Some((label, None)) => vec![label; ranges.len()],
+        Some((_, Some(mix))) => {
+            let labels: Vec<String> = (0..ranges.len()).map(|i| format!("[mix{}]", i)).collect();
+            graph.push(format!(
+                "{},asplit={}{}",
+                mix,
+                ranges.len(),
+                labels.concat()
+            ));
+            labels
+        }
+    };
+
+    for (index, range) in ranges.iter().enumerate() {
+        if video {
+            graph.push(format!(
+                "[{}:v]trim=start={}:end={},setpts={}[v{}]",
+                input, range.start, range.end, pts, index
+            ));
+            joined_inputs.push_str(&format!("[v{}]", index));
+        }
+        if let Some(audio_input) = audio_inputs.get(index) {
+            graph.push(format!(
+                "{}atrim=start={}:end={},asetpts=PTS-STARTPTS{}[a{}]",
+                audio_input, range.start, range.end, tempo, index
+            ));
+            joined_inputs.push_str(&format!("[a{}]", index));
+        }
+    }
+
+    // Reversing and the audio filters run on the joined clip, the concat
+    // writes straight to the final labels when neither does
+    let audio_filters = joined_audio
+        .map(|audio| audio.filters.join(","))
+        .filter(|filters| !filters.is_empty());
+    let reversing = args.playback != Playback::Forward;
+    // Where the audio is once joined and reversed
+    let played_a = match audio_filters {
+        Some(_) if reversing => "[played_a]",
+        Some(_) => "[concat_a]",
+        None => "[joined_a]",
+    };
+    let (concat_v, concat_a) = if reversing {
+        ("[concat_v]", "[concat_a]")
+    } else {
+        ("[joined_v]", played_a)
+    };
+
+    let mut outputs = String::new();
+    if video {
+        outputs.push_str(concat_v);
+    }
+    if joined_audio.is_some() {
+        outputs.push_str(concat_a);
+    }
+    if !joined_inputs.is_empty() {
+        graph.push(format!(
+            "{}concat=n={}:v={}:a={}{}",
+            joined_inputs,
+            ranges.len(),
+            video as u8,
+            joined_audio.is_some() as u8,
+            outputs
+        ));
+    }
+
+    match args.playback {
+        Playback::Forward => {}
+        Playback::Reverse => {
+            if video {
+                graph.push("[concat_v]reverse[joined_v]".to_string());
+            }
+            if joined_audio.is_some() {
+                graph.push(format!("[concat_a]areverse{}", played_a));
+            }
+        }
+        // The clip followed by its reversed copy
+        Playback::Boomerang if !outputs.is_empty() => {
+            let mut halves = [String::new(), String::new()];
+            if video {
+                graph.push("[concat_v]split[forward_v][backward_v]".to_string());
+                graph.push("[backward_v]reverse[reversed_v]".to_string());
+                halves[0].push_str("[forward_v]");
+                halves[1].push_str("[reversed_v]");
+            }
+            if joined_audio.is_some() {
+                graph.push("[concat_a]asplit[forward_a][backward_a]".to_string());
+                graph.push("[backward_a]areverse[reversed_a]".to_string());
+                halves[0].push_str("[forward_a]");
+                halves[1].push_str("[reversed_a]");
+            }
+            graph.push(format!(
+                "{}{}concat=n=2:v={}:a={}{}{}",
+                halves[0],
+                halves[1],
+                video as u8,
+                joined_audio.is_some() as u8,
+                if video { "[joined_v]" } else { "" },
+                if joined_audio.is_some() { played_a } else { "" },
+            ));
+        }
+        Playback::Boomerang => {}
+    }
+    if let Some(filters) = audio_filters {
+        graph.push(format!("{}{}[joined_a]", played_a, filters));
+    }
+    if let Some(replacement) = audio.and_then(|audio| audio.replacement.as_ref().and(audio.graph()))
+    {
+        graph.push(format!("{}[joined_a]", replacement));
+    }
+
+    graph.join(";")
+}
+
+// Seconds decoded ahead of the cut after seeking on the input
+const SEEK_PRE_ROLL: f64 = 5.0;
+
+/// `-i` of the source, seeking close to the first range on the input so
+/// only the last seconds before it are decoded. The cuts themselves stay on
+/// the output side or in `trim`, exact to the frame. `-copyts -start_at_zero`
+/// keep the timestamps they and the filters work with as without the seek.
+fn source_input_args(args: &ExportArgs) -> Vec<String> {
+    let first_start = args
+        .selection
+        .iter()
+        .map(|range| range.start)
+        .fold(f64::INFINITY, f64::min);
+    let seek = first_start - SEEK_PRE_ROLL;
+
+    let mut ffmpeg_args = vec![];
+    if args.trim_accuracy == TrimAccuracy::Seek && seek.is_finite() && seek > 0.0 {
+        ffmpeg_args.extend([
+            "-ss".into(),
+            seek.to_string(),
+            "-copyts".into(),
+            "-start_at_zero".into(),
+        ]);
+    }
+    if let Some(decoder) = &args.source_decoder {
+        ffmpeg_args.extend(["-c:v".into(), decoder.clone()]);
+    }
+    ffmpeg_args.extend(["-i".into(), args.input_path.clone()]);
+    ffmpeg_args
+}
+
+/// Cuts the selection out of input 0 and runs `filters` on the picture. A
+/// single range is cut with output-side `-ss`/`-to`, several are trimmed and
+/// joined with the concat filter, which also joins the audio if `audio` is set.
+fn trim_and_filter_args(
+    args: &ExportArgs,
+    filters: &[String],
+    audio: Option<&AudioChain>,
+) -> Vec<String> {
+    match single_cut(args) {
+        Some(range) => {
+            let mut ffmpeg_args = vec![
+                "-ss".into(),
+                range.start.to_string(),
+                "-to".into(),
+                range.end.to_string(),
+                "-filter:v".into(),
+                filters.join(","),
+            ];
+            // Picking or filtering tracks replaces FFmpeg's default stream selection
+            if let Some(audio) = audio.filter(|audio| !audio.is_default()) {
+                let (audio_map, graph) = audio.single_range();
+                if let Some(graph) = graph {
+                    ffmpeg_args.extend(["-filter_complex".into(), graph]);
+                }
+                ffmpeg_args.extend(["-map".into(), "0:v:0".into(), "-map".into(), audio_map]);
+            }
+            ffmpeg_args
+        }
+        None => {
+            let graph = format!(
+                "{};[joined_v]{}[picture]",
+                concat_graph(args, 0, true, audio),
+                filters.join(",")
+            );
+            let mut ffmpeg_args = vec![
+                "-filter_complex".into(),
+                graph,
+                "-map".into(),
+                "[picture]".into(),
+            ];
+            if audio.is_some() {
+                ffmpeg_args.extend(["-map".into(), "[joined_a]".into()]);
+            }
+            ffmpeg_args
+        }
+    }
+}
+
+/// Like `trim_and_filter_args`, with the watermark of `args`, if any, laid
+/// over the picture between `filters` and `suffix`. The watermark image has
+/// to be input `watermark_input`.
+fn trim_and_overlay_args(
+    args: &ExportArgs,
+    filters: &[String],
+    suffix: &[String],
+    audio: Option<&AudioChain>,
+) -> Vec<String> {
+    let Some(watermark) = &args.watermark else {
+        let filters = [filters, suffix].concat();
+        return trim_and_filter_args(args, &filters, audio);
+    };
+
+    // `0:a?` is mapped even when the audio is dropped
+    let (single_audio, mix) = audio.map_or(("0:a?".to_string(), None), AudioChain::single_range);
+    let (input, mut ffmpeg_args) = match single_cut(args) {
+        Some(range) => (
+            match mix {
+                Some(mix) => format!("{};[0:v]", mix),
+                None => "[0:v]".to_string(),
+            },
+            vec![
+                "-ss".into(),
+                range.start.to_string(),
+                "-to".into(),
+                range.end.to_string(),
+            ],
+        ),
+        None => (
+            format!("{};[joined_v]", concat_graph(args, 0, true, audio)),
+            vec![],
+        ),
+    };
+    let graph = format!(
+        "{}{}[main];[{}:v]{}[logo];[main][logo]{}{}[picture]",
+        input,
+        filters.join(","),
+        watermark_input(args),
+        watermark.image_filters(output_picture_size(args).0),
+        watermark.overlay_filter(filter_time_offset(args)),
+        suffix
+            .iter()
+            .map(|filter| format!(",{}", filter))
+            .collect::<String>(),
+    );
+
+    ffmpeg_args.extend([
+        "-filter_complex".into(),
+        graph,
+        "-map".into(),
+        "[picture]".into(),
+    ]);
+    let audio_map = match single_cut(args) {
+        Some(_) => Some(single_audio),
+        _ => audio.map(|_| "[joined_a]".to_string()),
+    };
+    if let Some(map) = audio_map {
+        ffmpeg_args.extend(["-map".into(), map]);
+    }
+    ffmpeg_args
+}
+
+/// Builds the re-encoding FFmpeg argument list. `pass` is 1 or 2 for
+/// two-pass encodes and `None` otherwise.
+fn build_encode_args(
+    args: &ExportArgs,
+    plan: &ExportPlan,
+    video: &VideoPlan,
+    pass: Option<u8>,
+) -> Vec<String> {
+    let encoder = video.encoder;
+
+    let filters = picture_filters(args, plan);
+    // Fades run after the watermark so it fades along with the picture
+    let mut suffix = picture_fades(args, plan);
+    suffix.extend(encoder.filter_suffix().map(String::from));
+    let audio = AudioChain::new(args, plan.clip_duration);
+
+    let mut ffmpeg_args = progress_args();
+    ffmpeg_args.extend(encoder.input_args().into_iter().map(String::from));
+    ffmpeg_args.extend(source_input_args(args));
+    ffmpeg_args.extend(external_audio_input_args(args));
+    if let Some(watermark) = &args.watermark {
+        ffmpeg_args.extend(["-i".into(), watermark.image_path.clone()]);
+    }
+    // Read last, after the watermark
+    let chapters = video.chapters.as_ref().filter(|_| pass != Some(1));
+    if let Some(path) = chapters {
+        ffmpeg_args.extend([
+            "-f".into(),
+            "ffmetadata".into(),
+            "-i".into(),
+            path.to_string_lossy().into_owned(),
+        ]);
+    }
+    // The first pass drops the audio, so there is none to join
+    ffmpeg_args.extend(trim_and_overlay_args(
+        args,
+        &filters,
+        &suffix,
+        (video.has_audio && pass != Some(1)).then_some(&audio),
+    ));
+    if let Some(name) = encoder.encoder_name(video.format.video_codec) {
+        ffmpeg_args.extend(["-c:v".into(), name.into()]);
+    }
+
+    match video.video_bitrate {
+        Some(kbps) => {
+            // A bitrate target overrides the CRF but keeps the speed preset
+            let quality = Quality {
+                crf: None,
+                ..args.quality.clone()
+            };
+            ffmpeg_args.extend(quality.encoder_args(encoder, video.format.video_codec));
+            ffmpeg_args.extend(["-b:v".into(), format!("{}k", kbps)]);
+            if pass.is_none() {
+                // Single pass (hardware encoders): cap the rate instead
+                ffmpeg_args.extend([
+                    "-maxrate".into(),
+                    format!("{}k", kbps),
+                    "-bufsize".into(),
+                    format!("{}k", kbps * 2),
+                ]);
+            }
+        }
+        None => ffmpeg_args.extend(args.quality.encoder_args(encoder, video.format.video_codec)),
+    }
+    if plan.tonemap.is_some() {
+        ffmpeg_args.extend(SDR_COLOR_ARGS.map(String::from));
+    }
+    if let Some(range) = &plan.color_range {
+        ffmpeg_args.extend(range.output_args());
+    }
+    if let Some(hdr) = &video.hdr {
+        ffmpeg_args.extend(hdr.encoder_args(encoder, video.format.video_codec));
+    }
+    if let Some(web) = &args.web {
+        ffmpeg_args.extend(web.encoder_args(encoder, video.format.video_codec));
+    }
+    if let Some(preset) = video.editing {
+        ffmpeg_args.extend(preset.encoder_args(video.alpha));
+    }
+    if video.alpha && video.format.video_codec == VideoCodec::Vp9 {
+        ffmpeg_args.extend(["-pix_fmt", "yuva420p"].map(String::from));
+    }
+
+    if let (Some(pass), Some(temp_dir)) = (pass, &plan.temp_dir) {
+        let passlog = temp_dir.join("passlog").to_string_lossy().into_owned();
+        if video.format.video_codec == VideoCodec::Hevc {
+            // libx265 ignores -pass, its stats file is set through its own params
+            ffmpeg_args.extend([
+                "-x265-params".into(),
+                format!("pass={}:stats='{}'", pass, passlog),
+            ]);
+        } else {
+            ffmpeg_args.extend([
+                "-pass".into(),
+                pass.to_string(),
+                "-passlogfile".into(),
+                passlog,
+            ]);
+        }
+    }
+
+    if pass == Some(1) {
+        // The first pass only gathers statistics, nothing is written
+        ffmpeg_args.extend([
+            "-an".into(),
+            "-f".into(),
+            "null".into(),
+            "-y".into(),
+            "-".into(),
+        ]);
+        return ffmpeg_args;
+    }
+
+    if chapters.is_some() {
+        let input = watermark_input(args) + args.watermark.is_some() as usize;
+        ffmpeg_args.extend(["-map_chapters".into(), input.to_string()]);
+    }
+    ffmpeg_args.extend(source_extras_args(&ffmpeg_args, video));
+    ffmpeg_args.extend(audio_codec_args(args, video));
+    ffmpeg_args.extend([
+        "-f".into(),
+        video.format.container.format_name().into(),
+        "-y".into(), // Overwrite output
+        args.output_path.clone(),
+    ]);
+
+    ffmpeg_args
+}
+
+fn segment_path(temp_dir: &std::path::Path, index: usize, format: &OutputFormat) -> PathBuf {
+    temp_dir.join(format!(
+        "segment-{}.{}",
+        index,
+        format.container.format_name()
+    ))
+}
+
+/// One pass per piece, each written to the temp dir, plus the final pass
+/// that joins them with the concat demuxer.
+fn build_segment_passes(
+    args: &ExportArgs,
+    plan: &ExportPlan,
+    video: &VideoPlan,
+    cut: &SegmentedCut,
+) -> Vec<Pass> {
+    let Some(temp_dir) = &plan.temp_dir else {
+        return vec![];
+    };
+    let container = video.format.container.format_name();
+    let mut passes = vec![];
+
+    for (index, (_, segment)) in cut.pieces.iter().enumerate() {
+        let mut ffmpeg_args = progress_args();
+        ffmpeg_args.extend([
+            "-ss".into(),
+            segment.start.to_string(),
+            "-i".into(),
+            args.input_path.clone(),
+            "-t".into(),
+            segment.duration().to_string(),
+            "-map".into(),
+            "0:v:0".into(),
+            "-map".into(),
+            AudioChain::new(args, plan.clip_duration).stream(),
+        ]);
+
+        let cost = match (segment.copy, &cut.source) {
+            (false, Some(source)) => {
+                let quality = Quality {
+                    crf: args.quality.crf.or(Some(SMART_CUT_CRF)),
+                    ..args.quality.clone()
+                };
+                ffmpeg_args.extend(["-c:v".into(), source.codec.cpu_encoder().into()]);
+                ffmpeg_args.extend(quality.encoder_args(Encoder::Cpu, source.codec));
+                if let Some(pix_fmt) = &source.pix_fmt {
+                    ffmpeg_args.extend(["-pix_fmt".into(), pix_fmt.clone()]);
+                }
+                ffmpeg_args.extend(["-c:a".into(), "copy".into()]);
+                segment.duration()
+            }
+            _ => {
+                ffmpeg_args.extend(["-c".into(), "copy".into()]);
+                segment.duration() * COPY_COST
+            }
+        };
+
+        ffmpeg_args.extend([
+            "-avoid_negative_ts".into(),
+            "make_zero".into(),
+            "-f".into(),
+            container.into(),
+            "-y".into(),
+            segment_path(temp_dir, index, &video.format)
+                .to_string_lossy()
+                .into_owned(),
+        ]);
+
+        passes.push((ffmpeg_args, segment.duration(), cost));
+    }
+
+    let mut concat_args = progress_args();
+    concat_args.extend([
+        "-f".into(),
+        "concat".into(),
+        "-safe".into(),
+        "0".into(),
+        "-i".into(),
+        temp_dir.join("segments.txt").to_string_lossy().into_owned(),
+        "-c".into(),
+        "copy".into(),
+    ]);
+    // The pieces keep the audio as is, the gain is applied once they are joined
+    concat_args.extend(stream_copy_audio_args(args, plan, video));
+    concat_args.extend([
+        "-f".into(),
+        container.into(),
+        "-y".into(),
+        args.output_path.clone(),
+    ]);
+    passes.push((
+        concat_args,
+        plan.clip_duration,
+        plan.clip_duration * COPY_COST,
+    ));
+
+    let mut passes = weighted_passes(passes);
+    for (pass, (range, _)) in passes.iter_mut().zip(&cut.pieces) {
+        pass.range = Some(*range);
+    }
+    passes
+}
+
+/// Animated image passes. GIF needs a palette generated from the same
+/// cropped and scaled frames first, WebP and APNG are a single pass.
+fn build_animated_passes(
+    args: &ExportArgs,
+    plan: &ExportPlan,
+    animated: &AnimatedOptions,
+) -> Vec<Pass> {
+    let mut frame_filters = picture_filters(args, plan);
+    frame_filters.extend(picture_fades(args, plan));
+    frame_filters.extend(animated.frame_filters());
+    let duration = plan.clip_duration;
+
+    if animated.format != AnimatedFormat::Gif {
+        let mut ffmpeg_args = progress_args();
+        ffmpeg_args.extend(source_input_args(args));
+        ffmpeg_args.extend(trim_and_filter_args(args, &frame_filters, None));
+        ffmpeg_args.push("-an".into());
+        ffmpeg_args.extend(animated.output_args());
+        ffmpeg_args.extend(["-y".into(), args.output_path.clone()]);
+        return weighted_passes(vec![(ffmpeg_args, duration, 1.0)]);
+    }
+
+    let Some(temp_dir) = &plan.temp_dir else {
+        return vec![];
+    };
+    let palette = temp_dir.join("palette.png").to_string_lossy().into_owned();
+
+    let mut palette_filters = frame_filters.clone();
+    palette_filters.push(animated.palettegen_filter().into());
+
+    let mut palette_args = progress_args();
+    palette_args.extend(source_input_args(args));
+    palette_args.extend(trim_and_filter_args(args, &palette_filters, None));
+    palette_args.extend(["-y".into(), palette.clone()]);
+
+    // The palette is input 0 so that output-side trimming follows the source
+    let mut encode_args = progress_args();
+    encode_args.extend(["-i".into(), palette]);
+    encode_args.extend(source_input_args(args));
+    let frames = match single_cut(args) {
+        Some(range) => {
+            encode_args.extend([
+                "-ss".into(),
+                range.start.to_string(),
+                "-to".into(),
+                range.end.to_string(),
+            ]);
+            "[1:v]".to_string()
+        }
+        None => format!("{};[joined_v]", concat_graph(args, 1, true, None)),
+    };
+    encode_args.extend([
+        "-lavfi".into(),
+        format!(
+            "{}{}[frames];[frames][0:v]{}",
+            frames,
+            frame_filters.join(","),
+            animated.paletteuse_filter()
+        ),
+    ]);
+    encode_args.extend(animated.output_args());
+    encode_args.extend(["-y".into(), args.output_path.clone()]);
+
+    weighted_passes(vec![
+        (palette_args, duration, 1.0),
+        (encode_args, duration, 2.0),
+    ])
+}
+
+/// Trims the selection out of the audio of `chain` and maps only that.
+fn trim_audio_args(args: &ExportArgs, chain: &AudioChain) -> Vec<String> {
+    match single_cut(args) {
+        // Audio replacing the source has nothing to join
+        None if chain.replacement.is_some() => vec![
+            "-filter_complex".into(),
+            chain.single_range().1.unwrap_or_default(),
+            "-map".into(),
+            "[audio]".into(),
+        ],
+        Some(range) => {
+            let (audio_map, graph) = chain.single_range();
+            let mut ffmpeg_args = vec![
+                "-ss".into(),
+                range.start.to_string(),
+                "-to".into(),
+                range.end.to_string(),
+            ];
+            if let Some(graph) = graph {
+                ffmpeg_args.extend(["-filter_complex".into(), graph]);
+            }
+            ffmpeg_args.extend(["-map".into(), audio_map]);
+            ffmpeg_args
+        }
+        None => vec![
+            "-filter_complex".into(),
+            concat_graph(args, 0, false, Some(chain)),
+            "-map".into(),
+            "[joined_a]".into(),
+        ],
+    }
+}
+
+/// Runs the audio of the export through `loudnorm` without writing anything,
+/// for the stats it prints at the end.
+fn build_loudness_measure_args(
+    args: &ExportArgs,
+    plan: &ExportPlan,
+    tracks: &[usize],
+    loudness: &Loudness,
+) -> Vec<String> {
+    let chain = AudioChain::measuring(args, tracks, loudness, plan.clip_duration);
+    let mut ffmpeg_args = progress_args();
+    match single_cut(args) {
+        // Seeking on the input keeps the audio before the range out of the
+        // measurement
+        Some(range) => {
+            let (audio_map, graph) = chain.single_range();
+            ffmpeg_args.extend([
+                "-ss".into(),
+                range.start.to_string(),
+                "-to".into(),
+                range.end.to_string(),
+                "-i".into(),
+                args.input_path.clone(),
+            ]);
+            ffmpeg_args.extend(external_audio_input_args(args));
+            if let Some(graph) = graph {
+                ffmpeg_args.extend(["-filter_complex".into(), graph]);
+            }
+            ffmpeg_args.extend(["-map".into(), audio_map]);
+        }
+        _ => {
+            ffmpeg_args.extend(source_input_args(args));
+            ffmpeg_args.extend(external_audio_input_args(args));
+            ffmpeg_args.extend(trim_audio_args(args, &chain));
+        }
+    }
+    ffmpeg_args.extend([
+        "-vn".into(),
+        "-f".into(),
+        "null".into(),
+        "-y".into(),
+        "-".into(),
+    ]);
+    ffmpeg_args
+}
+
+/// Trims the selection out of the picked audio tracks, the first one by
+/// default, and drops everything else.
+fn build_audio_args(args: &ExportArgs, plan: &ExportPlan, format: AudioFormat) -> Vec<String> {
+    let tracks = match args.audio_tracks.as_slice() {
+        [] => &[0][..],
+        tracks => tracks,
+    };
+    let mut ffmpeg_args = progress_args();
+    ffmpeg_args.extend(source_input_args(args));
+    ffmpeg_args.extend(external_audio_input_args(args));
+    ffmpeg_args.extend(trim_audio_args(
+        args,
+        &AudioChain::with_tracks(args, tracks, filter_time_offset(args), plan.clip_duration),
+    ));
+    ffmpeg_args.extend(["-vn".into(), "-c:a".into(), format.encoder().into()]);
+    if let (Some(kbps), true) = (args.audio_bitrate, format.is_lossy()) {
+        ffmpeg_args.extend(["-b:a".into(), format!("{}k", kbps)]);
+    }
+    ffmpeg_args.extend([
+        "-f".into(),
+        format.format_name().into(),
+        "-y".into(),
+        args.output_path.clone(),
+    ]);
+
+    ffmpeg_args
+}
+
+/// The argument vector of every FFmpeg run of the export, in order. Pure, so
+/// the exact command lines can be previewed and tested.
+fn build_ffmpeg_args(args: &ExportArgs, plan: &ExportPlan) -> Vec<Vec<String>> {
+    build_passes(args, plan)
+        .into_iter()
+        .map(|pass| pass.args)
+        .collect()
+}
+
+/// Builds every FFmpeg invocation the export needs, in order.
+fn build_passes(args: &ExportArgs, plan: &ExportPlan) -> Vec<Pass> {
+    let mut passes = build_output_passes(args, plan);
+    // Interpolation and nlmeans run in every pass that touches the picture
+    if args.interpolation.is_some() || args.denoise.is_some_and(Denoise::is_slow) {
+        for pass in &mut passes {
+            pass.slow = true;
+        }
+    }
+
+    // Two-pass normalization measures the audio before anything is encoded
+    let measured_tracks = match &plan.output {
+        PlannedOutput::Audio(_) if args.audio_tracks.is_empty() => Some(&[0][..]),
+        PlannedOutput::Audio(_) => Some(args.audio_tracks.as_slice()),
+        PlannedOutput::Video(video) if video.has_audio => Some(args.audio_tracks.as_slice()),
+        _ => None,
+    };
+    if let (Some(stabilize), Some(transforms)) = (args.stabilize, transforms_path(plan)) {
+        let share = 1.0 + STABILIZE_DETECT_COST;
+        for pass in &mut passes {
+            pass.weight /= share;
+        }
+        let mut filters = source_filters(args, plan);
+        filters.push(stabilize.detect_filter(&transforms));
+        let mut detect_args = progress_args();
+        detect_args.extend(source_input_args(args));
+        detect_args.extend(trim_and_filter_args(args, &filters, None));
+        detect_args.extend(["-an", "-f", "null", "-y", "-"].map(String::from));
+        passes.insert(
+            0,
+            Pass {
+                args: detect_args,
+                duration: plan.clip_duration,
+                weight: STABILIZE_DETECT_COST / share,
+                range: None,
+                measures_loudness: false,
+                slow: false,
+                lookahead_frames: 0,
+            },
+        );
+    }
+    if let (Some(loudness), Some(tracks)) = (&args.loudness, measured_tracks) {
+        if loudness.two_pass {
+            let share = 1.0 + LOUDNESS_MEASURE_COST;
+            for pass in &mut passes {
+                pass.weight /= share;
+            }
+            passes.insert(
+                0,
+                Pass {
+                    args: build_loudness_measure_args(args, plan, tracks, loudness),
+                    duration: plan.clip_duration,
+                    weight: LOUDNESS_MEASURE_COST / share,
+                    range: None,
+                    measures_loudness: true,
+                    slow: false,
+                    lookahead_frames: 0,
+                },
+            );
+        }
+    }
+
+    // Packages are written by the HLS and DASH muxers
+    let container = match &plan.output {
+        PlannedOutput::Video(video) if video.streaming.is_none() => Some(video.format.container),
+        _ => None,
+    };
+    let mut output_args = args.metadata.output_args(container, args.chapter_markers);
+    if let (Some(web), Some(Container::Mp4 | Container::Mov)) = (&args.web, container) {
+        output_args.extend(["-movflags".to_string(), web.movflags().to_string()]);
+    }
+    for pass in &mut passes {
+        // Only the runs writing the output, not scratch files or measurements
+        if pass.args.last() == Some(&args.output_path) {
+            insert_extra_args(&mut pass.args, &output_args);
+        }
+        insert_extra_args(&mut pass.args, &args.extra_args);
+        merge_movflags(&mut pass.args);
+        merge_x265_params(&mut pass.args);
+        // Encoders only, a `-c:v` before the input picks the decoder
+        let inputs_end = pass.args.iter().rposition(|arg| arg == "-i").unwrap_or(0);
+        pass.lookahead_frames = pass.args[inputs_end..]
+            .windows(2)
+            .filter(|pair| pair[0].starts_with("-c:v"))
+            .map(|pair| lookahead_frames(&pair[1]))
+            .max()
+            .unwrap_or(0);
+    }
+    passes
+}
+
+fn build_output_passes(args: &ExportArgs, plan: &ExportPlan) -> Vec<Pass> {
+    match &plan.output {
+        PlannedOutput::Animated(animated) => build_animated_passes(args, plan, animated),
+        PlannedOutput::Audio(format) => weighted_passes(vec![(
+            build_audio_args(args, plan, *format),
+            plan.clip_duration,
+            1.0,
+        )]),
+        PlannedOutput::Video(video) => build_video_passes(args, plan, video),
+    }
+}
+
+/// Puts `extra` before the trailing `-f <format> -y <output>` of a pass, or
+/// before `-y <output>` for passes without an explicit format.
+fn insert_extra_args(ffmpeg_args: &mut Vec<String>, extra: &[String]) {
+    let Some(mut position) = ffmpeg_args.iter().rposition(|arg| arg == "-y") else {
+        return;
+    };
+    if position >= 2 && ffmpeg_args[position - 2] == "-f" {
+        position -= 2;
+    }
+    ffmpeg_args.splice(position..position, extra.iter().cloned());
+}
+
+fn build_video_passes(args: &ExportArgs, plan: &ExportPlan, video: &VideoPlan) -> Vec<Pass> {
+    let duration = plan.clip_duration;
+
+    if let (Some(options), Some(streaming)) = (&args.streaming, &video.streaming) {
+        build_streaming_passes(args, plan, video, options, streaming)
+    } else if let Some(cut) = &video.segmented {
+        build_segment_passes(args, plan, video, cut)
+    } else if args.lossless {
+        weighted_passes(vec![(
+            build_lossless_args(args, plan, video),
+            duration,
+            1.0,
+        )])
+    } else if video.two_pass {
+        weighted_passes(vec![
+            (
+                build_encode_args(args, plan, video, Some(1)),
+                duration,
+                FIRST_PASS_COST,
+            ),
+            (build_encode_args(args, plan, video, Some(2)), duration, 1.0),
+        ])
+    } else {
+        weighted_passes(vec![(
+            build_encode_args(args, plan, video, None),
+            duration,
+            1.0,
+        )])
+    }
+}
+
+/// The passes of the export written to the mezzanine, with keyframes on the
+/// segment boundaries, and the pass packaging it.
+fn build_streaming_passes(
+    args: &ExportArgs,
+    plan: &ExportPlan,
+    video: &VideoPlan,
+    options: &StreamingOptions,
+    streaming: &StreamingPlan,
+) -> Vec<Pass> {
+    let mezzanine = streaming.mezzanine.to_string_lossy().into_owned();
+    let mezzanine_args = ExportArgs {
+        output_path: mezzanine.clone(),
+        streaming: None,
+        ..args.clone()
+    };
+    let mut passes = build_video_passes(&mezzanine_args, plan, video);
+    // Stream copies keep the keyframes of the source
+    if !(args.lossless || args.smart_cut) {
+        for pass in &mut passes {
+            insert_extra_args(&mut pass.args, &options.keyframe_args());
+        }
+    }
+
+    // VAAPI would need the frames uploaded first
+    let encoder = match video.encoder {
+        Encoder::Vaapi => Encoder::Cpu,
+        encoder => encoder,
+    };
+    let codec = video.format.video_codec;
+    let mut package_args = progress_args();
+    package_args.extend(["-i".into(), mezzanine]);
+    package_args.extend(options.package_args(
+        &streaming.dir,
+        encoder.encoder_name(codec).unwrap_or(codec.cpu_encoder()),
+        codec,
+        video.has_audio && !args.mute,
+    ));
+
+    let cost = match options.renditions.len() {
+        0 => COPY_COST,
+        count => count as f64 * RENDITION_COST,
+    };
+    for pass in &mut passes {
+        pass.weight /= 1.0 + cost;
+    }
+    passes.push(Pass {
+        args: package_args,
+        duration: plan.clip_duration,
+        weight: cost / (1.0 + cost),
+        range: None,
+        measures_loudness: false,
+        slow: false,
+        lookahead_frames: 0,
+    });
+    passes
+}
+
+/// Everything `spawn_export` runs for a job.
+struct ExportRun {
+    /// Passes in order, each with the files it writes to
+    passes: Vec<(Pass, Vec<String>)>,
+    /// Output time at which each selection range ends, over all outputs
+    range_ends: Vec<f64>,
+    temp_dir: PathBuf,
+    disk_space_low: Option<DiskSpaceLow>,
+    reverse_memory_high: Option<ReverseMemoryHigh>,
+    spherical_lost: Option<SphericalLost>,
+}
+
+/// Payload of `disk-space-low`, emitted when the output might not fit. Only
+/// rough estimates warn, exports that certainly do not fit fail instead.
+#[derive(Clone, Serialize)]
+struct DiskSpaceLow {
+    job_id: JobId,
+    path: String,
+    estimated_bytes: u64,
+    available_bytes: u64,
+}
+
+/// Payload of `reverse-memory-high`, emitted when reversing the clip holds
+/// more decoded frames in memory than most machines have to spare.
+#[derive(Clone, Serialize)]
+struct ReverseMemoryHigh {
+    job_id: JobId,
+    /// Length of the reversed selection in the source, in seconds
+    duration: f64,
+    estimated_bytes: u64,
+}
+
+/// Payload of `spherical-lost`, emitted when a 360° source is cropped or
+/// reshaped, so players show the output as a flat video.
+#[derive(Clone, Serialize)]
+struct SphericalLost {
+    job_id: JobId,
+    projection: String,
+}
+
+/// Expected size of the output, returned by `estimate_output_size`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeEstimate {
+    pub bytes: u64,
+    /// Set when the size follows from a fixed bitrate or a stream copy, rather
+    /// than from a guess at what the encoder will make of the source
+    pub exact: bool,
+}
+
+// Uncompressed 16-bit stereo at 48 kHz
+const PCM_KBPS: u32 = 1536;
+// FLAC typically halves PCM, a bit less for noisy sources
+const FLAC_RATIO: f64 = 0.6;
+// Headroom on top of the estimate for container overhead and misjudgement
+const DISK_SPACE_MARGIN: f64 = 1.05;
+// Decoded 8-bit 4:2:0 frames, as `reverse` buffers them
+const FRAME_BYTES_PER_PIXEL: f64 = 1.5;
+const REVERSE_MEMORY_WARNING: u64 = 2 << 30;
+
+/// Bytes per second of the source file as a whole.
+async fn source_byte_rate(input_path: &str, source: &VideoMetadata) -> Option<f64> {
+    let bytes = fs::metadata(input_path).await.ok()?.len() as f64;
+    (source.duration > 0.0).then(|| bytes / source.duration)
+}
+
+/// Size of the cropped picture, with the second crop if there is one.
+fn composed_size(args: &ExportArgs) -> (u32, u32) {
+    let (width, height) = (args.crop.width, args.crop.height);
+    match &args.second_crop {
+        Some(second) => second.size(width, height),
+        None => (width, height),
+    }
+}
+
+/// Size of the cropped picture after rotation.
+fn rotated_size(args: &ExportArgs) -> (u32, u32) {
+    let (width, height) = composed_size(args);
+    match args.rotate {
+        Some(90 | 270) => (height, width),
+        _ => (width, height),
+    }
+}
+
+fn output_picture_size(args: &ExportArgs) -> (u32, u32) {
+    let (width, height) = rotated_size(args);
+    let (width, height) = match &args.blur_fill {
+        Some(fill) => fill.canvas_size(width, height),
+        None => (width, height),
+    };
+    let (width, height) = args
+        .aspect_ratio
+        .as_ref()
+        .and_then(|aspect| aspect.canvas_size(width, height))
+        .unwrap_or((width, height));
+    match &args.scale {
+        Some(scale) => scale.output_size(width, height),
+        None => (width, height),
+    }
+}
+
+/// Frame rate of a re-encoded output from a source at `source_rate`.
+fn output_frame_rate(args: &ExportArgs, source_rate: f64) -> f64 {
+    match &args.interpolation {
+        Some(interpolation) => interpolation.fps(),
+        None => args.fps.unwrap_or(source_rate),
+    }
+}
+
+async fn estimate_plan_size(args: &ExportArgs, plan: &ExportPlan) -> Option<SizeEstimate> {
+    let duration = plan.clip_duration;
+    let at_kbps = |kbps: f64| (kbps * 1000.0 / 8.0 * duration) as u64;
+
+    match &plan.output {
+        PlannedOutput::Audio(format) => Some(match format {
+            AudioFormat::Wav => SizeEstimate {
+                bytes: at_kbps(PCM_KBPS as f64),
+                exact: true,
+            },
+            AudioFormat::Flac => SizeEstimate {
+                bytes: at_kbps(PCM_KBPS as f64 * FLAC_RATIO),
+                exact: false,
+            },
+            AudioFormat::Mp3 | AudioFormat::Aac => SizeEstimate {
+                bytes: at_kbps(args.audio_bitrate.unwrap_or(DEFAULT_AUDIO_BITRATE) as f64),
+                exact: true,
+            },
+        }),
+        PlannedOutput::Video(VideoPlan {
+            video_bitrate: Some(video_kbps),
+            audio_bitrate,
+            ..
+        }) => Some(SizeEstimate {
+            bytes: at_kbps((video_kbps + audio_bitrate.unwrap_or(0)) as f64),
+            exact: true,
+        }),
+        PlannedOutput::Video(video) if !args.lossless && !args.smart_cut => {
+            let metadata = &plan.source;
+            let (width, height) = output_picture_size(args);
+            let video_bits = args.quality.bits_per_pixel(video.format.video_codec)
+                * width as f64
+                * height as f64
+                * output_frame_rate(args, metadata.frame_rate);
+            // Copied audio is counted as if it was re-encoded, its rate is unknown
+            let audio_kbps = match &metadata.audio_codec {
+                Some(_) if !args.mute => video.audio_bitrate.unwrap_or(DEFAULT_AUDIO_BITRATE),
+                _ => 0,
+            };
+
+            Some(SizeEstimate {
+                bytes: ((video_bits / 8.0 + audio_kbps as f64 * 1000.0 / 8.0) * duration) as u64,
+                exact: false,
+            })
+        }
+        // Stream copies keep the rate of the source, which is also the best
+        // guess for animations
+        _ => Some(SizeEstimate {
+            bytes: (source_byte_rate(&args.input_path, &plan.source).await? * duration) as u64,
+            exact: args.lossless || args.smart_cut,
+        }),
+    }
+}
+
+/// Compares the estimated size of all outputs with the free space of the
+/// volume they are written to.
+async fn check_disk_space(
+    outputs: &[(ExportArgs, ExportPlan)],
+    job_id: JobId,
+) -> Result<Option<DiskSpaceLow>, ExportError> {
+    let Some((first, _)) = outputs.first() else {
+        return Ok(None);
+    };
+    let dir = Path::new(&first.output_path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let available_bytes = match fs2::available_space(dir) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            // FFmpeg reports a missing directory better than we can
+            println!("Could not check free space in {:?}: {}", dir, e);
+            return Ok(None);
+        }
+    };
+
+    let mut estimated_bytes = 0;
+    let mut exact = true;
+    for (args, plan) in outputs {
+        let Some(estimate) = estimate_plan_size(args, plan).await else {
+            return Ok(None);
+        };
+        estimated_bytes += estimate.bytes;
+        exact &= estimate.exact;
+    }
+
+    let required_bytes = (estimated_bytes as f64 * DISK_SPACE_MARGIN) as u64;
+    if required_bytes <= available_bytes {
+        return Ok(None);
+    }
+    let path = dir.to_string_lossy().into_owned();
+    if exact {
+        return Err(ExportError::InsufficientDiskSpace {
+            path,
+            required_bytes,
+            available_bytes,
+        });
+    }
+    Ok(Some(DiskSpaceLow {
+        job_id,
+        path,
+        estimated_bytes,
+        available_bytes,
+    }))
+}
+
+/// Estimates the memory `reverse` takes to buffer the selection of the most
+/// demanding output, and warns if it is above `REVERSE_MEMORY_WARNING`.
+async fn check_reverse_memory(
+    outputs: &[(ExportArgs, ExportPlan)],
+    job_id: JobId,
+) -> Option<ReverseMemoryHigh> {
+    let mut highest: Option<ReverseMemoryHigh> = None;
+    for (args, plan) in outputs {
+        if args.playback == Playback::Forward || !matches!(plan.output, PlannedOutput::Video(_)) {
+            continue;
+        }
+        let metadata = &plan.source;
+        let (width, height) = metadata.display_size();
+        // Every frame of the source is held, whatever the speed
+        let duration = plan.range_ends.last().copied().unwrap_or(0.0) * playback_speed(args);
+        let estimated_bytes =
+            (width as f64 * height as f64 * FRAME_BYTES_PER_PIXEL * metadata.frame_rate * duration)
+                as u64;
+        if highest
+            .as_ref()
+            .is_none_or(|high| estimated_bytes > high.estimated_bytes)
+        {
+            highest = Some(ReverseMemoryHigh {
+                job_id,
+                duration,
+                estimated_bytes,
+            });
+        }
+    }
+    highest.filter(|high| high.estimated_bytes > REVERSE_MEMORY_WARNING)
+}
+
+/// Extension `{ext}` stands for: that of the requested output type, falling
+/// back to the container of `output_path` and then to MP4.
+fn output_extension(args: &ExportArgs) -> &'static str {
+    if let Some(format) = args.audio_only {
+        return format.extension();
+    }
+    if let Some(animated) = &args.animated {
+        return animated.format.extension();
+    }
+    args.container
+        .or_else(|| Container::from_path(&args.output_path))
+        .unwrap_or(Container::Mp4)
+        .extension()
+}
+
+/// Replaces the placeholders of an output name:
+///
+/// - `{stem}` (or `{basename}`): input file name without its extension
+/// - `{index}`: 1-based number of the output
+/// - `{start}`, `{end}`: source seconds the output covers
+/// - `{width}`, `{height}`: size of the crop
+/// - `{ext}`: extension of the output type, see `output_extension`
+fn fill_placeholders(
+    template: &str,
+    args: &ExportArgs,
+    index: usize,
+    start: f64,
+    end: f64,
+) -> String {
+    let stem = Path::new(&args.input_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // Whole milliseconds at most, `12.5` rather than `12.500000001`
+    let seconds = |time: f64| ((time * 1000.0).round() / 1000.0).to_string();
+
+    template
+        .replace("{stem}", &stem)
+        .replace("{basename}", &stem)
+        .replace("{index}", &(index + 1).to_string())
+        .replace("{start}", &seconds(start))
+        .replace("{end}", &seconds(end))
+        .replace("{width}", &args.crop.width.to_string())
+        .replace("{height}", &args.crop.height.to_string())
+        .replace("{ext}", output_extension(args))
+}
+
+/// Resolves placeholders in `output_path` itself, over the whole selection.
+fn render_output_path(args: &ExportArgs) -> String {
+    match (args.selection.first(), args.selection.last()) {
+        (Some(first), Some(last)) => {
+            fill_placeholders(&args.output_path, args, 0, first.start, last.end)
+        }
+        _ => args.output_path.clone(),
+    }
+}
+
+/// Fills in the placeholders of `ExportArgs::output_template` for one range.
+/// The file goes next to `output_path`.
+fn render_output_template(
+    template: &str,
+    args: &ExportArgs,
+    index: usize,
+    range: &ClipSelection,
+) -> String {
+    let file_name = fill_placeholders(template, args, index, range.start, range.end);
+
+    Path::new(&args.output_path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// First of `name_1.ext`, `name_2.ext`, ... that is neither on disk nor in
+/// `taken`.
+fn unique_path(path: &str, taken: &[String]) -> String {
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| {
+            path.with_file_name(format!("{}_{}{}", stem, n, extension))
+                .to_string_lossy()
+                .into_owned()
+        })
+        .find(|candidate| !taken.contains(candidate) && !Path::new(candidate).exists())
+        .unwrap_or_default()
+}
+
+/// Paths of every file the export writes, in order, after applying
+/// `on_conflict` to those that already exist.
+fn output_paths(args: &ExportArgs) -> Result<Vec<String>, ExportError> {
+    let rendered: Vec<String> = match &args.output_template {
+        Some(template) => args
+            .selection
+            .iter()
+            .enumerate()
+            .map(|(index, range)| render_output_template(template, args, index, range))
+            .collect(),
+        None => std::iter::once(args.output_path.clone())
+            .chain(
+                args.extra_crops
+                    .iter()
+                    .map(|extra| extra.output_path.clone()),
+            )
+            .chain(args.ladder.iter().map(|rung| rung.path(&args.output_path)))
+            .collect(),
+    };
+
+    let mut paths: Vec<String> = vec![];
+    for path in rendered {
+        // Two ranges rendering the same name would overwrite each other too
+        let exists = paths.contains(&path) || Path::new(&path).exists();
+        let path = match args.on_conflict {
+            _ if !exists => path,
+            OnConflict::Overwrite => path,
+            OnConflict::Fail => return Err(format!("{} already exists", path).into()),
+            OnConflict::Rename => unique_path(&path, &paths),
+        };
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// `args` exporting its extra crop `index` instead of the main one. The
+/// crop stands still and has no second crop composed with it.
+fn extra_crop_args(args: &ExportArgs, index: usize) -> ExportArgs {
+    let extra = &args.extra_crops[index];
+    ExportArgs {
+        output_path: extra.output_path.clone(),
+        crop: extra.crop.clone(),
+        crop_keyframes: vec![],
+        second_crop: None,
+        extra_crops: vec![],
+        ..args.clone()
+    }
+}
+
+/// Plans every output of the job: a single export followed by its extra
+/// crops, or one per range when writing separate files.
+async fn plan_outputs(
+    args: &ExportArgs,
+    job_id: JobId,
+) -> Result<Vec<(ExportArgs, ExportPlan)>, ExportError> {
+    let temp_dir = job_temp_dir(job_id);
+
+    validate_selection(&args.selection)?;
+    if args.output_template.is_some() && !args.extra_crops.is_empty() {
+        return Err("Extra crops cannot be written to separate files per range".into());
+    }
+    if !args.ladder.is_empty() && (args.output_template.is_some() || !args.extra_crops.is_empty()) {
+        return Err("A ladder is made from a single output".into());
+    }
+    let paths = output_paths(args)?;
+    let source = probe(&args.input_path).await.map_err(ExportError::probe)?;
+
+    if args.output_template.is_none() {
+        let mut args = args.clone();
+        args.output_path = paths[0].clone();
+        for (extra, path) in args.extra_crops.iter_mut().zip(&paths[1..]) {
+            extra.output_path = path.clone();
+        }
+        for (rung, path) in args.ladder.iter_mut().zip(&paths[1..]) {
+            rung.output_path = Some(path.clone());
+        }
+        let plan = plan_export(&args, &source, &temp_dir).await?;
+        let mut outputs = vec![];
+        for index in 0..args.extra_crops.len() {
+            let crop_args = extra_crop_args(&args, index);
+            let crop_dir = temp_dir.join(format!("crop-{}", index));
+            let plan = plan_export(&crop_args, &source, &crop_dir).await?;
+            outputs.push((crop_args, plan));
+        }
+        outputs.insert(0, (args, plan));
+        return Ok(outputs);
+    }
+
+    let mut outputs = vec![];
+    for (index, (range, output_path)) in args.selection.iter().zip(paths).enumerate() {
+        let clip_args = ExportArgs {
+            output_path,
+            selection: vec![range.clone()],
+            output_template: None,
+            ..args.clone()
+        };
+        // Each plan writes its scratch files into a directory of its own
+        let clip_dir = temp_dir.join(format!("clip-{}", index));
+        let plan = plan_export(&clip_args, &source, &clip_dir).await?;
+        outputs.push((clip_args, plan));
+    }
+    Ok(outputs)
+}
+
+/// Plans the whole job and prepares its scratch files. Separate exports run
+/// back to back and share the progress in proportion to their duration.
+async fn plan_run(args: &ExportArgs, job_id: JobId) -> Result<ExportRun, ExportError> {
+    let outputs = plan_outputs(args, job_id).await?;
+    let disk_space_low = check_disk_space(&outputs, job_id).await?;
+    let reverse_memory_high = check_reverse_memory(&outputs, job_id).await;
+    let spherical_lost = outputs.iter().find_map(|(_, plan)| match &plan.output {
+        PlannedOutput::Video(VideoPlan {
+            spherical: Some(SphericalPlan::Lost { projection }),
+            ..
+        }) => Some(SphericalLost {
+            job_id,
+            projection: projection.clone(),
+        }),
+        _ => None,
+    });
+    for (_, plan) in &outputs {
+        create_scratch(plan).await?;
+    }
+
+    // Extra crops and rungs are written by the run writing the main output
+    if !args.extra_crops.is_empty() || !args.ladder.is_empty() {
+        let pass = match args.ladder.is_empty() {
+            true => merge_crop_passes(&outputs)?,
+            false => ladder_pass(&outputs[0].0, &outputs[0].1)?,
+        };
+        return Ok(ExportRun {
+            passes: vec![pass],
+            range_ends: outputs[0].1.range_ends.clone(),
+            temp_dir: job_temp_dir(job_id),
+            disk_space_low,
+            reverse_memory_high,
+            spherical_lost,
+        });
+    }
+
+    // A single output keeps the ranges of its own plan
+    if let [(args, plan)] = outputs.as_slice() {
+        return Ok(ExportRun {
+            passes: build_passes(args, plan)
+                .into_iter()
+                .map(|pass| (pass, vec![args.output_path.clone()]))
+                .collect(),
+            range_ends: plan.range_ends.clone(),
+            temp_dir: job_temp_dir(job_id),
+            disk_space_low,
+            reverse_memory_high,
+            spherical_lost,
+        });
+    }
+
+    let total_duration: f64 = outputs.iter().map(|(_, plan)| plan.clip_duration).sum();
+    let mut range_ends = vec![];
+    let mut passes = vec![];
+    for (index, (clip_args, plan)) in outputs.iter().enumerate() {
+        let share = if total_duration > 0.0 {
+            plan.clip_duration / total_duration
+        } else {
+            1.0 / outputs.len() as f64
+        };
+        range_ends.push(range_ends.last().copied().unwrap_or(0.0) + plan.clip_duration);
+        passes.extend(build_passes(clip_args, plan).into_iter().map(|mut pass| {
+            pass.weight *= share;
+            pass.range = Some(index);
+            (pass, vec![clip_args.output_path.clone()])
+        }));
+    }
+
+    Ok(ExportRun {
+        passes,
+        range_ends,
+        temp_dir: job_temp_dir(job_id),
+        disk_space_low,
+        reverse_memory_high,
+        spherical_lost,
+    })
+}
+
+/// Prefixes the link labels of `graph`, so that the graphs of several outputs
+/// can share an FFmpeg run. Stream specifiers such as `[0:v]` and escaped
+/// brackets are left alone.
+fn prefix_labels(graph: &str, prefix: &str) -> String {
+    let mut prefixed = String::with_capacity(graph.len());
+    let mut chars = graph.chars();
+    while let Some(c) = chars.next() {
+        prefixed.push(c);
+        match c {
+            '\\' => prefixed.extend(chars.next()),
+            '[' => {
+                let label: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if !label.contains(':') && label.parse::<u32>().is_err() {
+                    prefixed.push_str(prefix);
+                }
+                prefixed.push_str(&label);
+                prefixed.push(']');
+            }
+            _ => {}
+        }
+    }
+    prefixed
+}
+
+/// Writes the main output and its extra crops, `outputs` in that order, in a
+/// single FFmpeg run. The inputs are opened and decoded once and feed the
+/// filters and encoders of every output.
+fn merge_crop_passes(outputs: &[(ExportArgs, ExportPlan)]) -> Result<(Pass, Vec<String>), String> {
+    let mut passes = vec![];
+    for (args, plan) in outputs {
+        let [pass] = <[Pass; 1]>::try_from(build_passes(args, plan))
+            .map_err(|_| "Extra crops only work with exports FFmpeg runs in one pass")?;
+        passes.push(pass);
+    }
+
+    let mut merged = passes.remove(0);
+    for (index, pass) in passes.into_iter().enumerate() {
+        // Everything after the inputs belongs to the output of the pass
+        let inputs_end = pass
+            .args
+            .iter()
+            .rposition(|arg| arg == "-i")
+            .map_or(0, |i| i + 2);
+        let prefix = format!("crop{}_", index + 1);
+        for (position, arg) in pass.args.iter().enumerate().skip(inputs_end) {
+            let names_labels = matches!(
+                pass.args[position - 1].as_str(),
+                "-filter_complex" | "-lavfi" | "-map"
+            );
+            merged.args.push(match names_labels {
+                true => prefix_labels(arg, &prefix),
+                false => arg.clone(),
+            });
+        }
+        merged.slow |= pass.slow;
+        merged.lookahead_frames = merged.lookahead_frames.max(pass.lookahead_frames);
+    }
+    let output_paths = outputs
+        .iter()
+        .map(|(args, _)| args.output_path.clone())
+        .collect();
+    Ok((merged, output_paths))
+}
+
+/// The run writing the main output of `args` along with its ladder.
+fn ladder_pass(args: &ExportArgs, plan: &ExportPlan) -> Result<(Pass, Vec<String>), String> {
+    let [mut pass] = <[Pass; 1]>::try_from(build_passes(args, plan))
+        .map_err(|_| "A ladder only works with exports FFmpeg runs in one pass")?;
+    let paths: Vec<String> = args
+        .ladder
+        .iter()
+        .map(|rung| rung.path(&args.output_path))
+        .collect();
+    pass.args = add_rungs(&pass.args, &args.ladder, &paths)?;
+    let output_paths = std::iter::once(args.output_path.clone())
+        .chain(paths)
+        .collect();
+    Ok((pass, output_paths))
+}
+
+// Flags the export sets itself, overriding them would break the job
+const RESERVED_FLAGS: [&str; 8] = [
+    "-i",
+    "-y",
+    "-n",
+    "-f",
+    "-progress",
+    "-nostats",
+    "-pass",
+    "-passlogfile",
+];
+
+/// Keeps `ExportArgs::extra_args` from changing the inputs, outputs or
+/// progress reporting of the job.
+fn validate_extra_args(args: &ExportArgs) -> Result<(), String> {
+    for arg in &args.extra_args {
+        if arg.is_empty() {
+            return Err("Extra FFmpeg arguments cannot be empty".to_string());
+        }
+        if RESERVED_FLAGS.contains(&arg.as_str()) {
+            return Err(format!("{} cannot be passed as an extra argument", arg));
+        }
+        if *arg == args.input_path || *arg == args.output_path {
+            return Err("Extra FFmpeg arguments cannot name the input or output".to_string());
+        }
+    }
+
+    // FFmpeg takes a value that follows no flag as another output file
+    let is_value = |arg: &str| !arg.starts_with('-');
+    let mut previous: Option<&str> = None;
+    for arg in &args.extra_args {
+        if is_value(arg) && previous.is_none_or(is_value) {
+            return Err(format!("Extra FFmpeg argument {} is missing its flag", arg));
+        }
+        previous = Some(arg);
+    }
+
+    Ok(())
+}
+
+fn validate_selection(selection: &[ClipSelection]) -> Result<(), String> {
+    if selection.is_empty() {
+        return Err("Select at least one range to export".to_string());
+    }
+    match selection
+        .iter()
+        .find(|range| range.start < 0.0 || range.end <= range.start)
+    {
+        Some(range) => Err(format!(
+            "Invalid range {:.3}s - {:.3}s",
+            range.start, range.end
+        )),
+        None => Ok(()),
+    }
+}
+
+// Leftovers shorter than this between removed ranges are dropped
+const MIN_KEPT_RANGE: f64 = 0.001;
+
+/// Everything of a `duration` long source that is not in `removed`.
+fn complement_ranges(removed: &[ClipSelection], duration: f64) -> Vec<ClipSelection> {
+    let mut removed = removed.to_vec();
+    removed.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut kept = vec![];
+    let mut position = 0.0;
+    for range in &removed {
+        if range.start - position > MIN_KEPT_RANGE {
+            kept.push(ClipSelection {
+                start: position,
+                end: range.start,
+            });
+        }
+        position = range.end.max(position);
+    }
+    if duration - position > MIN_KEPT_RANGE {
+        kept.push(ClipSelection {
+            start: position,
+            end: duration,
+        });
+    }
+
+    kept
+}
+
+/// Output time at which each range ends once they are joined. Percentages
+/// are relative to the part of each range that actually exists.
+fn range_ends(args: &ExportArgs, source: &VideoMetadata) -> Vec<f64> {
+    let source_duration = (source.duration > 0.0).then_some(source.duration);
+    if source_duration.is_none() {
+        println!("Could not probe duration, using selection length");
+    }
+
+    args.selection
+        .iter()
+        .scan(0.0, |output_time, range| {
+            let end = source_duration.map_or(range.end, |duration| range.end.min(duration));
+            *output_time += (end - range.start).max(0.0) / playback_speed(args);
+            Some(*output_time)
+        })
+        .collect()
+}
+
+/// Copied audio cannot go through filters or have its size predicted.
+fn reencode_copied_audio(format: &mut OutputFormat) {
+    if format.audio_codec == AudioCodec::Copy {
+        format.audio_codec = match format.container {
+            Container::Webm => AudioCodec::Opus,
+            _ => AudioCodec::Aac,
+        };
+    }
+}
+
+/// Creates the scratch directory of `plan`, if it needs one, and the files
+/// FFmpeg reads from it.
+async fn create_scratch(plan: &ExportPlan) -> Result<(), ExportError> {
+    let Some(dir) = &plan.temp_dir else {
+        return Ok(());
+    };
+    check_budget().await?;
+    fs::create_dir_all(dir)
+        .await
+        .map_err(|e| ExportError::io("Cannot create temp dir", e))?;
+
+    // The concat demuxer reads the segment list from a file
+    if let PlannedOutput::Video(VideoPlan {
+        format,
+        segmented: Some(cut),
+        ..
+    }) = &plan.output
+    {
+        let list: String = (0..cut.pieces.len())
+            .map(|index| {
+                let path = segment_path(dir, index, format);
+                format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''"))
+            })
+            .collect();
+        fs::write(dir.join("segments.txt"), list)
+            .await
+            .map_err(|e| ExportError::io("Cannot write segment list", e))?;
+    }
+
+    if let PlannedOutput::Video(VideoPlan {
+        streaming: Some(streaming),
+        ..
+    }) = &plan.output
+    {
+        fs::create_dir_all(&streaming.dir)
+            .await
+            .map_err(|e| ExportError::io("Cannot create the output folder", e))?;
+    }
+
+    if let PlannedOutput::Video(VideoPlan {
+        chapters: Some(path),
+        ..
+    }) = &plan.output
+    {
+        fs::write(path, ffmetadata(&joined_ranges(&plan.range_ends)))
+            .await
+            .map_err(|e| ExportError::io("Cannot write chapters", e))?;
+    }
+
+    if let Some(SubtitlePlan {
+        path,
+        retimed: Some(contents),
+    }) = &plan.subtitles
+    {
+        fs::write(path, contents)
+            .await
+            .map_err(|e| ExportError::io("Cannot write retimed subtitles", e))?;
+    }
+
+    Ok(())
+}
+
+/// Validates `args` and resolves everything needed to run it. Nothing is
+/// written yet, `temp_dir` is only where scratch files would go.
+async fn plan_export(
+    args: &ExportArgs,
+    source: &VideoMetadata,
+    temp_dir: &Path,
+) -> Result<ExportPlan, ExportError> {
+    validate_selection(&args.selection)?;
+    validate_extra_args(args)?;
+    if args
+        .speed
+        .is_some_and(|speed| !(MIN_SPEED..=MAX_SPEED).contains(&speed))
+    {
+        return Err(format!("The speed must be between {} and {}", MIN_SPEED, MAX_SPEED).into());
+    }
+    if args.audio_gain_db.is_some_and(|gain| !gain.is_finite()) {
+        return Err("The audio gain must be a finite number of dB".into());
+    }
+    if let Some(loudness) = &args.loudness {
+        loudness.validate()?;
+    }
+    if let Some(external) = &args.external_audio {
+        validate_external_audio(args, external).await?;
+    }
+    if args.mute
+        && (args.audio_gain_db.is_some()
+            || !args.audio_tracks.is_empty()
+            || args.loudness.is_some()
+            || args.external_audio.is_some())
+    {
+        return Err("A muted export has no audio to pick or adjust".into());
+    }
+    validate_audio_tracks(args, source)?;
+    if !args.ladder.is_empty()
+        && (args.audio_only.is_some() || args.animated.is_some() || args.streaming.is_some())
+    {
+        return Err("A ladder needs a plain video export".into());
+    }
+    let range_ends = range_ends(args, source);
+    let clip_duration = range_ends.last().copied().unwrap_or(0.0) * args.playback.copies();
+    let moving_masks = args.masks.iter().any(|mask| !mask.keyframes.is_empty());
+    if args.playback != Playback::Forward
+        && (args.subtitle_path.is_some() || !args.crop_keyframes.is_empty() || moving_masks)
+    {
+        return Err(
+            "Subtitles, a panning crop and moving masks follow the source, which a reversed clip does not".into(),
+        );
+    }
+    validate_fades(args.fade_in, args.fade_out, clip_duration)?;
+
+    if let Some(format) = args.audio_only {
+        if args.animated.is_some()
+            || args.lossless
+            || args.smart_cut
+            || args.target_size_mb.is_some()
+            || args.video_bitrate.is_some()
+            || args.watermark.is_some()
+            || !args.text_overlays.is_empty()
+            || args.subtitle_path.is_some()
+            || args.interpolation.is_some()
+            || args.fps.is_some()
+            || args.color.is_some()
+            || args.lut_path.is_some()
+            || args.deinterlace.is_some()
+            || args.denoise.is_some()
+            || args.stabilize.is_some()
+            || !args.masks.is_empty()
+            || args.chroma_key.is_some()
+            || args.blur_fill.is_some()
+            || args.aspect_ratio.is_some()
+            || args.second_crop.is_some()
+            || !args.extra_crops.is_empty()
+            || args.mute
+            || args.streaming.is_some()
+        {
+            return Err("Audio-only exports cannot be combined with other modes".into());
+        }
+        if source.audio_codec.is_none() {
+            return Err("The source has no audio stream".into());
+        }
+
+        return Ok(ExportPlan {
+            clip_duration,
+            range_ends,
+            temp_dir: None,
+            subtitles: None,
+            tonemap: None,
+            deinterlace: None,
+            square_pixels: None,
+            chroma_key: None,
+            color_range: None,
+            source: source.clone(),
+            output: PlannedOutput::Audio(format),
+        });
+    }
+
+    validate_rotation(args.rotate)?;
+    if let Some(scale) = &args.scale {
+        scale.validate()?;
+    }
+    if let Some(watermark) = &args.watermark {
+        watermark.validate()?;
+    }
+    if let Some(interpolation) = &args.interpolation {
+        interpolation.validate()?;
+        if args.fps.is_some() {
+            return Err("Interpolation sets the frame rate itself".into());
+        }
+    }
+    validate_frame_rate(args.fps)?;
+    if let Some(color) = &args.color {
+        color.validate()?;
+    }
+    if let Some(path) = &args.lut_path {
+        validate_lut(path).await?;
+    }
+    if let Some(fill) = &args.blur_fill {
+        fill.validate()?;
+    }
+    if let Some(aspect) = &args.aspect_ratio {
+        aspect.validate()?;
+        if args.blur_fill.is_some() {
+            return Err("Choose either a blur fill or an aspect ratio".into());
+        }
+        if let (AspectFit::Crop, Some(DualCrop::Stacked { .. })) = (&aspect.fit, &args.second_crop)
+        {
+            return Err("Stacked crops can only be padded to an aspect ratio".into());
+        }
+    }
+    let chroma_key = match &args.chroma_key {
+        Some(key) => {
+            key.validate()?;
+            let (width, height) = composed_size(args);
+            Some(key.filter(width, height, source.frame_rate))
+        }
+        None => None,
+    };
+    if !args.masks.is_empty() || args.second_crop.is_some() || !args.extra_crops.is_empty() {
+        let (width, height) = source.display_size();
+        for mask in &args.masks {
+            mask.validate(width, height)?;
+        }
+        if let Some(second) = &args.second_crop {
+            second.validate(width, height)?;
+        }
+        for extra in &args.extra_crops {
+            extra.validate(width, height, args.animated.is_none())?;
+        }
+    }
+    if args.stabilize.is_some() {
+        let filters = &capabilities().await?.filters;
+        if !filters.iter().any(|filter| filter == "vidstabdetect") {
+            return Err("FFmpeg was built without the vid.stab filters (libvidstab)".into());
+        }
+    }
+    for overlay in &args.text_overlays {
+        overlay.validate()?;
+    }
+    let subtitles = plan_subtitles(args, &range_ends, temp_dir).await?;
+    let retimes_subtitles = subtitles
+        .as_ref()
+        .is_some_and(|subtitles| subtitles.retimed.is_some());
+
+    if let Some(animated) = &args.animated {
+        animated.validate()?;
+        if args.lossless
+            || args.smart_cut
+            || args.target_size_mb.is_some()
+            || args.video_bitrate.is_some()
+            || args.watermark.is_some()
+            || args.fps.is_some()
+            || args.streaming.is_some()
+        {
+            return Err(
+                "Lossless, smart cut, bitrate targets, watermarks, fps and streaming do not apply to animated exports, which set their own frame rate"
+                    .into(),
+            );
+        }
+
+        // GIF needs scratch space for its palette
+        let needs_scratch =
+            animated.format == AnimatedFormat::Gif || retimes_subtitles || args.stabilize.is_some();
+        return Ok(ExportPlan {
+            clip_duration,
+            range_ends,
+            temp_dir: needs_scratch.then(|| temp_dir.to_path_buf()),
+            subtitles,
+            // GIF and WebP have no HDR
+            tonemap: plan_tonemap(args, source).await?,
+            deinterlace: plan_deinterlace(args).await?,
+            square_pixels: source.square_pixels_filter(),
+            chroma_key,
+            color_range: Some(plan_color_range(args, source, false)),
+            source: source.clone(),
+            output: PlannedOutput::Animated(animated.clone()),
+        });
+    }
+
+    // Packages are cut from an MP4
+    if args.streaming.is_some() && args.container.is_some_and(|c| c != Container::Mp4) {
+        return Err("HLS and DASH packages are made from an MP4 export".into());
+    }
+    let video_codec = match (args.editing, args.video_codec) {
+        (Some(preset), Some(codec)) if preset.codec() != codec => {
+            return Err(format!(
+                "{:?} is a {:?} preset, not {:?}",
+                preset,
+                preset.codec(),
+                codec
+            )
+            .into())
+        }
+        (Some(preset), _) => Some(preset.codec()),
+        (None, None) if args.keep_alpha => args
+            .container
+            .or_else(|| Container::from_path(&args.output_path))
+            .and_then(Container::alpha_codec),
+        (None, codec) => codec,
+    };
+    let mut format = resolve_output_format(
+        args.container
+            .or(args.streaming.as_ref().map(|_| Container::Mp4)),
+        video_codec,
+        args.audio_codec,
+        &args.output_path,
+    )?;
+    args.quality.validate(format.video_codec)?;
+    let editing = plan_editing(args, source, format.video_codec)?;
+    let alpha = plan_alpha(args, source, format.video_codec, editing)?;
+    let streaming = match &args.streaming {
+        Some(options) => {
+            options.validate(format.video_codec, output_picture_size(args).1)?;
+            Some(StreamingPlan {
+                dir: Path::new(&args.output_path)
+                    .parent()
+                    .unwrap_or(Path::new(""))
+                    .to_path_buf(),
+                mezzanine: temp_dir.join("mezzanine.mp4"),
+            })
+        }
+        None => None,
+    };
+
+    if args.lossless && args.smart_cut {
+        return Err("Choose either lossless or smart cut mode, not both".into());
+    }
+    if args.target_size_mb.is_some() && args.video_bitrate.is_some() {
+        return Err("Choose either a target size or a video bitrate, not both".into());
+    }
+    if args.lossless || args.smart_cut {
+        validate_stream_copy(args, source)?;
+    }
+    let hdr = if args.lossless || args.smart_cut {
+        None
+    } else {
+        plan_hdr(args, source, format.video_codec)?.map(Box::new)
+    };
+    // Hardware encoders have no alpha channel
+    let encoder = if args.lossless || args.smart_cut || alpha {
+        Encoder::Cpu
+    } else {
+        match resolve_encoder(args.encoder.unwrap_or_default(), format.video_codec).await {
+            // Frames are uploaded to VAAPI as 8-bit NV12
+            Encoder::Vaapi if hdr.is_some() => Encoder::Cpu,
+            encoder => encoder,
+        }
+    };
+    if let Some(hdr) = hdr.as_ref().filter(|hdr| !hdr.keeps_mastering(encoder)) {
+        println!(
+            "{:?} drops the mastering display of {}, only its {} colors are kept",
+            encoder, args.input_path, hdr.transfer
+        );
+    }
+
+    let multiple_ranges = args.selection.len() > 1;
+    let joined = single_cut(args).is_none();
+    let chain = AudioChain::new(args, clip_duration);
+    let filters_audio = chain.replacement.is_some() || !chain.filters.is_empty();
+    let replaces_audio = chain.replacement.is_some();
+    let source_has_audio = (joined || !args.audio_tracks.is_empty() || filters_audio)
+        && !args.mute
+        && !replaces_audio
+        && source.audio_codec.is_some();
+    if args.external_audio.is_some() && !replaces_audio && !source_has_audio {
+        return Err(
+            "The source has no audio to mix the external audio into, replace it instead".into(),
+        );
+    }
+    let has_audio = replaces_audio || source_has_audio;
+    // The concat filter, mixdowns and audio filters output decoded audio.
+    // Stream copies join their pieces without filters.
+    let decodes_audio = filters_audio
+        || (!args.lossless && !args.smart_cut && (joined || args.audio_tracks.len() > 1));
+    if has_audio && decodes_audio {
+        reencode_copied_audio(&mut format);
+    }
+
+    let mut audio_bitrate = args.audio_bitrate;
+    let mut video_bitrate = None;
+
+    if let Some(target_mb) = args.target_size_mb {
+        reencode_copied_audio(&mut format);
+        let audio_kbps = if args.mute {
+            0
+        } else {
+            *audio_bitrate.get_or_insert(DEFAULT_AUDIO_BITRATE)
+        };
+        video_bitrate = Some(bitrate_for_target_size(
+            target_mb,
+            clip_duration,
+            audio_kbps,
+        )?);
+    } else if let Some(kbps) = args.video_bitrate {
+        if kbps < MIN_VIDEO_BITRATE {
+            return Err(format!(
+                "The video bitrate must be at least {} kbit/s",
+                MIN_VIDEO_BITRATE
+            )
+            .into());
+        }
+        video_bitrate = Some(kbps);
+    }
+    // Hardware encoders and SVT-AV1 have no two-pass mode in FFmpeg
+    let two_pass =
+        video_bitrate.is_some() && encoder == Encoder::Cpu && format.video_codec != VideoCodec::Av1;
+
+    let segmented = if args.smart_cut {
+        Some(plan_smart_cut(args, source).await?)
+    } else if args.lossless && multiple_ranges {
+        Some(SegmentedCut {
+            pieces: args
+                .selection
+                .iter()
+                .enumerate()
+                .map(|(index, range)| {
+                    let segment = Segment {
+                        start: range.start,
+                        end: range.end,
+                        copy: true,
+                    };
+                    (index, segment)
+                })
+                .collect(),
+            source: None,
+        })
+    } else {
+        None
+    };
+
+    let copies = args.lossless || args.smart_cut;
+    if !args.ladder.is_empty() {
+        if copies || two_pass || encoder == Encoder::Vaapi {
+            return Err(
+                "A ladder is scaled on the CPU and encoded in one pass, without stream copies or two-pass bitrates"
+                    .into(),
+            );
+        }
+        let (_, height) = output_picture_size(args);
+        for rung in &args.ladder {
+            rung.validate(height)?;
+        }
+    }
+    if args.chapter_markers && copies {
+        return Err("Chapter markers need a re-encode, not a stream copy".into());
+    }
+    let chapters = args.chapter_markers.then(|| temp_dir.join("chapters.txt"));
+    let (telemetry_streams, spherical) = plan_source_extras(args, source, &format);
+    // HEVC, VP9 and AV1 outputs could keep the HDR, stream copies have to
+    let tonemap = if format.video_codec == VideoCodec::H264 && !copies {
+        plan_tonemap(args, source).await?
+    } else {
+        None
+    };
+    let (deinterlace, square_pixels, color_range) = if copies {
+        (None, None, None)
+    } else {
+        (
+            plan_deinterlace(args).await?,
+            source.square_pixels_filter(),
+            Some(plan_color_range(args, source, true)),
+        )
+    };
+
+    Ok(ExportPlan {
+        clip_duration,
+        range_ends,
+        temp_dir: (two_pass
+            || segmented.is_some()
+            || retimes_subtitles
+            || args.stabilize.is_some()
+            || chapters.is_some()
+            || streaming.is_some())
+        .then(|| temp_dir.to_path_buf()),
+        subtitles,
+        tonemap,
+        deinterlace,
+        square_pixels,
+        chroma_key,
+        color_range,
+        source: source.clone(),
+        output: PlannedOutput::Video(VideoPlan {
+            format,
+            encoder,
+            video_bitrate,
+            audio_bitrate,
+            two_pass,
+            has_audio,
+            segmented,
+            chapters,
+            telemetry_streams,
+            spherical,
+            streaming,
+            editing,
+            alpha,
+            hdr,
+        }),
+    })
+}
+
+/// GoPro telemetry to copy and what to do with a 360° projection. Both only
+/// survive in MP4 and QuickTime files, the telemetry also only for a single
+/// range played as it is, as data streams cannot be joined or retimed.
+fn plan_source_extras(
+    args: &ExportArgs,
+    metadata: &VideoMetadata,
+    format: &OutputFormat,
+) -> (Vec<usize>, Option<SphericalPlan>) {
+    let keeps_tracks = matches!(format.container, Container::Mp4 | Container::Mov);
+
+    let telemetry = if keeps_tracks && single_cut(args).is_some() && !args.smart_cut {
+        metadata.telemetry_streams.clone()
+    } else {
+        if !metadata.telemetry_streams.is_empty() {
+            println!("Leaving out the telemetry of {}", args.input_path);
+        }
+        vec![]
+    };
+
+    let spherical = metadata.spherical.clone().map(|projection| {
+        let (width, height) = metadata.display_size();
+        let crop = &args.crop;
+        // The crop may be rounded down to even sizes
+        let whole_frame =
+            crop.x == 0 && crop.y == 0 && crop.width + 1 >= width && crop.height + 1 >= height;
+        let reshaped = args.rotate.unwrap_or(0) != 0
+            || args.flip.is_some()
+            || args.second_crop.is_some()
+            || args.blur_fill.is_some()
+            || args.aspect_ratio.is_some()
+            || !args.crop_keyframes.is_empty();
+        if keeps_tracks && (args.lossless || (whole_frame && !reshaped)) {
+            SphericalPlan::Keep
+        } else {
+            SphericalPlan::Lost { projection }
+        }
+    });
+    (telemetry, spherical)
+}
+
+/// Maps the telemetry streams of `video` and lets the muxer write the
+/// projection, after the `ffmpeg_args` built so far.
+fn source_extras_args(ffmpeg_args: &[String], video: &VideoPlan) -> Vec<String> {
+    let mut extras: Vec<String> = vec![];
+    if !video.telemetry_streams.is_empty() {
+        // Mapping any stream ends FFmpeg's default pick of picture and sound
+        if !ffmpeg_args.iter().any(|arg| arg == "-map") {
+            extras.extend(["-map", "0:v:0", "-map", "0:a:0?"].map(String::from));
+        }
+        for index in &video.telemetry_streams {
+            extras.extend(["-map".to_string(), format!("0:{}", index)]);
+        }
+        extras.extend(["-c:d", "copy"].map(String::from));
+    }
+    // The MP4 muxer only writes spherical boxes at this level
+    if video.spherical == Some(SphericalPlan::Keep) {
+        extras.extend(["-strict", "unofficial"].map(String::from));
+    }
+    extras
+}
+
+/// Profile of an editing codec output.
+fn plan_editing(
+    args: &ExportArgs,
+    source: &VideoMetadata,
+    codec: VideoCodec,
+) -> Result<Option<EditingPreset>, ExportError> {
+    if !codec.is_editing() {
+        return Ok(None);
+    }
+    if args.lossless
+        || args.smart_cut
+        || args.web.is_some()
+        || args.target_size_mb.is_some()
+        || args.video_bitrate.is_some()
+    {
+        return Err(
+            "ProRes and DNxHR outputs are re-encoded at the rate of their profile, not copied, sized or made for the web"
+                .into(),
+        );
+    }
+    Ok(args
+        .editing
+        .or(EditingPreset::default_for(codec, source.alpha)))
+}
+
+/// Whether the output keeps the alpha channel of the source. ProRes 4444
+/// keeps it on its own, anything else only with `keep_alpha`, which fails
+/// for codecs that cannot carry it.
+fn plan_alpha(
+    args: &ExportArgs,
+    source: &VideoMetadata,
+    codec: VideoCodec,
+    editing: Option<EditingPreset>,
+) -> Result<bool, ExportError> {
+    if !args.keep_alpha && editing.is_none() {
+        return Ok(false);
+    }
+    let source_alpha = source.alpha;
+    let has_room = match editing {
+        Some(preset) => preset.supports_alpha(),
+        None => codec == VideoCodec::Vp9,
+    };
+    if !args.keep_alpha {
+        if source_alpha && !has_room {
+            println!(
+                "{:?} drops the alpha channel of {}, ProRes 4444 keeps it",
+                editing, args.input_path
+            );
+        }
+        return Ok(source_alpha && has_room);
+    }
+
+    if !source_alpha {
+        return Err(format!("{} has no alpha channel to keep", args.input_path).into());
+    }
+    // Copies keep the picture as it is
+    if args.lossless || args.smart_cut {
+        return Ok(true);
+    }
+    if !has_room {
+        return Err(match editing {
+            Some(preset) => format!("{:?} has no alpha channel, choose ProRes 4444", preset),
+            None => format!(
+                "{:?} video cannot carry an alpha channel, choose ProRes 4444 or VP9",
+                codec
+            ),
+        }
+        .into());
+    }
+    if args.web.is_some() {
+        return Err("Web optimized outputs have no alpha channel".into());
+    }
+    Ok(true)
+}
+
+/// Color range of a re-encode, brought to limited range by default.
+fn plan_color_range(args: &ExportArgs, source: &VideoMetadata, video: bool) -> RangePlan {
+    RangePlan::new(
+        args.color_range,
+        source.color_range,
+        source.pix_fmt.as_deref(),
+        video,
+    )
+}
+
+/// Deinterlacing filter for `args`. Automatic mode samples up to 10 seconds
+/// from the start of the selection with `idet`.
+async fn plan_deinterlace(args: &ExportArgs) -> Result<Option<&'static str>, ExportError> {
+    match args.deinterlace {
+        None => Ok(None),
+        Some(Deinterlace::Yadif) => Ok(Some("yadif")),
+        Some(Deinterlace::Bwdif) => Ok(Some("bwdif")),
+        Some(Deinterlace::Auto) => {
+            let Some(range) = args.selection.first() else {
+                return Ok(None);
+            };
+            let report = detect_interlacing(
+                args.input_path.clone(),
+                Some(range.start),
+                Some((range.end - range.start).min(INTERLACE_SAMPLE_DURATION)),
+            )
+            .await
+            .map_err(ExportError::probe)?;
+            println!(
+                "idet: {} top field first, {} bottom field first, {} progressive frames",
+                report.top_field_first, report.bottom_field_first, report.progressive
+            );
+            Ok(report.interlaced.then_some("bwdif"))
+        }
+    }
+}
+
+/// The colors `preserve_hdr` keeps, none for SDR sources.
+fn plan_hdr(
+    args: &ExportArgs,
+    source: &VideoMetadata,
+    codec: VideoCodec,
+) -> Result<Option<HdrSignal>, ExportError> {
+    if !args.preserve_hdr {
+        return Ok(None);
+    }
+    if !source.is_hdr() {
+        println!("{} is SDR, there is no HDR to keep", args.input_path);
+        return Ok(None);
+    }
+    if !matches!(codec, VideoCodec::Hevc | VideoCodec::Av1) {
+        return Err(format!("HDR is kept in HEVC or AV1 video, not {:?}", codec).into());
+    }
+    if args.web.is_some() {
+        return Err("Web optimized outputs are 8-bit SDR".into());
+    }
+    Ok(Some(HdrSignal {
+        primaries: source
+            .color_primaries
+            .clone()
+            .unwrap_or("bt2020".to_string()),
+        transfer: source.color_transfer.clone().unwrap_or_default(),
+        matrix: source.color_space.clone().unwrap_or("bt2020nc".to_string()),
+        mastering_display: source.mastering_display.clone(),
+        content_light: source.content_light,
+    }))
+}
+
+/// The tonemapping of `args` if the source is HDR. Without zscale the source
+/// is exported as before, washed out.
+async fn plan_tonemap(
+    args: &ExportArgs,
+    source: &VideoMetadata,
+) -> Result<Option<TonemapOperator>, ExportError> {
+    if !source.is_hdr() {
+        return Ok(None);
+    }
+    let filters = &capabilities().await?.filters;
+    if !filters.iter().any(|filter| filter == "zscale") {
+        println!("FFmpeg was built without zscale (zimg), HDR is not tonemapped");
+        return Ok(None);
+    }
+    println!(
+        "Tonemapping {} HDR to SDR",
+        source.color_transfer.as_deref().unwrap_or_default()
+    );
+    Ok(Some(args.tonemap))
+}
+
+/// Subtitles are timed against the source. The filters see source time for a
+/// single range, so the file is used as is; joined ranges start at 0 and get
+/// a retimed copy.
+async fn plan_subtitles(
+    args: &ExportArgs,
+    range_ends: &[f64],
+    temp_dir: &Path,
+) -> Result<Option<SubtitlePlan>, ExportError> {
+    let Some(source) = &args.subtitle_path else {
+        return Ok(None);
+    };
+    let format = SubtitleFormat::from_path(source)?;
+    if !Path::new(source).is_file() {
+        return Err(format!("Subtitle file {} not found", source).into());
+    }
+    let filters = &capabilities().await?.filters;
+    if !filters.iter().any(|filter| filter == "subtitles") {
+        return Err("FFmpeg was built without the subtitles filter (libass)".into());
+    }
+
+    if single_cut(args).is_some() {
+        return Ok(Some(SubtitlePlan {
+            path: PathBuf::from(source),
+            retimed: None,
+        }));
+    }
+
+    // Non-UTF-8 files are rare and libass would misread them too
+    let contents = fs::read(source)
+        .await
+        .map_err(|e| ExportError::io("Cannot read subtitles", e))?;
+    let ranges: Vec<RetimedRange> = args
+        .selection
+        .iter()
+        .enumerate()
+        .map(|(index, range)| RetimedRange {
+            start: range.start,
+            end: range.end,
+            output_start: index
+                .checked_sub(1)
+                .map_or(0.0, |previous| range_ends[previous]),
+            speed: playback_speed(args),
+        })
+        .collect();
+
+    Ok(Some(SubtitlePlan {
+        path: temp_dir.join(format!("subtitles.{}", format.extension())),
+        retimed: Some(retime(&String::from_utf8_lossy(&contents), format, &ranges)),
+    }))
+}
+
+/// Splits each range at its keyframes. The re-encoded edges use the source
+/// codec and pixel format so the pieces can be joined without re-encoding.
+async fn plan_smart_cut(
+    args: &ExportArgs,
+    metadata: &VideoMetadata,
+) -> Result<SegmentedCut, ExportError> {
+    let codec = metadata
+        .video_codec
+        .as_deref()
+        .and_then(VideoCodec::from_ffprobe_name)
+        .ok_or(format!(
+            "Smart cut does not support {} sources",
+            metadata.video_codec.as_deref().unwrap_or("unknown")
+        ))?;
+
+    let mut pieces = vec![];
+    for (index, range) in args.selection.iter().enumerate() {
+        let keyframes = get_keyframes(args.input_path.clone(), Some(range.start), Some(range.end))
+            .await
+            .map_err(ExportError::probe)?;
+        pieces.extend(
+            plan_segments(&keyframes, range.start, range.end)
+                .into_iter()
+                .map(|segment| (index, segment)),
+        );
+    }
+
+    Ok(SegmentedCut {
+        pieces,
+        source: Some(SourceFormat {
+            codec,
+            pix_fmt: metadata.pix_fmt.clone(),
+        }),
+    })
+}
+
+/// Brings the areas of `args` to pixels of the frame the source displays as:
+/// the crop fraction, and areas drawn on the stored orientation or on a frame
+/// of another size. Returns the crop as requested and what was changed.
+fn resolve_areas(
+    args: &mut ExportArgs,
+    metadata: &VideoMetadata,
+) -> Result<(CropArea, Vec<String>), ExportError> {
+    let (frame_width, frame_height) = metadata.display_size();
+    let mut requested = args.crop.clone();
+    let mut reasons = vec![];
+    if orient_areas(args, metadata) {
+        reasons.push(format!(
+            "Turned {}° to the orientation the source displays in",
+            metadata.rotation
+        ));
+    }
+    if scale_areas(args, (frame_width, frame_height)) {
+        reasons.push(format!(
+            "Scaled to the {}x{} frame the source displays as",
+            frame_width, frame_height
+        ));
+    }
+    if let Some(fraction) = &args.crop_fraction {
+        fraction.validate()?;
+        args.crop = fraction.resolve(frame_width, frame_height);
+        requested = args.crop.clone();
+    }
+    Ok((requested, reasons))
+}
+
+/// Clamps the crop to the source frame and, for video outputs, rounds its size
+/// down to even values, since 4:2:0 encoders reject odd dimensions. `requested`
+/// and `reasons` come from `resolve_areas`.
+fn fit_crop(
+    args: &mut ExportArgs,
+    job_id: JobId,
+    metadata: &VideoMetadata,
+    (requested, mut reasons): (CropArea, Vec<String>),
+) -> Result<Option<CropAdjusted>, ExportError> {
+    let (frame_width, frame_height) = metadata.display_size();
+    let mut crop = args.crop.clone();
+
+    // A panning window can be anywhere, FFmpeg clamps its position per frame
+    let (x, y) = if args.crop_keyframes.is_empty() {
+        (crop.x, crop.y)
+    } else {
+        (0, 0)
+    };
+
+    if x >= frame_width || y >= frame_height {
+        return Err(format!(
+            "The crop area starts outside the {}x{} frame",
+            frame_width, frame_height
+        )
+        .into());
+    }
+    if x.saturating_add(crop.width) > frame_width {
+        crop.width = frame_width - x;
+        reasons.push(format!("Width clamped to the frame edge at {}", crop.width));
+    }
+    if y.saturating_add(crop.height) > frame_height {
+        crop.height = frame_height - y;
+        reasons.push(format!(
+            "Height clamped to the frame edge at {}",
+            crop.height
+        ));
+    }
+
+    // Animated images have no chroma subsampling to worry about
+    if args.animated.is_none() {
+        if crop.width % 2 == 1 {
+            crop.width -= 1;
+            reasons.push(format!(
+                "Width rounded down to the even value {}",
+                crop.width
+            ));
+        }
+        if crop.height % 2 == 1 {
+            crop.height -= 1;
+            reasons.push(format!(
+                "Height rounded down to the even value {}",
+                crop.height
+            ));
+        }
+    }
+    if crop.width == 0 || crop.height == 0 {
+        return Err("The crop area is too small to export".into());
+    }
+
+    if let Some(aspect) = args.aspect_ratio.clone() {
+        let (x, y) = (crop.x, crop.y);
+        let narrowed = narrow_to_aspect(&crop, &aspect, args.rotate)?;
+        if narrowed != crop {
+            crop = narrowed;
+            reasons.push(format!(
+                "Narrowed to {} at {}x{}",
+                aspect.ratio, crop.width, crop.height
+            ));
+        }
+        // A panning window keeps its center too
+        for keyframe in &mut args.crop_keyframes {
+            keyframe.x += crop.x - x;
+            keyframe.y += crop.y - y;
+        }
+        for (index, extra) in args.extra_crops.iter_mut().enumerate() {
+            let narrowed = narrow_to_aspect(&extra.crop, &aspect, args.rotate)?;
+            if narrowed != extra.crop {
+                reasons.push(format!(
+                    "Extra crop {} narrowed to {} at {}x{}",
+                    index + 1,
+                    aspect.ratio,
+                    narrowed.width,
+                    narrowed.height
+                ));
+                extra.crop = narrowed;
+            }
+        }
+    }
+
+    if reasons.is_empty() {
+        return Ok(None);
+    }
+    args.crop = crop.clone();
+    Ok(Some(CropAdjusted {
+        job_id,
+        requested,
+        adjusted: crop,
+        reasons,
+    }))
+}
+
+/// `area` of a `width` x `height` frame as stored, moved to where it shows
+/// once the frame is turned `rotation` degrees clockwise.
+fn rotate_area(area: &CropArea, rotation: u32, (width, height): (u32, u32)) -> CropArea {
+    match rotation {
+        90 => CropArea {
+            x: height.saturating_sub(area.y.saturating_add(area.height)),
+            y: area.x,
+            width: area.height,
+            height: area.width,
+        },
+        180 => CropArea {
+            x: width.saturating_sub(area.x.saturating_add(area.width)),
+            y: height.saturating_sub(area.y.saturating_add(area.height)),
+            ..area.clone()
+        },
+        270 => CropArea {
+            x: area.y,
+            y: width.saturating_sub(area.x.saturating_add(area.width)),
+            width: area.height,
+            height: area.width,
+        },
+        _ => area.clone(),
+    }
+}
+
+/// Moves every area of `args` drawn on the preview with `map`: the crop and
+/// its keyframes, the masks, the second crop and the extra crops.
+fn map_areas(args: &mut ExportArgs, map: impl Fn(&CropArea) -> CropArea) {
+    // Keyframes place a window of the crop's size
+    for keyframe in &mut args.crop_keyframes {
+        let window = map(&CropArea {
+            x: keyframe.x,
+            y: keyframe.y,
+            ..args.crop.clone()
+        });
+        (keyframe.x, keyframe.y) = (window.x, window.y);
+    }
+    args.crop = map(&args.crop);
+    for mask in &mut args.masks {
+        let area = |x, y| CropArea {
+            x,
+            y,
+            width: mask.width,
+            height: mask.height,
+        };
+        for keyframe in &mut mask.keyframes {
+            let moved = map(&area(keyframe.x, keyframe.y));
+            (keyframe.x, keyframe.y) = (moved.x, moved.y);
+        }
+        let moved = map(&area(mask.x, mask.y));
+        (mask.x, mask.y, mask.width, mask.height) = (moved.x, moved.y, moved.width, moved.height);
+    }
+    if let Some(second) = &mut args.second_crop {
+        *second.crop_mut() = map(second.crop_mut());
+    }
+    for extra in &mut args.extra_crops {
+        extra.crop = map(&extra.crop);
+    }
+}
+
+/// Turns every area of `args` drawn on the stored frame of a quarter-turned
+/// source to the orientation FFmpeg autorotates it to. Returns whether it
+/// did. A half turn keeps the frame size, so crops drawn on such a source are
+/// always taken as displayed.
+fn orient_areas(args: &mut ExportArgs, metadata: &VideoMetadata) -> bool {
+    let stored = (metadata.width, metadata.height);
+    let drawn_on_stored = metadata.rotation % 180 == 90
+        && args
+            .crop_frame
+            .is_some_and(|frame| (frame.width, frame.height) == stored);
+    if !drawn_on_stored {
+        return false;
+    }
+
+    map_areas(args, |area| rotate_area(area, metadata.rotation, stored));
+    args.crop_frame = Some(FrameSize {
+        width: stored.1,
+        height: stored.0,
+    });
+    true
+}
+
+/// Scales every area of `args` from the frame it was drawn on to the frame
+/// the export crops, e.g. from the stored pixels of an anamorphic source to
+/// square ones. Returns whether it did.
+fn scale_areas(args: &mut ExportArgs, (width, height): (u32, u32)) -> bool {
+    let Some(frame) = args
+        .crop_frame
+        .filter(|frame| frame.width > 0 && frame.height > 0)
+    else {
+        return false;
+    };
+    if (frame.width, frame.height) == (width, height) {
+        return false;
+    }
+
+    let x_scale = width as f64 / frame.width as f64;
+    let y_scale = height as f64 / frame.height as f64;
+    map_areas(args, |area| CropArea {
+        x: (area.x as f64 * x_scale).round() as u32,
+        y: (area.y as f64 * y_scale).round() as u32,
+        width: (area.width as f64 * x_scale).round() as u32,
+        height: (area.height as f64 * y_scale).round() as u32,
+    });
+    args.crop_frame = Some(FrameSize { width, height });
+    true
+}
+
+/// Largest part of `crop` around its center that comes out with the ratio of
+/// `aspect` once rotated. Padding aspects leave the crop alone.
+fn narrow_to_aspect(
+    crop: &CropArea,
+    aspect: &AspectRatio,
+    rotate: Option<u32>,
+) -> Result<CropArea, String> {
+    if !matches!(aspect.fit, AspectFit::Crop) {
+        return Ok(crop.clone());
+    }
+    let quarter_turn = matches!(rotate, Some(90 | 270));
+    let turn = |(width, height)| match quarter_turn {
+        true => (height, width),
+        false => (width, height),
+    };
+    let (width, height) = turn((crop.width, crop.height));
+    let (width, height) = turn(
+        aspect
+            .crop_size(width, height)
+            .ok_or_else(|| format!("The crop area is too small for {}", aspect.ratio))?,
+    );
+    Ok(CropArea {
+        x: crop.x + (crop.width - width) / 2,
+        y: crop.y + (crop.height - height) / 2,
+        width,
+        height,
+    })
+}
+
+/// Picked audio tracks have to exist, each picked once.
+fn validate_audio_tracks(args: &ExportArgs, source: &VideoMetadata) -> Result<(), ExportError> {
+    if args.audio_tracks.is_empty() {
+        return Ok(());
+    }
+    let count = source.audio_streams.len();
+
+    for (index, track) in args.audio_tracks.iter().enumerate() {
+        if *track >= count {
+            return Err(format!(
+                "Audio track {} does not exist, the source has {}",
+                track, count
+            )
+            .into());
+        }
+        if args.audio_tracks[..index].contains(track) {
+            return Err(format!("Audio track {} is picked twice", track).into());
+        }
+    }
+    Ok(())
+}
+
+async fn validate_external_audio(
+    args: &ExportArgs,
+    external: &ExternalAudio,
+) -> Result<(), ExportError> {
+    external.validate()?;
+    let replaces = external.mode == ExternalAudioMode::Replace;
+    if replaces && (args.audio_gain_db.is_some() || !args.audio_tracks.is_empty()) {
+        return Err("The original audio is replaced, there are no tracks to pick or adjust".into());
+    }
+
+    let metadata = probe(&external.path).await.map_err(ExportError::probe)?;
+    if metadata.audio_codec.is_none() {
+        return Err(format!("{} has no audio stream", external.path).into());
+    }
+    Ok(())
+}
+
+/// Stream copy cannot apply filters, so the crop has to cover the full frame.
+fn validate_stream_copy(args: &ExportArgs, source: &VideoMetadata) -> Result<(), ExportError> {
+    if args.target_size_mb.is_some() || args.video_bitrate.is_some() {
+        return Err("Lossless and smart cut modes cannot target a bitrate or file size".into());
+    }
+
+    if args.rotate.unwrap_or(0) != 0 || args.flip.is_some() {
+        return Err("Stream copy cannot rotate or flip the picture".into());
+    }
+    if args.scale.is_some() {
+        return Err("Stream copy cannot scale the picture".into());
+    }
+    if args.audio_tracks.len() > 1 {
+        return Err("Stream copy cannot mix audio tracks".into());
+    }
+    if args.watermark.is_some() || !args.text_overlays.is_empty() || args.subtitle_path.is_some() {
+        return Err("Stream copy cannot add a watermark, text or subtitles".into());
+    }
+    if !args.crop_keyframes.is_empty() {
+        return Err("Stream copy cannot pan the crop".into());
+    }
+    if args.fade_in.is_some() || args.fade_out.is_some() {
+        return Err("Stream copy cannot fade the clip in or out".into());
+    }
+    if args.external_audio.is_some() {
+        return Err("Stream copy cannot add external audio".into());
+    }
+    if playback_speed(args) != 1.0 || args.interpolation.is_some() {
+        return Err("Stream copy cannot change the speed or interpolate frames".into());
+    }
+    if args.fps.is_some() {
+        return Err("Stream copy keeps the frame rate of the source".into());
+    }
+    if matches!(
+        args.deinterlace,
+        Some(Deinterlace::Yadif | Deinterlace::Bwdif)
+    ) {
+        return Err("Stream copy cannot deinterlace".into());
+    }
+    if args.denoise.is_some() || args.stabilize.is_some() || !args.masks.is_empty() {
+        return Err("Stream copy cannot denoise, stabilize or mask regions".into());
+    }
+    if args.second_crop.is_some() || !args.extra_crops.is_empty() {
+        return Err("Stream copy cannot compose a second crop or write extra crops".into());
+    }
+    if args.chroma_key.is_some() || args.blur_fill.is_some() || args.aspect_ratio.is_some() {
+        return Err("Stream copy cannot key out, pad or fill a background".into());
+    }
+    if args.color.is_some() || args.lut_path.is_some() {
+        return Err("Stream copy cannot adjust the colors or apply a LUT".into());
+    }
+    if args.playback != Playback::Forward {
+        return Err("Stream copy cannot reverse the clip".into());
+    }
+
+    validate_copy_crop(args, source)
+}
+
+fn validate_copy_crop(args: &ExportArgs, metadata: &VideoMetadata) -> Result<(), ExportError> {
+    let (width, height) = metadata.display_size();
+    let crop = &args.crop;
+
+    if crop.x != 0 || crop.y != 0 || crop.width != width || crop.height != height {
+        return Err("Stream copy cannot crop, reset the crop to the full frame".into());
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn process_video(
+    window: Window,
+    jobs: State<'_, JobRegistry>,
+    args: ExportArgs,
+) -> Result<JobId, ExportError> {
+    let job_id = jobs.next_id();
+    spawn_export(window, job_id, args).await?;
+
+    Ok(job_id)
+}
+
+/// Spawns one FFmpeg pass and registers it under `job_id` so it can be
+/// cancelled.
+fn spawn_pass(
+    window: &Window,
+    job_id: JobId,
+    ffmpeg_args: &[String],
+    output_paths: &[String],
+) -> Result<Receiver<CommandEvent>, ExportError> {
+    let jobs = window.state::<JobRegistry>();
+    jobs.append_log(job_id, &format!("$ ffmpeg {}", ffmpeg_args.join(" ")));
+    let command = Command::new(binaries::ffmpeg()).args(ffmpeg_args);
+
+    let (rx, child) = command.spawn().map_err(|e| ExportError::SpawnFailed {
+        message: e.to_string(),
+    })?;
+
+    // Keep the child handle around so the job can be cancelled
+    jobs.insert(
+        job_id,
+        RunningJob {
+            child,
+            output_paths: output_paths.to_vec(),
+        },
+    );
+    // Record the outputs in case the app crashes before the pass ends
+    window.state::<ExportQueue>().save(&window.app_handle());
+
+    Ok(rx)
+}
+
+/// Stderr lines kept for the error report of a failed pass.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// How a single FFmpeg pass ended.
+struct PassResult {
+    exit_code: Option<i32>,
+    command_error: Option<String>,
+    stderr_tail: VecDeque<String>,
+}
+
+impl PassResult {
+    fn into_error(self) -> ExportError {
+        match self.command_error {
+            Some(message) => ExportError::SpawnFailed { message },
+            None => ExportError::from_exit(self.exit_code, self.stderr_tail.into()),
+        }
+    }
+}
+
+/// Forwards progress of one pass until FFmpeg exits.
+async fn monitor_pass(
+    window: &Window,
+    job_id: JobId,
+    rx: &mut Receiver<CommandEvent>,
+    progress: &mut ProgressParser,
+) -> PassResult {
+    let mut exit_code: Option<i32> = None;
+    let mut command_error: Option<String> = None;
+    let mut stderr_tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+
+    while let Some(event) = rx.recv().await {
+        match event {
+            CommandEvent::Stdout(line) => {
+                // `-progress pipe:1` writes key=value blocks to Stdout
+                if let Some(payload) = progress.feed(&line) {
+                    show_progress(window, job_id, payload.percent);
+                    emit_event(window, "ffmpeg-progress", payload);
+                }
+            }
+            CommandEvent::Stderr(line) => {
+                // With -nostats only warnings and errors end up here
+                println!("FFmpeg: {}", line);
+                window.state::<JobRegistry>().append_log(job_id, &line);
+                if stderr_tail.len() == STDERR_TAIL_LINES {
+                    stderr_tail.pop_front();
+                }
+                stderr_tail.push_back(line);
+            }
+            CommandEvent::Terminated(payload) => {
+                // The FFmpeg process has exited.
+                exit_code = payload.code;
+                println!("FFmpeg Terminated with code: {:?}", payload.code);
+                // Break the loop to handle final status
+                break;
+            }
+            CommandEvent::Error(err) => {
+                // An error occurred spawning or running the command itself
+                command_error = Some(err.clone());
+                println!("FFmpeg Command Error: {}", err);
+                // Break the loop to handle final status
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    PassResult {
+        exit_code,
+        command_error,
+        stderr_tail,
+    }
+}
+
+/// Puts the stats of a finished loudness measurement into the passes of the
+/// same output following it. Without usable stats, e.g. for a silent clip,
+/// `loudnorm` falls back to normalizing on the fly.
+fn fill_in_loudness(
+    window: &Window,
+    job_id: JobId,
+    passes: &mut [(Pass, Vec<String>)],
+    measurement: &PassResult,
+) {
+    let stderr: Vec<&str> = measurement.stderr_tail.iter().map(String::as_str).collect();
+    let options = measured_options(&stderr);
+    if options.is_none() {
+        println!(
+            "No loudness measured for {}, normalizing in one pass",
+            job_id
+        );
+        window
+            .state::<JobRegistry>()
+            .append_log(job_id, "No loudness measured, normalizing in one pass");
+    }
+    let options = options.unwrap_or_default();
+
+    for (pass, _) in passes {
+        if pass.measures_loudness {
+            break;
+        }
+        for arg in &mut pass.args {
+            *arg = arg.replace(MEASUREMENT_PLACEHOLDER, &options);
+        }
+    }
+}
+
+/// Copies every setting the preset defines over `args`.
+fn apply_preset(args: &mut ExportArgs, settings: PresetSettings) {
+    if let Some(encoder) = settings.encoder {
+        args.encoder = Some(encoder);
+    }
+    if let Some(quality) = settings.quality {
+        args.quality = quality;
+    }
+    args.video_codec = settings.video_codec.or(args.video_codec);
+    args.audio_codec = settings.audio_codec.or(args.audio_codec);
+    args.container = settings.container.or(args.container);
+    args.audio_bitrate = settings.audio_bitrate.or(args.audio_bitrate);
+    args.scale = settings.scale.or(args.scale);
+    args.crop_fraction = settings.crop_fraction.or(args.crop_fraction);
+    args.web = settings.web.or(args.web.take());
+}
+
+/// Falls back to the defaults of the settings for what the export leaves
+/// open. Outputs that are just a file name go to the default output folder.
+fn apply_settings(args: &mut ExportArgs, settings: &Settings) {
+    args.preset = args.preset.take().or(settings.default_preset.clone());
+    args.encoder = args.encoder.or(settings.encoder);
+
+    let Some(dir) = &settings.output_dir else {
+        return;
+    };
+    let in_dir = |path: &mut String| {
+        if Path::new(path.as_str()).parent() == Some(Path::new("")) {
+            *path = Path::new(dir).join(&path).to_string_lossy().into_owned();
+        }
+    };
+    in_dir(&mut args.output_path);
+    if let Some(template) = &mut args.output_template {
+        in_dir(template);
+    }
+    for extra in &mut args.extra_crops {
+        in_dir(&mut extra.output_path);
+    }
+}
+
+/// Turns the request into what is actually exported: the preset is applied,
+/// the crop is made encodable (stream copies keep the full frame as is) and
+/// removed ranges become the ranges to keep. Returns the crop change, if any.
+async fn resolve_args(
+    app_handle: &AppHandle,
+    args: &mut ExportArgs,
+    job_id: JobId,
+) -> Result<Option<CropAdjusted>, ExportError> {
+    apply_settings(args, &app_handle.state::<SettingsStore>().get());
+    if let Some(name) = args.preset.take() {
+        let settings = load_preset(app_handle, &name).await?;
+        apply_preset(args, settings);
+    }
+    let source = probe(&args.input_path).await.map_err(ExportError::probe)?;
+
+    // First, as the crop detection and everything after work on source times
+    if args.split_chapters {
+        if args.output_template.is_none() {
+            return Err(
+                "Splitting by chapters needs an output template, e.g. {stem}_{index}.{ext}".into(),
+            );
+        }
+        let chapters = read_chapters(&args.input_path)
+            .await
+            .map_err(ExportError::probe)?;
+        if chapters.is_empty() {
+            return Err(format!("{} has no chapters", args.input_path).into());
+        }
+        args.selection = chapters
+            .iter()
+            .map(|chapter| ClipSelection {
+                start: chapter.start,
+                end: chapter.end,
+            })
+            .collect();
+        args.relative_selection = false;
+        args.frame_selection.clear();
+        args.split_chapters = false;
+    }
+    if !args.frame_selection.is_empty() {
+        if args.relative_selection {
+            return Err("Frame ranges cannot be relative".into());
+        }
+        args.selection = to_selection(&args.frame_selection, source.frame_rate)?;
+        args.frame_selection.clear();
+    }
+    if args.relative_selection {
+        validate_selection(&args.selection)?;
+        if args.selection.iter().any(|range| range.end > 1.0) {
+            return Err("Relative ranges must lie between 0 and 1".into());
+        }
+        for range in &mut args.selection {
+            range.start *= source.duration;
+            range.end *= source.duration;
+        }
+        args.relative_selection = false;
+    }
+
+    let re_encodes = !(args.lossless || args.smart_cut || args.audio_only.is_some());
+    let adjusted = if args.audio_only.is_none() {
+        // Stream copies check the resolved crop against the frame too
+        let resolved = resolve_areas(args, &source)?;
+        if re_encodes {
+            args.source_decoder = match source.video_codec.as_deref() {
+                Some("vp9") if source.alpha => Some("libvpx-vp9".to_string()),
+                Some("vp8") if source.alpha => Some("libvpx".to_string()),
+                _ => None,
+            };
+            fit_crop(args, job_id, &source, resolved)?
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    for overlay in &mut args.text_overlays {
+        overlay.font = Some(resolve_font(overlay.font.as_deref()).await?);
+    }
+
+    if args.remove_selection {
+        validate_selection(&args.selection)?;
+        args.selection = complement_ranges(&args.selection, source.duration);
+        args.remove_selection = false;
+        if args.selection.is_empty() {
+            return Err("Nothing is left after removing the selection".into());
+        }
+    }
+
+    // Last, as the size of the pieces depends on everything above
+    if let Some(limit) = args.split.take() {
+        split_selection(args, &source, limit, job_id).await?;
+    }
+
+    // After the above, so the names show the crop and ranges actually exported
+    args.output_path = render_output_path(args);
+    for index in 0..args.extra_crops.len() {
+        args.extra_crops[index].output_path = render_output_path(&extra_crop_args(args, index));
+    }
+    // The output of a package is its playlist, inside the chosen folder
+    if let Some(streaming) = &args.streaming {
+        if args.output_template.is_some() || !args.extra_crops.is_empty() {
+            return Err("A streaming package is a single output".into());
+        }
+        args.output_path = Path::new(&args.output_path)
+            .join(streaming.format.playlist_name())
+            .to_string_lossy()
+            .into_owned();
+    }
+
+    Ok(adjusted)
+}
+
+// Share of a size limit split exports aim for, as the bitrate is estimated
+const SPLIT_SIZE_HEADROOM: f64 = 0.9;
+
+/// Cuts every range into pieces of at most `max_length` seconds. With
+/// `keyframes`, pieces end on the last keyframe within the limit, or the
+/// first after it when there is none.
+fn split_ranges(
+    selection: &[ClipSelection],
+    max_length: f64,
+    keyframes: &[f64],
+) -> Vec<ClipSelection> {
+    let mut pieces = vec![];
+    for range in selection {
+        let mut start = range.start;
+        while range.end - start > max_length {
+            let limit = start + max_length;
+            let end = if keyframes.is_empty() {
+                limit
+            } else {
+                let within = keyframes
+                    .iter()
+                    .rev()
+                    .find(|&&time| time > start && time <= limit);
+                let after = keyframes.iter().find(|&&time| time > limit);
+                match within.or(after) {
+                    Some(&keyframe) if keyframe < range.end => keyframe,
+                    _ => break,
+                }
+            };
+            pieces.push(ClipSelection { start, end });
+            start = end;
+        }
+        pieces.push(ClipSelection {
+            start,
+            end: range.end,
+        });
+    }
+    pieces
+}
+
+/// Replaces the selection with the pieces of `limit`.
+async fn split_selection(
+    args: &mut ExportArgs,
+    source: &VideoMetadata,
+    limit: SplitLimit,
+    job_id: JobId,
+) -> Result<(), ExportError> {
+    if args.output_template.is_none() {
+        return Err("Splitting needs an output template, e.g. {stem}_{index}.{ext}".into());
+    }
+    let max_output_seconds = match limit {
+        SplitLimit::Duration { seconds } if seconds > 0.0 => seconds,
+        SplitLimit::Size { megabytes } if megabytes > 0.0 => {
+            let plan = plan_export(args, source, &job_temp_dir(job_id)).await?;
+            let estimate = estimate_plan_size(args, &plan)
+                .await
+                .ok_or("Cannot estimate the size of this source")?;
+            let bytes_per_second = estimate.bytes as f64 / plan.clip_duration;
+            megabytes * 1024.0 * 1024.0 * SPLIT_SIZE_HEADROOM / bytes_per_second
+        }
+        _ => return Err("The split limit must be above 0".into()),
+    };
+    // Source seconds making up that much output
+    let max_length = max_output_seconds * playback_speed(args) / args.playback.copies();
+
+    let mut keyframes = vec![];
+    if args.lossless {
+        for range in &args.selection {
+            keyframes.extend(
+                get_keyframes(args.input_path.clone(), Some(range.start), Some(range.end))
+                    .await
+                    .map_err(ExportError::probe)?,
+            );
+        }
+    }
+    args.selection = split_ranges(&args.selection, max_length, &keyframes);
+    Ok(())
+}
+
+/// Paths the export of `args` would write to, with every placeholder filled
+/// in. Several when `output_template` splits the ranges into separate files.
+#[tauri::command]
+pub async fn resolve_output_name(
+    app_handle: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    mut args: ExportArgs,
+) -> Result<Vec<String>, ExportError> {
+    resolve_args(&app_handle, &mut args, jobs.next_id()).await?;
+    output_paths(&args)
+}
+
+/// Expected size of everything the export of `args` writes. `exact` is only
+/// set when every output has a fixed bitrate or is a stream copy.
+#[tauri::command]
+pub async fn estimate_output_size(
+    app_handle: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    mut args: ExportArgs,
+) -> Result<SizeEstimate, ExportError> {
+    let job_id = jobs.next_id();
+    resolve_args(&app_handle, &mut args, job_id).await?;
+
+    let mut total = SizeEstimate {
+        bytes: 0,
+        exact: true,
+    };
+    for (args, plan) in plan_outputs(&args, job_id).await? {
+        let estimate = estimate_plan_size(&args, &plan)
+            .await
+            .ok_or("Cannot estimate the size of this source")?;
+        total.bytes += estimate.bytes;
+        total.exact &= estimate.exact;
+    }
+    Ok(total)
+}
+
+/// Result of `preview_command`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandPreview {
+    pub program: String,
+    /// Arguments of each FFmpeg run, in order
+    pub passes: Vec<Vec<String>>,
+}
+
+/// Builds the FFmpeg command lines `process_video` would run for `args`
+/// without running them or writing any files.
+#[tauri::command]
+pub async fn preview_command(
+    app_handle: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    mut args: ExportArgs,
+) -> Result<CommandPreview, ExportError> {
+    let job_id = jobs.next_id();
+    resolve_args(&app_handle, &mut args, job_id).await?;
+
+    let outputs = plan_outputs(&args, job_id).await?;
+    let passes = if !args.ladder.is_empty() {
+        vec![ladder_pass(&outputs[0].0, &outputs[0].1)?.0.args]
+    } else if args.extra_crops.is_empty() {
+        outputs
+            .iter()
+            .flat_map(|(args, plan)| build_ffmpeg_args(args, plan))
+            .collect()
+    } else {
+        vec![merge_crop_passes(&outputs)?.0.args]
+    };
+
+    Ok(CommandPreview {
+        program: binaries::ffmpeg().to_string(),
+        passes,
+    })
+}
+
+// Previews are rendered with their longest side at most this many pixels
+const PREVIEW_SIZE: u32 = 640;
+// Seconds rendered by `render_preview` unless asked otherwise
+const DEFAULT_PREVIEW_LENGTH: f64 = 3.0;
+
+/// Result of `render_preview`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterPreview {
+    /// The rendered clip, or the filtered frame in frame mode
+    pub path: String,
+    /// The same frame of the source with only the crop, in frame mode
+    pub before_path: Option<String>,
+}
+
+/// Narrows the export to `length` seconds from `time`, small and fast to
+/// encode, keeping every filter that changes the look of the picture.
+fn limit_to_preview(args: &mut ExportArgs, time: f64, length: f64) {
+    args.selection = vec![ClipSelection {
+        start: time.max(0.0),
+        end: time.max(0.0) + length,
+    }];
+    args.relative_selection = false;
+    args.remove_selection = false;
+}
+
+/// Replaces what only matters to the final file once the settings and
+/// preset are applied: outputs, codecs, size and the audio.
+fn preview_output(args: &mut ExportArgs, output_path: String) {
+    // Also replaces the playlist a streaming package would have been named
+    // after
+    args.output_path = output_path;
+    args.streaming = None;
+    args.output_template = None;
+    args.extra_crops.clear();
+    args.ladder.clear();
+    args.on_conflict = OnConflict::Overwrite;
+    args.post_action = None;
+
+    args.encoder = Some(Encoder::Cpu);
+    args.video_codec = Some(VideoCodec::H264);
+    // An editing preset would insist on its own codec
+    args.editing = None;
+    // H.264 has no alpha channel, and 8-bit previews no HDR
+    args.keep_alpha = false;
+    args.preserve_hdr = false;
+    args.audio_codec = None;
+    args.container = Some(Container::Mp4);
+    args.quality = Quality {
+        crf: Some(23),
+        preset: Some(SpeedPreset::Ultrafast),
+    };
+    args.target_size_mb = None;
+    args.video_bitrate = None;
+    args.lossless = false;
+    args.smart_cut = false;
+    args.animated = None;
+    args.audio_only = None;
+    args.scale = Some(ScaleOptions {
+        size: ScaleSize::MaxDimension { size: PREVIEW_SIZE },
+        algorithm: Default::default(),
+    });
+
+    // Fades would fall on the ends of the preview instead of the clip
+    args.fade_in = None;
+    args.fade_out = None;
+    args.mute = true;
+    args.external_audio = None;
+    args.loudness = None;
+    args.audio_gain_db = None;
+    args.audio_tracks.clear();
+}
+
+/// Runs FFmpeg with `args` and waits for it, for runs too short to report
+/// progress.
+async fn run_to_end(args: &[String]) -> Result<(), ExportError> {
+    let output = tokio::process::Command::new(binaries::ffmpeg())
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| ExportError::SpawnFailed {
+            message: e.to_string(),
+        })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(ExportError::from_exit(
+        output.status.code(),
+        stderr.lines().map(String::from).collect(),
+    ))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CropArea {
-    x: u32,
-    y: u32,
-    width: u32,
-    height: u32,
+/// Renders `length` seconds (3 by default) of the export of `args` from
+/// source time `time` with the whole filter chain at reduced resolution, to
+/// check LUTs, denoising or watermarks before a long export. `frame` renders
+/// the frame at `time` instead, along with the source frame to compare it to.
+/// The files are scratch files, removed at the next startup.
+#[tauri::command]
+pub async fn render_preview(
+    app_handle: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    mut args: ExportArgs,
+    time: f64,
+    length: Option<f64>,
+    frame: Option<bool>,
+) -> Result<FilterPreview, ExportError> {
+    let frame = frame.unwrap_or(false);
+    let job_id = jobs.next_id();
+    // Long enough for filters that look at neighbouring frames
+    let length = if frame {
+        0.5
+    } else {
+        length.unwrap_or(DEFAULT_PREVIEW_LENGTH)
+    };
+    limit_to_preview(&mut args, time, length);
+    resolve_args(&app_handle, &mut args, job_id).await?;
+
+    let clip_path = scratch_file(&format!("preview-{}.mp4", job_id)).await?;
+    preview_output(&mut args, clip_path.to_string_lossy().into_owned());
+
+    let run = plan_run(&args, job_id).await?;
+    let mut result = Ok(());
+    for (pass, _) in &run.passes {
+        result = run_to_end(&pass.args).await;
+        if result.is_err() {
+            break;
+        }
+    }
+    remove_temp_dir(&run.temp_dir).await;
+    result?;
+
+    if !frame {
+        return Ok(FilterPreview {
+            path: args.output_path,
+            before_path: None,
+        });
+    }
+
+    let after_path = scratch_file(&format!("preview-{}.jpg", job_id)).await?;
+    let extracted = run_to_end(&[
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        args.output_path.clone(),
+        "-frames:v".into(),
+        "1".into(),
+        "-q:v".into(),
+        "3".into(),
+        "-y".into(),
+        after_path.to_string_lossy().into_owned(),
+    ])
+    .await;
+    let _ = fs::remove_file(&args.output_path).await;
+    extracted?;
+
+    let before_path = get_thumbnail(
+        app_handle,
+        args.input_path.clone(),
+        time,
+        Some(PREVIEW_SIZE),
+        Some(args.crop.clone()),
+        None,
+    )
+    .await?;
+    Ok(FilterPreview {
+        path: after_path.to_string_lossy().into_owned(),
+        before_path: Some(before_path),
+    })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExportArgs {
-    input_path: String,
-    output_path: String,
-    selection: ClipSelection,
-    crop: CropArea,
+/// Spawns FFmpeg for `args` and monitors it in the background.
+///
+/// The returned handle resolves to the final status once every pass has
+/// exited and the matching `ffmpeg-finished`/`ffmpeg-error`/`ffmpeg-cancelled`
+/// event has been emitted. The status is also kept for `get_job_status`.
+pub async fn spawn_export(
+    window: Window,
+    job_id: JobId,
+    args: ExportArgs,
+) -> Result<async_runtime::JoinHandle<JobStatus>, ExportError> {
+    let jobs = window.state::<JobRegistry>();
+    jobs.set_status(job_id, JobStatus::Running);
+
+    let history = PendingEntry::start(job_id, &args);
+    let result = start_export(window.clone(), job_id, args, history.clone()).await;
+    if let Err(e) = &result {
+        jobs.set_status(job_id, JobStatus::Failed);
+        let entry = history.finish(JobStatus::Failed, vec![], Some(e.to_string()));
+        record_history(&window.app_handle(), entry).await;
+        jobs.forget(job_id);
+    }
+    result
 }
-#[tauri::command]
-pub async fn process_video(window: Window, args: ExportArgs) -> Result<String, String> {
+
+async fn start_export(
+    window: Window,
+    job_id: JobId,
+    mut args: ExportArgs,
+    history: PendingEntry,
+) -> Result<async_runtime::JoinHandle<JobStatus>, ExportError> {
     println!("Processing video: {:?}", args);
+    if let Some(action) = &args.post_action {
+        action.validate()?;
+    }
 
-    // 1. Construct FFmpeg arguments
+    // 1. Fix up the crop and selection
+    if let Some(adjusted) = resolve_args(&window.app_handle(), &mut args, job_id).await? {
+        println!("Adjusted crop: {:?}", adjusted.reasons);
+        emit_event(&window, "crop-adjusted", adjusted);
+    }
 
-    // FIX: Store generated strings in named variables
-    let crop_filter = format!(
-        "crop={}:{}:{}:{}",
-        args.crop.width, args.crop.height, args.crop.x, args.crop.y
-    );
+    // 2. Construct FFmpeg arguments
+    let run = plan_run(&args, job_id).await?;
+    if let Some(warning) = run.disk_space_low {
+        println!("Output may not fit into {}", warning.path);
+        emit_event(&window, "disk-space-low", warning);
+    }
+    if let Some(warning) = run.spherical_lost {
+        println!(
+            "The {} projection does not survive the crop",
+            warning.projection
+        );
+        emit_event(&window, "spherical-lost", warning);
+    }
+    if let Some(warning) = run.reverse_memory_high {
+        println!(
+            "Reversing {:.1}s may take {} MB of memory",
+            warning.duration,
+            warning.estimated_bytes / 1_000_000
+        );
+        emit_event(&window, "reverse-memory-high", warning);
+    }
+    let mut passes = run.passes;
+    let post_action = args.post_action.clone();
+    let keeps_modified_time = args.metadata.keep_modified_time;
+    let input_path = args.input_path.clone();
 
-    // FIX: Store these temporary strings
-    let start_time_str = args.selection.start.to_string();
-    let end_time_str = args.selection.end.to_string();
-
-    let ffmpeg_args = vec![
-        "-i",
-        &args.input_path,
-        "-ss",
-        &start_time_str, // <-- Use the stored variable
-        "-to",
-        &end_time_str, // <-- Use the stored variable
-        "-filter:v",
-        &crop_filter,
-        "-c:a",
-        "copy",
-        "-y", // Overwrite output
-        &args.output_path,
-    ];
+    // 3. Spawn the first pass right away so spawn errors reach the caller
+    let (first_pass, first_output) = &passes[0];
+    let jobs = window.state::<JobRegistry>();
+    jobs.register(job_id);
+    let mut rx = match spawn_pass(&window, job_id, &first_pass.args, first_output) {
+        Ok(rx) => rx,
+        Err(e) => {
+            jobs.remove(job_id);
+            remove_temp_dir(&run.temp_dir).await;
+            return Err(e);
+        }
+    };
+
+    // 4. Monitor Progress
+    let handle = async_runtime::spawn(async move {
+        let mut index = 0;
+        let mut offset = 0.0;
+        // The pass `cancel_export` killed, if it did not cancel between passes
+        let mut killed = None;
 
-    // 2. Spawn Command
-    let command = Command::new("ffmpeg").args(ffmpeg_args);
+        let outcome = loop {
+            let (pass, output_paths) = &passes[index];
+            let measures_loudness = pass.measures_loudness;
+            let mut progress = ProgressParser::new(job_id, pass.duration)
+                .with_span(offset, pass.weight)
+                .with_ranges(run.range_ends.clone(), pass.range)
+                .with_pass(index, passes.len())
+                .with_slow(pass.slow)
+                .with_lookahead(pass.lookahead_frames)
+                .with_outputs(output_paths.clone());
+            let result = monitor_pass(&window, job_id, &mut rx, &mut progress).await;
+            index += 1;
+            offset += pass.weight;
+
+            // The finished pass is unregistered here; a job that does not go
+            // on was killed by `cancel_export` or `kill_all`
+            if !window.state::<JobRegistry>().end_pass(job_id) {
+                killed = Some(index - 1);
+                break Err(ExportError::Cancelled);
+            }
+            // Only start the next pass after a clean exit
+            if result.exit_code != Some(0) || result.command_error.is_some() {
+                break Err(result.into_error());
+            }
+            if index == passes.len() {
+                break Ok(());
+            }
+            if measures_loudness {
+                fill_in_loudness(&window, job_id, &mut passes[index..], &result);
+            }
 
-    let (mut rx, _) = command.spawn().map_err(|e| e.to_string())?;
+            // Cancelled between passes
+            if !window.state::<JobRegistry>().is_active(job_id) {
+                break Err(ExportError::Cancelled);
+            }
+            let (next_pass, next_output) = &passes[index];
+            match spawn_pass(&window, job_id, &next_pass.args, next_output) {
+                Ok(next) => rx = next,
+                Err(e) => break Err(e),
+            }
+        };
 
-    // 3. Monitor Progress (simplified)
-    async_runtime::spawn(async move {
-        let mut exit_code: Option<i32> = None;
-        let mut command_error: Option<String> = None;
-        let mut last_progress_line: Option<String> = None; // State to track the last emitted line
+        window.state::<JobRegistry>().remove(job_id);
+        remove_temp_dir(&run.temp_dir).await;
 
-        while let Some(event) = rx.recv().await {
-            match event {
-                tauri::api::process::CommandEvent::Stdout(_line) => {
-                    // Ignore Stdout for progress to prevent duplication,
-                    // as progress is usually on Stderr.
-                    // You can log it if needed for debugging, but don't emit to frontend.
-                }
-                tauri::api::process::CommandEvent::Stderr(line) => {
-                    // FFmpeg usually outputs stats to Stderr
-                    if last_progress_line.as_ref() != Some(&line) {
-                        // 1. Emit the progress line
-                        window.emit("ffmpeg-progress", line.clone()).unwrap();
-
-                        // 2. Update the tracking state
-                        last_progress_line = Some(line);
+        // --- Post-Execution Event Handling ---
+        let mut finished_paths = vec![];
+        let mut failure = None;
+        let status = match outcome {
+            Ok(()) => {
+                // 4a. Emit Finished once every pass exited with 0
+                let mut output_paths: Vec<String> = vec![];
+                for output in passes.iter().flat_map(|(_, outputs)| outputs) {
+                    if !output_paths.contains(output) {
+                        output_paths.push(output.clone());
                     }
                 }
-                tauri::api::process::CommandEvent::Terminated(payload) => {
-                    // The FFmpeg process has exited.
-                    exit_code = payload.code;
-                    println!("FFmpeg Terminated with code: {:?}", payload.code);
-                    // Break the loop to handle final status
-                    break;
+                emit_event(
+                    &window,
+                    "ffmpeg-finished",
+                    JobFinished {
+                        job_id,
+                        message: "Successfully processed video".to_string(),
+                        output_paths: output_paths.clone(),
+                    },
+                );
+                if keeps_modified_time {
+                    keep_modified_time(&input_path, &output_paths);
                 }
-                tauri::api::process::CommandEvent::Error(err) => {
-                    // An error occurred spawning or running the command itself
-                    command_error = Some(err.clone());
-                    println!("FFmpeg Command Error: {}", err);
-                    // Break the loop to handle final status
-                    break;
+                if let Some(action) = &post_action {
+                    run_post_action(&window, job_id, action, &output_paths).await;
                 }
-                _ => {}
+                finished_paths = output_paths;
+                JobStatus::Finished
             }
-        }
-
-        // --- Post-Execution Event Handling ---
-
-        if let Some(err) = command_error {
-            // 4a. Emit Error if the command failed to execute
-            window
-                .emit("ffmpeg-error", format!("Tauri Command Error: {}", err))
-                .unwrap();
-        } else if let Some(code) = exit_code {
-            if code == 0 {
-                // 4b. Emit Finished if the exit code is 0 (Success)
-                window
-                    .emit("ffmpeg-finished", "Successfully processed video")
-                    .unwrap();
-            } else {
-                // 4c. Emit Error if the exit code is non-zero (Failure)
-                window
-                    .emit(
-                        "ffmpeg-error",
-                        format!("FFmpeg exited with error code: {}", code),
-                    )
-                    .unwrap();
+            Err(ExportError::Cancelled) => {
+                // Drop whatever FFmpeg managed to write before being killed.
+                // Files finished by earlier passes are kept.
+                for output_path in killed.iter().flat_map(|&killed| &passes[killed].1) {
+                    if let Err(e) = fs::remove_file(output_path).await {
+                        println!("Could not remove partial output {}: {}", output_path, e);
+                    }
+                }
+                emit_event(
+                    &window,
+                    "ffmpeg-cancelled",
+                    JobMessage::new(job_id, "Export cancelled"),
+                );
+                JobStatus::Cancelled
             }
-        } else {
-            // Fallback for an unexpected loop exit
-            window
-                .emit(
-                    "ffmpeg-error",
-                    "FFmpeg process finished without explicit status code.",
-                )
-                .unwrap();
+            Err(error) => {
+                // 4b. Emit the error of the pass that failed
+                println!("Export {} failed: {}", job_id, error);
+                failure = Some(error.to_string());
+                emit_event(&window, "ffmpeg-error", JobError::new(job_id, error));
+                JobStatus::Failed
+            }
+        };
+
+        let jobs = window.state::<JobRegistry>();
+        if let Err(e) = jobs.persist_log(&window.app_handle(), job_id).await {
+            println!("Could not save the log of {}: {}", job_id, e);
+        }
+        jobs.set_status(job_id, status);
+        job_ended(&window, job_id, status);
+        let entry = history.finish(status, finished_paths, failure);
+        record_history(&window.app_handle(), entry).await;
+        // `get_job_status` finds it in the history from now on
+        jobs.forget(job_id);
+        if jobs.is_quitting() && !jobs.has_running_jobs() {
+            window.app_handle().exit(0);
         }
+        shutdown_if_idle(&window);
+        status
     });
 
-    Ok("Processing started".to_string())
+    Ok(handle)
 }
 
 #[tauri::command]
-pub async fn generate_video_proxy(
-    input_path: String,
-    app_handle: AppHandle,
-) -> Result<String, String> {
-    let input = PathBuf::from(&input_path);
-
-    // Validate input exists first
-    if !input.exists() {
-        return Err(format!("Input file not found: {}", input_path));
-    }
-
-    let cache_dir = app_handle
-        .path_resolver()
-        .app_local_data_dir()
-        .ok_or("Failed to get app directory")?
-        .join("video_previews");
-
-    fs::create_dir_all(&cache_dir)
-        .await
-        .map_err(|e| format!("Cannot create cache dir: {}", e))?;
-
-    let metadata = fs::metadata(&input)
-        .await
-        .map_err(|e| format!("Cannot read file metadata: {}", e))?;
-    let modified = metadata.modified().map_err(|e| e.to_string())?;
-    let modified_secs = modified
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    let input_hash = format!("{:x}", md5::compute(&input_path));
-    let output_path = cache_dir.join(format!("{}_{}.mp4", input_hash, modified_secs));
-
-    if output_path.exists() {
-        println!("Using existing proxy: {:?}", output_path);
-        return Ok(output_path.to_str().unwrap().to_string());
-    }
-
-    println!("Starting FFmpeg transcoding...");
-    println!("Input: {}", input_path);
-    println!("Output: {:?}", output_path);
-
-    let args = &[
-        "-hwaccel",
-        "videotoolbox",
-        "-i",
-        &input_path,
-        "-c:v",
-        "h264_videotoolbox", // Hardware encoder
-        "-b:v",
-        "2M", // Bitrate instead of CRF for hardware
-        "-vf",
-        "scale=-2:720",
-        "-c:a",
-        "aac",
-        "-y",
-        &output_path.to_str().unwrap(),
-    ];
-    // Run FFmpeg and capture both stdout and stderr
-    // .args(&[
-    //     "-i",
-    //     &input_path,
-    //     "-c:v",
-    //     "libx264",
-    //     "-preset",
-    //     "ultrafast",
-    //     "-crf",
-    //     "28",
-    //     "-vf",
-    //     "scale=-2:720",
-    //     "-c:a",
-    //     "aac",
-    //     "-b:a",
-    //     "128k",
-    //     "-movflags",
-    //     "+faststart",
-    //     "-pix_fmt",
-    //     "yuv420p",
-    //     "-y",
-    //     output_path.to_str().unwrap(),
-    // ])
-    let output = tokio::process::Command::new("ffmpeg")
-        .args(args)
-        .output()
-        .await
-        .map_err(|e| {
-            format!(
-                "Failed to execute FFmpeg: {}. Is FFmpeg installed and in PATH?",
-                e
-            )
-        })?;
+pub fn cancel_export(jobs: State<'_, JobRegistry>, job_id: JobId) -> Result<(), ExportError> {
+    let pass = jobs
+        .cancel(job_id)
+        .ok_or(ExportError::JobNotFound { job_id })?;
+    // The next pass is not started
+    let Some(job) = pass else {
+        println!("Cancelling export {} between passes", job_id);
+        return Ok(());
+    };
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        println!("FFmpeg stderr: {}", stderr);
-        println!("FFmpeg stdout: {}", stdout);
-
-        return Err(format!(
-            "FFmpeg failed (code: {:?})\nStderr: {}\nStdout: {}",
-            output.status.code(),
-            stderr,
-            stdout
-        ));
-    }
-
-    // Verify output was created
-    if !output_path.exists() {
-        return Err("FFmpeg reported success but output file not found".to_string());
-    }
+    println!(
+        "Cancelling export {} ({})",
+        job_id,
+        job.output_paths.join(", ")
+    );
 
-    Ok(output_path.to_str().unwrap().to_string())
+    // The monitor task picks up the termination and cleans up the output file
+    job.child
+        .kill()
+        .map_err(|e| ExportError::io("Cannot stop FFmpeg", e))
 }
 
 #[tauri::command]
 pub async fn get_video_codec(input_path: String) -> Result<String, String> {
-    let output = tokio::process::Command::new("ffprobe")
-        .args(&[
+    let output = tokio::process::Command::new(binaries::ffprobe())
+        .args([
             "-v",
             "error",
             "-select_streams",
@@ -277,3 +4673,1175 @@ pub async fn get_video_codec(input_path: String) -> Result<String, String> {
 
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Export of 1.5s-4s of `in.mp4` with a 640x360 crop, plus `extra` fields.
+    fn export_args(extra: serde_json::Value) -> ExportArgs {
+        let mut args = json!({
+            "input_path": "in.mp4",
+            "output_path": "out.mp4",
+            "selection": [{ "start": 1.5, "end": 4.0 }],
+            "crop": { "x": 10, "y": 20, "width": 640, "height": 360 },
+        });
+        if let (Some(args), Some(extra)) = (args.as_object_mut(), extra.as_object()) {
+            args.extend(extra.clone());
+        }
+        serde_json::from_value(args).unwrap()
+    }
+
+    fn video_plan(args: &ExportArgs, video: impl FnOnce(&mut VideoPlan)) -> ExportPlan {
+        let format = resolve_output_format(
+            args.container,
+            args.video_codec,
+            args.audio_codec,
+            &args.output_path,
+        )
+        .unwrap();
+        let mut plan = VideoPlan {
+            format,
+            encoder: Encoder::Cpu,
+            video_bitrate: None,
+            audio_bitrate: args.audio_bitrate,
+            two_pass: false,
+            has_audio: false,
+            segmented: None,
+            chapters: None,
+            telemetry_streams: vec![],
+            spherical: None,
+            streaming: None,
+            editing: None,
+            alpha: false,
+            hdr: None,
+        };
+        video(&mut plan);
+
+        ExportPlan {
+            clip_duration: 2.5,
+            range_ends: vec![2.5],
+            temp_dir: plan.two_pass.then(|| PathBuf::from("/tmp/job")),
+            subtitles: None,
+            tonemap: None,
+            deinterlace: None,
+            square_pixels: None,
+            chroma_key: None,
+            color_range: None,
+            source: VideoMetadata::default(),
+            output: PlannedOutput::Video(plan),
+        }
+    }
+
+    fn contains(args: &[String], expected: &[&str]) -> bool {
+        args.windows(expected.len())
+            .any(|window| window == expected)
+    }
+
+    #[test]
+    fn single_range_reencode() {
+        let args = export_args(json!({}));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+
+        assert_eq!(
+            passes,
+            vec![vec![
+                "-progress",
+                "pipe:1",
+                "-nostats",
+                "-i",
+                "in.mp4",
+                "-ss",
+                "1.5",
+                "-to",
+                "4",
+                "-filter:v",
+                "crop=640:360:10:20",
+                "-c:v",
+                "libx264",
+                "-c:a",
+                "copy",
+                "-f",
+                "mp4",
+                "-y",
+                "out.mp4",
+            ]]
+        );
+    }
+
+    #[test]
+    fn lossless_seeks_on_the_input() {
+        let args = export_args(json!({ "lossless": true }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+
+        assert_eq!(passes.len(), 1);
+        assert!(contains(
+            &passes[0],
+            &["-ss", "1.5", "-to", "4", "-i", "in.mp4", "-c", "copy"]
+        ));
+        assert!(!passes[0].iter().any(|arg| arg == "-filter:v"));
+    }
+
+    #[test]
+    fn target_size_runs_two_passes() {
+        let args = export_args(json!({ "target_size_mb": 10.0 }));
+        let plan = video_plan(&args, |video| {
+            video.video_bitrate = Some(1000);
+            video.two_pass = true;
+        });
+        let passes = build_ffmpeg_args(&args, &plan);
+
+        assert_eq!(passes.len(), 2);
+        assert!(contains(&passes[0], &["-b:v", "1000k", "-pass", "1"]));
+        assert!(passes[0].ends_with(&["-an", "-f", "null", "-y", "-"].map(String::from)));
+        assert!(contains(&passes[1], &["-pass", "2", "-passlogfile"]));
+        assert_eq!(passes[1].last().map(String::as_str), Some("out.mp4"));
+    }
+
+    #[test]
+    fn watermark_is_laid_over_the_cropped_picture() {
+        let args = export_args(json!({
+            "watermark": {
+                "image_path": "logo.png",
+                "position": "anchor",
+                "anchor": "bottom_right",
+                "scale": 0.25,
+                "opacity": 0.5,
+                "start": 1.0,
+            },
+        }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+
+        assert!(contains(&passes[0], &["-i", "in.mp4", "-i", "logo.png"]));
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter_complex",
+                "[0:v]crop=640:360:10:20[main];\
+                 [1:v]format=rgba,scale=160:-2,colorchannelmixer=aa=0.5[logo];\
+                 [main][logo]overlay=x=main_w-overlay_w-16:y=main_h-overlay_h-16:enable='gte(t,2.5)'[picture]",
+                "-map",
+                "[picture]",
+                "-map",
+                "0:a?",
+            ]
+        ));
+    }
+
+    #[test]
+    fn text_overlays_escape_the_text() {
+        let args = export_args(json!({
+            "text_overlays": [{
+                "text": "It's 50% off: a, b; [c]",
+                "font": "C:\\Fonts\\arial.ttf",
+                "position": "anchor",
+                "anchor": "bottom",
+                "end": 2.0,
+            }],
+        }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                concat!(
+                    r"crop=640:360:10:20,",
+                    r"drawtext=fontfile=C\\:\\\\Fonts\\\\arial.ttf:expansion=none:",
+                    r"text=It\\\'s 50% off\\: a\, b\; \[c\]:fontsize=48:fontcolor=white:",
+                    r"x=(w-text_w)/2:y=h-text_h-16:enable='lte(t,3.5)'",
+                ),
+            ]
+        ));
+    }
+
+    #[test]
+    fn picked_audio_tracks_are_mapped_or_mixed() {
+        let args = export_args(json!({ "audio_tracks": [1] }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(&passes[0], &["-map", "0:v:0", "-map", "0:a:1"]));
+
+        let args = export_args(json!({
+            "audio_tracks": [0, 2],
+            "selection": [{ "start": 0.0, "end": 1.0 }, { "start": 5.0, "end": 7.0 }],
+        }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let graph = &build_ffmpeg_args(&args, &plan)[0];
+        assert!(graph.iter().any(|arg| arg.starts_with(
+            "[0:a:0][0:a:2]amix=inputs=2:duration=longest:normalize=0,asplit=2[mix0][mix1];"
+        )));
+        assert!(graph
+            .iter()
+            .any(|arg| arg.contains("[mix1]atrim=start=5:end=7")));
+    }
+
+    #[test]
+    fn two_pass_loudness_is_measured_first() {
+        let args = export_args(json!({
+            "loudness": { "target_lufs": -14.0, "two_pass": true },
+        }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let passes = build_passes(&args, &plan);
+
+        assert!(passes[0].measures_loudness);
+        assert!(contains(
+            &passes[0].args,
+            &[
+                "-filter_complex",
+                "[0:a]loudnorm=I=-14:TP=-1:LRA=11:print_format=json[audio]",
+                "-map",
+                "[audio]",
+                "-vn",
+                "-f",
+                "null",
+            ]
+        ));
+        assert!(passes[1].args.contains(
+            &"[0:a]loudnorm=I=-14:TP=-1:LRA=11$measured_loudness,aresample=48000[audio]".into()
+        ));
+        let weights: f64 = passes.iter().map(|pass| pass.weight).sum();
+        assert!((weights - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stabilization_detects_the_motion_first() {
+        let args = export_args(json!({ "stabilize": { "strength": "strong" } }));
+        let mut plan = video_plan(&args, |_| {});
+        plan.temp_dir = Some(PathBuf::from("tmp"));
+        let passes = build_passes(&args, &plan);
+
+        assert!(contains(
+            &passes[0].args,
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,vidstabdetect=shakiness=8:accuracy=15:result=tmp/transforms.trf",
+                "-an",
+                "-f",
+                "null",
+            ]
+        ));
+        assert!(contains(
+            &passes[1].args,
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,vidstabtransform=input=tmp/transforms.trf:smoothing=40:optzoom=1,\
+                 unsharp=5:5:0.8:3:3:0.4",
+            ]
+        ));
+        let weights: f64 = passes.iter().map(|pass| pass.weight).sum();
+        assert!((weights - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn masks_are_hidden_before_the_crop() {
+        let args = export_args(json!({
+            "masks": [
+                { "x": 100, "y": 50, "width": 40, "height": 20 },
+                {
+                    "x": 0, "y": 0, "width": 64, "height": 64, "effect": "pixelate", "strength": 8,
+                    "keyframes": [{ "time": 2.0, "x": 10, "y": 10 }, { "time": 3.0, "x": 30, "y": 10 }],
+                },
+            ],
+        }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        let moving_x = "'if(lt((t),2),10,if(lt((t),3),10+(30-10)*((t)-2)/1,30))'";
+        let moving_y = "'if(lt((t),2),10,if(lt((t),3),10+(10-10)*((t)-2)/1,10))'";
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                &format!(
+                    "split[mask_base0][mask_in0];[mask_in0]crop=40:20:100:50,boxblur=5:2[mask_out0];\
+                     [mask_base0][mask_out0]overlay=100:50,\
+                     split[mask_base1][mask_in1];\
+                     [mask_in1]crop=64:64:{x}:{y},pixelize=width=8:height=8[mask_out1];\
+                     [mask_base1][mask_out1]overlay={x}:{y},crop=640:360:10:20",
+                    x = moving_x,
+                    y = moving_y
+                ),
+            ]
+        ));
+    }
+
+    #[test]
+    fn chroma_key_lays_the_clip_over_the_background() {
+        let args = export_args(json!({ "color": { "saturation": 1.1 } }));
+        let key: ChromaKey = serde_json::from_value(json!({
+            "color": "0x00ff00",
+            "similarity": 0.2,
+            "background": { "type": "image", "path": "beach.png" },
+        }))
+        .unwrap();
+        let mut plan = video_plan(&args, |_| {});
+        plan.chroma_key = Some(key.filter(640, 360, 30.0));
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,chromakey=color=0x00ff00:similarity=0.2:blend=0.05[chroma_fg];\
+                 movie=filename=beach.png,loop=loop=-1:size=1,setpts=N/(30*TB),\
+                 scale=640:360:force_original_aspect_ratio=increase,crop=640:360,setsar=1[chroma_bg];\
+                 [chroma_bg][chroma_fg]overlay=shortest=1,eq=saturation=1.1",
+            ]
+        ));
+    }
+
+    #[test]
+    fn fades_follow_the_trimmed_clip() {
+        let args = export_args(json!({ "fade_in": 0.5, "fade_out": 1.0 }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,fade=t=in:st=1.5:d=0.5,fade=t=out:st=3:d=1",
+                "-filter_complex",
+                "[0:a]afade=t=in:st=1.5:d=0.5,afade=t=out:st=3:d=1[audio]",
+            ]
+        ));
+
+        // Joined ranges start at 0 and fade after the concat
+        let args = export_args(json!({
+            "fade_out": 1.0,
+            "selection": [{ "start": 0.0, "end": 1.0 }, { "start": 5.0, "end": 6.5 }],
+        }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let graph = build_ffmpeg_args(&args, &plan)[0].clone();
+        assert!(graph.iter().any(|arg| arg.ends_with(
+            "concat=n=2:v=1:a=1[joined_v][concat_a];\
+             [concat_a]afade=t=out:st=1.5:d=1[joined_a];\
+             [joined_v]crop=640:360:10:20,fade=t=out:st=1.5:d=1[picture]"
+        )));
+    }
+
+    #[test]
+    fn external_audio_is_mixed_in_from_the_clip_start() {
+        let args = export_args(json!({
+            "external_audio": { "path": "music.mp3", "mode": "mix", "gain_db": -12.0, "duck": true },
+        }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(&passes[0], &["-i", "in.mp4", "-i", "music.mp3"]));
+        assert!(passes[0].contains(
+            &"[0:a]asplit=2[ext_orig][ext_key];\
+              [1:a]atrim=duration=2.5,adelay=delays=1500:all=1,volume=-12dB[ext_bed];\
+              [ext_bed][ext_key]sidechaincompress=threshold=0.03:ratio=8:attack=20:release=500[ext_audio];\
+              [ext_orig][ext_audio]amix=inputs=2:duration=first:normalize=0[audio]"
+                .into()
+        ));
+
+        // Replaced audio is not joined, and the watermark moves to input 2
+        let args = export_args(json!({
+            "external_audio": { "path": "voice.wav", "mode": "replace" },
+            "watermark": { "image_path": "logo.png", "position": "absolute", "x": 0, "y": 0 },
+            "selection": [{ "start": 0.0, "end": 1.0 }, { "start": 5.0, "end": 6.5 }],
+        }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let graph = build_ffmpeg_args(&args, &plan)[0].join(" ");
+        assert!(graph
+            .contains("concat=n=2:v=1:a=0[joined_v];[1:a]atrim=duration=2.5[joined_a];[joined_v]"));
+        assert!(graph.contains("[2:v]"));
+    }
+
+    #[test]
+    fn speed_changes_are_applied_per_range() {
+        let args = export_args(json!({ "speed": 0.2 }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let graph = build_ffmpeg_args(&args, &plan)[0].join(" ");
+        assert!(graph.contains(
+            "[0:v]trim=start=1.5:end=4,setpts=(PTS-STARTPTS)/0.2[v0];\
+             [0:a]atrim=start=1.5:end=4,asetpts=PTS-STARTPTS,atempo=0.5,atempo=0.5,atempo=0.8[a0];\
+             [v0][a0]concat=n=1:v=1:a=1[joined_v][joined_a]"
+        ));
+        assert!(!graph.contains("-ss"));
+
+        assert_eq!(atempo_filters(4.0), ["atempo=2", "atempo=2"]);
+    }
+
+    #[test]
+    fn boomerang_plays_the_clip_back_reversed() {
+        let args = export_args(json!({ "playback": "boomerang", "audio_gain_db": 2.0 }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let graph = build_ffmpeg_args(&args, &plan)[0].join(" ");
+        assert!(graph.contains(
+            "[v0][a0]concat=n=1:v=1:a=1[concat_v][concat_a];\
+             [concat_v]split[forward_v][backward_v];[backward_v]reverse[reversed_v];\
+             [concat_a]asplit[forward_a][backward_a];[backward_a]areverse[reversed_a];\
+             [forward_v][forward_a][reversed_v][reversed_a]concat=n=2:v=1:a=1[joined_v][played_a];\
+             [played_a]volume=2dB[joined_a]"
+        ));
+
+        let args = export_args(json!({ "playback": "reverse" }));
+        let graph = build_ffmpeg_args(&args, &video_plan(&args, |_| {}))[0].join(" ");
+        assert!(graph.contains("concat=n=1:v=1:a=0[concat_v];[concat_v]reverse[joined_v]"));
+    }
+
+    #[test]
+    fn interpolated_passes_are_reported_as_slow() {
+        let args = export_args(json!({
+            "speed": 0.5,
+            "interpolation": { "fps": 120.0, "mode": "mci", "motion_estimation": "bilat", "smooth": true },
+        }));
+        let passes = build_passes(&args, &video_plan(&args, |_| {}));
+
+        assert!(passes.iter().all(|pass| pass.slow));
+        assert!(passes[0].args.join(" ").contains(
+            "[joined_v]crop=640:360:10:20,minterpolate=fps=120:mi_mode=mci:me_mode=bilat:vsbmc=1[picture]"
+        ));
+    }
+
+    #[test]
+    fn colors_are_graded_after_the_crop() {
+        let args = export_args(json!({
+            "lut_path": "log.cube",
+            "color": { "contrast": 1.2, "gamma": 0.9, "temperature": 5000.0 },
+            "rotate": 90,
+        }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,lut3d=file=log.cube:interp=tetrahedral,eq=contrast=1.2:gamma=0.9,colortemperature=temperature=5000,transpose=clock"
+            ]
+        ));
+    }
+
+    #[test]
+    fn hdr_is_tonemapped_right_after_the_crop_and_tagged_sdr() {
+        let args = export_args(json!({ "tonemap": "mobius" }));
+        let mut plan = video_plan(&args, |_| {});
+        plan.tonemap = Some(args.tonemap);
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+                 tonemap=tonemap=mobius:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+            ]
+        ));
+        assert!(contains(&passes[0], &SDR_COLOR_ARGS));
+    }
+
+    #[test]
+    fn deinterlacing_and_denoising_surround_the_crop() {
+        let args = export_args(json!({ "deinterlace": "yadif" }));
+        let mut plan = video_plan(&args, |_| {});
+        plan.deinterlace = Some("yadif");
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(
+            &passes[0],
+            &["-filter:v", "yadif,crop=640:360:10:20"]
+        ));
+
+        let args = export_args(json!({ "denoise": { "filter": "nlmeans", "strength": "light" } }));
+        let passes = build_passes(&args, &video_plan(&args, |_| {}));
+        assert!(passes[0].slow);
+        assert!(contains(
+            &passes[0].args,
+            &["-filter:v", "crop=640:360:10:20,nlmeans=s=2"]
+        ));
+    }
+
+    #[test]
+    fn ntsc_frame_rates_are_written_as_fractions() {
+        let args = export_args(json!({ "fps": 23.976 }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        assert!(contains(
+            &passes[0],
+            &["-filter:v", "crop=640:360:10:20,fps=24000/1001"]
+        ));
+
+        assert_eq!(frame_rate_value(29.97), "30000/1001");
+        assert_eq!(frame_rate_value(25.0), "25");
+        assert_eq!(frame_rate_value(12.5), "12.5");
+    }
+
+    #[test]
+    fn gain_filters_the_audio_and_mute_drops_it() {
+        let args = export_args(json!({ "audio_gain_db": -3.5, "audio_codec": "aac" }));
+        let plan = video_plan(&args, |video| video.has_audio = true);
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter_complex",
+                "[0:a]volume=-3.5dB[audio]",
+                "-map",
+                "0:v:0",
+                "-map",
+                "[audio]"
+            ]
+        ));
+
+        let args = export_args(json!({ "mute": true }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        assert!(contains(&passes[0], &["-an", "-f", "mp4"]));
+        assert!(!passes[0].iter().any(|arg| arg == "-c:a"));
+    }
+
+    #[test]
+    fn hevc_two_pass_uses_x265_stats() {
+        let args = export_args(json!({ "video_bitrate": 1000, "video_codec": "hevc" }));
+        let plan = video_plan(&args, |video| {
+            video.video_bitrate = Some(1000);
+            video.two_pass = true;
+        });
+        let passes = build_ffmpeg_args(&args, &plan);
+
+        assert!(contains(
+            &passes[0],
+            &["-x265-params", "pass=1:stats='/tmp/job/passlog'"]
+        ));
+        assert!(!passes[1].iter().any(|arg| arg == "-pass"));
+    }
+
+    #[test]
+    fn hardware_bitrate_is_capped() {
+        let args = export_args(json!({}));
+        let plan = video_plan(&args, |video| {
+            video.encoder = Encoder::Nvenc;
+            video.video_bitrate = Some(1000);
+        });
+        let passes = build_ffmpeg_args(&args, &plan);
+
+        assert_eq!(passes.len(), 1);
+        assert!(contains(&passes[0], &["-hwaccel", "cuda", "-i", "in.mp4"]));
+        assert!(contains(&passes[0], &["-c:v", "h264_nvenc"]));
+        assert!(contains(
+            &passes[0],
+            &["-maxrate", "1000k", "-bufsize", "2000k"]
+        ));
+    }
+
+    #[test]
+    fn multiple_ranges_are_joined() {
+        let args = export_args(json!({
+            "selection": [{ "start": 0.0, "end": 1.0 }, { "start": 5.0, "end": 7.0 }],
+        }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |video| video.has_audio = true));
+
+        assert_eq!(passes.len(), 1);
+        let graph = passes[0]
+            .iter()
+            .skip_while(|arg| *arg != "-filter_complex")
+            .nth(1)
+            .unwrap();
+        assert!(graph.contains("concat=n=2"));
+        assert!(contains(
+            &passes[0],
+            &["-map", "[picture]", "-map", "[joined_a]"]
+        ));
+        assert!(!passes[0].iter().any(|arg| arg == "-ss"));
+    }
+
+    #[test]
+    fn gif_generates_a_palette_first() {
+        let args = export_args(json!({ "animated": { "format": "gif" } }));
+        let plan = ExportPlan {
+            clip_duration: 2.5,
+            range_ends: vec![2.5],
+            temp_dir: Some(PathBuf::from("/tmp/job")),
+            subtitles: None,
+            tonemap: None,
+            deinterlace: None,
+            square_pixels: None,
+            chroma_key: None,
+            color_range: None,
+            source: VideoMetadata::default(),
+            output: PlannedOutput::Animated(args.animated.clone().unwrap()),
+        };
+        let passes = build_ffmpeg_args(&args, &plan);
+
+        assert_eq!(passes.len(), 2);
+        assert!(passes[0]
+            .iter()
+            .any(|arg| arg.ends_with("palettegen=stats_mode=diff")));
+        assert_eq!(
+            passes[0].last().map(String::as_str),
+            Some("/tmp/job/palette.png")
+        );
+        assert!(contains(
+            &passes[1],
+            &["-i", "/tmp/job/palette.png", "-i", "in.mp4"]
+        ));
+        assert!(contains(&passes[1], &["-loop", "0", "-f", "gif"]));
+    }
+
+    #[test]
+    fn audio_only_drops_the_video() {
+        let args = export_args(json!({ "audio_only": "mp3", "audio_bitrate": 192 }));
+        let plan = ExportPlan {
+            clip_duration: 2.5,
+            range_ends: vec![2.5],
+            temp_dir: None,
+            subtitles: None,
+            tonemap: None,
+            deinterlace: None,
+            square_pixels: None,
+            chroma_key: None,
+            color_range: None,
+            source: VideoMetadata::default(),
+            output: PlannedOutput::Audio(AudioFormat::Mp3),
+        };
+        let passes = build_ffmpeg_args(&args, &plan);
+
+        assert_eq!(passes.len(), 1);
+        assert!(contains(&passes[0], &["-map", "0:a:0", "-vn"]));
+        assert!(contains(&passes[0], &["-b:a", "192k"]));
+        assert!(!passes[0].iter().any(|arg| arg.starts_with("crop=")));
+    }
+
+    #[test]
+    fn extra_args_go_before_the_output() {
+        let args = export_args(json!({ "extra_args": ["-tune", "film"] }));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+
+        assert!(
+            passes[0].ends_with(&["-tune", "film", "-f", "mp4", "-y", "out.mp4"].map(String::from))
+        );
+    }
+
+    #[test]
+    fn extra_args_cannot_clobber_the_output() {
+        for extra in [
+            json!(["-y"]),
+            json!(["-i", "other.mp4"]),
+            json!(["out.mp4"]),
+        ] {
+            let args = export_args(json!({ "extra_args": extra }));
+            assert!(validate_extra_args(&args).is_err(), "{:?}", args.extra_args);
+        }
+        let args = export_args(json!({ "extra_args": ["-x264-params", "keyint=60"] }));
+        assert!(validate_extra_args(&args).is_ok());
+    }
+
+    #[test]
+    fn output_names_fill_placeholders() {
+        let args = export_args(json!({
+            "input_path": "/videos/holiday.mov",
+            "output_path": "/out/{stem}_crop_{width}x{height}_{start}-{end}.{ext}",
+            "container": "mkv",
+        }));
+        assert_eq!(
+            render_output_path(&args),
+            "/out/holiday_crop_640x360_1.5-4.mkv"
+        );
+
+        let args = export_args(json!({
+            "output_path": "/out/clips.mp4",
+            "output_template": "{stem}_{index}.{ext}",
+            "selection": [{ "start": 0.0, "end": 1.0 }, { "start": 5.0, "end": 7.0 }],
+            "animated": { "format": "webp" },
+        }));
+        assert_eq!(
+            output_paths(&args).unwrap(),
+            vec!["/out/in_1.webp", "/out/in_2.webp"]
+        );
+    }
+
+    #[test]
+    fn existing_outputs_follow_on_conflict() {
+        let dir = std::env::temp_dir().join(format!("video-cropper-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("clip.mp4");
+        std::fs::write(&existing, b"").unwrap();
+        let output_path = existing.to_string_lossy();
+
+        let args = export_args(json!({ "output_path": output_path }));
+        assert!(output_paths(&args).is_err());
+
+        let args = export_args(json!({ "output_path": output_path, "on_conflict": "overwrite" }));
+        assert_eq!(output_paths(&args).unwrap(), vec![output_path.to_string()]);
+
+        let args = export_args(json!({
+            "output_path": output_path,
+            "output_template": "clip.mp4",
+            "selection": [{ "start": 0.0, "end": 1.0 }, { "start": 5.0, "end": 7.0 }],
+            "on_conflict": "rename",
+        }));
+        let renamed = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        assert_eq!(
+            output_paths(&args).unwrap(),
+            vec![renamed("clip_1.mp4"), renamed("clip_2.mp4")]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn picture_size_follows_rotation_and_scale() {
+        let args = export_args(json!({ "rotate": 90 }));
+        assert_eq!(output_picture_size(&args), (360, 640));
+
+        let args = export_args(json!({ "scale": { "mode": "max_dimension", "size": 320 } }));
+        assert_eq!(output_picture_size(&args), (320, 180));
+
+        // Fitting never upscales
+        let args = export_args(json!({ "scale": { "mode": "preset", "preset": "1080p" } }));
+        assert_eq!(output_picture_size(&args), (640, 360));
+    }
+
+    #[test]
+    fn second_crops_are_stacked_or_laid_over() {
+        let args = export_args(json!({
+            "second_crop": { "layout": "stacked", "crop": { "x": 0, "y": 0, "width": 320, "height": 240 } },
+        }));
+        assert_eq!(output_picture_size(&args), (640, 840));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                "split[dual_main][dual_second];\
+                 [dual_second]crop=320:240:0:0,scale=640:480,setsar=1[dual_scaled];\
+                 [dual_main]crop=640:360:10:20,setsar=1[dual_cropped];\
+                 [dual_scaled][dual_cropped]vstack",
+            ]
+        ));
+
+        let args = export_args(json!({
+            "second_crop": {
+                "layout": "picture_in_picture",
+                "crop": { "x": 0, "y": 0, "width": 320, "height": 240 },
+                "position": "anchor",
+                "anchor": "bottom_right",
+                "scale": 0.25,
+            },
+        }));
+        assert_eq!(output_picture_size(&args), (640, 360));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        assert!(passes[0].iter().any(|arg| arg.ends_with(
+            "[dual_second]crop=320:240:0:0,scale=160:120,setsar=1[dual_scaled];\
+             [dual_main]crop=640:360:10:20,setsar=1[dual_cropped];\
+             [dual_cropped][dual_scaled]overlay=W-w-16:H-h-16"
+        )));
+    }
+
+    #[test]
+    fn extra_crops_are_written_by_the_same_run() {
+        let args = export_args(json!({
+            "selection": [{ "start": 1.0, "end": 2.0 }, { "start": 5.0, "end": 6.0 }],
+            "extra_crops": [{
+                "crop": { "x": 0, "y": 0, "width": 320, "height": 240 },
+                "output_path": "cam.mp4",
+            }],
+        }));
+        let outputs: Vec<_> = [args.clone(), extra_crop_args(&args, 0)]
+            .into_iter()
+            .map(|args| {
+                let plan = video_plan(&args, |_| {});
+                (args, plan)
+            })
+            .collect();
+        let (pass, output_paths) = merge_crop_passes(&outputs).unwrap();
+        assert_eq!(output_paths, vec!["out.mp4", "cam.mp4"]);
+
+        // One input feeding two graphs whose labels do not clash
+        assert_eq!(pass.args.iter().filter(|arg| *arg == "-i").count(), 1);
+        assert!(contains(&pass.args, &["-map", "[picture]"]));
+        assert!(contains(&pass.args, &["-map", "[crop1_picture]"]));
+        assert!(pass.args.iter().any(|arg| arg.ends_with(
+            "[crop1_v0][crop1_v1]concat=n=2:v=1:a=0[crop1_joined_v];\
+             [crop1_joined_v]crop=320:240:0:0[crop1_picture]"
+        )));
+        assert_eq!(pass.args.last().unwrap(), "cam.mp4");
+    }
+
+    #[test]
+    fn crops_drawn_on_the_stored_frame_follow_the_rotation() {
+        let area = CropArea {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 50,
+        };
+        // The top left corner of the stored frame shows at the top right
+        let turned = rotate_area(&area, 90, (1920, 1080));
+        assert_eq!(
+            turned,
+            CropArea {
+                x: 1030,
+                y: 0,
+                width: 50,
+                height: 100,
+            }
+        );
+        assert_eq!(rotate_area(&turned, 270, (1080, 1920)), area);
+        assert_eq!(
+            rotate_area(&area, 180, (1920, 1080)),
+            CropArea {
+                x: 1820,
+                y: 1030,
+                ..area.clone()
+            }
+        );
+        // Oversized areas are left for validation to reject
+        let oversized = CropArea {
+            width: u32::MAX,
+            ..area.clone()
+        };
+        assert_eq!(rotate_area(&oversized, 270, (1920, 1080)).y, 0);
+    }
+
+    #[test]
+    fn crop_fractions_fit_any_frame_size() {
+        let crop = FractionalCrop {
+            x: 0.25,
+            y: 0.0,
+            width: 0.5,
+            height: 1.0,
+        };
+        assert_eq!(crop.validate(), Ok(()));
+        assert_eq!(
+            crop.resolve(1920, 1080),
+            CropArea {
+                x: 480,
+                y: 0,
+                width: 960,
+                height: 1080,
+            }
+        );
+        assert_eq!(crop.resolve(1280, 720).width, 640);
+
+        let past_the_edge = FractionalCrop { x: 0.75, ..crop };
+        assert!(past_the_edge.validate().is_err());
+    }
+
+    #[test]
+    fn settings_fill_in_what_the_export_leaves_open() {
+        let settings = Settings {
+            output_dir: Some("/exports".to_string()),
+            default_preset: Some("discord".to_string()),
+            encoder: Some(Encoder::Nvenc),
+            ..Settings::default()
+        };
+        let mut args = export_args(json!({ "extra_crops": [{
+            "crop": { "x": 0, "y": 0, "width": 320, "height": 320 },
+            "output_path": "/elsewhere/cam.mp4",
+        }] }));
+        apply_settings(&mut args, &settings);
+        assert_eq!(
+            args.output_path,
+            Path::new("/exports").join("out.mp4").to_string_lossy()
+        );
+        assert_eq!(args.extra_crops[0].output_path, "/elsewhere/cam.mp4");
+        assert_eq!(args.preset.as_deref(), Some("discord"));
+        assert_eq!(args.encoder, Some(Encoder::Nvenc));
+
+        let mut args = export_args(json!({ "preset": "youtube", "encoder": "cpu" }));
+        apply_settings(&mut args, &settings);
+        assert_eq!(args.preset.as_deref(), Some("youtube"));
+        assert_eq!(args.encoder, Some(Encoder::Cpu));
+    }
+
+    #[test]
+    fn previews_keep_the_look_and_drop_the_delivery() {
+        let mut args = export_args(json!({
+            "selection": [{ "start": 0.2, "end": 0.8 }],
+            "relative_selection": true,
+            "lut_path": "/luts/rec709.cube",
+            "denoise": { "strength": "light" },
+            "fade_in": 1.0,
+            "target_size_mb": 8.0,
+            "editing": "prores_hq",
+            "keep_alpha": true,
+            "preserve_hdr": true,
+            "ladder": [{ "height": 360 }],
+            "streaming": { "format": "hls" },
+            "extra_crops": [{
+                "crop": { "x": 0, "y": 0, "width": 320, "height": 320 },
+                "output_path": "/out/cam.mp4",
+            }],
+        }));
+        limit_to_preview(&mut args, 42.0, 3.0);
+        preview_output(&mut args, "/tmp/preview.mp4".to_string());
+
+        assert_eq!(args.selection.len(), 1);
+        assert_eq!(
+            (args.selection[0].start, args.selection[0].end),
+            (42.0, 45.0)
+        );
+        assert!(!args.relative_selection);
+        assert!(args.extra_crops.is_empty());
+        assert!(args.ladder.is_empty());
+        assert!(args.streaming.is_none());
+        assert_eq!(args.output_path, "/tmp/preview.mp4");
+        assert_eq!(args.target_size_mb, None);
+        assert_eq!(args.editing, None);
+        assert!(!args.keep_alpha);
+        assert!(!args.preserve_hdr);
+        assert_eq!(args.fade_in, None);
+        assert!(args.mute);
+        // The filters under review stay
+        assert_eq!(args.lut_path.as_deref(), Some("/luts/rec709.cube"));
+        assert!(args.denoise.is_some());
+    }
+
+    #[test]
+    fn late_cuts_seek_on_the_input_first() {
+        let args = export_args(json!({ "selection": [
+            { "start": 3600.0, "end": 3610.0 },
+            { "start": 1800.0, "end": 1805.0 },
+        ] }));
+        assert_eq!(
+            source_input_args(&args),
+            ["-ss", "1795", "-copyts", "-start_at_zero", "-i", "in.mp4"]
+        );
+
+        let args = export_args(json!({ "selection": [{ "start": 2.0, "end": 4.0 }] }));
+        assert_eq!(source_input_args(&args), ["-i", "in.mp4"]);
+        let args = export_args(json!({
+            "selection": [{ "start": 3600.0, "end": 3610.0 }],
+            "trim_accuracy": "decode",
+        }));
+        assert_eq!(source_input_args(&args), ["-i", "in.mp4"]);
+    }
+
+    #[test]
+    fn splits_end_on_keyframes_when_copying() {
+        let selection = [ClipSelection {
+            start: 10.0,
+            end: 100.0,
+        }];
+        let ends = |pieces: Vec<ClipSelection>| -> Vec<f64> {
+            pieces.iter().map(|piece| piece.end).collect()
+        };
+        assert_eq!(
+            ends(split_ranges(&selection, 30.0, &[])),
+            [40.0, 70.0, 100.0]
+        );
+        // A keyframe every 4 seconds, and a gap without any between 60 and 95
+        let keyframes = [
+            8.0, 12.0, 16.0, 20.0, 24.0, 28.0, 32.0, 36.0, 40.0, 44.0, 60.0, 95.0,
+        ];
+        assert_eq!(
+            ends(split_ranges(&selection, 30.0, &keyframes)),
+            [40.0, 60.0, 95.0, 100.0]
+        );
+    }
+
+    #[test]
+    fn batch_exports_are_named_after_each_input() {
+        let settings = export_args(json!({ "output_path": "/out/{stem}-vertical.mp4" }));
+        assert!(settings.names_output_after_input());
+        let args = settings.for_input("/videos/match 2.mkv");
+        assert_eq!(render_output_path(&args), "/out/match 2-vertical.mp4");
+
+        assert!(!export_args(json!({})).names_output_after_input());
+    }
+
+    #[test]
+    fn anamorphic_crops_are_scaled_to_square_pixels() {
+        // NTSC DV drawn on its stored 720x480 pixels, displayed as 720x528
+        let mut args = export_args(json!({
+            "crop": { "x": 360, "y": 240, "width": 360, "height": 240 },
+            "crop_frame": { "width": 720, "height": 480 },
+        }));
+        assert!(scale_areas(&mut args, (720, 528)));
+        assert_eq!(
+            args.crop,
+            CropArea {
+                x: 360,
+                y: 264,
+                width: 360,
+                height: 264,
+            }
+        );
+        assert!(!scale_areas(&mut args, (720, 528)));
+
+        let mut plan = video_plan(&args, |_| {});
+        plan.square_pixels = Some("scale=720:528,setsar=1".to_string());
+        let passes = build_ffmpeg_args(&args, &plan);
+        assert!(contains(
+            &passes[0],
+            &["-filter:v", "scale=720:528,setsar=1,crop=360:264:360:264"]
+        ));
+    }
+
+    #[test]
+    fn aspect_ratios_narrow_the_crop_or_pad_the_picture() {
+        let args = export_args(json!({ "aspect_ratio": { "ratio": "9:16", "fit": "crop" } }));
+        let aspect = args.aspect_ratio.clone().unwrap();
+        assert_eq!(
+            narrow_to_aspect(&args.crop, &aspect, None),
+            Ok(CropArea {
+                x: 231,
+                y: 24,
+                width: 198,
+                height: 352,
+            })
+        );
+        // Rotated a quarter turn, the crop itself has to be wide
+        let narrowed = narrow_to_aspect(&args.crop, &aspect, Some(90)).unwrap();
+        assert_eq!((narrowed.width, narrowed.height), (640, 360));
+
+        let args = export_args(json!({
+            "aspect_ratio": { "ratio": "9:16", "fit": "pad", "color": "white" },
+        }));
+        assert_eq!(output_picture_size(&args), (648, 1152));
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,pad=648:1152:(ow-iw)/2:(oh-ih)/2:color=white,setsar=1",
+            ]
+        ));
+    }
+
+    #[test]
+    fn blur_fill_centers_the_picture_on_a_vertical_canvas() {
+        let args = export_args(json!({ "blur_fill": {} }));
+        assert_eq!(output_picture_size(&args), (640, 1138));
+
+        let passes = build_ffmpeg_args(&args, &video_plan(&args, |_| {}));
+        assert!(contains(
+            &passes[0],
+            &[
+                "-filter:v",
+                "crop=640:360:10:20,split[fill_bg][fill_fg];\
+                 [fill_bg]scale=640:1138:force_original_aspect_ratio=increase,crop=640:1138,\
+                 boxblur=20:2,setsar=1[fill_blur];[fill_blur][fill_fg]overlay=(W-w)/2:(H-h)/2",
+            ]
+        ));
+
+        // Pictures narrower than the canvas are filled at the sides
+        let args = export_args(json!({ "blur_fill": { "aspect": "16:9" }, "rotate": 90 }));
+        assert_eq!(output_picture_size(&args), (1138, 640));
+    }
+
+    #[test]
+    fn gopro_telemetry_and_projection_survive_stream_copies() {
+        let args = export_args(json!({ "lossless": true, "audio_tracks": [1] }));
+        let plan = video_plan(&args, |video| {
+            video.telemetry_streams = vec![3];
+            video.spherical = Some(SphericalPlan::Keep);
+        });
+        let PlannedOutput::Video(video) = &plan.output else {
+            unreachable!()
+        };
+        let ffmpeg_args = build_lossless_args(&args, &plan, video);
+        assert!(contains(
+            &ffmpeg_args,
+            &["-map", "0:v:0", "-map", "0:a:1", "-map", "0:3", "-c:d", "copy"]
+        ));
+        assert!(contains(&ffmpeg_args, &["-strict", "unofficial"]));
+
+        // Without picked tracks the telemetry must not hide the sound
+        let args = export_args(json!({}));
+        let plan = video_plan(&args, |video| video.telemetry_streams = vec![3]);
+        let PlannedOutput::Video(video) = &plan.output else {
+            unreachable!()
+        };
+        assert_eq!(
+            source_extras_args(&[], video),
+            ["-map", "0:v:0", "-map", "0:a:0?", "-map", "0:3", "-c:d", "copy"]
+        );
+    }
+
+    #[test]
+    fn streaming_packages_the_encoded_export() {
+        let args = export_args(json!({
+            "output_path": "/site/clip/master.m3u8",
+            "container": "mp4",
+            "streaming": { "format": "hls" },
+        }));
+        let plan = video_plan(&args, |video| {
+            video.has_audio = true;
+            video.streaming = Some(StreamingPlan {
+                dir: PathBuf::from("/site/clip"),
+                mezzanine: PathBuf::from("/tmp/job/mezzanine.mp4"),
+            });
+        });
+        let passes = build_passes(&args, &plan);
+
+        assert_eq!(passes.len(), 2);
+        assert!(contains(
+            &passes[0].args,
+            &["-force_key_frames", "expr:gte(t,n_forced*6)"]
+        ));
+        assert_eq!(passes[0].args.last().unwrap(), "/tmp/job/mezzanine.mp4");
+        assert!(contains(
+            &passes[1].args,
+            &[
+                "-i",
+                "/tmp/job/mezzanine.mp4",
+                "-map",
+                "0:v:0",
+                "-c:v",
+                "copy"
+            ]
+        ));
+        assert_eq!(passes[1].args.last().unwrap(), "/site/clip/master.m3u8");
+        assert!((passes[0].weight + passes[1].weight - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn webm_alpha_is_decoded_and_kept_in_vp9() {
+        let mut args = export_args(json!({ "output_path": "out.webm", "keep_alpha": true }));
+        args.source_decoder = Some("libvpx-vp9".to_string());
+        let plan = video_plan(&args, |video| video.alpha = true);
+        let PlannedOutput::Video(video) = &plan.output else {
+            unreachable!()
+        };
+        let ffmpeg_args = build_encode_args(&args, &plan, video, None);
+        assert!(contains(
+            &ffmpeg_args,
+            &["-c:v", "libvpx-vp9", "-i", "in.mp4"]
+        ));
+        assert!(contains(&ffmpeg_args, &["-pix_fmt", "yuva420p"]));
+        assert_eq!(Container::Mp4.alpha_codec(), None);
+    }
+
+    #[test]
+    fn stream_copies_take_a_full_frame_crop_fraction() {
+        let metadata = VideoMetadata {
+            width: 1920,
+            height: 1080,
+            ..Default::default()
+        };
+        let mut args = export_args(json!({
+            "lossless": true,
+            "crop_fraction": { "x": 0.0, "y": 0.0, "width": 1.0, "height": 1.0 },
+        }));
+        resolve_areas(&mut args, &metadata).unwrap();
+        assert!(validate_copy_crop(&args, &metadata).is_ok());
+
+        // Drawn on the stored frame of a portrait phone video
+        let metadata = VideoMetadata {
+            rotation: 90,
+            ..metadata
+        };
+        let mut args = export_args(json!({
+            "lossless": true,
+            "crop": { "x": 0, "y": 0, "width": 1920, "height": 1080 },
+            "crop_frame": { "width": 1920, "height": 1080 },
+        }));
+        resolve_areas(&mut args, &metadata).unwrap();
+        assert!(validate_copy_crop(&args, &metadata).is_ok());
+    }
+
+    #[test]
+    fn oversized_crops_are_clamped_to_the_frame() {
+        let metadata = VideoMetadata {
+            width: 1920,
+            height: 1080,
+            ..Default::default()
+        };
+        let mut args = export_args(json!({
+            "crop": { "x": 100, "y": 50, "width": u32::MAX, "height": u32::MAX },
+        }));
+        let requested = args.crop.clone();
+        let adjusted = fit_crop(&mut args, JobId::nil(), &metadata, (requested, vec![]))
+            .unwrap()
+            .unwrap();
+        assert_eq!((args.crop.width, args.crop.height), (1820, 1030));
+        assert_eq!(adjusted.reasons.len(), 2);
+    }
+}
@@ -0,0 +1,956 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flip {
+    /// Mirror left to right
+    Horizontal,
+    /// Mirror top to bottom
+    Vertical,
+}
+
+/// Only quarter turns are supported, anything else would need padding.
+pub fn validate_rotation(rotate: Option<u32>) -> Result<(), String> {
+    match rotate {
+        None | Some(0) | Some(90) | Some(180) | Some(270) => Ok(()),
+        Some(degrees) => Err(format!(
+            "Cannot rotate by {} degrees, use 90, 180 or 270",
+            degrees
+        )),
+    }
+}
+
+/// Filters for a clockwise `rotate` followed by `flip`. They run after the
+/// crop, because the crop coordinates refer to the frame as previewed.
+pub fn transform_filters(rotate: Option<u32>, flip: Option<Flip>) -> Vec<String> {
+    let mut filters: Vec<String> = match rotate {
+        Some(90) => vec!["transpose=clock".into()],
+        Some(180) => vec!["hflip".into(), "vflip".into()],
+        Some(270) => vec!["transpose=cclock".into()],
+        _ => vec![],
+    };
+    match flip {
+        Some(Flip::Horizontal) => filters.push("hflip".into()),
+        Some(Flip::Vertical) => filters.push("vflip".into()),
+        None => {}
+    }
+    filters
+}
+
+/// Named output sizes. The video is fitted inside the box without upscaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalePreset {
+    #[serde(rename = "2160p")]
+    P2160,
+    #[serde(rename = "1440p")]
+    P1440,
+    #[serde(rename = "1080p")]
+    P1080,
+    #[serde(rename = "720p")]
+    P720,
+    #[serde(rename = "480p")]
+    P480,
+    /// 1080×1350 portrait feed post
+    #[serde(rename = "instagram_portrait")]
+    InstagramPortrait,
+    /// 1080×1080 square feed post
+    #[serde(rename = "instagram_square")]
+    InstagramSquare,
+    /// 1080×1920 stories and reels
+    #[serde(rename = "instagram_story")]
+    InstagramStory,
+}
+
+impl ScalePreset {
+    /// Width and height of the box, or `None` for the `p` presets whose box
+    /// follows the orientation of the video.
+    fn fixed_box(self) -> Option<(u32, u32)> {
+        match self {
+            ScalePreset::InstagramPortrait => Some((1080, 1350)),
+            ScalePreset::InstagramSquare => Some((1080, 1080)),
+            ScalePreset::InstagramStory => Some((1080, 1920)),
+            _ => None,
+        }
+    }
+
+    /// Short side of the `p` presets.
+    fn lines(self) -> u32 {
+        match self {
+            ScalePreset::P2160 => 2160,
+            ScalePreset::P1440 => 1440,
+            ScalePreset::P1080 => 1080,
+            ScalePreset::P720 => 720,
+            _ => 480,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ScaleSize {
+    /// Exactly this size, odd values are rounded down to even
+    Exact {
+        width: u32,
+        height: u32,
+    },
+    /// Longest side at most `size`, keeping the aspect ratio
+    MaxDimension {
+        size: u32,
+    },
+    Preset {
+        preset: ScalePreset,
+    },
+}
+
+/// Resampling algorithms of the `scale` filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleAlgorithm {
+    Bilinear,
+    #[default]
+    Bicubic,
+    Lanczos,
+    Spline,
+    Area,
+    Neighbor,
+}
+
+impl ScaleAlgorithm {
+    fn flag(self) -> &'static str {
+        match self {
+            ScaleAlgorithm::Bilinear => "bilinear",
+            ScaleAlgorithm::Bicubic => "bicubic",
+            ScaleAlgorithm::Lanczos => "lanczos",
+            ScaleAlgorithm::Spline => "spline",
+            ScaleAlgorithm::Area => "area",
+            ScaleAlgorithm::Neighbor => "neighbor",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ScaleOptions {
+    #[serde(flatten)]
+    pub size: ScaleSize,
+    #[serde(default)]
+    pub algorithm: ScaleAlgorithm,
+}
+
+impl ScaleOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.size {
+            ScaleSize::Exact { width, height } if width < 2 || height < 2 => {
+                Err(format!("Cannot scale to {}x{}", width, height))
+            }
+            ScaleSize::MaxDimension { size } if size < 2 => {
+                Err(format!("Cannot scale to a maximum of {} pixels", size))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Size the `filter` turns a `width`x`height` picture into.
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (box_width, box_height) = match self.size {
+            ScaleSize::Exact { width, height } => return (width & !1, height & !1),
+            ScaleSize::MaxDimension { size } => (size, size),
+            ScaleSize::Preset { preset } => preset.fixed_box().unwrap_or_else(|| {
+                let short = preset.lines();
+                let long = short * 16 / 9;
+                if width >= height {
+                    (long, short)
+                } else {
+                    (short, long)
+                }
+            }),
+        };
+
+        let ratio = (box_width as f64 / width as f64)
+            .min(box_height as f64 / height as f64)
+            .min(1.0);
+        let fit = |side: u32| ((side as f64 * ratio) as u32) & !1;
+        (fit(width), fit(height))
+    }
+
+    /// The `scale` filter, appended after the crop and transforms. Every mode
+    /// produces even dimensions, which most encoders require.
+    pub fn filter(&self) -> String {
+        let flags = self.algorithm.flag();
+
+        let (width, height) = match self.size {
+            ScaleSize::Exact { width, height } => {
+                return format!("scale={}:{}:flags={}", width & !1, height & !1, flags)
+            }
+            ScaleSize::MaxDimension { size } => (size.to_string(), size.to_string()),
+            ScaleSize::Preset { preset } => match preset.fixed_box() {
+                Some((width, height)) => (width.to_string(), height.to_string()),
+                None => {
+                    let short = preset.lines();
+                    let long = short * 16 / 9;
+                    (
+                        format!("if(gte(iw,ih),{},{})", long, short),
+                        format!("if(gte(iw,ih),{},{})", short, long),
+                    )
+                }
+            },
+        };
+
+        // Fit inside the box without ever upscaling
+        format!(
+            "scale=w='min(iw,{})':h='min(ih,{})':force_original_aspect_ratio=decrease:force_divisible_by=2:flags={}",
+            width, height, flags
+        )
+    }
+}
+
+// Distance of an anchored overlay from the edges, in output pixels
+const DEFAULT_OVERLAY_MARGIN: u32 = 16;
+
+/// Where an overlay sits in the picture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "position", rename_all = "snake_case")]
+pub enum OverlayPosition {
+    /// At `anchor`, `margin` pixels away from the edges it touches
+    Anchor { anchor: Anchor, margin: Option<u32> },
+    /// Top left of the overlay at `x`, `y` of the output picture
+    Absolute { x: u32, y: u32 },
+}
+
+impl OverlayPosition {
+    /// `x` and `y` expressions, given the names the filter uses for the width
+    /// and height of the picture and of the overlay.
+    pub fn exprs(&self, picture: (&str, &str), overlay: (&str, &str)) -> (String, String) {
+        let (anchor, margin) = match *self {
+            OverlayPosition::Anchor { anchor, margin } => {
+                (anchor, margin.unwrap_or(DEFAULT_OVERLAY_MARGIN))
+            }
+            OverlayPosition::Absolute { x, y } => return (x.to_string(), y.to_string()),
+        };
+        // -1 for the start of an axis, 0 for the middle and 1 for the end
+        let (column, row) = match anchor {
+            Anchor::TopLeft => (-1, -1),
+            Anchor::Top => (0, -1),
+            Anchor::TopRight => (1, -1),
+            Anchor::Left => (-1, 0),
+            Anchor::Center => (0, 0),
+            Anchor::Right => (1, 0),
+            Anchor::BottomLeft => (-1, 1),
+            Anchor::Bottom => (0, 1),
+            Anchor::BottomRight => (1, 1),
+        };
+        let axis = |side: i32, picture: &str, overlay: &str| match side {
+            -1 => margin.to_string(),
+            0 => format!("({}-{})/2", picture, overlay),
+            _ => format!("{}-{}-{}", picture, overlay, margin),
+        };
+
+        (
+            axis(column, picture.0, overlay.0),
+            axis(row, picture.1, overlay.1),
+        )
+    }
+}
+
+/// `enable` option showing a filter from `start` until `end` seconds of the
+/// clip, or `None` when it is shown throughout. `time_offset` is the
+/// timestamp the clip starts at in the filtered stream.
+pub fn enable_option(start: Option<f64>, end: Option<f64>, time_offset: f64) -> Option<String> {
+    let start = start.map(|start| start + time_offset);
+    let end = end.map(|end| end + time_offset);
+    match (start, end) {
+        (Some(start), Some(end)) => Some(format!("enable='between(t,{},{})'", start, end)),
+        (Some(start), None) => Some(format!("enable='gte(t,{})'", start)),
+        (None, Some(end)) => Some(format!("enable='lte(t,{})'", end)),
+        (None, None) => None,
+    }
+}
+
+/// Checks the optional time range of an overlay.
+pub fn validate_time_range(start: Option<f64>, end: Option<f64>) -> Result<(), String> {
+    match (start, end) {
+        (Some(start), _) if start < 0.0 => {
+            Err("An overlay cannot start before the clip".to_string())
+        }
+        (Some(start), Some(end)) if start >= end => {
+            Err("An overlay has to end after it starts".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the fades are positive durations that fit into the clip.
+pub fn validate_fades(
+    fade_in: Option<f64>,
+    fade_out: Option<f64>,
+    duration: f64,
+) -> Result<(), String> {
+    for fade in [fade_in, fade_out].into_iter().flatten() {
+        if !fade.is_finite() || fade < 0.0 {
+            return Err("A fade must last a positive number of seconds".to_string());
+        }
+        if fade > duration {
+            return Err(format!(
+                "A fade of {}s does not fit into the {}s clip",
+                fade, duration
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `fade` or `afade` filters, as `filter` names, fading in over the first
+/// `fade_in` and out over the last `fade_out` seconds of a clip starting at
+/// `time_offset` in the filtered stream and lasting `duration`.
+pub fn fade_filters(
+    filter: &str,
+    fade_in: Option<f64>,
+    fade_out: Option<f64>,
+    time_offset: f64,
+    duration: f64,
+) -> Vec<String> {
+    let fade_in = fade_in
+        .filter(|fade| *fade > 0.0)
+        .map(|fade| format!("{}=t=in:st={}:d={}", filter, time_offset, fade));
+    let fade_out = fade_out.filter(|fade| *fade > 0.0).map(|fade| {
+        format!(
+            "{}=t=out:st={}:d={}",
+            filter,
+            time_offset + duration - fade,
+            fade
+        )
+    });
+    fade_in.into_iter().chain(fade_out).collect()
+}
+
+const DEFAULT_INTERPOLATION_FPS: f64 = 60.0;
+const MAX_INTERPOLATION_FPS: f64 = 240.0;
+const MAX_FRAME_RATE: f64 = 240.0;
+
+pub fn validate_frame_rate(fps: Option<f64>) -> Result<(), String> {
+    match fps {
+        Some(fps) if !(1.0..=MAX_FRAME_RATE).contains(&fps) => Err(format!(
+            "The frame rate must be between 1 and {}",
+            MAX_FRAME_RATE
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Frame rate as FFmpeg takes it. NTSC rates such as 23.976 or 29.97 are
+/// written as the exact fractions they round.
+pub fn frame_rate_value(fps: f64) -> String {
+    let ntsc = fps * 1.001;
+    if fps.fract() != 0.0 && (ntsc - ntsc.round()).abs() < 0.01 {
+        format!("{}/1001", ntsc.round() as u64 * 1000)
+    } else {
+        fps.to_string()
+    }
+}
+
+/// How `minterpolate` makes up the frames in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterpolationMode {
+    /// Motion compensated, smooth but by far the slowest
+    Mci,
+    /// Blends neighbouring frames
+    Blend,
+    /// Duplicates frames
+    Dup,
+}
+
+/// Motion estimation of `minterpolate`'s motion compensated mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MotionEstimation {
+    /// Vectors from both neighbours, the default
+    Bidir,
+    /// Vectors from the frame in between, better with fast motion
+    Bilat,
+}
+
+/// Frame interpolation with `minterpolate`, for slow motion that does not
+/// stutter. Missing settings keep FFmpeg's defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interpolation {
+    /// Frame rate to interpolate to, 60 by default
+    pub fps: Option<f64>,
+    pub mode: Option<InterpolationMode>,
+    pub motion_estimation: Option<MotionEstimation>,
+    /// Block search range in pixels
+    pub search_range: Option<u32>,
+    /// Smooth the motion vectors (variable-size block motion compensation)
+    #[serde(default)]
+    pub smooth: bool,
+}
+
+impl Interpolation {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1.0..=MAX_INTERPOLATION_FPS).contains(&self.fps()) {
+            return Err(format!(
+                "The interpolated frame rate must be between 1 and {}",
+                MAX_INTERPOLATION_FPS
+            ));
+        }
+        if self.search_range == Some(0) {
+            return Err("The motion search range must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps.unwrap_or(DEFAULT_INTERPOLATION_FPS)
+    }
+
+    pub fn filter(&self) -> String {
+        let mut options = vec![format!("fps={}", frame_rate_value(self.fps()))];
+        if let Some(mode) = self.mode {
+            let mode = match mode {
+                InterpolationMode::Mci => "mci",
+                InterpolationMode::Blend => "blend",
+                InterpolationMode::Dup => "dup",
+            };
+            options.push(format!("mi_mode={}", mode));
+        }
+        if let Some(estimation) = self.motion_estimation {
+            let estimation = match estimation {
+                MotionEstimation::Bidir => "bidir",
+                MotionEstimation::Bilat => "bilat",
+            };
+            options.push(format!("me_mode={}", estimation));
+        }
+        if let Some(range) = self.search_range {
+            options.push(format!("search_param={}", range));
+        }
+        if self.smooth {
+            options.push("vsbmc=1".to_string());
+        }
+        format!("minterpolate={}", options.join(":"))
+    }
+}
+
+const DEFAULT_FILL_ASPECT: &str = "9:16";
+const DEFAULT_FILL_BLUR: u32 = 20;
+
+/// Puts the picture on a canvas of another aspect ratio, filled with a blurred
+/// and enlarged copy of itself, e.g. 16:9 footage in a 9:16 vertical video.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlurFill {
+    /// Aspect ratio of the canvas as "width:height", 9:16 by default
+    pub aspect: Option<String>,
+    /// Blur radius of the background
+    pub blur: Option<u32>,
+}
+
+/// Parses an aspect ratio written as "width:height".
+fn parse_aspect(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once(':')?;
+    let (width, height) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// Sub-graph centering the picture on a `width` x `height` canvas filled
+/// with a blurred copy of itself.
+fn blurred_canvas(blur: u32, width: u32, height: u32) -> String {
+    format!(
+        "split[fill_bg][fill_fg];\
+         [fill_bg]scale={w}:{h}:force_original_aspect_ratio=increase,crop={w}:{h},\
+         boxblur={}:2,setsar=1[fill_blur];\
+         [fill_blur][fill_fg]overlay=(W-w)/2:(H-h)/2",
+        blur,
+        w = width,
+        h = height
+    )
+}
+
+impl BlurFill {
+    fn aspect(&self) -> Option<(u32, u32)> {
+        parse_aspect(self.aspect.as_deref().unwrap_or(DEFAULT_FILL_ASPECT))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.aspect().is_none() {
+            return Err("The fill aspect ratio must look like 9:16".to_string());
+        }
+        if self.blur == Some(0) {
+            return Err("The fill blur must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Smallest canvas of the aspect ratio that holds a `width` x `height`
+    /// picture, in even dimensions.
+    pub fn canvas_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (aspect_width, aspect_height) = self.aspect().unwrap_or((9, 16));
+        let even = |value: f64| ((value / 2.0).ceil() * 2.0) as u32;
+        if width as u64 * aspect_height as u64 > height as u64 * aspect_width as u64 {
+            (
+                even(width as f64),
+                even(width as f64 * aspect_height as f64 / aspect_width as f64),
+            )
+        } else {
+            (
+                even(height as f64 * aspect_width as f64 / aspect_height as f64),
+                even(height as f64),
+            )
+        }
+    }
+
+    /// Sub-graph centering the picture on the canvas. It fits into a filter
+    /// chain like a single filter.
+    pub fn filter(&self, width: u32, height: u32) -> String {
+        let (canvas_width, canvas_height) = self.canvas_size(width, height);
+        blurred_canvas(
+            self.blur.unwrap_or(DEFAULT_FILL_BLUR),
+            canvas_width,
+            canvas_height,
+        )
+    }
+}
+
+/// How the picture is brought to the aspect ratio of `AspectRatio`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "fit", rename_all = "lowercase")]
+pub enum AspectFit {
+    /// Narrow the crop around its center before exporting
+    Crop,
+    /// Bars of `color`, black by default
+    Pad { color: Option<String> },
+    /// A blurred copy of the picture behind it, like `BlurFill`
+    Blur { blur: Option<u32> },
+}
+
+/// Output aspect ratio the export must have exactly, e.g. 9:16 for vertical
+/// video platforms that reject or crop anything else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AspectRatio {
+    /// "width:height"
+    pub ratio: String,
+    #[serde(flatten)]
+    pub fit: AspectFit,
+}
+
+impl AspectRatio {
+    pub fn validate(&self) -> Result<(), String> {
+        if parse_aspect(&self.ratio).is_none() {
+            return Err("The aspect ratio must look like 9:16".to_string());
+        }
+        if let AspectFit::Blur { blur: Some(0) } = self.fit {
+            return Err("The fill blur must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Sides of the smallest even-sized picture with exactly the ratio, and
+    /// the steps its size grows in.
+    fn unit(&self) -> (u32, u32, u32) {
+        let (width, height) = parse_aspect(&self.ratio).unwrap_or((9, 16));
+        let (mut a, mut b) = (width, height);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        let (width, height) = (width / a, height / a);
+        let step = if width % 2 == 1 || height % 2 == 1 {
+            2
+        } else {
+            1
+        };
+        (width, height, step)
+    }
+
+    /// Largest size with the ratio that fits into `width` x `height`, `None`
+    /// if even the smallest does not.
+    pub fn crop_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (unit_width, unit_height, step) = self.unit();
+        let count = (width / unit_width).min(height / unit_height) / step * step;
+        (count > 0).then_some((unit_width * count, unit_height * count))
+    }
+
+    /// Smallest canvas with the ratio that holds a `width` x `height`
+    /// picture, `None` in the crop mode which pads nothing.
+    pub fn canvas_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if let AspectFit::Crop = self.fit {
+            return None;
+        }
+        let (unit_width, unit_height, step) = self.unit();
+        let count = width
+            .div_ceil(unit_width)
+            .max(height.div_ceil(unit_height))
+            .div_ceil(step)
+            * step;
+        Some((unit_width * count, unit_height * count))
+    }
+
+    /// Filter padding a `width` x `height` picture to the ratio. The crop
+    /// mode has already adjusted the crop and needs none.
+    pub fn filter(&self, width: u32, height: u32) -> Option<String> {
+        let (canvas_width, canvas_height) = self.canvas_size(width, height)?;
+        match &self.fit {
+            AspectFit::Crop => None,
+            AspectFit::Pad { color } => Some(format!(
+                "pad={}:{}:(ow-iw)/2:(oh-ih)/2:color={},setsar=1",
+                canvas_width,
+                canvas_height,
+                escape_value(color.as_deref().unwrap_or("black"))
+            )),
+            AspectFit::Blur { blur } => Some(blurred_canvas(
+                blur.unwrap_or(DEFAULT_FILL_BLUR),
+                canvas_width,
+                canvas_height,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StabilizationStrength {
+    Light,
+    #[default]
+    Medium,
+    Strong,
+}
+
+/// Two-pass stabilization with vid.stab: `vidstabdetect` writes the camera
+/// motion to a transforms file, `vidstabtransform` then smooths it out.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Stabilization {
+    #[serde(default)]
+    pub strength: StabilizationStrength,
+}
+
+impl Stabilization {
+    /// How shaky the footage is (1 - 10), and over how many frames the
+    /// motion is smoothed.
+    fn settings(self) -> (u32, u32) {
+        match self.strength {
+            StabilizationStrength::Light => (4, 10),
+            StabilizationStrength::Medium => (6, 20),
+            StabilizationStrength::Strong => (8, 40),
+        }
+    }
+
+    pub fn detect_filter(self, transforms: &str) -> String {
+        format!(
+            "vidstabdetect=shakiness={}:accuracy=15:result={}",
+            self.settings().0,
+            escape_value(transforms)
+        )
+    }
+
+    /// Zooms in just enough to hide the moving borders, then sharpens what
+    /// the resampling softened.
+    pub fn transform_filters(self, transforms: &str) -> Vec<String> {
+        vec![
+            format!(
+                "vidstabtransform=input={}:smoothing={}:optzoom=1",
+                escape_value(transforms),
+                self.settings().1
+            ),
+            "unsharp=5:5:0.8:3:3:0.4".to_string(),
+        ]
+    }
+}
+
+/// Denoising filters, see `Denoise`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DenoiseFilter {
+    /// Fast, can smear fine detail
+    #[default]
+    Hqdn3d,
+    /// Non-local means, keeps detail but is very slow
+    Nlmeans,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DenoiseStrength {
+    Light,
+    #[default]
+    Medium,
+    Strong,
+}
+
+/// Noise removal before encoding, so the bitrate goes to the picture rather
+/// than to the noise of low-light recordings.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Denoise {
+    #[serde(default)]
+    pub filter: DenoiseFilter,
+    #[serde(default)]
+    pub strength: DenoiseStrength,
+}
+
+impl Denoise {
+    pub fn filter(self) -> String {
+        match (self.filter, self.strength) {
+            // Spatial luma, chroma, then temporal luma, chroma. Medium is the
+            // default of hqdn3d.
+            (DenoiseFilter::Hqdn3d, DenoiseStrength::Light) => "hqdn3d=2:1.5:3:2.25",
+            (DenoiseFilter::Hqdn3d, DenoiseStrength::Medium) => "hqdn3d=4:3:6:4.5",
+            (DenoiseFilter::Hqdn3d, DenoiseStrength::Strong) => "hqdn3d=8:6:12:9",
+            (DenoiseFilter::Nlmeans, DenoiseStrength::Light) => "nlmeans=s=2",
+            (DenoiseFilter::Nlmeans, DenoiseStrength::Medium) => "nlmeans=s=4",
+            (DenoiseFilter::Nlmeans, DenoiseStrength::Strong) => "nlmeans=s=7",
+        }
+        .to_string()
+    }
+
+    pub fn is_slow(self) -> bool {
+        self.filter == DenoiseFilter::Nlmeans
+    }
+}
+
+/// Deinterlacing of interlaced sources, such as old camcorder footage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Deinterlace {
+    /// bwdif, if `idet` finds the start of the selection interlaced
+    Auto,
+    Yadif,
+    /// Keeps more detail than yadif, but is slower
+    Bwdif,
+}
+
+/// Curve `tonemap` squeezes HDR highlights into SDR with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TonemapOperator {
+    /// Filmic, keeps detail in the highlights
+    #[default]
+    Hable,
+    /// Keeps the midtones, compresses the highlights late
+    Mobius,
+    Reinhard,
+    /// Clips everything above SDR white
+    Clip,
+    Linear,
+}
+
+impl TonemapOperator {
+    /// Converts HDR (PQ or HLG, BT.2020) to BT.709 SDR through linear light.
+    pub fn filters(self) -> Vec<String> {
+        let operator = match self {
+            TonemapOperator::Hable => "hable",
+            TonemapOperator::Mobius => "mobius",
+            TonemapOperator::Reinhard => "reinhard",
+            TonemapOperator::Clip => "clip",
+            TonemapOperator::Linear => "linear",
+        };
+        vec![
+            "zscale=t=linear:npl=100".to_string(),
+            "format=gbrpf32le".to_string(),
+            "zscale=p=bt709".to_string(),
+            format!("tonemap=tonemap={}:desat=0", operator),
+            "zscale=t=bt709:m=bt709:r=tv".to_string(),
+            "format=yuv420p".to_string(),
+        ]
+    }
+}
+
+/// Quick grading with `eq`, and `colortemperature` for the white balance.
+/// Missing values leave the picture as is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ColorAdjustment {
+    /// -1 to 1, 0 keeps it
+    pub brightness: Option<f64>,
+    /// 0 to 2, 1 keeps it
+    pub contrast: Option<f64>,
+    /// 0 for grayscale to 3, 1 keeps it
+    pub saturation: Option<f64>,
+    /// 0.1 to 10, 1 keeps it
+    pub gamma: Option<f64>,
+    /// White balance in Kelvin from 1000 (warm) to 40000 (cold), 6500 keeps it
+    pub temperature: Option<f64>,
+}
+
+impl ColorAdjustment {
+    pub fn validate(&self) -> Result<(), String> {
+        let ranges = [
+            ("brightness", self.brightness, -1.0, 1.0),
+            ("contrast", self.contrast, 0.0, 2.0),
+            ("saturation", self.saturation, 0.0, 3.0),
+            ("gamma", self.gamma, 0.1, 10.0),
+            (
+                "white balance temperature",
+                self.temperature,
+                1000.0,
+                40000.0,
+            ),
+        ];
+        for (name, value, min, max) in ranges {
+            if value.is_some_and(|value| !(min..=max).contains(&value)) {
+                return Err(format!("The {} must be between {} and {}", name, min, max));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn filters(&self) -> Vec<String> {
+        let options: Vec<String> = [
+            ("brightness", self.brightness),
+            ("contrast", self.contrast),
+            ("saturation", self.saturation),
+            ("gamma", self.gamma),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some(format!("{}={}", name, value?)))
+        .collect();
+
+        let mut filters = vec![];
+        if !options.is_empty() {
+            filters.push(format!("eq={}", options.join(":")));
+        }
+        if let Some(temperature) = self.temperature {
+            filters.push(format!("colortemperature=temperature={}", temperature));
+        }
+        filters
+    }
+}
+
+/// `atempo` filters changing the audio speed by `speed`. Each one only takes
+/// factors from 0.5 to 2, so larger changes are chained.
+pub fn atempo_filters(speed: f64) -> Vec<String> {
+    let mut remaining = speed;
+    let mut filters = vec![];
+    while remaining > 2.0 {
+        filters.push("atempo=2".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        filters.push("atempo=0.5".to_string());
+        remaining /= 0.5;
+    }
+    // Rounded, repeated halving leaves float noise like 0.8000000000000002
+    let remaining = (remaining * 1e6).round() / 1e6;
+    if remaining != 1.0 {
+        filters.push(format!("atempo={}", remaining));
+    }
+    filters
+}
+
+/// Image laid over the finished picture, after crop, transforms and scaling,
+/// so its position and size refer to the output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Watermark {
+    pub image_path: String,
+    #[serde(flatten)]
+    pub position: OverlayPosition,
+    /// Width of the image as a share of the output width, its own size when unset
+    pub scale: Option<f64>,
+    /// 0.0 (invisible) - 1.0 (opaque, the default)
+    pub opacity: Option<f64>,
+    /// Seconds of the exported clip the image is shown from and until
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl Watermark {
+    pub fn validate(&self) -> Result<(), String> {
+        if !Path::new(&self.image_path).is_file() {
+            return Err(format!("Watermark image {} not found", self.image_path));
+        }
+        if self.scale.is_some_and(|scale| scale <= 0.0) {
+            return Err("The watermark scale must be positive".to_string());
+        }
+        if self
+            .opacity
+            .is_some_and(|opacity| !(0.0..=1.0).contains(&opacity))
+        {
+            return Err("The watermark opacity must be between 0 and 1".to_string());
+        }
+        validate_time_range(self.start, self.end)
+    }
+
+    /// Filters preparing the image for an output `video_width` pixels wide.
+    pub fn image_filters(&self, video_width: u32) -> String {
+        let mut filters = vec!["format=rgba".to_string()];
+        if let Some(scale) = self.scale {
+            let width = ((video_width as f64 * scale) as u32).max(2) & !1;
+            filters.push(format!("scale={}:-2", width));
+        }
+        if let Some(opacity) = self.opacity.filter(|opacity| *opacity < 1.0) {
+            filters.push(format!("colorchannelmixer=aa={}", opacity));
+        }
+        filters.join(",")
+    }
+
+    /// The `overlay` filter. `time_offset` is the timestamp the clip starts
+    /// at in the filtered stream.
+    pub fn overlay_filter(&self, time_offset: f64) -> String {
+        let (x, y) = self
+            .position
+            .exprs(("main_w", "main_h"), ("overlay_w", "overlay_h"));
+
+        let mut filter = format!("overlay=x={}:y={}", x, y);
+        if let Some(enable) = enable_option(self.start, self.end, time_offset) {
+            filter.push(':');
+            filter.push_str(&enable);
+        }
+        filter
+    }
+}
+
+/// Escapes a filter option value twice: once for the option parser of the
+/// filter, once for the filter graph parser around it.
+pub fn escape_value(value: &str) -> String {
+    let escape_chars = |value: &str, special: &[char]| {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if special.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    };
+
+    let option = escape_chars(value, &['\\', '\'', ':']);
+    escape_chars(&option, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+/// Piecewise linear interpolation between `(time, value)` points as an FFmpeg
+/// expression of `time`, holding the first and last value outside of them.
+/// `points` must be sorted by time.
+pub fn interpolation_expr(points: &[(f64, f64)], time: &str) -> String {
+    let Some(&(_, last_value)) = points.last() else {
+        return "0".to_string();
+    };
+
+    let mut expr = last_value.to_string();
+    for pair in points.windows(2).rev() {
+        let ((t0, v0), (t1, v1)) = (pair[0], pair[1]);
+        if t1 - t0 <= 0.0 {
+            continue;
+        }
+        expr = format!(
+            "if(lt({time},{t1}),{v0}+({v1}-{v0})*({time}-{t0})/{span},{expr})",
+            time = time,
+            t0 = t0,
+            t1 = t1,
+            v0 = v0,
+            v1 = v1,
+            span = t1 - t0,
+            expr = expr
+        );
+    }
+
+    let (first_time, first_value) = points[0];
+    format!("if(lt({},{}),{},{})", time, first_time, first_value, expr)
+}
@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tauri::http::{Request, Response, ResponseBuilder};
+use tauri::{AppHandle, Manager, Url};
+
+use crate::binaries;
+use crate::thumbnails::preview_filters;
+
+/// Scheme the preview loads exact frames from, as in
+/// `frame://localhost/?path=/videos/a.mp4&t=12.345&w=640`. Windows serves
+/// it as `https://frame.localhost/`.
+pub const SCHEME: &str = "frame";
+
+// Frames kept in memory, enough to step back and forth over a few seconds
+const MAX_CACHED_FRAMES: usize = 240;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FrameRequest {
+    input_path: String,
+    /// Milliseconds, finer than any frame rate the preview shows
+    time_ms: u64,
+    max_width: Option<u32>,
+}
+
+fn parse_request(uri: &str) -> Result<FrameRequest, String> {
+    let url = Url::parse(uri).map_err(|e| format!("Invalid frame URL {}: {}", uri, e))?;
+    let mut input_path = None;
+    let mut time = None;
+    let mut max_width = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "path" => input_path = Some(value.into_owned()),
+            "t" => time = value.parse::<f64>().ok(),
+            "w" => max_width = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    Ok(FrameRequest {
+        input_path: input_path.ok_or("The frame URL has no path")?,
+        time_ms: (time.ok_or("The frame URL has no time")?.max(0.0) * 1000.0).round() as u64,
+        max_width,
+    })
+}
+
+/// Recently served frames, most recent last.
+#[derive(Default)]
+pub struct FrameCache {
+    frames: Mutex<VecDeque<(FrameRequest, Arc<Vec<u8>>)>>,
+}
+
+impl FrameCache {
+    fn get(&self, request: &FrameRequest) -> Option<Arc<Vec<u8>>> {
+        let mut frames = self.frames.lock().unwrap();
+        let index = frames.iter().position(|(cached, _)| cached == request)?;
+        let entry = frames.remove(index)?;
+        let image = entry.1.clone();
+        frames.push_back(entry);
+        Some(image)
+    }
+
+    fn insert(&self, request: FrameRequest, image: Arc<Vec<u8>>) {
+        let mut frames = self.frames.lock().unwrap();
+        frames.push_back((request, image));
+        while frames.len() > MAX_CACHED_FRAMES {
+            frames.pop_front();
+        }
+    }
+}
+
+/// Decodes the frame shown at the requested time as a JPEG. Seeking on the
+/// input decodes from the previous keyframe, so the frame is exact while
+/// seeking a `<video>` element may land on a neighbouring one.
+fn extract_frame(request: &FrameRequest) -> Result<Vec<u8>, String> {
+    let mut command = std::process::Command::new(binaries::ffmpeg());
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-ss"])
+        .arg(format!("{:.3}", request.time_ms as f64 / 1000.0))
+        .args(["-i", &request.input_path, "-frames:v", "1"]);
+    if let Some(filters) = preview_filters(None, request.max_width) {
+        command.args(["-vf", &filters]);
+    }
+    let result = command
+        .args(["-f", "image2pipe", "-c:v", "mjpeg", "-q:v", "3", "pipe:1"])
+        .output()
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+    if !result.status.success() || result.stdout.is_empty() {
+        return Err(format!(
+            "Could not extract a frame at {}ms: {}",
+            request.time_ms,
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    Ok(result.stdout)
+}
+
+/// Handler of the `frame` scheme.
+pub fn serve(
+    app_handle: &AppHandle,
+    request: &Request,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let frame = match parse_request(request.uri()) {
+        Ok(frame) => frame,
+        Err(e) => {
+            return ResponseBuilder::new()
+                .status(400)
+                .mimetype("text/plain")
+                .body(e.into_bytes())
+        }
+    };
+
+    let cache = app_handle.state::<FrameCache>();
+    let image = match cache.get(&frame) {
+        Some(image) => image,
+        None => match extract_frame(&frame) {
+            Ok(image) => {
+                let image = Arc::new(image);
+                cache.insert(frame, image.clone());
+                image
+            }
+            Err(e) => {
+                println!("{}", e);
+                return ResponseBuilder::new()
+                    .status(404)
+                    .mimetype("text/plain")
+                    .body(e.into_bytes());
+            }
+        },
+    };
+
+    ResponseBuilder::new()
+        .status(200)
+        .mimetype("image/jpeg")
+        .header("Cache-Control", "max-age=3600")
+        .body(image.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_urls_carry_path_time_and_width() {
+        let request =
+            parse_request("frame://localhost/?path=%2Fvideos%2Fmy%20clip.mp4&t=12.3456&w=640")
+                .unwrap();
+        assert_eq!(
+            request,
+            FrameRequest {
+                input_path: "/videos/my clip.mp4".to_string(),
+                time_ms: 12346,
+                max_width: Some(640),
+            }
+        );
+        // Windows serves custom schemes over https
+        let request = parse_request("https://frame.localhost/?path=C%3A%5Ca.mp4&t=1").unwrap();
+        assert_eq!(request.input_path, "C:\\a.mp4");
+        assert_eq!(request.max_width, None);
+
+        assert!(parse_request("frame://localhost/?path=%2Fa.mp4").is_err());
+    }
+}
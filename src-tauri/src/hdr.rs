@@ -0,0 +1,221 @@
+use serde::Serialize;
+
+use crate::codecs::VideoCodec;
+use crate::encoders::Encoder;
+
+/// Display an HDR10 source was graded on, as CIE 1931 xy chromaticities and
+/// luminances in cd/m².
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MasteringDisplay {
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+    pub white_point: (f64, f64),
+    pub min_luminance: f64,
+    pub max_luminance: f64,
+}
+
+impl MasteringDisplay {
+    /// `master-display` of x265, counted in 0.00002 and 0.0001 cd/m² steps.
+    fn x265_value(&self) -> String {
+        let xy = |(x, y): (f64, f64)| {
+            format!(
+                "({},{})",
+                (x * 50000.0).round() as u32,
+                (y * 50000.0).round() as u32
+            )
+        };
+        format!(
+            "G{}B{}R{}WP{}L({},{})",
+            xy(self.green),
+            xy(self.blue),
+            xy(self.red),
+            xy(self.white_point),
+            (self.max_luminance * 10000.0).round() as u32,
+            (self.min_luminance * 10000.0).round() as u32
+        )
+    }
+
+    /// `mastering-display` of SVT-AV1, which takes the plain values.
+    fn svt_av1_value(&self) -> String {
+        let xy = |(x, y): (f64, f64)| format!("({:.4},{:.4})", x, y);
+        format!(
+            "G{}B{}R{}WP{}L({:.4},{:.4})",
+            xy(self.green),
+            xy(self.blue),
+            xy(self.red),
+            xy(self.white_point),
+            self.max_luminance,
+            self.min_luminance
+        )
+    }
+}
+
+/// Brightest pixel and brightest frame average of the source, in cd/m².
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ContentLight {
+    pub max_content: u32,
+    pub max_average: u32,
+}
+
+/// Colors of an HDR source that a re-encode keeps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrSignal {
+    /// FFmpeg names, e.g. `bt2020`
+    pub primaries: String,
+    /// `smpte2084` (PQ) or `arib-std-b67` (HLG)
+    pub transfer: String,
+    /// e.g. `bt2020nc`
+    pub matrix: String,
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light: Option<ContentLight>,
+}
+
+impl HdrSignal {
+    /// Whether `encoder` writes the mastering display and content light of
+    /// the source; hardware encoders only keep the color tags.
+    pub fn keeps_mastering(&self, encoder: Encoder) -> bool {
+        matches!(encoder, Encoder::Cpu | Encoder::Auto)
+            || (self.mastering_display.is_none() && self.content_light.is_none())
+    }
+
+    /// 10-bit pictures tagged with the colors of the source, plus its HDR10
+    /// metadata for the software encoders.
+    pub fn encoder_args(&self, encoder: Encoder, codec: VideoCodec) -> Vec<String> {
+        let software = matches!(encoder, Encoder::Cpu | Encoder::Auto);
+        // Hardware encoders take 10-bit frames as P010
+        let pix_fmt = if software { "yuv420p10le" } else { "p010le" };
+        let mut args: Vec<String> = ["-pix_fmt", pix_fmt].map(String::from).to_vec();
+        if !software && codec == VideoCodec::Hevc {
+            args.extend(["-profile:v", "main10"].map(String::from));
+        }
+        args.extend([
+            "-color_primaries".to_string(),
+            self.primaries.clone(),
+            "-color_trc".to_string(),
+            self.transfer.clone(),
+            "-colorspace".to_string(),
+            self.matrix.clone(),
+        ]);
+        if !software {
+            return args;
+        }
+
+        match codec {
+            VideoCodec::Hevc => {
+                // libx265 does not read the tags FFmpeg sets on the stream
+                let mut params = vec![
+                    "repeat-headers=1".to_string(),
+                    format!("colorprim={}", self.primaries),
+                    format!("transfer={}", self.transfer),
+                    format!("colormatrix={}", self.matrix),
+                ];
+                if let Some(display) = &self.mastering_display {
+                    params.push("hdr10=1".to_string());
+                    params.push(format!("master-display={}", display.x265_value()));
+                }
+                if let Some(light) = self.content_light {
+                    params.push(format!(
+                        "max-cll={},{}",
+                        light.max_content, light.max_average
+                    ));
+                }
+                args.extend(["-x265-params".to_string(), params.join(":")]);
+            }
+            VideoCodec::Av1 => {
+                let mut params = vec![];
+                if let Some(display) = &self.mastering_display {
+                    params.push(format!("mastering-display={}", display.svt_av1_value()));
+                }
+                if let Some(light) = self.content_light {
+                    params.push(format!(
+                        "content-light={},{}",
+                        light.max_content, light.max_average
+                    ));
+                }
+                if !params.is_empty() {
+                    args.extend(["-svtav1-params".to_string(), params.join(":")]);
+                }
+            }
+            _ => {}
+        }
+        args
+    }
+}
+
+/// Joins repeated `-x265-params` into the first, as FFmpeg only keeps the
+/// last.
+pub fn merge_x265_params(ffmpeg_args: &mut Vec<String>) {
+    let mut first: Option<usize> = None;
+    let mut index = 0;
+    while index + 1 < ffmpeg_args.len() {
+        if ffmpeg_args[index] != "-x265-params" {
+            index += 1;
+            continue;
+        }
+        match first {
+            Some(first) => {
+                let params = ffmpeg_args[index + 1].clone();
+                ffmpeg_args[first + 1].push(':');
+                ffmpeg_args[first + 1].push_str(&params);
+                ffmpeg_args.drain(index..index + 2);
+            }
+            None => {
+                first = Some(index);
+                index += 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x265_gets_the_hdr10_metadata_of_the_source() {
+        let signal = HdrSignal {
+            primaries: "bt2020".to_string(),
+            transfer: "smpte2084".to_string(),
+            matrix: "bt2020nc".to_string(),
+            mastering_display: Some(MasteringDisplay {
+                red: (0.708, 0.292),
+                green: (0.17, 0.797),
+                blue: (0.131, 0.046),
+                white_point: (0.3127, 0.329),
+                min_luminance: 0.0001,
+                max_luminance: 1000.0,
+            }),
+            content_light: Some(ContentLight {
+                max_content: 1000,
+                max_average: 400,
+            }),
+        };
+        let mut args = signal.encoder_args(Encoder::Cpu, VideoCodec::Hevc);
+        args.extend(["-x265-params", "pass=1"].map(String::from));
+        merge_x265_params(&mut args);
+        assert_eq!(
+            args,
+            [
+                "-pix_fmt",
+                "yuv420p10le",
+                "-color_primaries",
+                "bt2020",
+                "-color_trc",
+                "smpte2084",
+                "-colorspace",
+                "bt2020nc",
+                "-x265-params",
+                "repeat-headers=1:colorprim=bt2020:transfer=smpte2084:colormatrix=bt2020nc:\
+                 hdr10=1:master-display=G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)\
+                 L(10000000,1):max-cll=1000,400:pass=1"
+            ]
+        );
+
+        assert!(!signal.keeps_mastering(Encoder::Nvenc));
+        assert_eq!(
+            signal.encoder_args(Encoder::Nvenc, VideoCodec::Hevc)[..4],
+            ["-pix_fmt", "p010le", "-profile:v", "main10"]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, Window};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::ffmpeg::ExportArgs;
+use crate::jobs::{JobId, JobStatus};
+use crate::queue::enqueue;
+
+// Older entries are dropped
+const MAX_HISTORY_ENTRIES: usize = 500;
+
+/// A job that ended, kept in `history.json` in the app data directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub job_id: JobId,
+    /// The export as requested, before presets and settings were applied
+    pub args: ExportArgs,
+    pub status: JobStatus,
+    pub output_paths: Vec<String>,
+    pub error: Option<String>,
+    /// Unix seconds
+    pub started_at: u64,
+    /// Seconds from start to end
+    pub duration: f64,
+}
+
+/// A job on its way into the history.
+#[derive(Clone)]
+pub struct PendingEntry {
+    job_id: JobId,
+    args: ExportArgs,
+    started: SystemTime,
+}
+
+impl PendingEntry {
+    pub fn start(job_id: JobId, args: &ExportArgs) -> Self {
+        Self {
+            job_id,
+            args: args.clone(),
+            started: SystemTime::now(),
+        }
+    }
+
+    pub fn finish(
+        self,
+        status: JobStatus,
+        output_paths: Vec<String>,
+        error: Option<String>,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            job_id: self.job_id,
+            args: self.args,
+            status,
+            output_paths,
+            error,
+            started_at: self
+                .started
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or_default(),
+            duration: self.started.elapsed().unwrap_or_default().as_secs_f64(),
+        }
+    }
+}
+
+// Serializes read-modify-write cycles of the history file
+static HISTORY_FILE: Mutex<()> = Mutex::const_new(());
+
+fn history_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .ok_or("Failed to get app directory")?
+        .join("history.json"))
+}
+
+async fn read_history(app_handle: &AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    match fs::read(history_path(app_handle)?).await {
+        Ok(json) => {
+            serde_json::from_slice(&json).map_err(|e| format!("Invalid history file: {}", e))
+        }
+        // Nothing exported yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(format!("Cannot read history: {}", e)),
+    }
+}
+
+async fn write_history(app_handle: &AppHandle, history: &[HistoryEntry]) -> Result<(), String> {
+    let path = history_path(app_handle)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Cannot create app dir: {}", e))?;
+    }
+    let json = serde_json::to_vec_pretty(history).map_err(|e| e.to_string())?;
+    fs::write(&path, json)
+        .await
+        .map_err(|e| format!("Cannot write history: {}", e))
+}
+
+/// Adds an ended job. Failing to only costs the entry, so it is logged.
+pub async fn record(app_handle: &AppHandle, entry: HistoryEntry) {
+    let _guard = HISTORY_FILE.lock().await;
+    let result = async {
+        let mut history = read_history(app_handle).await?;
+        history.push(entry);
+        let excess = history.len().saturating_sub(MAX_HISTORY_ENTRIES);
+        history.drain(..excess);
+        write_history(app_handle, &history).await
+    };
+    if let Err(e) = result.await {
+        println!("Could not record the job in the history: {}", e);
+    }
+}
+
+/// The entry of an ended job, if it is still in the history.
+pub async fn find(app_handle: &AppHandle, job_id: JobId) -> Result<Option<HistoryEntry>, String> {
+    let _guard = HISTORY_FILE.lock().await;
+    Ok(read_history(app_handle)
+        .await?
+        .into_iter()
+        .find(|entry| entry.job_id == job_id))
+}
+
+/// Ended jobs, the latest first.
+#[tauri::command]
+pub async fn list_history(app_handle: AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    let _guard = HISTORY_FILE.lock().await;
+    let mut history = read_history(&app_handle).await?;
+    history.reverse();
+    Ok(history)
+}
+
+/// Queues the export of a past job again, with the settings it was requested
+/// with. Returns the id of the new job.
+#[tauri::command]
+pub async fn rerun_job(window: Window, job_id: JobId) -> Result<JobId, String> {
+    let args = find(&window.app_handle(), job_id)
+        .await?
+        .map(|entry| entry.args)
+        .ok_or(format!("No job {} in the history", job_id))?;
+    Ok(enqueue(window, args))
+}
+
+/// Removes the given entries, or all of them when `job_ids` is left out.
+#[tauri::command]
+pub async fn clear_history(
+    app_handle: AppHandle,
+    job_ids: Option<Vec<JobId>>,
+) -> Result<(), String> {
+    let _guard = HISTORY_FILE.lock().await;
+    let mut history = read_history(&app_handle).await?;
+    match job_ids {
+        Some(ids) => history.retain(|entry| !ids.contains(&entry.job_id)),
+        None => history.clear(),
+    }
+    write_history(&app_handle, &history).await
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+use crate::codecs::VideoCodec;
+
+/// Editing codec settings for round trips through Premiere, Resolve or
+/// Final Cut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditingPreset {
+    ProresProxy,
+    ProresLt,
+    #[serde(rename = "prores_422")]
+    Prores422,
+    ProresHq,
+    /// Carries the alpha of the source
+    #[serde(rename = "prores_4444")]
+    Prores4444,
+    #[serde(rename = "prores_4444_xq")]
+    Prores4444Xq,
+    DnxhrLb,
+    DnxhrSq,
+    DnxhrHq,
+    /// 10-bit 4:2:2
+    DnxhrHqx,
+    #[serde(rename = "dnxhr_444")]
+    Dnxhr444,
+}
+
+impl EditingPreset {
+    pub fn codec(self) -> VideoCodec {
+        match self {
+            EditingPreset::ProresProxy
+            | EditingPreset::ProresLt
+            | EditingPreset::Prores422
+            | EditingPreset::ProresHq
+            | EditingPreset::Prores4444
+            | EditingPreset::Prores4444Xq => VideoCodec::Prores,
+            _ => VideoCodec::Dnxhr,
+        }
+    }
+
+    /// The preset of an editing `codec` chosen without one, keeping the alpha
+    /// channel when the source has one.
+    pub fn default_for(codec: VideoCodec, alpha: bool) -> Option<Self> {
+        match codec {
+            VideoCodec::Prores if alpha => Some(EditingPreset::Prores4444),
+            VideoCodec::Prores => Some(EditingPreset::ProresHq),
+            VideoCodec::Dnxhr => Some(EditingPreset::DnxhrHq),
+            _ => None,
+        }
+    }
+
+    pub fn supports_alpha(self) -> bool {
+        matches!(
+            self,
+            EditingPreset::Prores4444 | EditingPreset::Prores4444Xq
+        )
+    }
+
+    /// Profile and pixel format of the encoder, with an alpha channel when
+    /// `alpha` and the preset has room for it.
+    pub fn encoder_args(self, alpha: bool) -> Vec<String> {
+        let (profile, pix_fmt) = match self {
+            EditingPreset::ProresProxy => ("0", "yuv422p10le"),
+            EditingPreset::ProresLt => ("1", "yuv422p10le"),
+            EditingPreset::Prores422 => ("2", "yuv422p10le"),
+            EditingPreset::ProresHq => ("3", "yuv422p10le"),
+            EditingPreset::Prores4444 if alpha => ("4", "yuva444p10le"),
+            EditingPreset::Prores4444 => ("4", "yuv444p10le"),
+            EditingPreset::Prores4444Xq if alpha => ("5", "yuva444p10le"),
+            EditingPreset::Prores4444Xq => ("5", "yuv444p10le"),
+            EditingPreset::DnxhrLb => ("dnxhr_lb", "yuv422p"),
+            EditingPreset::DnxhrSq => ("dnxhr_sq", "yuv422p"),
+            EditingPreset::DnxhrHq => ("dnxhr_hq", "yuv422p"),
+            EditingPreset::DnxhrHqx => ("dnxhr_hqx", "yuv422p10le"),
+            EditingPreset::Dnxhr444 => ("dnxhr_444", "yuv444p10le"),
+        };
+        let mut args: Vec<String> = ["-profile:v", profile, "-pix_fmt", pix_fmt]
+            .map(String::from)
+            .to_vec();
+        // Apple apps check the vendor before trusting the profile
+        if self.codec() == VideoCodec::Prores {
+            args.extend(["-vendor", "apl0"].map(String::from));
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::has_alpha;
+
+    #[test]
+    fn prores_keeps_the_alpha_it_has_room_for() {
+        assert!(has_alpha("yuva444p10le"));
+        assert!(has_alpha("argb"));
+        assert!(!has_alpha("yuv420p"));
+
+        let preset = EditingPreset::default_for(VideoCodec::Prores, true).unwrap();
+        assert_eq!(preset, serde_json::from_str("\"prores_4444\"").unwrap());
+        assert_eq!(
+            preset.encoder_args(true),
+            [
+                "-profile:v",
+                "4",
+                "-pix_fmt",
+                "yuva444p10le",
+                "-vendor",
+                "apl0"
+            ]
+        );
+        // 4:2:2 ProRes has no alpha channel
+        assert_eq!(
+            EditingPreset::ProresHq.encoder_args(true)[..4],
+            ["-profile:v", "3", "-pix_fmt", "yuv422p10le"]
+        );
+        assert_eq!(
+            EditingPreset::DnxhrHqx.encoder_args(false),
+            ["-profile:v", "dnxhr_hqx", "-pix_fmt", "yuv422p10le"]
+        );
+    }
+}
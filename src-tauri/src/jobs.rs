@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::api::process::CommandChild;
+use tauri::{AppHandle, Manager, State, Window};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::cli::{print_event, Headless};
+use crate::history;
+use crate::power::SleepInhibitor;
+
+pub type JobId = Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+/// Payload of `ffmpeg-cancelled`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobMessage {
+    pub job_id: JobId,
+    pub message: String,
+}
+
+impl JobMessage {
+    pub fn new(job_id: JobId, message: impl Into<String>) -> Self {
+        Self {
+            job_id,
+            message: message.into(),
+        }
+    }
+}
+
+/// Payload of `ffmpeg-finished`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobFinished {
+    pub job_id: JobId,
+    pub message: String,
+    /// Files written, after any renaming to avoid existing files
+    pub output_paths: Vec<String>,
+}
+
+/// Emits `payload` on `event`, or prints it when running from the command
+/// line. A failed emit only means the window is gone, which must not take
+/// the export task down with it.
+pub fn emit_event<S: Serialize + Clone>(window: &Window, event: &str, payload: S) {
+    if window.try_state::<Headless>().is_some() {
+        print_event(event, &payload);
+        return;
+    }
+    if let Err(e) = window.emit(event, payload) {
+        println!("Could not emit {}: {}", event, e);
+    }
+}
+
+/// A spawned FFmpeg process and the files it is writing to.
+pub struct RunningJob {
+    pub child: CommandChild,
+    pub output_paths: Vec<String>,
+}
+
+/// A job from its first pass until its last one exits.
+#[derive(Default)]
+struct TrackedJob {
+    /// None between passes
+    pass: Option<RunningJob>,
+    /// Set by `cancel_export`, so no further pass is started
+    cancelled: bool,
+}
+
+/// Managed state holding every FFmpeg child process that is still running,
+/// the status of every job that has not ended yet, and the FFmpeg stderr of jobs that
+/// have not been written to their log file yet.
+///
+/// A job stays registered across all of its passes. `cancel_export` marks it
+/// cancelled before killing the running pass, so the monitor task can tell a
+/// cancelled job apart from one that exited on its own. `kill_all` removes
+/// every job and kills their passes when the app exits.
+#[derive(Default)]
+pub struct JobRegistry {
+    running: Mutex<HashMap<JobId, TrackedJob>>,
+    statuses: Mutex<HashMap<JobId, JobStatus>>,
+    logs: Mutex<HashMap<JobId, String>>,
+    /// Set when the user chose to quit once the running jobs are done
+    quitting: AtomicBool,
+    /// Set by a `Shutdown` post-export action
+    shutdown_when_idle: AtomicBool,
+    /// Held while any job is running
+    sleep_inhibitor: Mutex<Option<SleepInhibitor>>,
+    /// Set when the user opted out of keeping the system awake
+    allow_sleep: AtomicBool,
+}
+
+impl JobRegistry {
+    pub fn next_id(&self) -> JobId {
+        Uuid::new_v4()
+    }
+
+    pub fn set_status(&self, id: JobId, status: JobStatus) {
+        self.statuses.lock().unwrap().insert(id, status);
+        self.update_sleep_inhibitor();
+    }
+
+    pub fn set_prevent_sleep(&self, enabled: bool) {
+        self.allow_sleep.store(!enabled, Ordering::SeqCst);
+        self.update_sleep_inhibitor();
+    }
+
+    /// Keeps the system awake while a job runs, unless opted out.
+    fn update_sleep_inhibitor(&self) {
+        let wanted = self.has_running_jobs() && !self.allow_sleep.load(Ordering::SeqCst);
+        let mut inhibitor = self.sleep_inhibitor.lock().unwrap();
+        if wanted == inhibitor.is_some() {
+            return;
+        }
+        if !wanted {
+            *inhibitor = None;
+            return;
+        }
+        match SleepInhibitor::acquire() {
+            Ok(acquired) => *inhibitor = Some(acquired),
+            Err(e) => println!("Could not keep the system awake: {}", e),
+        }
+    }
+
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.statuses.lock().unwrap().get(&id).copied()
+    }
+
+    /// Drops the status of a job that ended and is in the history, or that
+    /// left the queue without running.
+    pub fn forget(&self, id: JobId) {
+        self.statuses.lock().unwrap().remove(&id);
+    }
+
+    pub fn has_running_jobs(&self) -> bool {
+        self.statuses
+            .lock()
+            .unwrap()
+            .values()
+            .any(|status| *status == JobStatus::Running)
+    }
+
+    pub fn is_quitting(&self) -> bool {
+        self.quitting.load(Ordering::SeqCst)
+    }
+
+    pub fn shutdown_when_idle(&self) -> bool {
+        self.shutdown_when_idle.load(Ordering::SeqCst)
+    }
+
+    pub fn set_shutdown_when_idle(&self, shutdown: bool) {
+        self.shutdown_when_idle.store(shutdown, Ordering::SeqCst);
+    }
+
+    /// Tracks a job about to start its first pass, until `remove`.
+    pub fn register(&self, id: JobId) {
+        self.running
+            .lock()
+            .unwrap()
+            .insert(id, TrackedJob::default());
+    }
+
+    /// Registers the pass the job is running. A pass started after the job
+    /// was cancelled, or removed by `kill_all`, is killed right away.
+    pub fn insert(&self, id: JobId, pass: RunningJob) {
+        let mut running = self.running.lock().unwrap();
+        match running.get_mut(&id) {
+            Some(job) if !job.cancelled => job.pass = Some(pass),
+            _ => {
+                if let Err(e) = pass.child.kill() {
+                    println!("Could not kill FFmpeg: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Files the job is writing, none between passes or when it is not
+    /// running.
+    pub fn output_paths(&self, id: JobId) -> Vec<String> {
+        self.running
+            .lock()
+            .unwrap()
+            .get(&id)
+            .and_then(|job| job.pass.as_ref())
+            .map(|pass| pass.output_paths.clone())
+            .unwrap_or_default()
+    }
+
+    /// Unregisters the pass that exited, keeping the job. Returns whether
+    /// the job goes on, which it does not once cancelled or killed.
+    pub fn end_pass(&self, id: JobId) -> bool {
+        match self.running.lock().unwrap().get_mut(&id) {
+            Some(job) => {
+                job.pass = None;
+                !job.cancelled
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the job is still registered and was not cancelled.
+    pub fn is_active(&self, id: JobId) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .get(&id)
+            .is_some_and(|job| !job.cancelled)
+    }
+
+    /// Marks the job cancelled and hands back the pass to kill, if one is
+    /// running. None for jobs that are not registered.
+    pub fn cancel(&self, id: JobId) -> Option<Option<RunningJob>> {
+        let mut running = self.running.lock().unwrap();
+        let job = running.get_mut(&id)?;
+        job.cancelled = true;
+        Some(job.pass.take())
+    }
+
+    /// Removes the job once its last pass has exited.
+    pub fn remove(&self, id: JobId) {
+        self.running.lock().unwrap().remove(&id);
+    }
+
+    /// Kills every FFmpeg process and deletes the files they were writing,
+    /// which would be unplayable anyway.
+    pub fn kill_all(&self) {
+        let running: Vec<_> = self.running.lock().unwrap().drain().collect();
+        for (id, pass) in running
+            .into_iter()
+            .filter_map(|(id, job)| Some((id, job.pass?)))
+        {
+            println!("Killing export {} ({})", id, pass.output_paths.join(", "));
+            if let Err(e) = pass.child.kill() {
+                println!("Could not kill FFmpeg: {}", e);
+            }
+            for output_path in &pass.output_paths {
+                if let Err(e) = std::fs::remove_file(output_path) {
+                    println!("Could not remove partial output {}: {}", output_path, e);
+                }
+            }
+        }
+    }
+
+    pub fn append_log(&self, id: JobId, line: &str) {
+        let mut logs = self.logs.lock().unwrap();
+        let log = logs.entry(id).or_default();
+        log.push_str(line.trim_end());
+        log.push('\n');
+    }
+
+    pub fn log(&self, id: JobId) -> Option<String> {
+        self.logs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Writes the buffered log of a job that has ended to its log file and
+    /// drops it from memory.
+    pub async fn persist_log(&self, app_handle: &AppHandle, id: JobId) -> Result<(), String> {
+        let Some(log) = self.log(id) else {
+            return Ok(());
+        };
+
+        let dir = log_dir(app_handle)?;
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("Cannot create log dir: {}", e))?;
+        fs::write(log_path(&dir, id), log)
+            .await
+            .map_err(|e| format!("Cannot write job log: {}", e))?;
+
+        self.logs.lock().unwrap().remove(&id);
+        Ok(())
+    }
+}
+
+/// Directory holding the FFmpeg log of every finished job.
+fn log_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .ok_or("Failed to get app directory")?
+        .join("logs"))
+}
+
+fn log_path(dir: &std::path::Path, id: JobId) -> PathBuf {
+    dir.join(format!("{}.log", id))
+}
+
+/// Whether running jobs keep the system from sleeping, which they do unless
+/// turned off here.
+#[tauri::command]
+pub fn set_prevent_sleep(jobs: State<'_, JobRegistry>, enabled: bool) {
+    jobs.set_prevent_sleep(enabled);
+}
+
+/// Status of a queued or running job, or of an ended one from the history.
+#[tauri::command]
+pub async fn get_job_status(
+    app_handle: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    job_id: JobId,
+) -> Result<JobStatus, String> {
+    if let Some(status) = jobs.status(job_id) {
+        return Ok(status);
+    }
+
+    history::find(&app_handle, job_id)
+        .await?
+        .map(|entry| entry.status)
+        .ok_or(format!("No job with id {}", job_id))
+}
+
+/// Everything FFmpeg wrote to stderr for the job, preceded by the command line
+/// of each pass. Running jobs return what has been logged so far.
+#[tauri::command]
+pub async fn get_job_log(
+    app_handle: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    job_id: JobId,
+) -> Result<String, String> {
+    if let Some(log) = jobs.log(job_id) {
+        return Ok(log);
+    }
+
+    fs::read_to_string(log_path(&log_dir(&app_handle)?, job_id))
+        .await
+        .map_err(|_| format!("No log for job {}", job_id))
+}
+
+/// Answer to `exit-requested`, emitted when the window is closed while jobs
+/// are running. Either quits right away, killing FFmpeg, or lets the running
+/// jobs finish and quits after the last one. Queued jobs are not started.
+#[tauri::command]
+pub fn confirm_exit(app_handle: AppHandle, jobs: State<'_, JobRegistry>, finish_current: bool) {
+    if finish_current && jobs.has_running_jobs() {
+        jobs.quitting.store(true, Ordering::SeqCst);
+        return;
+    }
+
+    jobs.kill_all();
+    app_handle.exit(0);
+}
@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A smaller copy of the export, scaled from the same filtered picture by
+/// the run writing the main output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LadderRung {
+    pub height: u32,
+    /// Cap in kbit/s on top of the quality of the export
+    pub video_bitrate: Option<u32>,
+    /// Next to the main output with the height appended by default,
+    /// `clip_720p.mp4`
+    pub output_path: Option<String>,
+}
+
+impl LadderRung {
+    pub fn validate(&self, output_height: u32) -> Result<(), String> {
+        if self.height < 2 || self.height >= output_height {
+            return Err(format!(
+                "A {}p rung has to be smaller than the {} pixels of the export",
+                self.height, output_height
+            ));
+        }
+        if self.video_bitrate == Some(0) {
+            return Err("The bitrate of a rung must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Where the rung is written next to `output_path`.
+    pub fn path(&self, output_path: &str) -> String {
+        if let Some(path) = &self.output_path {
+            return path.clone();
+        }
+        let path = Path::new(output_path);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!("{}_{}p.{}", stem, self.height, extension.to_string_lossy()),
+            None => format!("{}_{}p", stem, self.height),
+        };
+        path.with_file_name(name).to_string_lossy().into_owned()
+    }
+}
+
+/// Renames the label `[label]` where `graph` makes it and splits the stream
+/// into `[label]` again plus one `[<label>_<index>]` per copy.
+fn split_label(graph: &str, label: &str, copies: usize, filter: &str) -> String {
+    let source = format!("{}_all", label);
+    let outputs: String = (0..copies)
+        .map(|index| format!("[{}_{}]", label, index))
+        .collect();
+    format!(
+        "{};[{}]{}={}[{}]{}",
+        graph.replacen(&format!("[{}]", label), &format!("[{}]", source), 1),
+        source,
+        filter,
+        copies + 1,
+        label,
+        outputs
+    )
+}
+
+/// Adds the outputs of `rungs`, written to `paths`, to the single-output
+/// FFmpeg run `ffmpeg_args`. The picture is split after every filter and
+/// scaled once per rung; filtered audio is split for each rung the same way.
+/// Every rung repeats the output options of the main output.
+pub fn add_rungs(
+    ffmpeg_args: &[String],
+    rungs: &[LadderRung],
+    paths: &[String],
+) -> Result<Vec<String>, String> {
+    let inputs_end = ffmpeg_args
+        .iter()
+        .rposition(|arg| arg == "-i")
+        .map_or(0, |i| i + 2);
+    let (inputs, output) = ffmpeg_args.split_at(inputs_end);
+    let mut output = output.to_vec();
+
+    // A plain filter on the picture becomes a labelled graph that can be split
+    if let Some(index) = output.iter().position(|arg| arg == "-filter:v") {
+        let filters = output[index + 1].clone();
+        output.splice(
+            index..index + 2,
+            [
+                "-filter_complex".to_string(),
+                format!("[0:v:0]{}[picture]", filters),
+            ],
+        );
+        let video_map = output
+            .iter()
+            .position(|arg| arg == "0:v:0")
+            .filter(|&i| output[i - 1] == "-map");
+        match video_map {
+            Some(map) => output[map] = "[picture]".to_string(),
+            // Mapping anything ends FFmpeg's default pick of the audio
+            None => {
+                output.splice(
+                    index + 2..index + 2,
+                    ["-map", "[picture]", "-map", "0:a:0?"].map(String::from),
+                );
+            }
+        }
+    }
+    let labels: Vec<String> = output
+        .windows(2)
+        .filter(|pair| pair[0] == "-map" && pair[1].starts_with('['))
+        .map(|pair| pair[1].trim_matches(['[', ']']).to_string())
+        .collect();
+    if !labels.iter().any(|label| label == "picture") {
+        return Err("The ladder needs a re-encoded picture".to_string());
+    }
+
+    let mut graphs: Vec<String> = vec![];
+    let mut rung_output: Vec<String> = vec![];
+    let mut args = output.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-filter_complex" {
+            graphs.extend(args.next());
+        } else {
+            rung_output.push(arg);
+        }
+    }
+    for label in &labels {
+        let filter = if label == "picture" {
+            "split"
+        } else {
+            "asplit"
+        };
+        let graph = graphs
+            .iter_mut()
+            .find(|graph| graph.contains(&format!("[{}]", label)))
+            .ok_or(format!("No filter graph makes [{}]", label))?;
+        *graph = split_label(graph, label, rungs.len(), filter);
+    }
+    let picture_graph = graphs
+        .iter_mut()
+        .find(|graph| graph.contains("[picture_all]"))
+        .ok_or("No filter graph makes the picture")?;
+    for (index, rung) in rungs.iter().enumerate() {
+        picture_graph.push_str(&format!(
+            ";[picture_{}]scale=-2:{}[rung{}]",
+            index, rung.height, index
+        ));
+    }
+
+    let mut merged = inputs.to_vec();
+    for graph in graphs {
+        merged.extend(["-filter_complex".to_string(), graph]);
+    }
+    // The main output keeps its options, without the graphs moved above
+    merged.extend(rung_output.iter().cloned());
+
+    for (index, (rung, path)) in rungs.iter().zip(paths).enumerate() {
+        let mut output: Vec<String> = rung_output
+            .iter()
+            .enumerate()
+            .map(|(position, arg)| {
+                let is_map = position > 0 && rung_output[position - 1] == "-map";
+                match arg.strip_prefix('[').and_then(|arg| arg.strip_suffix(']')) {
+                    Some("picture") if is_map => format!("[rung{}]", index),
+                    Some(label) if is_map => format!("[{}_{}]", label, index),
+                    _ => arg.clone(),
+                }
+            })
+            .collect();
+        if let Some(kbps) = rung.video_bitrate {
+            let position = output
+                .iter()
+                .rposition(|arg| arg == "-f")
+                .unwrap_or(output.len());
+            output.splice(
+                position..position,
+                [
+                    "-b:v".to_string(),
+                    format!("{}k", kbps),
+                    "-maxrate".to_string(),
+                    format!("{}k", kbps),
+                    "-bufsize".to_string(),
+                    format!("{}k", kbps * 2),
+                ],
+            );
+        }
+        if let Some(last) = output.last_mut() {
+            *last = path.clone();
+        }
+        merged.extend(output);
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rungs_share_the_filtered_picture() {
+        let main = [
+            "-i",
+            "in.mp4",
+            "-ss",
+            "1",
+            "-to",
+            "4",
+            "-filter:v",
+            "crop=640:360:0:0",
+            "-c:v",
+            "libx264",
+            "-crf",
+            "23",
+            "-f",
+            "mp4",
+            "-y",
+            "out.mp4",
+        ]
+        .map(String::from);
+        let rung = LadderRung {
+            height: 180,
+            video_bitrate: Some(400),
+            output_path: None,
+        };
+        assert_eq!(rung.path("/videos/out.mp4"), "/videos/out_180p.mp4");
+
+        let merged = add_rungs(&main, &[rung], &["out_180p.mp4".to_string()]).unwrap();
+        assert_eq!(
+            merged,
+            [
+                "-i",
+                "in.mp4",
+                "-filter_complex",
+                "[0:v:0]crop=640:360:0:0[picture_all];\
+                 [picture_all]split=2[picture][picture_0];[picture_0]scale=-2:180[rung0]",
+                "-ss",
+                "1",
+                "-to",
+                "4",
+                "-map",
+                "[picture]",
+                "-map",
+                "0:a:0?",
+                "-c:v",
+                "libx264",
+                "-crf",
+                "23",
+                "-f",
+                "mp4",
+                "-y",
+                "out.mp4",
+                "-ss",
+                "1",
+                "-to",
+                "4",
+                "-map",
+                "[rung0]",
+                "-map",
+                "0:a:0?",
+                "-c:v",
+                "libx264",
+                "-crf",
+                "23",
+                "-b:v",
+                "400k",
+                "-maxrate",
+                "400k",
+                "-bufsize",
+                "800k",
+                "-f",
+                "mp4",
+                "-y",
+                "out_180p.mp4"
+            ]
+        );
+    }
+}
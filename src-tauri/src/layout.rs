@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ffmpeg::CropArea;
+use crate::filters::OverlayPosition;
+
+// Width of a picture-in-picture as a share of the main crop
+const DEFAULT_PIP_SCALE: f64 = 0.3;
+
+/// A second crop of the same source composed with the main one, such as a
+/// facecam next to the gameplay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "layout", rename_all = "snake_case")]
+pub enum DualCrop {
+    /// The second crop above the main one, scaled to its width, as in
+    /// vertical gameplay clips
+    Stacked { crop: CropArea },
+    /// The second crop scaled down and laid over the main one
+    PictureInPicture {
+        crop: CropArea,
+        #[serde(flatten)]
+        position: OverlayPosition,
+        /// Width as a share of the main crop's width
+        scale: Option<f64>,
+    },
+}
+
+/// Rounds down to an even number of pixels, at least 2.
+fn even(value: f64) -> u32 {
+    ((value / 2.0).floor() as u32 * 2).max(2)
+}
+
+impl DualCrop {
+    fn crop(&self) -> &CropArea {
+        match self {
+            DualCrop::Stacked { crop } | DualCrop::PictureInPicture { crop, .. } => crop,
+        }
+    }
+
+    pub fn crop_mut(&mut self) -> &mut CropArea {
+        match self {
+            DualCrop::Stacked { crop } | DualCrop::PictureInPicture { crop, .. } => crop,
+        }
+    }
+
+    pub fn validate(&self, frame_width: u32, frame_height: u32) -> Result<(), String> {
+        let crop = self.crop();
+        if crop.width < 2 || crop.height < 2 {
+            return Err("The second crop is too small to export".to_string());
+        }
+        if crop.x.saturating_add(crop.width) > frame_width
+            || crop.y.saturating_add(crop.height) > frame_height
+        {
+            return Err(format!(
+                "The second crop does not fit into the {}x{} frame",
+                frame_width, frame_height
+            ));
+        }
+        if let DualCrop::PictureInPicture {
+            scale: Some(scale), ..
+        } = self
+        {
+            if *scale <= 0.0 || *scale > 1.0 {
+                return Err("The picture-in-picture scale must be between 0 and 1".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Size the second crop is scaled to next to a `width` wide main crop.
+    fn second_size(&self, width: u32) -> (u32, u32) {
+        let crop = self.crop();
+        let second_width = match self {
+            DualCrop::Stacked { .. } => width,
+            DualCrop::PictureInPicture { scale, .. } => {
+                even(width as f64 * scale.unwrap_or(DEFAULT_PIP_SCALE))
+            }
+        };
+        let second_height = even(crop.height as f64 * second_width as f64 / crop.width as f64);
+        (second_width, second_height)
+    }
+
+    /// Size of the composed picture around a `width` x `height` main crop.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            DualCrop::Stacked { .. } => (width, height + self.second_size(width).1),
+            DualCrop::PictureInPicture { .. } => (width, height),
+        }
+    }
+
+    /// Sub-graph taking both crops out of the source and composing them. It
+    /// fits into a filter chain like a single filter, in place of
+    /// `main_crop`, which crops `width` pixels wide.
+    pub fn filter(&self, main_crop: &str, width: u32) -> String {
+        let crop = self.crop();
+        let (second_width, second_height) = self.second_size(width);
+        let second = format!(
+            "split[dual_main][dual_second];\
+             [dual_second]crop={}:{}:{}:{},scale={}:{},setsar=1[dual_scaled];\
+             [dual_main]{},setsar=1[dual_cropped]",
+            crop.width, crop.height, crop.x, crop.y, second_width, second_height, main_crop
+        );
+        match self {
+            DualCrop::Stacked { .. } => format!("{};[dual_scaled][dual_cropped]vstack", second),
+            DualCrop::PictureInPicture { position, .. } => {
+                let (x, y) = position.exprs(("W", "H"), ("w", "h"));
+                format!("{};[dual_cropped][dual_scaled]overlay={}:{}", second, x, y)
+            }
+        }
+    }
+}
+
+/// Another crop of the source written to a file of its own, by the same
+/// FFmpeg run as the main output so the source is decoded once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CropOutput {
+    pub crop: CropArea,
+    /// May contain the placeholders of the main output path
+    pub output_path: String,
+}
+
+impl CropOutput {
+    /// `even` asks for the even sizes chroma subsampled video needs.
+    pub fn validate(&self, frame_width: u32, frame_height: u32, even: bool) -> Result<(), String> {
+        let crop = &self.crop;
+        if crop.width < 2 || crop.height < 2 {
+            return Err("An extra crop is too small to export".to_string());
+        }
+        if crop.x.saturating_add(crop.width) > frame_width
+            || crop.y.saturating_add(crop.height) > frame_height
+        {
+            return Err(format!(
+                "The extra crop {}x{} does not fit into the {}x{} frame",
+                crop.width, crop.height, frame_width, frame_height
+            ));
+        }
+        if even && (crop.width % 2 == 1 || crop.height % 2 == 1) {
+            return Err(format!(
+                "The extra crop {}x{} needs an even width and height",
+                crop.width, crop.height
+            ));
+        }
+        Ok(())
+    }
+}
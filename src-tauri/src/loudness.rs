@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_TRUE_PEAK: f64 = -1.0;
+const DEFAULT_LOUDNESS_RANGE: f64 = 11.0;
+/// loudnorm always outputs 192 kHz, this is what the audio is brought back to
+const OUTPUT_SAMPLE_RATE: u32 = 48000;
+
+/// Stands in for the measured values in the filters of the passes after the
+/// measurement, see `Loudness::measured_options`.
+pub const MEASUREMENT_PLACEHOLDER: &str = "$measured_loudness";
+
+/// EBU R128 loudness normalization with FFmpeg's `loudnorm`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Loudness {
+    /// Integrated loudness to aim for, in LUFS
+    pub target_lufs: f64,
+    /// Maximum true peak in dBTP
+    pub true_peak: Option<f64>,
+    /// Loudness range to aim for, in LU
+    pub loudness_range: Option<f64>,
+    /// Measure the clip in a pass of its own first, so the gain is applied
+    /// linearly instead of adjusted on the fly
+    #[serde(default)]
+    pub two_pass: bool,
+}
+
+impl Loudness {
+    /// Checks the targets against the ranges `loudnorm` accepts.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-70.0..=-5.0).contains(&self.target_lufs) {
+            return Err("The target loudness must be between -70 and -5 LUFS".to_string());
+        }
+        if !(-9.0..=0.0).contains(&self.true_peak()) {
+            return Err("The true peak must be between -9 and 0 dBTP".to_string());
+        }
+        if !(1.0..=50.0).contains(&self.loudness_range()) {
+            return Err("The loudness range must be between 1 and 50 LU".to_string());
+        }
+        Ok(())
+    }
+
+    fn true_peak(&self) -> f64 {
+        self.true_peak.unwrap_or(DEFAULT_TRUE_PEAK)
+    }
+
+    fn loudness_range(&self) -> f64 {
+        self.loudness_range.unwrap_or(DEFAULT_LOUDNESS_RANGE)
+    }
+
+    fn targets(&self) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}",
+            self.target_lufs,
+            self.true_peak(),
+            self.loudness_range()
+        )
+    }
+
+    /// The normalizing filters. Two-pass ones carry `MEASUREMENT_PLACEHOLDER`
+    /// until the measurement is in.
+    pub fn filters(&self) -> Vec<String> {
+        let placeholder = if self.two_pass {
+            MEASUREMENT_PLACEHOLDER
+        } else {
+            ""
+        };
+        vec![
+            format!("{}{}", self.targets(), placeholder),
+            format!("aresample={}", OUTPUT_SAMPLE_RATE),
+        ]
+    }
+
+    /// Filter of the measurement pass, which prints its stats at the end.
+    pub fn measure_filter(&self) -> String {
+        format!("{}:print_format=json", self.targets())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Measurement {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+/// Reads the stats of a measurement pass from its last stderr lines and turns
+/// them into the `loudnorm` options replacing `MEASUREMENT_PLACEHOLDER`.
+/// `None` if they are missing or unusable, e.g. for a silent clip.
+pub fn measured_options<S: AsRef<str>>(stderr: &[S]) -> Option<String> {
+    let start = stderr
+        .iter()
+        .rposition(|line| line.as_ref().trim() == "{")?;
+    let json: String = stderr[start..]
+        .iter()
+        .map(|line| line.as_ref())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let end = json.find('}')?;
+    let measurement: Measurement = serde_json::from_str(&json[..=end]).ok()?;
+
+    let values = [
+        &measurement.input_i,
+        &measurement.input_tp,
+        &measurement.input_lra,
+        &measurement.input_thresh,
+        &measurement.target_offset,
+    ];
+    // Silence measures as -inf, which loudnorm does not take back
+    if values
+        .iter()
+        .any(|value| !value.parse::<f64>().is_ok_and(f64::is_finite))
+    {
+        return None;
+    }
+
+    Some(format!(
+        ":measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        values[0], values[1], values[2], values[3], values[4]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measurement_is_read_from_the_stderr_tail() {
+        let stderr = [
+            "[Parsed_loudnorm_0 @ 0x5581] ",
+            "{",
+            "\t\"input_i\" : \"-27.61\",",
+            "\t\"input_tp\" : \"-4.47\",",
+            "\t\"input_lra\" : \"18.06\",",
+            "\t\"input_thresh\" : \"-39.20\",",
+            "\t\"output_i\" : \"-16.58\",",
+            "\t\"output_tp\" : \"-1.50\",",
+            "\t\"output_lra\" : \"14.78\",",
+            "\t\"output_thresh\" : \"-27.71\",",
+            "\t\"normalization_type\" : \"dynamic\",",
+            "\t\"target_offset\" : \"0.58\"",
+            "}",
+        ];
+        assert_eq!(
+            measured_options(&stderr).as_deref(),
+            Some(":measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.20:offset=0.58:linear=true")
+        );
+
+        let silent = stderr.map(|line| line.replace("-27.61", "-inf"));
+        assert_eq!(measured_options(&silent), None);
+    }
+}
@@ -0,0 +1,98 @@
+use tokio::fs;
+
+use crate::capabilities::capabilities;
+use crate::filters::escape_value;
+
+// Largest cube lut3d loads
+const MAX_CUBE_SIZE: usize = 256;
+
+/// Checks that `contents` is a 3D LUT in the `.cube` format: a `LUT_3D_SIZE`
+/// header and then size³ lines of red, green and blue. Returns the size.
+fn parse_cube(contents: &str) -> Result<usize, String> {
+    let mut size = None;
+    let mut entries = 0;
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let keyword = fields.next().unwrap_or_default();
+        match keyword {
+            "LUT_3D_SIZE" => {
+                size = fields
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|size| (2..=MAX_CUBE_SIZE).contains(size));
+                if size.is_none() {
+                    return Err(format!(
+                        "LUT_3D_SIZE must be between 2 and {}",
+                        MAX_CUBE_SIZE
+                    ));
+                }
+            }
+            "LUT_1D_SIZE" => return Err("1D LUTs are not supported, use a 3D one".to_string()),
+            "TITLE" | "DOMAIN_MIN" | "DOMAIN_MAX" | "LUT_3D_INPUT_RANGE" => {}
+            _ => {
+                let values: Vec<f64> = line
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("Line {} is not a LUT entry", number + 1))?;
+                if values.len() != 3 {
+                    return Err(format!("Line {} does not have 3 values", number + 1));
+                }
+                entries += 1;
+            }
+        }
+    }
+
+    let size = size.ok_or("The LUT has no LUT_3D_SIZE")?;
+    if entries != size.pow(3) {
+        return Err(format!(
+            "A LUT of size {} needs {} entries, the file has {}",
+            size,
+            size.pow(3),
+            entries
+        ));
+    }
+    Ok(size)
+}
+
+/// Checks that `path` is a readable `.cube` LUT and that FFmpeg can apply it.
+pub async fn validate_lut(path: &str) -> Result<(), String> {
+    let contents = fs::read(path)
+        .await
+        .map_err(|e| format!("Cannot read LUT {}: {}", path, e))?;
+    parse_cube(&String::from_utf8_lossy(&contents)).map_err(|e| format!("{}: {}", path, e))?;
+
+    let filters = &capabilities().await?.filters;
+    if !filters.iter().any(|filter| filter == "lut3d") {
+        return Err("FFmpeg was built without the lut3d filter".to_string());
+    }
+    Ok(())
+}
+
+pub fn lut_filter(path: &str) -> String {
+    format!("lut3d=file={}:interp=tetrahedral", escape_value(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_entries_must_fill_the_cube() {
+        let cube = "# Log to Rec.709\nTITLE \"Test\"\nLUT_3D_SIZE 2\n\n\
+                    0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+        assert_eq!(parse_cube(cube), Ok(2));
+
+        let short = cube.trim_end().trim_end_matches("1 1 1");
+        assert_eq!(
+            parse_cube(short),
+            Err("A LUT of size 2 needs 8 entries, the file has 7".to_string())
+        );
+        assert!(parse_cube("LUT_3D_SIZE 2\n0 0 zero\n").is_err());
+    }
+}
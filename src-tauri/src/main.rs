@@ -1,7 +1,54 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod actions;
+mod analysis;
+mod animated;
+mod binaries;
+mod capabilities;
+mod chapters;
+mod chroma;
+mod cli;
+mod codecs;
+mod color_range;
+mod encoders;
+mod errors;
+mod external_audio;
 mod ffmpeg;
+mod filters;
+mod frames;
+mod hdr;
+mod history;
+mod intermediate;
+mod jobs;
+mod ladder;
+mod layout;
+mod loudness;
+mod lut;
+mod masks;
+mod metadata;
+mod power;
+mod presets;
+mod probe;
+mod progress;
+mod project;
+mod proxy;
+mod quality;
+mod queue;
+mod recent;
+mod scratch;
+mod settings;
+mod smartcut;
+mod streaming;
+mod subtitles;
+mod taskbar;
+mod text;
+mod thumbnails;
+mod timecode;
+mod watcher;
+mod web;
+
+use tauri::{Manager, RunEvent, WindowEvent};
 
 // Learn more about Tauri commands at https://v1.tauri.app/v1/guides/features/command
 #[tauri::command]
@@ -10,13 +57,110 @@ fn greet(name: &str) -> String {
 }
 
 fn main() {
+    let cli_args: Vec<String> = std::env::args().skip(1).collect();
+    let headless = match cli::parse(&cli_args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
     tauri::Builder::default()
+        .manage(jobs::JobRegistry::default())
+        .manage(queue::ExportQueue::default())
+        .manage(watcher::FolderWatcher::default())
+        .manage(taskbar::TaskbarProgress::default())
+        .manage(settings::SettingsStore::default())
+        .manage(frames::FrameCache::default())
+        .register_uri_scheme_protocol(frames::SCHEME, frames::serve)
         .invoke_handler(tauri::generate_handler![
             ffmpeg::process_video,
+            ffmpeg::cancel_export,
+            ffmpeg::preview_command,
+            ffmpeg::render_preview,
+            ffmpeg::resolve_output_name,
+            ffmpeg::estimate_output_size,
+            jobs::get_job_status,
+            jobs::get_job_log,
+            jobs::confirm_exit,
+            jobs::set_prevent_sleep,
             greet,
+            binaries::check_ffmpeg,
+            capabilities::get_ffmpeg_capabilities,
             ffmpeg::get_video_codec,
-            ffmpeg::generate_video_proxy
+            encoders::list_hw_encoders,
+            presets::list_presets,
+            presets::save_preset,
+            presets::update_preset,
+            presets::delete_preset,
+            probe::probe_video,
+            probe::get_keyframes,
+            analysis::detect_crop,
+            analysis::get_waveform,
+            analysis::detect_scenes,
+            analysis::detect_silence,
+            analysis::detect_interlacing,
+            subtitles::list_subtitle_tracks,
+            subtitles::extract_subtitles,
+            chapters::get_chapters,
+            thumbnails::get_thumbnail,
+            thumbnails::save_frame,
+            thumbnails::generate_filmstrip,
+            thumbnails::generate_contact_sheet,
+            queue::enqueue_export,
+            queue::list_jobs,
+            queue::remove_job,
+            queue::reorder_jobs,
+            queue::set_queue_concurrency,
+            queue::process_batch,
+            queue::list_interrupted_jobs,
+            queue::resume_queue,
+            queue::discard_interrupted_jobs,
+            watcher::start_watch_folder,
+            watcher::stop_watch_folder,
+            watcher::list_watch_folders,
+            actions::open_path,
+            actions::reveal_path,
+            actions::cancel_shutdown,
+            settings::get_settings,
+            settings::update_settings,
+            project::save_project,
+            project::load_project,
+            history::list_history,
+            history::rerun_job,
+            history::clear_history,
+            recent::add_recent_file,
+            recent::get_recent_files,
+            recent::clear_recent_files,
+            proxy::generate_proxy,
+            proxy::prepare_preview
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .setup(|app| {
+            settings::load(&app.handle());
+            queue::restore(&app.handle());
+            tauri::async_runtime::spawn(scratch::remove_leftovers());
+            if let (Some(args), Some(window)) = (headless, app.get_window("main")) {
+                cli::run(app.handle(), window, args);
+            }
+            Ok(())
+        })
+        // Ask before closing mid-export, the frontend answers with `confirm_exit`
+        .on_window_event(|event| {
+            if let WindowEvent::CloseRequested { api, .. } = event.event() {
+                let jobs = event.window().state::<jobs::JobRegistry>();
+                if jobs.has_running_jobs() && !jobs.is_quitting() {
+                    api.prevent_close();
+                    jobs::emit_event(event.window(), "exit-requested", ());
+                }
+            }
+        })
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            // FFmpeg would keep writing to the outputs after we are gone
+            if let RunEvent::Exit = event {
+                app_handle.state::<jobs::JobRegistry>().kill_all();
+            }
+        });
 }
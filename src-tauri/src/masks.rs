@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::filters::interpolation_expr;
+
+const DEFAULT_BLUR_RADIUS: u32 = 10;
+const DEFAULT_PIXEL_SIZE: u32 = 16;
+
+/// How a mask hides what is under it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskEffect {
+    #[default]
+    Blur,
+    Pixelate,
+}
+
+/// Position of a moving mask at `time` (seconds in the source), like
+/// `CropKeyframe`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaskKeyframe {
+    pub time: f64,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Rectangle of the source hidden from the export, such as a face or a
+/// license plate. In video pixel coordinates, like the crop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaskRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub effect: MaskEffect,
+    /// Blur radius or pixel size in pixels
+    pub strength: Option<u32>,
+    /// Moves the mask along with what it covers, replacing `x` and `y`
+    #[serde(default)]
+    pub keyframes: Vec<MaskKeyframe>,
+}
+
+impl MaskRegion {
+    /// Checks that the mask, at every keyframe, lies inside the frame.
+    pub fn validate(&self, frame_width: u32, frame_height: u32) -> Result<(), String> {
+        if self.width < 2 || self.height < 2 {
+            return Err("A mask must be at least 2x2 pixels".to_string());
+        }
+        if self.strength == Some(0) {
+            return Err("The mask strength must be positive".to_string());
+        }
+        let positions: Vec<(u32, u32)> = if self.keyframes.is_empty() {
+            vec![(self.x, self.y)]
+        } else {
+            self.keyframes.iter().map(|key| (key.x, key.y)).collect()
+        };
+        if positions.iter().any(|(x, y)| {
+            x.saturating_add(self.width) > frame_width
+                || y.saturating_add(self.height) > frame_height
+        }) {
+            return Err(format!(
+                "A {}x{} mask does not fit into the {}x{} frame",
+                self.width, self.height, frame_width, frame_height
+            ));
+        }
+        Ok(())
+    }
+
+    /// `x` and `y` as FFmpeg expressions of the source time `time`.
+    fn position(&self, time: &str) -> (String, String) {
+        if self.keyframes.is_empty() {
+            return (self.x.to_string(), self.y.to_string());
+        }
+        let mut keyframes = self.keyframes.clone();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let position = |value: fn(&MaskKeyframe) -> u32| {
+            let points: Vec<(f64, f64)> = keyframes
+                .iter()
+                .map(|keyframe| (keyframe.time, value(keyframe) as f64))
+                .collect();
+            format!("'{}'", interpolation_expr(&points, time))
+        };
+        (position(|key| key.x), position(|key| key.y))
+    }
+
+    fn effect_filter(&self) -> String {
+        match self.effect {
+            // boxblur takes at most half the smaller side of the chroma planes
+            MaskEffect::Blur => {
+                let radius = self
+                    .strength
+                    .unwrap_or(DEFAULT_BLUR_RADIUS)
+                    .min(self.width.min(self.height) / 4)
+                    .max(1);
+                format!("boxblur={}:2", radius)
+            }
+            MaskEffect::Pixelate => {
+                let size = self.strength.unwrap_or(DEFAULT_PIXEL_SIZE);
+                format!("pixelize=width={}:height={}", size, size)
+            }
+        }
+    }
+
+    /// Sub-graph copying the region out, hiding it and laying it back in
+    /// place. It fits into a filter chain like a single filter; `index` keeps
+    /// the labels of several masks apart.
+    pub fn filter(&self, index: usize, time: &str) -> String {
+        let (x, y) = self.position(time);
+        format!(
+            "split[mask_base{i}][mask_in{i}];\
+             [mask_in{i}]crop={}:{}:{x}:{y},{}[mask_out{i}];\
+             [mask_base{i}][mask_out{i}]overlay={x}:{y}",
+            self.width,
+            self.height,
+            self.effect_filter(),
+            i = index,
+            x = x,
+            y = y
+        )
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::codecs::Container;
+
+/// Which tags of the source the outputs carry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataMode {
+    /// What FFmpeg copies by itself, which depends on the inputs of the run
+    #[default]
+    Default,
+    /// Every global and stream tag of the source
+    Copy,
+    /// No tags or chapters of the source, for privacy: devices, locations
+    /// and dates are left behind
+    Strip,
+}
+
+/// Tags of the exported files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataOptions {
+    #[serde(default)]
+    pub mode: MetadataMode,
+    pub title: Option<String>,
+    pub comment: Option<String>,
+    /// ISO 8601 such as `2024-05-01T18:30:00Z`, or `now`
+    pub creation_time: Option<String>,
+    /// Give the outputs the modification time of the source
+    #[serde(default)]
+    pub keep_modified_time: bool,
+}
+
+impl MetadataOptions {
+    /// Output options writing the tags. `container` is `None` for animated
+    /// and audio-only outputs. `chapters` keeps the chapter mapping of the
+    /// run when stripping.
+    pub fn output_args(&self, container: Option<Container>, chapters: bool) -> Vec<String> {
+        let mut args: Vec<String> = vec![];
+        match self.mode {
+            MetadataMode::Default => {}
+            MetadataMode::Copy => {
+                // Stream tags follow their streams anyway
+                args.extend(["-map_metadata", "0"].map(String::from));
+                // MP4 and QuickTime only keep the tags they know without it
+                if matches!(container, Some(Container::Mp4) | Some(Container::Mov)) {
+                    args.extend(["-movflags", "+use_metadata_tags"].map(String::from));
+                }
+            }
+            MetadataMode::Strip => {
+                args.extend(["-map_metadata", "-1", "-map_metadata:s", "-1"].map(String::from));
+                if !chapters {
+                    args.extend(["-map_chapters", "-1"].map(String::from));
+                }
+            }
+        }
+
+        // Last, so they replace copied tags of the same name
+        let tags = [
+            ("title", &self.title),
+            ("comment", &self.comment),
+            ("creation_time", &self.creation_time),
+        ];
+        for (key, value) in tags {
+            if let Some(value) = value {
+                args.extend(["-metadata".to_string(), format!("{}={}", key, value)]);
+            }
+        }
+        args
+    }
+}
+
+/// Sets the modification time of the outputs to the one of the source.
+pub fn keep_modified_time(input_path: &str, output_paths: &[String]) {
+    let modified = match std::fs::metadata(input_path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(e) => {
+            println!("Cannot read the modification time of {}: {}", input_path, e);
+            return;
+        }
+    };
+    for output_path in output_paths {
+        let result = std::fs::File::options()
+            .append(true)
+            .open(output_path)
+            .and_then(|file| file.set_modified(modified));
+        if let Err(e) = result {
+            println!("Cannot set the modification time of {}: {}", output_path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stripping_drops_the_source_tags_but_writes_new_ones() {
+        let options = MetadataOptions {
+            mode: MetadataMode::Strip,
+            title: Some("Final cut".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.output_args(Some(Container::Mp4), false),
+            [
+                "-map_metadata",
+                "-1",
+                "-map_metadata:s",
+                "-1",
+                "-map_chapters",
+                "-1",
+                "-metadata",
+                "title=Final cut"
+            ]
+        );
+        // Chapters written by the export itself stay
+        assert!(!options
+            .output_args(None, true)
+            .contains(&"-map_chapters".to_string()));
+    }
+}
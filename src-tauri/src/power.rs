@@ -0,0 +1,83 @@
+#[cfg(not(windows))]
+use std::process::{Child, Command, Stdio};
+
+/// Keeps the system from sleeping for as long as it lives.
+///
+/// Linux holds a logind inhibitor lock through `systemd-inhibit`, which runs
+/// `cat` until its stdin is closed, so the lock also goes away with the app.
+/// macOS runs `caffeinate`, Windows sets the execution state of a thread
+/// that waits to be released.
+pub struct SleepInhibitor {
+    #[cfg(not(windows))]
+    child: Child,
+    #[cfg(windows)]
+    release: std::sync::mpsc::Sender<()>,
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn SetThreadExecutionState(flags: u32) -> u32;
+}
+
+#[cfg(windows)]
+const ES_CONTINUOUS: u32 = 0x8000_0000;
+#[cfg(windows)]
+const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+impl SleepInhibitor {
+    #[cfg(target_os = "linux")]
+    pub fn acquire() -> Result<Self, String> {
+        let child = Command::new("systemd-inhibit")
+            .args([
+                "--what=idle:sleep",
+                "--who=video-cropper",
+                "--why=Exporting video",
+                "cat",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Cannot run systemd-inhibit: {}", e))?;
+        Ok(Self { child })
+    }
+
+    #[cfg(all(not(windows), not(target_os = "linux")))]
+    pub fn acquire() -> Result<Self, String> {
+        let child = Command::new("caffeinate")
+            .args(["-i", "-w", &std::process::id().to_string()])
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Cannot run caffeinate: {}", e))?;
+        Ok(Self { child })
+    }
+
+    #[cfg(windows)]
+    pub fn acquire() -> Result<Self, String> {
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            // The state holds until the thread resets it or ends
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+            let _ = released.recv();
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        });
+        Ok(Self { release })
+    }
+}
+
+impl Drop for SleepInhibitor {
+    #[cfg(not(windows))]
+    fn drop(&mut self) {
+        // Closing stdin ends `cat`, caffeinate has to be killed
+        drop(self.child.stdin.take());
+        if cfg!(not(target_os = "linux")) {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+
+    #[cfg(windows)]
+    fn drop(&mut self) {
+        let _ = self.release.send(());
+    }
+}
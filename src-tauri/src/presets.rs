@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::AppHandle;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::codecs::{AudioCodec, Container, VideoCodec};
+use crate::encoders::Encoder;
+use crate::ffmpeg::FractionalCrop;
+use crate::filters::ScaleOptions;
+use crate::quality::Quality;
+use crate::web::WebOptions;
+
+/// Export settings a preset can hold. Unset fields leave the export's own
+/// value alone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresetSettings {
+    pub encoder: Option<Encoder>,
+    pub video_codec: Option<VideoCodec>,
+    pub audio_codec: Option<AudioCodec>,
+    pub container: Option<Container>,
+    pub quality: Option<Quality>,
+    /// Audio bitrate in kbit/s
+    pub audio_bitrate: Option<u32>,
+    pub scale: Option<ScaleOptions>,
+    /// Crop that fits inputs of any size
+    pub crop_fraction: Option<FractionalCrop>,
+    pub web: Option<WebOptions>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(flatten)]
+    pub settings: PresetSettings,
+}
+
+impl Preset {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Preset name cannot be empty".to_string());
+        }
+        if let Some(scale) = &self.settings.scale {
+            scale.validate()?;
+        }
+        if let Some(crop) = &self.settings.crop_fraction {
+            crop.validate()?;
+        }
+        if let (Some(quality), Some(codec)) = (&self.settings.quality, self.settings.video_codec) {
+            quality.validate(codec)?;
+        }
+        Ok(())
+    }
+}
+
+// Serializes read-modify-write cycles of the presets file
+static PRESETS_FILE: Mutex<()> = Mutex::const_new(());
+
+fn presets_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_config_dir()
+        .ok_or("Failed to get app config directory")?
+        .join("presets.json"))
+}
+
+async fn read_presets(app_handle: &AppHandle) -> Result<Vec<Preset>, String> {
+    let path = presets_path(app_handle)?;
+    match fs::read(&path).await {
+        Ok(json) => {
+            serde_json::from_slice(&json).map_err(|e| format!("Invalid presets file: {}", e))
+        }
+        // Nothing saved yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(format!("Cannot read presets: {}", e)),
+    }
+}
+
+async fn write_presets(app_handle: &AppHandle, presets: &[Preset]) -> Result<(), String> {
+    let path = presets_path(app_handle)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Cannot create config dir: {}", e))?;
+    }
+    let json = serde_json::to_vec_pretty(presets).map_err(|e| e.to_string())?;
+    fs::write(&path, json)
+        .await
+        .map_err(|e| format!("Cannot write presets: {}", e))
+}
+
+/// Settings of the preset called `name`, for `ExportArgs::preset`.
+pub async fn load_preset(app_handle: &AppHandle, name: &str) -> Result<PresetSettings, String> {
+    let _guard = PRESETS_FILE.lock().await;
+    read_presets(app_handle)
+        .await?
+        .into_iter()
+        .find(|preset| preset.name == name)
+        .map(|preset| preset.settings)
+        .ok_or(format!("No preset named {}", name))
+}
+
+#[tauri::command]
+pub async fn list_presets(app_handle: AppHandle) -> Result<Vec<Preset>, String> {
+    let _guard = PRESETS_FILE.lock().await;
+    read_presets(&app_handle).await
+}
+
+/// Adds a new preset, failing if one with the same name exists.
+#[tauri::command]
+pub async fn save_preset(app_handle: AppHandle, preset: Preset) -> Result<(), String> {
+    preset.validate()?;
+
+    let _guard = PRESETS_FILE.lock().await;
+    let mut presets = read_presets(&app_handle).await?;
+    if presets.iter().any(|existing| existing.name == preset.name) {
+        return Err(format!("A preset named {} already exists", preset.name));
+    }
+    presets.push(preset);
+    write_presets(&app_handle, &presets).await
+}
+
+/// Replaces the preset called `name`, which may be renamed in the process.
+#[tauri::command]
+pub async fn update_preset(
+    app_handle: AppHandle,
+    name: String,
+    preset: Preset,
+) -> Result<(), String> {
+    preset.validate()?;
+
+    let _guard = PRESETS_FILE.lock().await;
+    let mut presets = read_presets(&app_handle).await?;
+    if preset.name != name && presets.iter().any(|existing| existing.name == preset.name) {
+        return Err(format!("A preset named {} already exists", preset.name));
+    }
+    let existing = presets
+        .iter_mut()
+        .find(|existing| existing.name == name)
+        .ok_or(format!("No preset named {}", name))?;
+    *existing = preset;
+    write_presets(&app_handle, &presets).await
+}
+
+#[tauri::command]
+pub async fn delete_preset(app_handle: AppHandle, name: String) -> Result<(), String> {
+    let _guard = PRESETS_FILE.lock().await;
+    let mut presets = read_presets(&app_handle).await?;
+    let count = presets.len();
+    presets.retain(|preset| preset.name != name);
+    if presets.len() == count {
+        return Err(format!("No preset named {}", name));
+    }
+    write_presets(&app_handle, &presets).await
+}
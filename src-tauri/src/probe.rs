@@ -0,0 +1,474 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::binaries;
+use crate::color_range::ColorRange;
+use crate::hdr::{ContentLight, MasteringDisplay};
+
+// Raw ffprobe output, only the fields we care about
+
+#[derive(Debug, Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeStream {
+    index: usize,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    codec_tag_string: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    channels: Option<u32>,
+    pix_fmt: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    color_space: Option<String>,
+    color_range: Option<String>,
+    sample_aspect_ratio: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    #[serde(default)]
+    disposition: HashMap<String, u8>,
+    #[serde(default)]
+    side_data_list: Vec<FfprobeSideData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeSideData {
+    rotation: Option<f64>,
+    /// Set on `Spherical Mapping` side data, e.g. `equirectangular`
+    projection: Option<String>,
+    // `Mastering display metadata`, rationals such as `35400/50000`
+    red_x: Option<String>,
+    red_y: Option<String>,
+    green_x: Option<String>,
+    green_y: Option<String>,
+    blue_x: Option<String>,
+    blue_y: Option<String>,
+    white_point_x: Option<String>,
+    white_point_y: Option<String>,
+    min_luminance: Option<String>,
+    max_luminance: Option<String>,
+    // `Content light level metadata`
+    max_content: Option<u32>,
+    max_average: Option<u32>,
+}
+
+impl FfprobeSideData {
+    fn mastering_display(&self) -> Option<MasteringDisplay> {
+        let value = |field: &Option<String>| field.as_deref().and_then(parse_rational);
+        Some(MasteringDisplay {
+            red: (value(&self.red_x)?, value(&self.red_y)?),
+            green: (value(&self.green_x)?, value(&self.green_y)?),
+            blue: (value(&self.blue_x)?, value(&self.blue_y)?),
+            white_point: (value(&self.white_point_x)?, value(&self.white_point_y)?),
+            min_luminance: value(&self.min_luminance)?,
+            max_luminance: value(&self.max_luminance)?,
+        })
+    }
+
+    fn content_light(&self) -> Option<ContentLight> {
+        Some(ContentLight {
+            max_content: self.max_content?,
+            max_average: self.max_average?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+}
+
+/// Source file metadata returned by `probe_video`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VideoMetadata {
+    pub duration: f64,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    pub video_codec: Option<String>,
+    pub pix_fmt: Option<String>,
+    /// Transfer characteristics, `smpte2084` (PQ) or `arib-std-b67` (HLG) for
+    /// HDR
+    pub color_transfer: Option<String>,
+    /// e.g. `bt2020` for HDR
+    pub color_primaries: Option<String>,
+    /// Matrix of the YUV values, e.g. `bt2020nc` for HDR
+    pub color_space: Option<String>,
+    /// Limited or full range, if the source says
+    pub color_range: Option<ColorRange>,
+    /// HDR10 metadata of the stream
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light: Option<ContentLight>,
+    /// Codec and channels of the first audio stream
+    pub audio_codec: Option<String>,
+    pub audio_channels: Option<u32>,
+    /// Every audio stream, for picking `ExportArgs::audio_tracks`
+    pub audio_streams: Vec<AudioStream>,
+    /// Clockwise rotation (0, 90, 180 or 270) players apply when displaying
+    pub rotation: u32,
+    /// Width to height of a pixel when it is not square, e.g. 10:11 for
+    /// NTSC DV
+    pub sample_aspect_ratio: Option<(u32, u32)>,
+    pub container: Option<String>,
+    /// Projection of 360° video, e.g. `equirectangular`
+    pub spherical: Option<String>,
+    /// Stream indices of GoPro telemetry (GPMF) tracks
+    pub telemetry_streams: Vec<usize>,
+    /// Whether the picture has an alpha channel, e.g. a screen recording
+    /// with transparency
+    pub alpha: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioStream {
+    /// Position among the audio streams, as `ExportArgs::audio_tracks` takes it
+    pub track: usize,
+    pub codec: Option<String>,
+    pub channels: Option<u32>,
+    pub language: Option<String>,
+    /// Recorders like OBS name their tracks, e.g. `Mic` or `Desktop`
+    pub title: Option<String>,
+    pub default: bool,
+}
+
+impl VideoMetadata {
+    /// Frame size after rotation and with square pixels, as players show it
+    /// and the export filters see it.
+    pub fn display_size(&self) -> (u32, u32) {
+        let (width, height) = self.square_size();
+        if self.rotation % 180 == 90 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Stored frame size with its pixels stretched square. Like players, it
+    /// only ever enlarges a side.
+    fn square_size(&self) -> (u32, u32) {
+        let even = |value: f64| ((value / 2.0).round() as u32 * 2).max(2);
+        match self.sample_aspect_ratio {
+            Some((num, den)) if num > den => (
+                even(self.width as f64 * num as f64 / den as f64),
+                self.height,
+            ),
+            Some((num, den)) => (
+                self.width,
+                even(self.height as f64 * den as f64 / num as f64),
+            ),
+            None => (self.width, self.height),
+        }
+    }
+
+    /// Filter stretching the decoded, autorotated frames to square pixels,
+    /// `None` if they already are.
+    pub fn square_pixels_filter(&self) -> Option<String> {
+        self.sample_aspect_ratio?;
+        let (width, height) = self.display_size();
+        Some(format!("scale={}:{},setsar=1", width, height))
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.color_transfer.as_deref(),
+            Some("smpte2084" | "arib-std-b67")
+        )
+    }
+}
+
+/// Parses ffprobe rationals such as `30000/1001`.
+fn parse_rational(value: &str) -> Option<f64> {
+    match value.split_once('/') {
+        Some((num, den)) => {
+            let num: f64 = num.parse().ok()?;
+            let den: f64 = den.parse().ok()?;
+            (den != 0.0).then(|| num / den)
+        }
+        None => value.parse().ok(),
+    }
+}
+
+impl FfprobeStream {
+    /// `sample_aspect_ratio` such as `10:11`, `None` for square or unknown
+    /// (`0:1`) pixels.
+    fn sample_aspect_ratio(&self) -> Option<(u32, u32)> {
+        let (num, den) = self.sample_aspect_ratio.as_deref()?.split_once(':')?;
+        let (num, den): (u32, u32) = (num.parse().ok()?, den.parse().ok()?);
+        (num > 0 && den > 0 && num != den).then_some((num, den))
+    }
+
+    fn frame_rate(&self) -> Option<f64> {
+        [&self.avg_frame_rate, &self.r_frame_rate]
+            .into_iter()
+            .flatten()
+            .filter_map(|rate| parse_rational(rate))
+            .find(|rate| *rate > 0.0)
+    }
+
+    /// Rotation from the display matrix, falling back to the legacy `rotate` tag.
+    fn rotation(&self) -> u32 {
+        let degrees = self
+            .side_data_list
+            .iter()
+            .find_map(|side_data| side_data.rotation)
+            // The display matrix rotates counter-clockwise, the tag clockwise
+            .map(|rotation| -rotation)
+            .or_else(|| self.tags.get("rotate").and_then(|tag| tag.parse().ok()))
+            .unwrap_or(0.0);
+
+        ((degrees.round() as i64).rem_euclid(360)) as u32
+    }
+}
+
+async fn run_ffprobe(input_path: &str) -> Result<FfprobeOutput, String> {
+    let output = tokio::process::Command::new(binaries::ffprobe())
+        .args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+            input_path,
+        ])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid ffprobe output: {}", e))
+}
+
+pub async fn probe(input_path: &str) -> Result<VideoMetadata, String> {
+    let probed = run_ffprobe(input_path).await?;
+
+    let video = probed
+        .streams
+        .iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("video"))
+        .ok_or("No video stream found")?;
+    let audio_streams: Vec<&FfprobeStream> = probed
+        .streams
+        .iter()
+        .filter(|stream| stream.codec_type.as_deref() == Some("audio"))
+        .collect();
+    let audio = audio_streams.first();
+
+    Ok(VideoMetadata {
+        duration: probed
+            .format
+            .as_ref()
+            .and_then(|format| format.duration.as_ref())
+            .and_then(|duration| duration.parse().ok())
+            .unwrap_or(0.0),
+        width: video.width.unwrap_or(0),
+        height: video.height.unwrap_or(0),
+        frame_rate: video.frame_rate().unwrap_or(0.0),
+        video_codec: video.codec_name.clone(),
+        pix_fmt: video.pix_fmt.clone(),
+        color_transfer: video.color_transfer.clone(),
+        color_primaries: video.color_primaries.clone(),
+        color_space: video.color_space.clone(),
+        color_range: ColorRange::probed(video.color_range.as_deref(), video.pix_fmt.as_deref()),
+        mastering_display: video
+            .side_data_list
+            .iter()
+            .find_map(FfprobeSideData::mastering_display),
+        content_light: video
+            .side_data_list
+            .iter()
+            .find_map(FfprobeSideData::content_light),
+        audio_codec: audio.and_then(|stream| stream.codec_name.clone()),
+        audio_channels: audio.and_then(|stream| stream.channels),
+        audio_streams: audio_streams
+            .iter()
+            .enumerate()
+            .map(|(track, stream)| AudioStream {
+                track,
+                codec: stream.codec_name.clone(),
+                channels: stream.channels,
+                language: stream.tags.get("language").cloned(),
+                title: stream.tags.get("title").cloned(),
+                default: stream.disposition.get("default") == Some(&1),
+            })
+            .collect(),
+        rotation: video.rotation(),
+        sample_aspect_ratio: video.sample_aspect_ratio(),
+        container: probed.format.and_then(|format| format.format_name),
+        spherical: video
+            .side_data_list
+            .iter()
+            .find_map(|side_data| side_data.projection.clone()),
+        telemetry_streams: probed
+            .streams
+            .iter()
+            .filter(|stream| stream.codec_tag_string.as_deref() == Some("gpmd"))
+            .map(|stream| stream.index)
+            .collect(),
+        // WebM stores the alpha of VP8 and VP9 next to a yuv420p picture
+        alpha: video.pix_fmt.as_deref().is_some_and(has_alpha)
+            || video.tags.get("alpha_mode").is_some_and(|mode| mode == "1"),
+    })
+}
+
+/// Whether frames of the ffprobe `pix_fmt` carry an alpha channel.
+pub fn has_alpha(pix_fmt: &str) -> bool {
+    pix_fmt.starts_with("yuva")
+        || pix_fmt.starts_with("gbrap")
+        || pix_fmt.starts_with("ya")
+        || ["rgba", "bgra", "argb", "abgr", "rgb32", "bgr32"]
+            .iter()
+            .any(|name| pix_fmt.starts_with(name))
+}
+
+/// Returns the container duration of `input_path` in seconds.
+pub async fn probe_duration(input_path: &str) -> Result<f64, String> {
+    let output = tokio::process::Command::new(binaries::ffprobe())
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+            input_path,
+        ])
+        .output()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid duration from ffprobe: {}", e))
+}
+
+/// Presentation timestamps of the video keyframes, optionally limited to the
+/// `start`..`end` range. Reads packet flags only, so nothing is decoded.
+#[tauri::command]
+pub async fn get_keyframes(
+    input_path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+) -> Result<Vec<f64>, String> {
+    let mut args = vec![
+        "-v".to_string(),
+        "error".into(),
+        "-select_streams".into(),
+        "v:0".into(),
+        "-show_entries".into(),
+        "packet=pts_time,flags".into(),
+        "-of".into(),
+        "csv=p=0".into(),
+    ];
+    if start.is_some() || end.is_some() {
+        let start = start.map(|s| s.to_string()).unwrap_or_default();
+        let end = end.map(|e| e.to_string()).unwrap_or_default();
+        args.extend(["-read_intervals".into(), format!("{}%{}", start, end)]);
+    }
+    args.push(input_path);
+
+    let output = tokio::process::Command::new(binaries::ffprobe())
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
+    // Otherwise a broken file would look like one without keyframes
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // Each line looks like `12.345000,K__`
+    let mut keyframes: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pts, flags) = line.split_once(',')?;
+            flags.starts_with('K').then(|| pts.parse().ok()).flatten()
+        })
+        .collect();
+    keyframes.sort_by(|a, b| a.total_cmp(b));
+
+    Ok(keyframes)
+}
+
+#[tauri::command]
+pub async fn probe_video(input_path: String) -> Result<VideoMetadata, String> {
+    probe(&input_path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stream(fields: serde_json::Value) -> FfprobeStream {
+        let mut value = json!({ "index": 0, "codec_type": "video" });
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn rationals_and_frame_rates() {
+        assert_eq!(parse_rational("30000/1001"), Some(30000.0 / 1001.0));
+        assert_eq!(parse_rational("0/0"), None);
+        assert_eq!(parse_rational("25"), Some(25.0));
+
+        // Streams of unknown rate fall back to the real base rate
+        let video = stream(json!({ "avg_frame_rate": "0/0", "r_frame_rate": "24000/1001" }));
+        assert_eq!(video.frame_rate(), Some(24000.0 / 1001.0));
+    }
+
+    #[test]
+    fn rotation_prefers_the_display_matrix() {
+        // A portrait phone video turns its display matrix -90 degrees
+        let video = stream(json!({
+            "side_data_list": [{ "side_data_type": "Display Matrix", "rotation": -90 }],
+            "tags": { "rotate": "180" },
+        }));
+        assert_eq!(video.rotation(), 90);
+
+        assert_eq!(
+            stream(json!({ "tags": { "rotate": "-90" } })).rotation(),
+            270
+        );
+        assert_eq!(stream(json!({})).rotation(), 0);
+    }
+
+    #[test]
+    fn sample_aspect_ratios_skip_square_pixels() {
+        let ratio = |sar: &str| stream(json!({ "sample_aspect_ratio": sar })).sample_aspect_ratio();
+        assert_eq!(ratio("10:11"), Some((10, 11)));
+        assert_eq!(ratio("1:1"), None);
+        assert_eq!(ratio("0:1"), None);
+    }
+
+    #[test]
+    fn alpha_is_read_from_the_pixel_format() {
+        assert!(has_alpha("yuva444p10le"));
+        assert!(has_alpha("rgba"));
+        assert!(has_alpha("gbrap"));
+        assert!(!has_alpha("yuv420p"));
+        assert!(!has_alpha("rgb24"));
+    }
+}
@@ -0,0 +1,252 @@
+use serde::Serialize;
+use std::time::Instant;
+
+use crate::jobs::JobId;
+
+/// Payload emitted on `ffmpeg-progress`, built from FFmpeg's `-progress` output.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProgressPayload {
+    pub job_id: JobId,
+    /// 0.0 - 100.0, relative to the duration of the exported clip
+    pub percent: f64,
+    /// Seconds of output written so far
+    pub current_time: f64,
+    pub fps: Option<f64>,
+    /// Encoding speed as a multiple of realtime (`1.5` for `1.5x`)
+    pub speed: Option<f64>,
+    /// Output bitrate in kbit/s
+    pub bitrate: Option<f64>,
+    pub eta_seconds: Option<f64>,
+    /// Index of the selection range being processed, for multi-range exports
+    pub segment: Option<usize>,
+    pub segment_count: Option<usize>,
+    /// FFmpeg run in progress, counted from 1, for exports that need several
+    pub pass: Option<usize>,
+    pub pass_count: Option<usize>,
+    /// Set while a pass runs filters far slower than encoding, such as frame
+    /// interpolation or nlmeans, so a low speed is expected
+    pub slow: bool,
+    /// Files the running pass writes. Crops written by the same run advance
+    /// together, so `percent` holds for each of them.
+    pub output_paths: Vec<String>,
+}
+
+/// Accumulates the `key=value` lines FFmpeg writes with `-progress pipe:1`.
+///
+/// FFmpeg prints one block of keys per update and terminates each block with
+/// `progress=continue` (or `progress=end` for the last one).
+pub struct ProgressParser {
+    total_duration: f64,
+    offset: f64,
+    weight: f64,
+    /// Output time at which each selection range ends
+    range_ends: Vec<f64>,
+    /// Range this run is limited to, if it only processes one
+    range: Option<usize>,
+    /// Frames the encoder holds back, see `with_lookahead`
+    lookahead_frames: u32,
+    /// Frames written so far
+    frames: u64,
+    started: Instant,
+    current: ProgressPayload,
+}
+
+impl ProgressParser {
+    pub fn new(job_id: JobId, total_duration: f64) -> Self {
+        Self {
+            total_duration,
+            offset: 0.0,
+            weight: 1.0,
+            range_ends: vec![],
+            range: None,
+            lookahead_frames: 0,
+            frames: 0,
+            started: Instant::now(),
+            current: ProgressPayload {
+                job_id,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Reports this run as the part of a multi-pass export that starts at
+    /// `offset` and makes up `weight` of it (both 0.0 - 1.0), so the percentage
+    /// and ETA cover the whole export instead of restarting at 0.
+    pub fn with_span(mut self, offset: f64, weight: f64) -> Self {
+        self.offset = offset;
+        self.weight = weight;
+        self
+    }
+
+    /// Reports which of the selection ranges is being processed. Runs that
+    /// process the joined output derive it from `range_ends`, runs that only
+    /// process one range pass it as `range`.
+    pub fn with_ranges(mut self, range_ends: Vec<f64>, range: Option<usize>) -> Self {
+        if range_ends.len() > 1 {
+            self.current.segment_count = Some(range_ends.len());
+        }
+        self.range_ends = range_ends;
+        self.range = range;
+        self
+    }
+
+    /// Reports this run as run `index` (from 0) of `count`.
+    pub fn with_pass(mut self, index: usize, count: usize) -> Self {
+        if count > 1 {
+            self.current.pass = Some(index + 1);
+            self.current.pass_count = Some(count);
+        }
+        self
+    }
+
+    /// Marks the payloads of this run as `slow`.
+    pub fn with_slow(mut self, slow: bool) -> Self {
+        self.current.slow = slow;
+        self
+    }
+
+    /// Counts the `frames` the encoder of this run reads ahead as done. The
+    /// output of libvpx-vp9 and SVT-AV1 trails what they have read by
+    /// seconds, which would hold every update back at their low speeds.
+    pub fn with_lookahead(mut self, frames: u32) -> Self {
+        self.lookahead_frames = frames;
+        self
+    }
+
+    /// Reports `output_paths` as the files this run writes.
+    pub fn with_outputs(mut self, output_paths: Vec<String>) -> Self {
+        self.current.output_paths = output_paths;
+        self
+    }
+
+    /// Feeds a single line of progress output, returning a payload whenever a
+    /// block is complete.
+    pub fn feed(&mut self, line: &str) -> Option<ProgressPayload> {
+        let (key, value) = line.trim().split_once('=')?;
+        let value = value.trim();
+
+        match key {
+            "frame" => self.frames = value.parse().unwrap_or(self.frames),
+            "fps" => self.current.fps = value.parse().ok(),
+            "bitrate" => {
+                self.current.bitrate = value.trim_end_matches("kbits/s").trim().parse().ok()
+            }
+            "speed" => self.current.speed = value.trim_end_matches('x').trim().parse().ok(),
+            // `out_time_ms` is also in microseconds, `out_time_us` is the honest name
+            "out_time_us" => {
+                if let Ok(us) = value.parse::<i64>() {
+                    self.current.current_time = us.max(0) as f64 / 1_000_000.0;
+                }
+            }
+            "progress" => return Some(self.finish_block(value == "end")),
+            _ => {}
+        }
+
+        None
+    }
+
+    fn finish_block(&mut self, ended: bool) -> ProgressPayload {
+        let total = self.total_duration;
+
+        let mut written = self.current.current_time;
+        if self.lookahead_frames > 0 && self.frames > 0 && written > 0.0 {
+            // Seconds per frame of the output, as written so far
+            written += self.lookahead_frames as f64 * written / self.frames as f64;
+        }
+        let pass_time = if ended {
+            total
+        } else {
+            written.clamp(0.0, total.max(0.0))
+        };
+        let pass_fraction = if ended {
+            1.0
+        } else if total > 0.0 {
+            pass_time / total
+        } else {
+            0.0
+        };
+
+        self.current.percent =
+            ((self.offset + self.weight * pass_fraction) * 100.0).clamp(0.0, 100.0);
+
+        if self.current.segment_count.is_some() {
+            self.current.segment = self.range.or_else(|| {
+                let last = self.range_ends.len() - 1;
+                self.range_ends
+                    .iter()
+                    .position(|end| pass_time < *end)
+                    .or(Some(last))
+            });
+        }
+
+        // Later passes are assumed to take as long per unit of weight as this one
+        let remaining_weight = (1.0 - self.offset - self.weight).max(0.0);
+        // Slow encoders report `speed=N/A` for a while, the clock still knows
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let speed = self
+            .current
+            .speed
+            .filter(|speed| *speed > 0.0)
+            .or((pass_time > 0.0 && elapsed > 0.0).then(|| pass_time / elapsed));
+        self.current.eta_seconds = speed.filter(|_| self.weight > 0.0).map(|speed| {
+            let pass_eta = (total - pass_time) / speed;
+            pass_eta + remaining_weight / self.weight * total / speed
+        });
+
+        self.current.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookahead_counts_as_encoded() {
+        let mut parser = ProgressParser::new(JobId::nil(), 10.0).with_lookahead(25);
+        for line in ["frame=25", "out_time_us=1000000", "speed=0.2x"] {
+            assert!(parser.feed(line).is_none());
+        }
+        let payload = parser.feed("progress=continue").unwrap();
+        // 25 frames in a second are held back on top of the one written
+        assert!((payload.percent - 20.0).abs() < 1e-9);
+        assert!((payload.eta_seconds.unwrap() - 40.0).abs() < 1e-9);
+        assert_eq!(payload.current_time, 1.0);
+    }
+
+    fn feed_block(parser: &mut ProgressParser, lines: &[&str]) -> ProgressPayload {
+        for line in lines {
+            assert!(parser.feed(line).is_none());
+        }
+        parser.feed("progress=continue").unwrap()
+    }
+
+    #[test]
+    fn passes_report_their_share_of_the_export() {
+        let mut first = ProgressParser::new(JobId::nil(), 10.0)
+            .with_span(0.0, 0.5)
+            .with_pass(0, 2);
+        let payload = feed_block(&mut first, &["out_time_us=5000000", "speed=2x"]);
+        assert!((payload.percent - 25.0).abs() < 1e-9);
+        // 2.5s left of this pass and 5s for the second
+        assert!((payload.eta_seconds.unwrap() - 7.5).abs() < 1e-9);
+        assert_eq!((payload.pass, payload.pass_count), (Some(1), Some(2)));
+
+        let mut second = ProgressParser::new(JobId::nil(), 10.0)
+            .with_span(0.5, 0.5)
+            .with_pass(1, 2);
+        let payload = feed_block(&mut second, &["out_time_us=5000000", "speed=2x"]);
+        assert!((payload.percent - 75.0).abs() < 1e-9);
+        assert!((payload.eta_seconds.unwrap() - 2.5).abs() < 1e-9);
+        assert_eq!(second.feed("progress=end").unwrap().percent, 100.0);
+    }
+
+    #[test]
+    fn output_past_the_duration_is_clamped() {
+        let mut parser = ProgressParser::new(JobId::nil(), 10.0).with_span(0.5, 0.5);
+        let payload = feed_block(&mut parser, &["out_time_us=12000000", "speed=1x"]);
+        assert_eq!(payload.percent, 100.0);
+        assert_eq!(payload.eta_seconds, Some(0.0));
+        assert_eq!(payload.current_time, 12.0);
+    }
+}
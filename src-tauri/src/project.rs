@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::fs;
+
+use crate::ffmpeg::ExportArgs;
+
+/// Schema version `save_project` writes.
+const PROJECT_VERSION: u64 = 1;
+
+/// Upgrades a project from version `index + 1` to the next one. Append one
+/// whenever the saved format changes, so older files keep loading.
+const MIGRATIONS: [fn(&mut Value); (PROJECT_VERSION - 1) as usize] = [];
+
+/// An editing session saved to a `.vcproj` file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub version: u64,
+    /// Input, selection, crop and export settings of the session
+    pub args: ExportArgs,
+    /// Editor state the export does not need, kept as the frontend wrote it
+    #[serde(default)]
+    pub editor: Value,
+}
+
+/// Brings a parsed project file up to `PROJECT_VERSION`.
+fn migrate(mut project: Value) -> Result<Value, String> {
+    let version = project
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or("The file is not a video-cropper project")?;
+    if version == 0 || version > PROJECT_VERSION {
+        return Err(format!(
+            "The project was saved by a newer version of the app (format {})",
+            version
+        ));
+    }
+
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(&mut project);
+    }
+    project["version"] = PROJECT_VERSION.into();
+    Ok(project)
+}
+
+fn parse_project(json: &[u8]) -> Result<Project, String> {
+    let project =
+        serde_json::from_slice(json).map_err(|e| format!("Invalid project file: {}", e))?;
+    serde_json::from_value(migrate(project)?).map_err(|e| format!("Invalid project file: {}", e))
+}
+
+#[tauri::command]
+pub async fn save_project(
+    path: String,
+    args: ExportArgs,
+    editor: Option<Value>,
+) -> Result<(), String> {
+    let project = Project {
+        version: PROJECT_VERSION,
+        args,
+        editor: editor.unwrap_or_default(),
+    };
+    let json = serde_json::to_vec_pretty(&project).map_err(|e| e.to_string())?;
+    fs::write(&path, json)
+        .await
+        .map_err(|e| format!("Cannot write project {}: {}", path, e))
+}
+
+/// Reads a project, upgrading files saved in an older format.
+#[tauri::command]
+pub async fn load_project(path: String) -> Result<Project, String> {
+    let json = fs::read(&path)
+        .await
+        .map_err(|e| format!("Cannot read project {}: {}", path, e))?;
+    parse_project(&json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projects_of_unknown_versions_are_refused() {
+        let project = r#"{
+            "version": 1,
+            "args": {
+                "input_path": "in.mp4",
+                "output_path": "out.mp4",
+                "selection": [{ "start": 1.5, "end": 4.0 }]
+            },
+            "editor": { "zoom": 2 }
+        }"#;
+        let loaded = parse_project(project.as_bytes()).unwrap();
+        assert_eq!(loaded.args.input_path(), "in.mp4");
+        assert_eq!(loaded.editor["zoom"], 2);
+
+        let newer = project.replace("\"version\": 1", "\"version\": 99");
+        assert!(parse_project(newer.as_bytes()).is_err());
+        assert!(parse_project(br#"{ "args": {} }"#).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tauri::{AppHandle, Manager, Window};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::binaries;
+use crate::jobs::emit_event;
+use crate::probe::{probe, VideoMetadata};
+use crate::progress::ProgressParser;
+
+// Height of proxies unless asked otherwise, enough to place a crop
+const DEFAULT_PROXY_HEIGHT: u32 = 540;
+// Taller sources stutter in the preview and get a proxy
+const MAX_PREVIEW_HEIGHT: u32 = 1440;
+
+/// Payload of `proxy-progress`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyProgress {
+    pub input_path: String,
+    pub percent: f64,
+    pub eta_seconds: Option<f64>,
+}
+
+fn proxy_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .ok_or("Failed to get app directory")?
+        .join("video_previews"))
+}
+
+/// Size of a proxy at most `max_height` high, in square pixels and the
+/// orientation the source displays in. Crops drawn on it are scaled back to
+/// the source through `ExportArgs::crop_frame`.
+fn proxy_size((width, height): (u32, u32), max_height: u32) -> (u32, u32) {
+    let proxy_height = height.min(max_height) / 2 * 2;
+    let proxy_width = (width as f64 * proxy_height as f64 / height as f64 / 2.0).round() as u32 * 2;
+    (proxy_width.max(2), proxy_height.max(2))
+}
+
+/// Where the preview of `input_path` tagged `kind` is cached, keyed by file
+/// and modification time.
+async fn cached_path(
+    app_handle: &AppHandle,
+    input_path: &str,
+    kind: &str,
+) -> Result<PathBuf, String> {
+    let modified = fs::metadata(input_path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("Cannot read {}: {}", input_path, e))?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+
+    let dir = proxy_dir(app_handle)?;
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Cannot create cache dir: {}", e))?;
+    Ok(dir.join(format!(
+        "{:x}_{}_{}.mp4",
+        md5::compute(input_path),
+        modified,
+        kind
+    )))
+}
+
+/// Writes the first video and audio stream of the input to `output` with
+/// `codec_args`, emitting `proxy-progress`. Goes through a partial file, so
+/// an interrupted run leaves nothing in the cache.
+async fn convert(
+    window: &Window,
+    input_path: &str,
+    duration: f64,
+    codec_args: &[String],
+    output: &Path,
+) -> Result<(), String> {
+    let partial = output.with_extension("partial.mp4");
+    let mut child = tokio::process::Command::new(binaries::ffmpeg())
+        .args(["-hide_banner", "-loglevel", "error", "-nostats"])
+        .args(["-progress", "pipe:1", "-i", input_path])
+        .args(["-map", "0:v:0", "-map", "0:a:0?"])
+        .args(codec_args)
+        .args(["-movflags", "+faststart", "-y"])
+        .arg(&partial)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Cannot run FFmpeg: {}", e))?;
+
+    let mut progress = ProgressParser::new(uuid::Uuid::new_v4(), duration);
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(payload) = progress.feed(&line) {
+                emit_event(
+                    window,
+                    "proxy-progress",
+                    ProxyProgress {
+                        input_path: input_path.to_string(),
+                        percent: payload.percent,
+                        eta_seconds: payload.eta_seconds,
+                    },
+                );
+            }
+        }
+    }
+
+    let result = child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("FFmpeg did not finish: {}", e))?;
+    if !result.status.success() {
+        let _ = fs::remove_file(&partial).await;
+        return Err(format!(
+            "Preparing the preview failed: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+
+    fs::rename(&partial, output)
+        .await
+        .map_err(|e| format!("Cannot store the preview: {}", e))
+}
+
+async fn create_proxy(
+    window: &Window,
+    input_path: &str,
+    metadata: &VideoMetadata,
+    max_height: u32,
+) -> Result<String, String> {
+    let proxy_path = cached_path(
+        &window.app_handle(),
+        input_path,
+        &format!("{}p", max_height),
+    )
+    .await?;
+    if !proxy_path.exists() {
+        let (width, height) = proxy_size(metadata.display_size(), max_height);
+        println!("Creating {}x{} proxy of {}", width, height, input_path);
+        let codec_args: Vec<String> = [
+            "-vf",
+            &format!("scale={}:{},setsar=1", width, height),
+            "-c:v",
+            "libx264",
+            "-preset",
+            "ultrafast",
+            "-crf",
+            "28",
+            "-pix_fmt",
+            "yuv420p",
+            "-c:a",
+            "aac",
+            "-b:a",
+            "128k",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        convert(
+            window,
+            input_path,
+            metadata.duration,
+            &codec_args,
+            &proxy_path,
+        )
+        .await?;
+    }
+    Ok(proxy_path.to_string_lossy().into_owned())
+}
+
+/// Creates a low resolution H.264 copy of the input for the preview, which
+/// plays smoothly where 4K or HEVC sources stutter, and returns its path.
+/// Proxies are cached by file, modification time and height. Exports keep
+/// reading the original.
+#[tauri::command]
+pub async fn generate_proxy(
+    window: Window,
+    input_path: String,
+    max_height: Option<u32>,
+) -> Result<String, String> {
+    let metadata = probe(&input_path).await?;
+    create_proxy(
+        &window,
+        &input_path,
+        &metadata,
+        max_height.unwrap_or(DEFAULT_PROXY_HEIGHT),
+    )
+    .await
+}
+
+/// How the preview gets a source the webview can play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewMode {
+    /// Plays as it is
+    Original,
+    /// H.264 in a container the webview cannot open, copied into an MP4
+    Remux,
+    /// A codec the webview cannot decode, or too large to play smoothly
+    Proxy,
+}
+
+/// Returned by `prepare_preview`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewSource {
+    pub path: String,
+    pub mode: PreviewMode,
+}
+
+/// Whether the webviews of every platform play the source, which leaves
+/// H.264 in MP4 or QuickTime files and VP8 or VP9 in WebM.
+fn preview_mode(metadata: &VideoMetadata, extension: &str) -> PreviewMode {
+    if metadata.display_size().1 > MAX_PREVIEW_HEIGHT {
+        return PreviewMode::Proxy;
+    }
+    let video = metadata.video_codec.as_deref().unwrap_or_default();
+    let audio = metadata.audio_codec.as_deref();
+    let eight_bit_420 = matches!(
+        metadata.pix_fmt.as_deref(),
+        None | Some("yuv420p") | Some("yuvj420p")
+    );
+    let mp4 = metadata
+        .container
+        .as_deref()
+        .is_some_and(|container| container.contains("mp4"));
+
+    match video {
+        "vp8" | "vp9"
+            if extension == "webm" && matches!(audio, None | Some("opus") | Some("vorbis")) =>
+        {
+            PreviewMode::Original
+        }
+        "h264" if eight_bit_420 && mp4 && matches!(audio, None | Some("aac") | Some("mp3")) => {
+            PreviewMode::Original
+        }
+        "h264" if eight_bit_420 => PreviewMode::Remux,
+        _ => PreviewMode::Proxy,
+    }
+}
+
+/// Returns a file the preview can play for the input: the input itself, an
+/// MP4 copy of its H.264 stream, or a proxy. Copies are cached like proxies.
+/// Exports always read the original.
+#[tauri::command]
+pub async fn prepare_preview(window: Window, input_path: String) -> Result<PreviewSource, String> {
+    let metadata = probe(&input_path).await?;
+    let extension = Path::new(&input_path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let mode = preview_mode(&metadata, &extension);
+    let path = match mode {
+        PreviewMode::Original => input_path,
+        PreviewMode::Remux => {
+            let remux_path = cached_path(&window.app_handle(), &input_path, "remux").await?;
+            if !remux_path.exists() {
+                println!("Remuxing {} for the preview", input_path);
+                let audio = match metadata.audio_codec.as_deref() {
+                    Some("aac") | Some("mp3") => "copy",
+                    _ => "aac",
+                };
+                let codec_args = ["-c:v", "copy", "-c:a", audio].map(String::from);
+                convert(
+                    &window,
+                    &input_path,
+                    metadata.duration,
+                    &codec_args,
+                    &remux_path,
+                )
+                .await?;
+            }
+            remux_path.to_string_lossy().into_owned()
+        }
+        PreviewMode::Proxy => {
+            create_proxy(&window, &input_path, &metadata, DEFAULT_PROXY_HEIGHT).await?
+        }
+    };
+    Ok(PreviewSource { path, mode })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxies_keep_the_displayed_aspect() {
+        assert_eq!(proxy_size((3840, 2160), 540), (960, 540));
+        // Portrait phone video, already turned to how it displays
+        assert_eq!(proxy_size((2160, 3840), 540), (304, 540));
+        // Small sources are not scaled up
+        assert_eq!(proxy_size((640, 360), 540), (640, 360));
+    }
+
+    #[test]
+    fn previews_pick_the_cheapest_playable_source() {
+        let source =
+            |video: &str, audio: Option<&str>, container: &str, height: u32| VideoMetadata {
+                duration: 10.0,
+                width: height * 16 / 9,
+                height,
+                frame_rate: 30.0,
+                video_codec: Some(video.to_string()),
+                pix_fmt: Some("yuv420p".to_string()),
+                color_transfer: None,
+                color_primaries: None,
+                color_space: None,
+                color_range: None,
+                mastering_display: None,
+                content_light: None,
+                audio_codec: audio.map(String::from),
+                audio_channels: audio.map(|_| 2),
+                audio_streams: vec![],
+                rotation: 0,
+                sample_aspect_ratio: None,
+                container: Some(container.to_string()),
+                spherical: None,
+                telemetry_streams: vec![],
+                alpha: false,
+            };
+        const MP4: &str = "mov,mp4,m4a,3gp,3g2,mj2";
+        const MKV: &str = "matroska,webm";
+
+        let h264 = source("h264", Some("aac"), MP4, 1080);
+        assert_eq!(preview_mode(&h264, "mp4"), PreviewMode::Original);
+        let mkv = source("h264", Some("ac3"), MKV, 1080);
+        assert_eq!(preview_mode(&mkv, "mkv"), PreviewMode::Remux);
+        let vp9 = source("vp9", Some("opus"), MKV, 1080);
+        assert_eq!(preview_mode(&vp9, "webm"), PreviewMode::Original);
+        let hevc = source("hevc", Some("aac"), MP4, 1080);
+        assert_eq!(preview_mode(&hevc, "mov"), PreviewMode::Proxy);
+        let prores = source("prores", None, MP4, 1080);
+        assert_eq!(preview_mode(&prores, "mov"), PreviewMode::Proxy);
+        let uhd = source("h264", Some("aac"), MP4, 2160);
+        assert_eq!(preview_mode(&uhd, "mp4"), PreviewMode::Proxy);
+    }
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+use crate::codecs::VideoCodec;
+use crate::encoders::Encoder;
+
+/// x264-style speed presets, also mapped onto other encoders' speed knobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedPreset {
+    Ultrafast,
+    Superfast,
+    Veryfast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    Veryslow,
+}
+
+impl SpeedPreset {
+    fn x264_name(self) -> &'static str {
+        match self {
+            SpeedPreset::Ultrafast => "ultrafast",
+            SpeedPreset::Superfast => "superfast",
+            SpeedPreset::Veryfast => "veryfast",
+            SpeedPreset::Faster => "faster",
+            SpeedPreset::Fast => "fast",
+            SpeedPreset::Medium => "medium",
+            SpeedPreset::Slow => "slow",
+            SpeedPreset::Slower => "slower",
+            SpeedPreset::Veryslow => "veryslow",
+        }
+    }
+
+    fn nvenc_name(self) -> &'static str {
+        match self {
+            SpeedPreset::Ultrafast | SpeedPreset::Superfast => "p1",
+            SpeedPreset::Veryfast => "p2",
+            SpeedPreset::Faster | SpeedPreset::Fast => "p3",
+            SpeedPreset::Medium => "p4",
+            SpeedPreset::Slow => "p5",
+            SpeedPreset::Slower => "p6",
+            SpeedPreset::Veryslow => "p7",
+        }
+    }
+
+    fn qsv_name(self) -> &'static str {
+        match self {
+            SpeedPreset::Ultrafast | SpeedPreset::Superfast => "veryfast",
+            other => other.x264_name(),
+        }
+    }
+
+    fn amf_quality(self) -> &'static str {
+        match self {
+            SpeedPreset::Ultrafast
+            | SpeedPreset::Superfast
+            | SpeedPreset::Veryfast
+            | SpeedPreset::Faster => "speed",
+            SpeedPreset::Fast | SpeedPreset::Medium => "balanced",
+            _ => "quality",
+        }
+    }
+
+    /// libvpx `-cpu-used`, 0 is slowest
+    fn vpx_cpu_used(self) -> u8 {
+        match self {
+            SpeedPreset::Ultrafast | SpeedPreset::Superfast => 5,
+            SpeedPreset::Veryfast | SpeedPreset::Faster => 4,
+            SpeedPreset::Fast => 3,
+            SpeedPreset::Medium => 2,
+            SpeedPreset::Slow => 1,
+            SpeedPreset::Slower | SpeedPreset::Veryslow => 0,
+        }
+    }
+
+    /// SVT-AV1 `-preset`, 0 is slowest
+    fn svt_preset(self) -> u8 {
+        match self {
+            SpeedPreset::Ultrafast => 12,
+            SpeedPreset::Superfast => 11,
+            SpeedPreset::Veryfast => 10,
+            SpeedPreset::Faster => 9,
+            SpeedPreset::Fast => 8,
+            SpeedPreset::Medium => 6,
+            SpeedPreset::Slow => 5,
+            SpeedPreset::Slower => 4,
+            SpeedPreset::Veryslow => 2,
+        }
+    }
+}
+
+/// Frames libvpx-vp9 and SVT-AV1 read ahead before they write one, so the
+/// output time FFmpeg reports trails the decoded picture by about as many.
+/// SVT-AV1's lookahead depends on its preset, this is a typical value.
+pub fn lookahead_frames(encoder_name: &str) -> u32 {
+    match encoder_name {
+        "libvpx-vp9" => 25,
+        "libsvtav1" => 48,
+        _ => 0,
+    }
+}
+
+/// Quality settings of an export. Unset fields leave the encoder default,
+/// except the speed of libvpx-vp9, whose default is far slower than the
+/// others.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Quality {
+    /// Constant quality, lower is better (0-51, or 0-63 for VP9/AV1)
+    pub crf: Option<u8>,
+    pub preset: Option<SpeedPreset>,
+}
+
+impl Quality {
+    pub fn validate(&self, codec: VideoCodec) -> Result<(), String> {
+        let max_crf = match codec {
+            VideoCodec::Vp9 | VideoCodec::Av1 => 63,
+            VideoCodec::H264 | VideoCodec::Hevc => 51,
+            VideoCodec::Prores | VideoCodec::Dnxhr if self.crf.is_some() => {
+                return Err(format!(
+                    "{:?} has no CRF, its profile sets the quality",
+                    codec
+                ))
+            }
+            VideoCodec::Prores | VideoCodec::Dnxhr => return Ok(()),
+        };
+
+        match self.crf {
+            Some(crf) if crf > max_crf => Err(format!(
+                "CRF {} is out of range for {:?} (0-{})",
+                crf, codec, max_crf
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rough bits per pixel and frame an encode at this quality takes, for
+    /// size estimates. Each codec starts from its encoder's default CRF and
+    /// the size doubles every 6 CRF steps.
+    pub fn bits_per_pixel(&self, codec: VideoCodec) -> f64 {
+        let (default_crf, default_bpp) = match codec {
+            VideoCodec::H264 => (23, 0.1),
+            VideoCodec::Hevc => (28, 0.06),
+            VideoCodec::Vp9 => (32, 0.065),
+            VideoCodec::Av1 => (35, 0.05),
+            // A fixed rate per profile, about that of ProRes HQ
+            VideoCodec::Prores | VideoCodec::Dnxhr => return 2.8,
+        };
+        let crf = self.crf.unwrap_or(default_crf);
+
+        default_bpp * 2f64.powf((default_crf as f64 - crf as f64) / 6.0)
+    }
+
+    /// Encoder flags for this quality setting on the resolved encoder.
+    pub fn encoder_args(&self, encoder: Encoder, codec: VideoCodec) -> Vec<String> {
+        let mut args = vec![];
+        let crf = self.crf.map(|crf| crf.to_string());
+
+        match encoder {
+            Encoder::Cpu | Encoder::Auto => match codec {
+                VideoCodec::H264 | VideoCodec::Hevc => {
+                    if let Some(crf) = crf {
+                        args.extend(["-crf".into(), crf]);
+                    }
+                    if let Some(preset) = self.preset {
+                        args.extend(["-preset".into(), preset.x264_name().into()]);
+                    }
+                }
+                VideoCodec::Vp9 => {
+                    if let Some(crf) = crf {
+                        // Without -b:v 0 libvpx treats the CRF as a cap only
+                        args.extend(["-crf".into(), crf, "-b:v".into(), "0".into()]);
+                    }
+                    let preset = self.preset.unwrap_or(SpeedPreset::Medium);
+                    args.extend([
+                        "-deadline".into(),
+                        "good".into(),
+                        "-cpu-used".into(),
+                        preset.vpx_cpu_used().to_string(),
+                        // Off by default, libvpx then only threads over tiles
+                        "-row-mt".into(),
+                        "1".into(),
+                    ]);
+                }
+                // The profile of the `EditingPreset` sets the quality
+                VideoCodec::Prores | VideoCodec::Dnxhr => {}
+                VideoCodec::Av1 => {
+                    if let Some(crf) = crf {
+                        args.extend(["-crf".into(), crf]);
+                    }
+                    if let Some(preset) = self.preset {
+                        args.extend(["-preset".into(), preset.svt_preset().to_string()]);
+                    }
+                }
+            },
+            Encoder::Nvenc => {
+                if let Some(crf) = crf {
+                    args.extend(["-rc".into(), "vbr".into(), "-cq".into(), crf]);
+                }
+                if let Some(preset) = self.preset {
+                    args.extend(["-preset".into(), preset.nvenc_name().into()]);
+                }
+            }
+            Encoder::Qsv => {
+                if let Some(crf) = crf {
+                    args.extend(["-global_quality".into(), crf]);
+                }
+                if let Some(preset) = self.preset {
+                    args.extend(["-preset".into(), preset.qsv_name().into()]);
+                }
+            }
+            Encoder::Amf => {
+                if let Some(crf) = crf {
+                    args.extend([
+                        "-rc".into(),
+                        "cqp".into(),
+                        "-qp_i".into(),
+                        crf.clone(),
+                        "-qp_p".into(),
+                        crf,
+                    ]);
+                }
+                if let Some(preset) = self.preset {
+                    args.extend(["-quality".into(), preset.amf_quality().into()]);
+                }
+            }
+            Encoder::VideoToolbox => {
+                // VideoToolbox has no speed presets and a 1-100 quality scale
+                // where higher is better
+                if let Some(crf) = self.crf {
+                    let q = 100u32.saturating_sub(crf as u32 * 2).max(1);
+                    args.extend(["-q:v".into(), q.to_string()]);
+                }
+            }
+            Encoder::Vaapi => {
+                if let Some(crf) = crf {
+                    args.extend(["-rc_mode".into(), "CQP".into(), "-qp".into(), crf]);
+                }
+            }
+        }
+
+        args
+    }
+}
@@ -0,0 +1,545 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{async_runtime, AppHandle, Manager, State, Window};
+use tokio::fs;
+
+use crate::errors::JobError;
+use crate::ffmpeg::{spawn_export, ExportArgs};
+use crate::jobs::{emit_event, JobId, JobRegistry, JobStatus};
+use crate::scratch::{job_temp_dir, remove_temp_dir};
+
+// Files a folder given to `process_batch` is searched for
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "mov", "mkv", "webm", "avi", "m4v", "mts", "m2ts", "ts", "flv", "wmv", "mpg",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub id: JobId,
+    pub status: JobStatus,
+    pub args: ExportArgs,
+    /// Set for the jobs `process_batch` queued
+    pub batch_id: Option<JobId>,
+}
+
+/// Payload of `batch-progress`, emitted whenever a job of the batch ends.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub batch_id: JobId,
+    pub total: usize,
+    pub finished: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// Share of the jobs that have ended, however they ended
+    pub percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchEntry {
+    pub job_id: JobId,
+    pub input_path: String,
+    pub status: JobStatus,
+}
+
+/// Payload of `batch-finished`, emitted once every job of the batch has ended.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchReport {
+    #[serde(flatten)]
+    pub progress: BatchProgress,
+    pub entries: Vec<BatchEntry>,
+}
+
+/// A job that was queued or running when the app last went down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterruptedJob {
+    #[serde(flatten)]
+    pub job: QueuedJob,
+    /// Outputs it was writing, only partly written if they exist at all
+    pub partial_outputs: Vec<String>,
+}
+
+struct QueueState {
+    jobs: Vec<QueuedJob>,
+    concurrency: usize,
+}
+
+/// Managed state for exports waiting to run.
+///
+/// Jobs are started in list order, at most `concurrency` at a time. Finished
+/// jobs stay in the list (with their final status) until they are removed.
+///
+/// Unfinished jobs are saved to `queue.json` whenever the queue changes, so
+/// the ones a crash interrupted can be resumed on the next start.
+pub struct ExportQueue {
+    state: Mutex<QueueState>,
+    /// Read from `queue.json` at startup, until resumed or discarded
+    interrupted: Mutex<Vec<InterruptedJob>>,
+}
+
+impl Default for ExportQueue {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                jobs: Vec::new(),
+                concurrency: 1,
+            }),
+            interrupted: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl ExportQueue {
+    fn set_status(&self, id: JobId, status: JobStatus) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(job) = state.jobs.iter_mut().find(|job| job.id == id) {
+            job.status = status;
+        }
+    }
+
+    /// Marks as many queued jobs as the concurrency limit allows as running
+    /// and returns them.
+    fn take_startable(&self) -> Vec<QueuedJob> {
+        let mut state = self.state.lock().unwrap();
+        let running = state
+            .jobs
+            .iter()
+            .filter(|job| job.status == JobStatus::Running)
+            .count();
+        let free_slots = state.concurrency.saturating_sub(running);
+
+        state
+            .jobs
+            .iter_mut()
+            .filter(|job| job.status == JobStatus::Queued)
+            .take(free_slots)
+            .map(|job| {
+                job.status = JobStatus::Running;
+                job.clone()
+            })
+            .collect()
+    }
+
+    pub fn set_concurrency(&self, limit: usize) {
+        self.state.lock().unwrap().concurrency = limit;
+    }
+
+    pub fn has_queued_jobs(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .jobs
+            .iter()
+            .any(|job| job.status == JobStatus::Queued)
+    }
+
+    fn snapshot(&self) -> Vec<QueuedJob> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    /// Writes the unfinished jobs, and the interrupted ones not dealt with
+    /// yet, to `queue.json`. Written to a temporary file first, so a crash
+    /// mid-write leaves the previous version.
+    pub fn save(&self, app_handle: &AppHandle) {
+        let jobs = app_handle.state::<JobRegistry>();
+        let mut unfinished: Vec<_> = self
+            .snapshot()
+            .into_iter()
+            .filter(|job| matches!(job.status, JobStatus::Queued | JobStatus::Running))
+            .map(|job| InterruptedJob {
+                partial_outputs: jobs.output_paths(job.id),
+                job,
+            })
+            .collect();
+        unfinished.extend(self.interrupted.lock().unwrap().iter().cloned());
+
+        let result = queue_path(app_handle).and_then(|path| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            let json = serde_json::to_vec_pretty(&unfinished).map_err(|e| e.to_string())?;
+            let temp = path.with_extension("json.tmp");
+            std::fs::write(&temp, json).map_err(|e| e.to_string())?;
+            std::fs::rename(&temp, &path).map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            println!("Could not save the queue: {}", e);
+        }
+    }
+
+    /// State of every job of the batch still in the queue.
+    fn batch_report(&self, batch_id: JobId) -> BatchReport {
+        let entries: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .jobs
+            .iter()
+            .filter(|job| job.batch_id == Some(batch_id))
+            .map(|job| BatchEntry {
+                job_id: job.id,
+                input_path: job.args.input_path().to_string(),
+                status: job.status,
+            })
+            .collect();
+        BatchReport {
+            progress: batch_progress(batch_id, &entries),
+            entries,
+        }
+    }
+}
+
+fn batch_progress(batch_id: JobId, entries: &[BatchEntry]) -> BatchProgress {
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+    let (finished, failed, cancelled) = (
+        count(JobStatus::Finished),
+        count(JobStatus::Failed),
+        count(JobStatus::Cancelled),
+    );
+    let total = entries.len();
+    let ended = finished + failed + cancelled;
+    BatchProgress {
+        batch_id,
+        total,
+        finished,
+        failed,
+        cancelled,
+        percent: if total == 0 {
+            100.0
+        } else {
+            ended as f64 / total as f64 * 100.0
+        },
+    }
+}
+
+fn queue_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .ok_or("Failed to get app directory")?
+        .join("queue.json"))
+}
+
+/// Reads the jobs the last run left unfinished at startup. They wait for
+/// `resume_queue` or `discard_interrupted_jobs`.
+pub fn restore(app_handle: &AppHandle) {
+    let Ok(json) =
+        queue_path(app_handle).and_then(|path| std::fs::read(path).map_err(|e| e.to_string()))
+    else {
+        return;
+    };
+    let interrupted: Vec<InterruptedJob> = match serde_json::from_slice(&json) {
+        Ok(interrupted) => interrupted,
+        Err(e) => {
+            println!("Invalid queue file, dropping it: {}", e);
+            vec![]
+        }
+    };
+    if !interrupted.is_empty() {
+        println!(
+            "{} jobs were interrupted by the last exit",
+            interrupted.len()
+        );
+    }
+    *app_handle
+        .state::<ExportQueue>()
+        .interrupted
+        .lock()
+        .unwrap() = interrupted;
+}
+
+/// Deletes what interrupted jobs left behind: partial outputs, which would
+/// be unplayable, and their scratch directories.
+async fn remove_leftovers(interrupted: &[InterruptedJob]) {
+    for job in interrupted {
+        for output_path in &job.partial_outputs {
+            if let Err(e) = fs::remove_file(output_path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    println!("Could not remove partial output {}: {}", output_path, e);
+                }
+            }
+        }
+        remove_temp_dir(&job_temp_dir(job.job.id)).await;
+    }
+}
+
+/// Starts queued jobs until the concurrency limit is reached. Each finished
+/// job calls back into here so the queue keeps draining.
+pub fn pump(window: Window) {
+    if window.state::<JobRegistry>().is_quitting() {
+        return;
+    }
+
+    let queue = window.state::<ExportQueue>();
+    let started = queue.take_startable();
+
+    if !started.is_empty() {
+        queue.save(&window.app_handle());
+        emit_event(&window, "queue-updated", queue.snapshot());
+    }
+
+    for job in started {
+        let window = window.clone();
+
+        async_runtime::spawn(async move {
+            let status = match spawn_export(window.clone(), job.id, job.args).await {
+                Ok(handle) => handle.await.unwrap_or(JobStatus::Failed),
+                Err(e) => {
+                    emit_event(&window, "ffmpeg-error", JobError::new(job.id, e));
+                    JobStatus::Failed
+                }
+            };
+
+            let queue = window.state::<ExportQueue>();
+            queue.set_status(job.id, status);
+            queue.save(&window.app_handle());
+            emit_event(&window, "queue-updated", queue.snapshot());
+
+            if let Some(batch_id) = job.batch_id {
+                let report = queue.batch_report(batch_id);
+                emit_event(&window, "batch-progress", report.progress.clone());
+                if report.progress.percent >= 100.0 {
+                    println!(
+                        "Batch {} done: {} finished, {} failed, {} cancelled",
+                        batch_id,
+                        report.progress.finished,
+                        report.progress.failed,
+                        report.progress.cancelled
+                    );
+                    emit_event(&window, "batch-finished", report);
+                }
+            }
+
+            pump(window);
+        });
+    }
+}
+
+/// Queues an export, also for the backend's own sources such as watch
+/// folders.
+pub fn enqueue(window: Window, args: ExportArgs) -> JobId {
+    let jobs = window.state::<JobRegistry>();
+    let queue = window.state::<ExportQueue>();
+    let id = jobs.next_id();
+    jobs.set_status(id, JobStatus::Queued);
+
+    queue.state.lock().unwrap().jobs.push(QueuedJob {
+        id,
+        status: JobStatus::Queued,
+        args,
+        batch_id: None,
+    });
+    queue.save(&window.app_handle());
+    emit_event(&window, "queue-updated", queue.snapshot());
+
+    pump(window);
+    id
+}
+
+#[tauri::command]
+pub fn enqueue_export(window: Window, args: ExportArgs) -> JobId {
+    enqueue(window, args)
+}
+
+/// Video files in `inputs`, with folders replaced by the videos directly in
+/// them, sorted by name.
+async fn expand_inputs(inputs: &[String]) -> Result<Vec<String>, String> {
+    let mut paths = vec![];
+    for input in inputs {
+        let path = Path::new(input);
+        if !path.is_dir() {
+            paths.push(input.clone());
+            continue;
+        }
+
+        let mut entries = fs::read_dir(path)
+            .await
+            .map_err(|e| format!("Cannot read folder {}: {}", input, e))?;
+        let mut videos = vec![];
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.is_file() && is_video(&path) {
+                videos.push(path.to_string_lossy().into_owned());
+            }
+        }
+        videos.sort();
+        paths.extend(videos);
+    }
+    Ok(paths)
+}
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Queues the export described by `settings` once for every file in
+/// `inputs`, which may also name folders of videos. Returns the batch id the
+/// `batch-progress` and `batch-finished` events carry.
+#[tauri::command]
+pub async fn process_batch(
+    window: Window,
+    jobs: State<'_, JobRegistry>,
+    queue: State<'_, ExportQueue>,
+    inputs: Vec<String>,
+    settings: ExportArgs,
+) -> Result<JobId, String> {
+    let inputs = expand_inputs(&inputs).await?;
+    if inputs.is_empty() {
+        return Err("The batch contains no videos".to_string());
+    }
+    if inputs.len() > 1 && !settings.names_output_after_input() {
+        return Err(
+            "Name the outputs of a batch after their input with {stem} or {basename}".to_string(),
+        );
+    }
+
+    let batch_id = jobs.next_id();
+    let queued: Vec<_> = inputs
+        .iter()
+        .map(|input| {
+            let id = jobs.next_id();
+            jobs.set_status(id, JobStatus::Queued);
+            QueuedJob {
+                id,
+                status: JobStatus::Queued,
+                args: settings.for_input(input),
+                batch_id: Some(batch_id),
+            }
+        })
+        .collect();
+    println!("Queued batch {} of {} videos", batch_id, queued.len());
+
+    queue.state.lock().unwrap().jobs.extend(queued);
+    queue.save(&window.app_handle());
+    emit_event(&window, "queue-updated", queue.snapshot());
+    emit_event(
+        &window,
+        "batch-progress",
+        queue.batch_report(batch_id).progress,
+    );
+
+    pump(window);
+
+    Ok(batch_id)
+}
+
+#[tauri::command]
+pub fn list_jobs(queue: State<'_, ExportQueue>) -> Vec<QueuedJob> {
+    queue.snapshot()
+}
+
+/// Removes a job that is not currently running. Running jobs have to be
+/// stopped with `cancel_export` first.
+#[tauri::command]
+pub fn remove_job(
+    app_handle: AppHandle,
+    queue: State<'_, ExportQueue>,
+    jobs: State<'_, JobRegistry>,
+    job_id: JobId,
+) -> Result<(), String> {
+    {
+        let mut state = queue.state.lock().unwrap();
+        let index = state
+            .jobs
+            .iter()
+            .position(|job| job.id == job_id)
+            .ok_or(format!("No queued job with id {}", job_id))?;
+
+        if state.jobs[index].status == JobStatus::Running {
+            return Err(format!("Job {} is running, cancel it instead", job_id));
+        }
+
+        state.jobs.remove(index);
+    }
+    jobs.forget(job_id);
+    queue.save(&app_handle);
+    Ok(())
+}
+
+/// Reorders the queue to follow `order`. Jobs missing from `order` keep their
+/// relative position after the listed ones.
+#[tauri::command]
+pub fn reorder_jobs(
+    window: Window,
+    queue: State<'_, ExportQueue>,
+    order: Vec<JobId>,
+) -> Vec<QueuedJob> {
+    queue.state.lock().unwrap().jobs.sort_by_key(|job| {
+        order
+            .iter()
+            .position(|id| *id == job.id)
+            .unwrap_or(order.len())
+    });
+
+    queue.save(&window.app_handle());
+    let snapshot = queue.snapshot();
+    emit_event(&window, "queue-updated", snapshot.clone());
+    snapshot
+}
+
+/// Jobs the last run left queued or running, with the partial outputs of
+/// the running ones.
+#[tauri::command]
+pub fn list_interrupted_jobs(queue: State<'_, ExportQueue>) -> Vec<InterruptedJob> {
+    queue.interrupted.lock().unwrap().clone()
+}
+
+/// Queues the interrupted jobs again, in their old order, after deleting
+/// their partial outputs. Returns the new job ids.
+#[tauri::command]
+pub async fn resume_queue(window: Window) -> Result<Vec<JobId>, String> {
+    let queue = window.state::<ExportQueue>();
+    let interrupted = std::mem::take(&mut *queue.interrupted.lock().unwrap());
+    remove_leftovers(&interrupted).await;
+
+    let jobs = window.state::<JobRegistry>();
+    let resumed: Vec<_> = interrupted
+        .into_iter()
+        .map(|interrupted| {
+            let id = jobs.next_id();
+            jobs.set_status(id, JobStatus::Queued);
+            QueuedJob {
+                id,
+                status: JobStatus::Queued,
+                ..interrupted.job
+            }
+        })
+        .collect();
+    let ids = resumed.iter().map(|job| job.id).collect();
+    println!("Resuming {} interrupted jobs", resumed.len());
+
+    queue.state.lock().unwrap().jobs.extend(resumed);
+    queue.save(&window.app_handle());
+    emit_event(&window, "queue-updated", queue.snapshot());
+
+    pump(window.clone());
+    Ok(ids)
+}
+
+/// Forgets the interrupted jobs and deletes their partial outputs.
+#[tauri::command]
+pub async fn discard_interrupted_jobs(window: Window) {
+    let queue = window.state::<ExportQueue>();
+    let interrupted = std::mem::take(&mut *queue.interrupted.lock().unwrap());
+    remove_leftovers(&interrupted).await;
+    queue.save(&window.app_handle());
+}
+
+#[tauri::command]
+pub fn set_queue_concurrency(
+    window: Window,
+    queue: State<'_, ExportQueue>,
+    limit: usize,
+) -> Result<(), String> {
+    if limit == 0 {
+        return Err("Concurrency limit must be at least 1".to_string());
+    }
+
+    queue.set_concurrency(limit);
+
+    // A higher limit may free up slots for waiting jobs right away
+    pump(window);
+    Ok(())
+}
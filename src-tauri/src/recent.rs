@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::probe::probe_duration;
+use crate::thumbnails::get_thumbnail;
+
+// Older files drop off the list
+const MAX_RECENT_FILES: usize = 20;
+// Width of the start screen thumbnails
+const THUMBNAIL_WIDTH: u32 = 320;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredFile {
+    path: String,
+    /// Unix seconds
+    opened_at: u64,
+    duration: f64,
+}
+
+/// Entry of `get_recent_files`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentFile {
+    pub path: String,
+    pub opened_at: u64,
+    pub duration: f64,
+    /// Cached frame a tenth into the video, `None` when it cannot be read
+    pub thumbnail: Option<String>,
+}
+
+// Serializes read-modify-write cycles of the recent files list
+static RECENT_FILE: Mutex<()> = Mutex::const_new(());
+
+fn recent_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .ok_or("Failed to get app directory")?
+        .join("recent.json"))
+}
+
+async fn read_recent(app_handle: &AppHandle) -> Result<Vec<StoredFile>, String> {
+    match fs::read(recent_path(app_handle)?).await {
+        Ok(json) => {
+            serde_json::from_slice(&json).map_err(|e| format!("Invalid recent files list: {}", e))
+        }
+        // Nothing opened yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(format!("Cannot read recent files: {}", e)),
+    }
+}
+
+async fn write_recent(app_handle: &AppHandle, files: &[StoredFile]) -> Result<(), String> {
+    let path = recent_path(app_handle)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Cannot create app dir: {}", e))?;
+    }
+    let json = serde_json::to_vec_pretty(files).map_err(|e| e.to_string())?;
+    fs::write(&path, json)
+        .await
+        .map_err(|e| format!("Cannot write recent files: {}", e))
+}
+
+/// Puts a video the editor opened at the top of the list.
+#[tauri::command]
+pub async fn add_recent_file(app_handle: AppHandle, path: String) -> Result<(), String> {
+    let duration = probe_duration(&path).await?;
+    let opened_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+
+    let _guard = RECENT_FILE.lock().await;
+    let mut files = read_recent(&app_handle).await?;
+    files.retain(|file| file.path != path);
+    files.insert(
+        0,
+        StoredFile {
+            path,
+            opened_at,
+            duration,
+        },
+    );
+    files.truncate(MAX_RECENT_FILES);
+    write_recent(&app_handle, &files).await
+}
+
+/// Recently opened videos that still exist, the latest first. Missing ones
+/// are dropped from the list.
+#[tauri::command]
+pub async fn get_recent_files(app_handle: AppHandle) -> Result<Vec<RecentFile>, String> {
+    let files = {
+        let _guard = RECENT_FILE.lock().await;
+        let mut files = read_recent(&app_handle).await?;
+        let count = files.len();
+        files.retain(|file| Path::new(&file.path).is_file());
+        if files.len() != count {
+            write_recent(&app_handle, &files).await?;
+        }
+        files
+    };
+
+    let mut recent = Vec::with_capacity(files.len());
+    for file in files {
+        let thumbnail = get_thumbnail(
+            app_handle.clone(),
+            file.path.clone(),
+            file.duration * 0.1,
+            Some(THUMBNAIL_WIDTH),
+            None,
+            None,
+        )
+        .await
+        .ok();
+        recent.push(RecentFile {
+            path: file.path,
+            opened_at: file.opened_at,
+            duration: file.duration,
+            thumbnail,
+        });
+    }
+    Ok(recent)
+}
+
+#[tauri::command]
+pub async fn clear_recent_files(app_handle: AppHandle) -> Result<(), String> {
+    let _guard = RECENT_FILE.lock().await;
+    write_recent(&app_handle, &[]).await
+}
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+
+use crate::jobs::JobId;
+
+// Job folders untouched for this long are left over from an earlier run,
+// younger ones may belong to another instance of the app
+const LEFTOVER_AGE: Duration = Duration::from_secs(60 * 60);
+
+// Scratch files of every job live under `<root>/video-cropper/<job id>`.
+// Set from the settings, the planning code reads them without the app state
+static ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+// Most bytes all scratch folders may take before new jobs are refused, 0
+// for no limit
+static BUDGET: AtomicU64 = AtomicU64::new(0);
+
+/// `dir` replaces the system temp dir as the root, `budget` limits the
+/// total size of all scratch folders.
+pub fn configure(dir: Option<PathBuf>, budget: Option<u64>) {
+    *ROOT.write().unwrap() = dir;
+    BUDGET.store(budget.unwrap_or(0), Ordering::SeqCst);
+}
+
+fn scratch_root() -> PathBuf {
+    ROOT.read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("video-cropper")
+}
+
+/// Scratch directory of a single job.
+pub fn job_temp_dir(job_id: JobId) -> PathBuf {
+    scratch_root().join(job_id.to_string())
+}
+
+/// Path for a scratch file that belongs to no job, such as a subtitle
+/// extraction. Left over ones are removed at the next startup.
+pub async fn scratch_file(name: &str) -> Result<PathBuf, String> {
+    let root = scratch_root();
+    fs::create_dir_all(&root)
+        .await
+        .map_err(|e| format!("Cannot create temp dir: {}", e))?;
+    Ok(root.join(name))
+}
+
+/// Deletes the scratch files of a job, including two-pass logs.
+pub async fn remove_temp_dir(dir: &Path) {
+    if fs::metadata(dir).await.is_ok() {
+        if let Err(e) = fs::remove_dir_all(dir).await {
+            println!("Could not remove temp dir {:?}: {}", dir, e);
+        }
+    }
+}
+
+/// Total size of the files under `dir` and the last time one changed.
+async fn usage(dir: &Path) -> (u64, SystemTime) {
+    let mut bytes = 0;
+    let mut modified = SystemTime::UNIX_EPOCH;
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if let Ok(time) = metadata.modified() {
+                modified = modified.max(time);
+            }
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                bytes += metadata.len();
+            }
+        }
+    }
+    (bytes, modified)
+}
+
+/// Fails when the scratch folders already take up the whole budget, checked
+/// before a job creates its own.
+pub async fn check_budget() -> Result<(), String> {
+    let budget = BUDGET.load(Ordering::SeqCst);
+    if budget == 0 {
+        return Ok(());
+    }
+    let (bytes, _) = usage(&scratch_root()).await;
+    if bytes >= budget {
+        return Err(format!(
+            "Temp files take {} MB of the {} MB budget, wait for running exports or raise it",
+            bytes / 1_000_000,
+            budget / 1_000_000
+        ));
+    }
+    Ok(())
+}
+
+/// Deletes the scratch folders earlier runs left behind, such as those of
+/// jobs a crash interrupted. Run at startup.
+pub async fn remove_leftovers() {
+    let root = scratch_root();
+    let Ok(mut entries) = fs::read_dir(&root).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let dir = entry.path();
+        if !dir.is_dir() {
+            let old = entry
+                .metadata()
+                .await
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|time| time.elapsed().unwrap_or_default() >= LEFTOVER_AGE);
+            if old {
+                let _ = fs::remove_file(&dir).await;
+            }
+            continue;
+        }
+        let (bytes, modified) = usage(&dir).await;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age >= LEFTOVER_AGE {
+            println!(
+                "Removing {} MB of leftover temp files in {:?}",
+                bytes / 1_000_000,
+                dir
+            );
+            remove_temp_dir(&dir).await;
+        }
+    }
+}
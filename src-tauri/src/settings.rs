@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tauri::{AppHandle, Manager, State, Window};
+use tokio::fs;
+
+use crate::encoders::Encoder;
+use crate::jobs::JobRegistry;
+use crate::queue::{pump, ExportQueue};
+use crate::scratch;
+
+/// App-wide defaults, saved to `settings.json` in the app config directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Folder for outputs that are just a file name
+    pub output_dir: Option<String>,
+    /// Preset of exports that name none
+    pub default_preset: Option<String>,
+    /// Encoder of exports that choose none
+    pub encoder: Option<Encoder>,
+    /// Jobs the queue runs at once
+    pub concurrency: usize,
+    /// Where jobs keep their scratch files, the system temp dir by default
+    pub temp_dir: Option<String>,
+    /// Most MB the scratch files of all jobs may take, no limit by default
+    pub temp_budget_mb: Option<u64>,
+    /// Keep the system awake while exports run
+    pub prevent_sleep: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            output_dir: None,
+            default_preset: None,
+            encoder: None,
+            concurrency: 1,
+            temp_dir: None,
+            temp_budget_mb: None,
+            prevent_sleep: true,
+        }
+    }
+}
+
+impl Settings {
+    fn validate(&self) -> Result<(), String> {
+        if self.concurrency == 0 {
+            return Err("Concurrency limit must be at least 1".to_string());
+        }
+        for dir in [&self.output_dir, &self.temp_dir].into_iter().flatten() {
+            if !Path::new(dir).is_dir() {
+                return Err(format!("{} is not a folder", dir));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Managed state holding the current settings.
+#[derive(Default)]
+pub struct SettingsStore {
+    settings: RwLock<Settings>,
+}
+
+impl SettingsStore {
+    pub fn get(&self) -> Settings {
+        self.settings.read().unwrap().clone()
+    }
+}
+
+fn settings_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_config_dir()
+        .ok_or("Failed to get app config directory")?
+        .join("settings.json"))
+}
+
+/// Makes `settings` the current ones, for the whole app.
+fn apply(app_handle: &AppHandle, settings: Settings) {
+    scratch::configure(
+        settings.temp_dir.as_ref().map(PathBuf::from),
+        settings.temp_budget_mb.map(|mb| mb * 1_000_000),
+    );
+    app_handle
+        .state::<ExportQueue>()
+        .set_concurrency(settings.concurrency);
+    app_handle
+        .state::<JobRegistry>()
+        .set_prevent_sleep(settings.prevent_sleep);
+    *app_handle
+        .state::<SettingsStore>()
+        .settings
+        .write()
+        .unwrap() = settings;
+}
+
+/// Reads the saved settings at startup. A missing, broken or invalid file
+/// leaves the defaults in place.
+pub fn load(app_handle: &AppHandle) {
+    let Ok(path) = settings_path(app_handle) else {
+        return;
+    };
+    let settings: Settings = match std::fs::read(&path) {
+        Ok(json) => match serde_json::from_slice(&json) {
+            Ok(settings) => settings,
+            Err(e) => {
+                println!("Invalid settings file, using the defaults: {}", e);
+                return;
+            }
+        },
+        // Nothing saved yet
+        Err(_) => return,
+    };
+    if let Err(e) = settings.validate() {
+        println!("Invalid settings file, using the defaults: {}", e);
+        return;
+    }
+    apply(app_handle, settings);
+}
+
+#[tauri::command]
+pub fn get_settings(store: State<'_, SettingsStore>) -> Settings {
+    store.get()
+}
+
+/// Saves and applies `settings`, returning them as stored.
+#[tauri::command]
+pub async fn update_settings(window: Window, settings: Settings) -> Result<Settings, String> {
+    settings.validate()?;
+
+    let app_handle = window.app_handle();
+    let path = settings_path(&app_handle)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Cannot create config dir: {}", e))?;
+    }
+    let json = serde_json::to_vec_pretty(&settings).map_err(|e| e.to_string())?;
+    fs::write(&path, json)
+        .await
+        .map_err(|e| format!("Cannot write settings: {}", e))?;
+
+    apply(&app_handle, settings.clone());
+    // A higher limit may free up slots for waiting jobs right away
+    pump(window);
+    Ok(settings)
+}
@@ -0,0 +1,129 @@
+/// A piece of a smart cut selection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    /// Stream copied when true, re-encoded otherwise
+    pub copy: bool,
+}
+
+impl Segment {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+// Timestamps closer than this are treated as the same frame
+const EPSILON: f64 = 0.001;
+
+/// Splits `start..end` so that everything between the first and last keyframe
+/// inside the range is stream copied, and only the partial GOPs before the
+/// first and after the last keyframe are re-encoded.
+///
+/// Without at least two keyframes in the range there is nothing worth copying
+/// and the whole range is re-encoded as one segment.
+pub fn plan_segments(keyframes: &[f64], start: f64, end: f64) -> Vec<Segment> {
+    let inside: Vec<f64> = keyframes
+        .iter()
+        .copied()
+        .filter(|k| *k >= start - EPSILON && *k <= end + EPSILON)
+        .collect();
+
+    let (Some(&first), Some(&last)) = (inside.first(), inside.last()) else {
+        return vec![Segment {
+            start,
+            end,
+            copy: false,
+        }];
+    };
+    if inside.len() < 2 {
+        return vec![Segment {
+            start,
+            end,
+            copy: false,
+        }];
+    }
+
+    let mut segments = vec![];
+    if first - start > EPSILON {
+        segments.push(Segment {
+            start,
+            end: first,
+            copy: false,
+        });
+    }
+    segments.push(Segment {
+        start: first.max(start),
+        end: last.min(end),
+        copy: true,
+    });
+    if end - last > EPSILON {
+        segments.push(Segment {
+            start: last,
+            end,
+            copy: false,
+        });
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reencode(start: f64, end: f64) -> Segment {
+        Segment {
+            start,
+            end,
+            copy: false,
+        }
+    }
+
+    #[test]
+    fn ranges_without_two_keyframes_are_reencoded_whole() {
+        assert_eq!(plan_segments(&[], 1.0, 5.0), [reencode(1.0, 5.0)]);
+        // Keyframes outside the range do not count
+        assert_eq!(
+            plan_segments(&[0.0, 3.0, 10.0], 1.0, 5.0),
+            [reencode(1.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn partial_gops_around_the_copy_are_reencoded() {
+        assert_eq!(
+            plan_segments(&[0.0, 2.0, 4.0, 6.0], 1.0, 5.0),
+            [
+                reencode(1.0, 2.0),
+                Segment {
+                    start: 2.0,
+                    end: 4.0,
+                    copy: true,
+                },
+                reencode(4.0, 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyframes_on_the_range_ends_are_copied_from() {
+        // Within EPSILON of the start and end, clamped to the range
+        assert_eq!(
+            plan_segments(&[0.9995, 3.0, 5.0004], 1.0, 5.0),
+            [Segment {
+                start: 1.0,
+                end: 5.0,
+                copy: true,
+            }]
+        );
+        assert_eq!(
+            plan_segments(&[1.0, 5.0], 1.0, 5.0),
+            [Segment {
+                start: 1.0,
+                end: 5.0,
+                copy: true,
+            }]
+        );
+    }
+}
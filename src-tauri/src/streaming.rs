@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::codecs::VideoCodec;
+
+/// Adaptive streaming format of a packaged export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFormat {
+    Hls,
+    Dash,
+}
+
+impl StreamFormat {
+    /// File players open, in the output folder.
+    pub fn playlist_name(self) -> &'static str {
+        match self {
+            StreamFormat::Hls => "master.m3u8",
+            StreamFormat::Dash => "manifest.mpd",
+        }
+    }
+}
+
+/// One quality of the ladder players switch between.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rendition {
+    pub height: u32,
+    /// kbit/s
+    pub video_bitrate: u32,
+    /// kbit/s, 128 by default
+    pub audio_bitrate: Option<u32>,
+}
+
+impl Rendition {
+    fn name(&self) -> String {
+        format!("{}p", self.height)
+    }
+
+    fn audio_bitrate(&self) -> u32 {
+        self.audio_bitrate.unwrap_or(DEFAULT_AUDIO_BITRATE)
+    }
+}
+
+const DEFAULT_AUDIO_BITRATE: u32 = 128;
+
+fn default_segment_duration() -> f64 {
+    6.0
+}
+
+/// Packages the export as a playlist of segments in the folder of
+/// `ExportArgs::output_path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamingOptions {
+    pub format: StreamFormat,
+    /// Seconds per segment
+    #[serde(default = "default_segment_duration")]
+    pub segment_duration: f64,
+    /// Qualities to encode, or none for a single one at the export size
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
+}
+
+impl StreamingOptions {
+    pub fn validate(&self, codec: VideoCodec, output_height: u32) -> Result<(), String> {
+        if !(1.0..=60.0).contains(&self.segment_duration) {
+            return Err("Segments must last between 1 and 60 seconds".to_string());
+        }
+        if self.format == StreamFormat::Hls && !matches!(codec, VideoCodec::H264 | VideoCodec::Hevc)
+        {
+            return Err(format!("HLS cannot carry {:?} video", codec));
+        }
+        for (index, rendition) in self.renditions.iter().enumerate() {
+            if rendition.height == 0 || rendition.video_bitrate == 0 {
+                return Err("Renditions need a height and a video bitrate".to_string());
+            }
+            if rendition.height > output_height {
+                return Err(format!(
+                    "The {} rendition is taller than the {} pixels of the export",
+                    rendition.name(),
+                    output_height
+                ));
+            }
+            if self.renditions[..index]
+                .iter()
+                .any(|other| other.height == rendition.height)
+            {
+                return Err(format!("There are two {} renditions", rendition.name()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Keyframes at every segment boundary, so that segments have the
+    /// requested length and renditions switch cleanly.
+    pub fn keyframe_args(&self) -> Vec<String> {
+        vec![
+            "-force_key_frames".to_string(),
+            format!("expr:gte(t,n_forced*{})", self.segment_duration),
+        ]
+    }
+
+    /// Output options packaging input 0 into `dir`, ending with the playlist.
+    /// A single quality copies the picture, a ladder encodes each rendition
+    /// with `video_encoder`.
+    pub fn package_args(
+        &self,
+        dir: &Path,
+        video_encoder: &str,
+        codec: VideoCodec,
+        has_audio: bool,
+    ) -> Vec<String> {
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let mut args: Vec<String> = vec![];
+
+        if self.renditions.is_empty() {
+            args.extend(["-map", "0:v:0", "-c:v", "copy"].map(String::from));
+            if has_audio {
+                args.extend(["-map", "0:a:0", "-c:a", "aac", "-b:a"].map(String::from));
+                args.push(format!("{}k", DEFAULT_AUDIO_BITRATE));
+            }
+        } else {
+            let count = self.renditions.len();
+            let mut graph = format!(
+                "[0:v]split={}{}",
+                count,
+                (0..count).map(|i| format!("[s{}]", i)).collect::<String>()
+            );
+            for (i, rendition) in self.renditions.iter().enumerate() {
+                graph.push_str(&format!(";[s{}]scale=-2:{}[v{}]", i, rendition.height, i));
+            }
+            args.extend(["-filter_complex".to_string(), graph]);
+            for i in 0..count {
+                args.extend(["-map".to_string(), format!("[v{}]", i)]);
+            }
+            args.extend(["-c:v".to_string(), video_encoder.to_string()]);
+            for (i, rendition) in self.renditions.iter().enumerate() {
+                let kbps = rendition.video_bitrate;
+                args.extend([
+                    format!("-b:v:{}", i),
+                    format!("{}k", kbps),
+                    format!("-maxrate:v:{}", i),
+                    format!("{}k", kbps),
+                    format!("-bufsize:v:{}", i),
+                    format!("{}k", kbps * 2),
+                ]);
+            }
+            args.extend(self.keyframe_args());
+
+            if has_audio {
+                // HLS variants each carry their sound, DASH shares one track
+                let audio_bitrates: Vec<u32> = match self.format {
+                    StreamFormat::Hls => self
+                        .renditions
+                        .iter()
+                        .map(Rendition::audio_bitrate)
+                        .collect(),
+                    StreamFormat::Dash => self
+                        .renditions
+                        .iter()
+                        .map(Rendition::audio_bitrate)
+                        .max()
+                        .into_iter()
+                        .collect(),
+                };
+                for _ in &audio_bitrates {
+                    args.extend(["-map", "0:a:0"].map(String::from));
+                }
+                args.extend(["-c:a", "aac"].map(String::from));
+                for (i, kbps) in audio_bitrates.iter().enumerate() {
+                    args.extend([format!("-b:a:{}", i), format!("{}k", kbps)]);
+                }
+            }
+        }
+
+        let duration = self.segment_duration.to_string();
+        match self.format {
+            StreamFormat::Hls => {
+                // MPEG-TS is read by the most players but cannot carry HEVC
+                let fmp4 = codec == VideoCodec::Hevc;
+                args.extend(
+                    [
+                        "-f",
+                        "hls",
+                        "-hls_time",
+                        &duration,
+                        "-hls_playlist_type",
+                        "vod",
+                        "-hls_flags",
+                        "independent_segments",
+                        "-hls_segment_type",
+                        if fmp4 { "fmp4" } else { "mpegts" },
+                    ]
+                    .map(String::from),
+                );
+                let extension = if fmp4 { "m4s" } else { "ts" };
+                if self.renditions.is_empty() {
+                    args.extend([
+                        "-hls_segment_filename".to_string(),
+                        path(&format!("segment_%03d.{}", extension)),
+                        "-y".to_string(),
+                        path(StreamFormat::Hls.playlist_name()),
+                    ]);
+                } else {
+                    // `%v` stands for the name of each variant
+                    let variants: Vec<String> = self
+                        .renditions
+                        .iter()
+                        .enumerate()
+                        .map(|(i, rendition)| match has_audio {
+                            true => format!("v:{},a:{},name:{}", i, i, rendition.name()),
+                            false => format!("v:{},name:{}", i, rendition.name()),
+                        })
+                        .collect();
+                    if fmp4 {
+                        args.extend(["-hls_fmp4_init_filename", "%v_init.mp4"].map(String::from));
+                    }
+                    args.extend([
+                        "-hls_segment_filename".to_string(),
+                        path(&format!("%v_%03d.{}", extension)),
+                        "-master_pl_name".to_string(),
+                        StreamFormat::Hls.playlist_name().to_string(),
+                        "-var_stream_map".to_string(),
+                        variants.join(" "),
+                        "-y".to_string(),
+                        path("%v.m3u8"),
+                    ]);
+                }
+            }
+            StreamFormat::Dash => {
+                let sets = match has_audio {
+                    true => "id=0,streams=v id=1,streams=a",
+                    false => "id=0,streams=v",
+                };
+                args.extend(
+                    [
+                        "-f",
+                        "dash",
+                        "-seg_duration",
+                        &duration,
+                        "-use_template",
+                        "1",
+                        "-use_timeline",
+                        "1",
+                        "-adaptation_sets",
+                        sets,
+                        "-y",
+                    ]
+                    .map(String::from),
+                );
+                args.push(path(StreamFormat::Dash.playlist_name()));
+            }
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hls_ladders_name_each_variant() {
+        let options = StreamingOptions {
+            format: StreamFormat::Hls,
+            segment_duration: 4.0,
+            renditions: vec![
+                Rendition {
+                    height: 720,
+                    video_bitrate: 3000,
+                    audio_bitrate: None,
+                },
+                Rendition {
+                    height: 360,
+                    video_bitrate: 800,
+                    audio_bitrate: Some(64),
+                },
+            ],
+        };
+        let args = options.package_args(Path::new("/site/clip"), "libx264", VideoCodec::H264, true);
+        let value = |option: &str| {
+            let index = args.iter().position(|arg| arg == option).unwrap();
+            args[index + 1].as_str()
+        };
+        assert_eq!(
+            value("-filter_complex"),
+            "[0:v]split=2[s0][s1];[s0]scale=-2:720[v0];[s1]scale=-2:360[v1]"
+        );
+        assert_eq!(value("-b:a:1"), "64k");
+        assert_eq!(
+            value("-var_stream_map"),
+            "v:0,a:0,name:720p v:1,a:1,name:360p"
+        );
+        assert_eq!(value("-hls_segment_filename"), "/site/clip/%v_%03d.ts");
+        assert_eq!(args.last().unwrap(), "/site/clip/%v.m3u8");
+
+        assert!(options.validate(VideoCodec::Vp9, 1080).is_err());
+        assert!(options.validate(VideoCodec::H264, 480).is_err());
+        assert!(options.validate(VideoCodec::H264, 720).is_ok());
+    }
+}
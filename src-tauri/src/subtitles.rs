@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs;
+
+use crate::binaries;
+use crate::ffmpeg::ClipSelection;
+use crate::scratch::scratch_file;
+
+/// Subtitle formats the `subtitles` filter is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    /// ASS and its predecessor SSA, which share the event format
+    Ass,
+}
+
+impl SubtitleFormat {
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("srt") => Ok(SubtitleFormat::Srt),
+            Some("ass" | "ssa") => Ok(SubtitleFormat::Ass),
+            _ => Err(format!("{} is not an SRT or ASS subtitle file", path)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Ass => "ass",
+        }
+    }
+}
+
+/// A source range kept by the export, the output time it starts at and the
+/// speed it is played at.
+pub struct RetimedRange {
+    pub start: f64,
+    pub end: f64,
+    pub output_start: f64,
+    pub speed: f64,
+}
+
+/// Moves every cue from source time to output time. Cues are clipped to the
+/// ranges they overlap, repeated if they overlap several and dropped if they
+/// overlap none.
+pub fn retime(contents: &str, format: SubtitleFormat, ranges: &[RetimedRange]) -> String {
+    match format {
+        SubtitleFormat::Srt => retime_srt(contents, ranges),
+        SubtitleFormat::Ass => retime_ass(contents, ranges),
+    }
+}
+
+/// Output time spans of a cue shown from `start` until `end` in the source.
+fn output_spans(start: f64, end: f64, ranges: &[RetimedRange]) -> Vec<(f64, f64)> {
+    ranges
+        .iter()
+        .filter_map(|range| {
+            let (from, to) = (start.max(range.start), end.min(range.end));
+            (from < to).then_some((
+                range.output_start + (from - range.start) / range.speed,
+                range.output_start + (to - range.start) / range.speed,
+            ))
+        })
+        .collect()
+}
+
+/// Parses `H:MM:SS` followed by `,mmm` (SRT) or `.cc` (ASS).
+fn parse_time(time: &str) -> Option<f64> {
+    let mut parts = time.trim().splitn(3, ':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.replace(',', ".").parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+fn srt_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn ass_time(seconds: f64) -> String {
+    let centis = (seconds * 100.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+fn retime_srt(contents: &str, ranges: &[RetimedRange]) -> String {
+    let contents = contents
+        .trim_start_matches('\u{feff}')
+        .replace("\r\n", "\n");
+    let mut output = String::new();
+    let mut index = 0;
+
+    for block in contents.split("\n\n") {
+        let mut lines = block.trim_matches('\n').lines();
+        // The sequence number is optional in practice, the timing line is not
+        let Some(mut timing) = lines.next() else {
+            continue;
+        };
+        if !timing.contains("-->") {
+            match lines.next() {
+                Some(line) => timing = line,
+                None => continue,
+            }
+        }
+        let Some((start, end)) = timing.split_once("-->") else {
+            continue;
+        };
+        // Position hints may follow the end time
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (parse_time(start), parse_time(end)) else {
+            continue;
+        };
+        let text: Vec<&str> = lines.collect();
+
+        for (from, to) in output_spans(start, end, ranges) {
+            index += 1;
+            output.push_str(&format!(
+                "{}\n{} --> {}\n{}\n\n",
+                index,
+                srt_time(from),
+                srt_time(to),
+                text.join("\n")
+            ));
+        }
+    }
+    output
+}
+
+fn retime_ass(contents: &str, ranges: &[RetimedRange]) -> String {
+    let mut output = String::new();
+
+    for line in contents.lines() {
+        let event = ["Dialogue:", "Comment:"]
+            .into_iter()
+            .find_map(|kind| Some((kind, line.strip_prefix(kind)?)));
+        let Some((kind, fields)) = event else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+
+        // Layer, Start, End and the rest, which may contain commas itself
+        let fields: Vec<&str> = fields.splitn(4, ',').collect();
+        let [layer, start, end, rest] = fields.as_slice() else {
+            continue;
+        };
+        let (Some(start), Some(end)) = (parse_time(start), parse_time(end)) else {
+            continue;
+        };
+        for (from, to) in output_spans(start, end, ranges) {
+            output.push_str(&format!(
+                "{}{},{},{},{}\n",
+                kind,
+                layer,
+                ass_time(from),
+                ass_time(to),
+                rest
+            ));
+        }
+    }
+    output
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeSubtitles {
+    #[serde(default)]
+    streams: Vec<FfprobeSubtitleStream>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeSubtitleStream {
+    codec_name: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    #[serde(default)]
+    disposition: HashMap<String, u8>,
+}
+
+// Codecs FFmpeg can convert to SRT or ASS. Bitmap subtitles (PGS, DVD, DVB)
+// would need OCR.
+const TEXT_SUBTITLE_CODECS: &[&str] =
+    &["subrip", "srt", "ass", "ssa", "mov_text", "webvtt", "text"];
+
+/// Subtitle stream of a source file, returned by `list_subtitle_tracks`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubtitleTrack {
+    /// Position among the subtitle streams, as `extract_subtitles` takes it
+    pub track: usize,
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+    /// Only text subtitles can be extracted
+    pub text: bool,
+}
+
+#[tauri::command]
+pub async fn list_subtitle_tracks(input_path: String) -> Result<Vec<SubtitleTrack>, String> {
+    let output = tokio::process::Command::new(binaries::ffprobe())
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "s",
+            "-show_entries",
+            "stream=codec_name:stream_tags=language,title:stream_disposition=default,forced",
+            "-of",
+            "json",
+            &input_path,
+        ])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let probed: FfprobeSubtitles = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Invalid ffprobe output: {}", e))?;
+
+    Ok(probed
+        .streams
+        .into_iter()
+        .enumerate()
+        .map(|(track, stream)| SubtitleTrack {
+            track,
+            text: stream
+                .codec_name
+                .as_deref()
+                .is_some_and(|codec| TEXT_SUBTITLE_CODECS.contains(&codec)),
+            codec: stream.codec_name,
+            language: stream.tags.get("language").cloned(),
+            title: stream.tags.get("title").cloned(),
+            default: stream.disposition.get("default") == Some(&1),
+            forced: stream.disposition.get("forced") == Some(&1),
+        })
+        .collect())
+}
+
+/// Writes subtitle track `track` of the source to `output_path` as SRT, or
+/// ASS when the path ends in `.ass`. With a `selection` only the cues inside
+/// it are kept, timed like the export of that selection.
+#[tauri::command]
+pub async fn extract_subtitles(
+    input_path: String,
+    track: usize,
+    output_path: String,
+    selection: Option<Vec<ClipSelection>>,
+) -> Result<String, String> {
+    let format = SubtitleFormat::from_path(&output_path)?;
+    // Retimed copies are written from a full extraction in the temp dir
+    let extracted = match &selection {
+        Some(_) => {
+            scratch_file(&format!(
+                "subtitles-{}.{}",
+                uuid::Uuid::new_v4(),
+                format.extension()
+            ))
+            .await?
+        }
+        None => output_path.clone().into(),
+    };
+
+    let result = tokio::process::Command::new(binaries::ffmpeg())
+        .args(["-hide_banner", "-loglevel", "error", "-i", &input_path])
+        .args(["-map", &format!("0:s:{}", track), "-c:s"])
+        .arg(format.extension())
+        .arg("-y")
+        .arg(&extracted)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+    if !result.status.success() {
+        let _ = fs::remove_file(&extracted).await;
+        return Err(format!(
+            "Could not extract subtitle track {}: {}",
+            track,
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+
+    let Some(selection) = selection else {
+        return Ok(output_path);
+    };
+    let contents = fs::read(&extracted).await;
+    let _ = fs::remove_file(&extracted).await;
+    let contents = contents.map_err(|e| format!("Cannot read extracted subtitles: {}", e))?;
+
+    let mut output_start = 0.0;
+    let ranges: Vec<RetimedRange> = selection
+        .iter()
+        .map(|range| {
+            let retimed = RetimedRange {
+                start: range.start,
+                end: range.end,
+                output_start,
+                speed: 1.0,
+            };
+            output_start += range.end - range.start;
+            retimed
+        })
+        .collect();
+    fs::write(
+        &output_path,
+        retime(&String::from_utf8_lossy(&contents), format, &ranges),
+    )
+    .await
+    .map_err(|e| format!("Cannot write {}: {}", output_path, e))?;
+
+    Ok(output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps 10s-20s, then 30s-40s of the source.
+    fn ranges() -> Vec<RetimedRange> {
+        vec![
+            RetimedRange {
+                start: 10.0,
+                end: 20.0,
+                output_start: 0.0,
+                speed: 1.0,
+            },
+            RetimedRange {
+                start: 30.0,
+                end: 40.0,
+                output_start: 10.0,
+                speed: 1.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn srt_cues_move_to_output_time() {
+        let srt = "1\r\n00:00:05,000 --> 00:00:06,000\r\nCut\r\n\r\n\
+                   2\r\n00:00:18,500 --> 00:00:31,000\r\nTwo\r\nlines\r\n";
+
+        assert_eq!(
+            retime(srt, SubtitleFormat::Srt, &ranges()),
+            "1\n00:00:08,500 --> 00:00:10,000\nTwo\nlines\n\n\
+             2\n00:00:10,000 --> 00:00:11,000\nTwo\nlines\n\n"
+        );
+    }
+
+    #[test]
+    fn ass_events_keep_their_fields() {
+        let ass = "[Events]\n\
+                   Dialogue: 0,0:00:35.00,0:00:45.00,Default,,0,0,0,,Hi, there\n\
+                   Dialogue: 0,0:00:21.00,0:00:29.00,Default,,0,0,0,,Cut\n";
+
+        assert_eq!(
+            retime(ass, SubtitleFormat::Ass, &ranges()),
+            "[Events]\nDialogue: 0,0:00:15.00,0:00:20.00,Default,,0,0,0,,Hi, there\n"
+        );
+    }
+}
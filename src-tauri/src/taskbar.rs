@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{Manager, UserAttentionType, Window};
+
+use crate::jobs::{JobId, JobStatus};
+
+/// Managed state putting the progress of the running jobs into the window
+/// title, which the Windows taskbar and the macOS window menu show, and
+/// asking for attention once they end.
+#[derive(Default)]
+pub struct TaskbarProgress {
+    /// Percent of every running job
+    percents: Mutex<HashMap<JobId, f64>>,
+    /// Title from before the first job, put back after the last
+    title: Mutex<Option<String>>,
+    shown: Mutex<Option<u32>>,
+}
+
+impl TaskbarProgress {
+    /// Mean percent of the running jobs, `None` when there are none.
+    fn overall(&self) -> Option<u32> {
+        let percents = self.percents.lock().unwrap();
+        if percents.is_empty() {
+            return None;
+        }
+        let mean = percents.values().sum::<f64>() / percents.len() as f64;
+        Some(mean.clamp(0.0, 100.0).floor() as u32)
+    }
+
+    /// Retitles the window when the whole percent changed.
+    fn refresh(&self, window: &Window) {
+        let overall = self.overall();
+        let mut shown = self.shown.lock().unwrap();
+        if *shown == overall {
+            return;
+        }
+        *shown = overall;
+
+        let mut title = self.title.lock().unwrap();
+        let base = title.get_or_insert_with(|| window.title().unwrap_or_default());
+        let result = match overall {
+            Some(percent) => window.set_title(&format!("{}% - {}", percent, base)),
+            None => window.set_title(&title.take().unwrap_or_default()),
+        };
+        if let Err(e) = result {
+            println!("Could not update the window title: {}", e);
+        }
+    }
+}
+
+/// Called from the job monitor loop with every progress update.
+pub fn show_progress(window: &Window, job_id: JobId, percent: f64) {
+    let taskbar = window.state::<TaskbarProgress>();
+    taskbar.percents.lock().unwrap().insert(job_id, percent);
+    taskbar.refresh(window);
+}
+
+/// Drops the job from the title and, unless the window has the focus,
+/// flashes the taskbar button or bounces the dock icon. Failures keep at it
+/// until the window is focused.
+pub fn job_ended(window: &Window, job_id: JobId, status: JobStatus) {
+    let taskbar = window.state::<TaskbarProgress>();
+    taskbar.percents.lock().unwrap().remove(&job_id);
+    taskbar.refresh(window);
+
+    if window.is_focused().unwrap_or(true) || !window.is_visible().unwrap_or(false) {
+        return;
+    }
+    let attention = match status {
+        JobStatus::Finished => UserAttentionType::Informational,
+        JobStatus::Failed => UserAttentionType::Critical,
+        _ => return,
+    };
+    if let Err(e) = window.request_user_attention(Some(attention)) {
+        println!("Could not request attention: {}", e);
+    }
+}
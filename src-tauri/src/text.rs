@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::filters::{enable_option, escape_value, validate_time_range, OverlayPosition};
+
+const DEFAULT_FONT_SIZE: u32 = 48;
+const DEFAULT_FONT_COLOR: &str = "white";
+
+#[cfg(target_os = "windows")]
+const DEFAULT_FONT: &str = "Arial";
+#[cfg(target_os = "macos")]
+const DEFAULT_FONT: &str = "Helvetica";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const DEFAULT_FONT: &str = "DejaVu Sans";
+
+/// Caption burned into the picture with `drawtext`, after crop, transforms
+/// and scaling, so its position and size refer to the output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextOverlay {
+    pub text: String,
+    /// Font family or path of a font file. Replaced by the file it resolves
+    /// to before the export, see `resolve_font`.
+    pub font: Option<String>,
+    /// Font size in output pixels
+    pub size: Option<u32>,
+    /// FFmpeg color: a name like `white` or `#rrggbb`, optionally `@opacity`
+    pub color: Option<String>,
+    /// Background box behind the text, in the same format as `color`
+    pub box_color: Option<String>,
+    #[serde(flatten)]
+    pub position: OverlayPosition,
+    /// Seconds of the exported clip the text is shown from and until
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl TextOverlay {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.text.trim().is_empty() {
+            return Err("A text overlay needs some text".to_string());
+        }
+        if self.size == Some(0) {
+            return Err("The font size must be positive".to_string());
+        }
+        for color in [&self.color, &self.box_color].into_iter().flatten() {
+            validate_color(color)?;
+        }
+        validate_time_range(self.start, self.end)
+    }
+
+    /// The `drawtext` filter. `time_offset` is the timestamp the clip starts
+    /// at in the filtered stream.
+    pub fn filter(&self, time_offset: f64) -> String {
+        let (x, y) = self.position.exprs(("w", "h"), ("text_w", "text_h"));
+
+        let mut options = vec![];
+        if let Some(font) = &self.font {
+            options.push(format!("fontfile={}", escape_value(font)));
+        }
+        options.extend([
+            // Without expansion `%` needs no escaping
+            "expansion=none".to_string(),
+            format!("text={}", escape_value(&self.text)),
+            format!("fontsize={}", self.size.unwrap_or(DEFAULT_FONT_SIZE)),
+            format!(
+                "fontcolor={}",
+                self.color.as_deref().unwrap_or(DEFAULT_FONT_COLOR)
+            ),
+            format!("x={}", x),
+            format!("y={}", y),
+        ]);
+        if let Some(box_color) = &self.box_color {
+            options.extend([
+                "box=1".to_string(),
+                format!("boxcolor={}", box_color),
+                "boxborderw=8".to_string(),
+            ]);
+        }
+        options.extend(enable_option(self.start, self.end, time_offset));
+
+        format!("drawtext={}", options.join(":"))
+    }
+}
+
+/// Colors end up unquoted in the filter graph, so only the characters of
+/// color names, hex values and `@opacity` are allowed.
+fn validate_color(color: &str) -> Result<(), String> {
+    let valid = !color.is_empty()
+        && color
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '#' | '@' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{} is not a valid color", color))
+    }
+}
+
+/// Resolves `font` to a font file: paths are checked, family names looked up
+/// with fontconfig where there is one, otherwise in the system font folders.
+pub async fn resolve_font(font: Option<&str>) -> Result<String, String> {
+    let font = font.unwrap_or(DEFAULT_FONT);
+    let path = Path::new(font);
+    if path.extension().is_some() || path.components().count() > 1 {
+        return if path.is_file() {
+            Ok(font.to_string())
+        } else {
+            Err(format!("Font file {} not found", font))
+        };
+    }
+
+    if let Some(file) = fontconfig_match(font).await {
+        return Ok(file);
+    }
+    find_font_file(font)
+        .map(|file| file.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("Font {} is not installed", font))
+}
+
+/// Asks fontconfig for `family`. It always answers with some font, so the
+/// match only counts if it is the requested family.
+async fn fontconfig_match(family: &str) -> Option<String> {
+    let output = tokio::process::Command::new("fc-match")
+        .args(["--format=%{family}\n%{file}", family])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (families, file) = stdout.split_once('\n')?;
+    families
+        .split(',')
+        .any(|name| name.trim().eq_ignore_ascii_case(family))
+        .then(|| file.trim().to_string())
+}
+
+/// Folders fonts are installed to on this platform.
+fn font_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+
+    if cfg!(target_os = "windows") {
+        let mut dirs: Vec<PathBuf> = std::env::var_os("WINDIR")
+            .map(|windir| PathBuf::from(windir).join("Fonts"))
+            .into_iter()
+            .collect();
+        dirs.extend(
+            std::env::var_os("LOCALAPPDATA")
+                .map(|local| PathBuf::from(local).join("Microsoft/Windows/Fonts")),
+        );
+        dirs
+    } else if cfg!(target_os = "macos") {
+        let mut dirs = vec![
+            PathBuf::from("/System/Library/Fonts"),
+            PathBuf::from("/System/Library/Fonts/Supplemental"),
+            PathBuf::from("/Library/Fonts"),
+        ];
+        dirs.extend(home.map(|home| home.join("Library/Fonts")));
+        dirs
+    } else {
+        let mut dirs = vec![
+            PathBuf::from("/usr/share/fonts"),
+            PathBuf::from("/usr/local/share/fonts"),
+        ];
+        dirs.extend(home.map(|home| home.join(".local/share/fonts")));
+        dirs
+    }
+}
+
+/// Looks for a font file named after `family`, ignoring case and spaces, so
+/// `Segoe UI` finds `segoeui.ttf`.
+fn find_font_file(family: &str) -> Option<PathBuf> {
+    let wanted: String = family
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    let mut pending = font_dirs();
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_font = path.extension().is_some_and(|ext| {
+                ["ttf", "otf", "ttc"].contains(&ext.to_string_lossy().to_lowercase().as_str())
+            });
+            let stem = path.file_stem().map(|stem| {
+                stem.to_string_lossy()
+                    .replace([' ', '-', '_'], "")
+                    .to_lowercase()
+            });
+            if is_font && stem.as_deref() == Some(wanted.as_str()) {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
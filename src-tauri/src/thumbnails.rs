@@ -0,0 +1,482 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use tokio::fs;
+
+use crate::binaries;
+use crate::ffmpeg::CropArea;
+use crate::filters::escape_value;
+use crate::probe::probe;
+use crate::text::resolve_font;
+
+// Least recently used thumbnails beyond this count are deleted
+const MAX_CACHED_THUMBNAILS: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+/// Directory shared by every cached preview image.
+pub fn cache_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path_resolver()
+        .app_local_data_dir()
+        .ok_or("Failed to get app directory")?
+        .join("thumbnails"))
+}
+
+/// Cache key covering everything that changes the picture, including the
+/// modification time of the source so edited files are not served stale.
+pub async fn cache_key(input_path: &str, parts: &str) -> Result<String, String> {
+    let modified = fs::metadata(input_path)
+        .await
+        .map_err(|e| format!("Cannot read file metadata: {}", e))?
+        .modified()
+        .map_err(|e| e.to_string())?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    Ok(format!(
+        "{:x}",
+        md5::compute(format!("{}|{}|{}", input_path, modified, parts))
+    ))
+}
+
+/// Marks a cached file as recently used.
+pub fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().append(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// Deletes the least recently used files until at most
+/// `MAX_CACHED_THUMBNAILS` are left.
+pub async fn evict(cache_dir: &Path) {
+    let Ok(mut entries) = fs::read_dir(cache_dir).await else {
+        return;
+    };
+    let mut files = vec![];
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Ok(modified) = entry.metadata().await.and_then(|m| m.modified()) {
+            files.push((modified, entry.path()));
+        }
+    }
+    if files.len() <= MAX_CACHED_THUMBNAILS {
+        return;
+    }
+
+    files.sort();
+    let excess = files.len() - MAX_CACHED_THUMBNAILS;
+    for (_, path) in files.into_iter().take(excess) {
+        if let Err(e) = fs::remove_file(&path).await {
+            println!("Could not evict thumbnail {:?}: {}", path, e);
+        }
+    }
+}
+
+/// The `-vf` chain for a preview image, or `None` when the frame is used as is.
+pub fn preview_filters(crop: Option<&CropArea>, max_width: Option<u32>) -> Option<String> {
+    let mut filters = vec![];
+    if let Some(crop) = crop {
+        filters.push(format!(
+            "crop={}:{}:{}:{}",
+            crop.width, crop.height, crop.x, crop.y
+        ));
+    }
+    if let Some(max_width) = max_width {
+        filters.push(format!("scale='min({},iw)':-2", max_width));
+    }
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+/// Writes the frame at `time` of the input to `output` as an image.
+async fn extract_frame(
+    input_path: &str,
+    time: f64,
+    crop: Option<&CropArea>,
+    max_width: Option<u32>,
+    format: ImageFormat,
+    output: &str,
+) -> Result<(), String> {
+    // Seeking on the input is fast and decodes from the previous keyframe,
+    // so the frame is still exact
+    let mut args = vec![
+        "-hide_banner".to_string(),
+        "-loglevel".into(),
+        "error".into(),
+        "-ss".into(),
+        time.to_string(),
+        "-i".into(),
+        input_path.to_string(),
+        "-frames:v".into(),
+        "1".into(),
+    ];
+    if let Some(filters) = preview_filters(crop, max_width) {
+        args.extend(["-vf".into(), filters]);
+    }
+    if format == ImageFormat::Jpeg {
+        args.extend(["-q:v".into(), "3".into()]);
+    }
+    args.extend(["-y".into(), output.to_string()]);
+
+    let result = tokio::process::Command::new(binaries::ffmpeg())
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+    if !result.status.success() || !Path::new(output).exists() {
+        return Err(format!(
+            "Could not extract a frame at {}s: {}",
+            time,
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Extracts the frame at `time` as an image and returns its path. Images are
+/// cached on disk, keyed by file, time, size, crop and format.
+#[tauri::command]
+pub async fn get_thumbnail(
+    app_handle: AppHandle,
+    input_path: String,
+    time: f64,
+    max_width: Option<u32>,
+    crop: Option<CropArea>,
+    format: Option<ImageFormat>,
+) -> Result<String, String> {
+    let format = format.unwrap_or_default();
+    let cache_dir = cache_dir(&app_handle)?;
+    fs::create_dir_all(&cache_dir)
+        .await
+        .map_err(|e| format!("Cannot create cache dir: {}", e))?;
+
+    let key = cache_key(
+        &input_path,
+        &format!("{:.3}|{:?}|{:?}|{:?}", time, max_width, crop, format),
+    )
+    .await?;
+    let output_path = cache_dir.join(format!("{}.{}", key, format.extension()));
+    let output = output_path.to_string_lossy().into_owned();
+
+    if output_path.exists() {
+        touch(&output_path);
+        return Ok(output);
+    }
+
+    extract_frame(&input_path, time, crop.as_ref(), max_width, format, &output).await?;
+    evict(&cache_dir).await;
+    Ok(output)
+}
+
+/// Image format of a file named `path`, JPEG unless it ends in `.png`.
+fn format_of(path: &str) -> ImageFormat {
+    let extension = Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("png") => ImageFormat::Png,
+        _ => ImageFormat::Jpeg,
+    }
+}
+
+/// Saves the frame at `time` at full resolution to `output_path`, cropped
+/// when `crop` is given, for thumbnails and bug reports. The format follows
+/// the extension unless `format` is set.
+#[tauri::command]
+pub async fn save_frame(
+    input_path: String,
+    time: f64,
+    output_path: String,
+    crop: Option<CropArea>,
+    format: Option<ImageFormat>,
+) -> Result<String, String> {
+    let format = format.unwrap_or_else(|| format_of(&output_path));
+    extract_frame(&input_path, time, crop.as_ref(), None, format, &output_path).await?;
+    println!("Saved the frame at {}s to {}", time, output_path);
+    Ok(output_path)
+}
+
+// Default size of a filmstrip frame
+const DEFAULT_FILMSTRIP_WIDTH: u32 = 160;
+
+/// Sprite of evenly spaced frames returned by `generate_filmstrip`. Frame `i`
+/// sits at column `i % columns`, row `i / columns`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Filmstrip {
+    pub path: String,
+    /// Source time of each frame in seconds
+    pub timestamps: Vec<f64>,
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+}
+
+/// Extracts `count` evenly spaced frames with a single FFmpeg run and tiles
+/// them into one image. `columns` defaults to a single row.
+#[tauri::command]
+pub async fn generate_filmstrip(
+    app_handle: AppHandle,
+    input_path: String,
+    count: u32,
+    tile_width: Option<u32>,
+    columns: Option<u32>,
+) -> Result<Filmstrip, String> {
+    if count == 0 {
+        return Err("A filmstrip needs at least one frame".to_string());
+    }
+    let tile_width = tile_width.unwrap_or(DEFAULT_FILMSTRIP_WIDTH).max(2) & !1;
+    let columns = columns.unwrap_or(count).clamp(1, count);
+    let rows = count.div_ceil(columns);
+
+    let metadata = probe(&input_path).await?;
+    if metadata.duration <= 0.0 || metadata.width == 0 || metadata.height == 0 {
+        return Err("Cannot read the duration and size of the video".to_string());
+    }
+    // FFmpeg rotates the decoded frames to display orientation
+    let (width, height) = metadata.display_size();
+    let tile_height =
+        ((tile_width as f64 * height as f64 / width as f64 / 2.0).round() as u32 * 2).max(2);
+
+    let interval = metadata.duration / count as f64;
+    let timestamps: Vec<f64> = (0..count).map(|i| i as f64 * interval).collect();
+
+    let cache_dir = cache_dir(&app_handle)?;
+    fs::create_dir_all(&cache_dir)
+        .await
+        .map_err(|e| format!("Cannot create cache dir: {}", e))?;
+    let key = cache_key(
+        &input_path,
+        &format!("filmstrip|{}|{}|{}", count, tile_width, columns),
+    )
+    .await?;
+    let output_path = cache_dir.join(format!("{}.jpg", key));
+    let output = output_path.to_string_lossy().into_owned();
+
+    let filmstrip = Filmstrip {
+        path: output.clone(),
+        timestamps,
+        columns,
+        rows,
+        tile_width,
+        tile_height,
+    };
+    if output_path.exists() {
+        touch(&output_path);
+        return Ok(filmstrip);
+    }
+
+    let filters = format!(
+        "fps=1/{},scale={}:{},tile={}x{}",
+        interval, tile_width, tile_height, columns, rows
+    );
+    let result = tokio::process::Command::new(binaries::ffmpeg())
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-i",
+            &input_path,
+            "-vf",
+            &filters,
+            "-frames:v",
+            "1",
+            "-q:v",
+            "4",
+            "-y",
+            &output,
+        ])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+    if !result.status.success() || !output_path.exists() {
+        return Err(format!(
+            "Could not generate the filmstrip: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+
+    evict(&cache_dir).await;
+    Ok(filmstrip)
+}
+
+// Default size of a contact sheet frame
+const DEFAULT_SHEET_TILE_WIDTH: u32 = 320;
+
+/// Grid of frames with their timestamps returned by
+/// `generate_contact_sheet`, laid out like a `Filmstrip`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContactSheet {
+    pub path: String,
+    /// Source time of each frame in seconds
+    pub timestamps: Vec<f64>,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// Picks a frame every `interval` seconds, burns its time into the bottom
+/// left corner and tiles the frames with a small gap in between.
+fn contact_sheet_filters(
+    interval: f64,
+    (tile_width, tile_height): (u32, u32),
+    (columns, rows): (u32, u32),
+    font: &str,
+) -> String {
+    let timestamp = [
+        format!("fontfile={}", escape_value(font)),
+        format!("text={}", escape_value("%{pts:hms}")),
+        format!("fontsize={}", (tile_height / 10).max(10)),
+        "fontcolor=white".to_string(),
+        "box=1".to_string(),
+        "boxcolor=black@0.6".to_string(),
+        "boxborderw=4".to_string(),
+        "x=8".to_string(),
+        "y=h-th-8".to_string(),
+    ];
+    format!(
+        "fps=1/{},scale={}:{},setsar=1,drawtext={},tile={}x{}:padding=4:margin=4",
+        interval,
+        tile_width,
+        tile_height,
+        timestamp.join(":"),
+        columns,
+        rows
+    )
+}
+
+/// Tiles `rows` x `columns` frames of the input, each with its timestamp,
+/// into one image for an overview of long recordings. Frames are `interval`
+/// seconds apart, spread over the whole video by default. Written to
+/// `output_path` when given, cached like thumbnails otherwise.
+#[tauri::command]
+pub async fn generate_contact_sheet(
+    app_handle: AppHandle,
+    input_path: String,
+    rows: u32,
+    columns: u32,
+    interval: Option<f64>,
+    tile_width: Option<u32>,
+    output_path: Option<String>,
+) -> Result<ContactSheet, String> {
+    if rows == 0 || columns == 0 {
+        return Err("A contact sheet needs at least one row and column".to_string());
+    }
+    let tile_width = tile_width.unwrap_or(DEFAULT_SHEET_TILE_WIDTH).max(2) & !1;
+
+    let metadata = probe(&input_path).await?;
+    if metadata.duration <= 0.0 || metadata.width == 0 || metadata.height == 0 {
+        return Err("Cannot read the duration and size of the video".to_string());
+    }
+    let (width, height) = metadata.display_size();
+    let tile_height =
+        ((tile_width as f64 * height as f64 / width as f64 / 2.0).round() as u32 * 2).max(2);
+
+    let count = rows * columns;
+    let interval = match interval {
+        Some(interval) if interval > 0.0 => interval,
+        Some(_) => return Err("The interval must be above 0".to_string()),
+        None => metadata.duration / count as f64,
+    };
+    let timestamps: Vec<f64> = (0..count)
+        .map(|i| i as f64 * interval)
+        .take_while(|time| *time < metadata.duration)
+        .collect();
+
+    let output = match output_path {
+        Some(output_path) => output_path,
+        None => {
+            let cache_dir = cache_dir(&app_handle)?;
+            fs::create_dir_all(&cache_dir)
+                .await
+                .map_err(|e| format!("Cannot create cache dir: {}", e))?;
+            let key = cache_key(
+                &input_path,
+                &format!("sheet|{}|{}|{}|{}", rows, columns, interval, tile_width),
+            )
+            .await?;
+            let cached = cache_dir.join(format!("{}.jpg", key));
+            if cached.exists() {
+                touch(&cached);
+                return Ok(ContactSheet {
+                    path: cached.to_string_lossy().into_owned(),
+                    timestamps,
+                    columns,
+                    rows,
+                });
+            }
+            evict(&cache_dir).await;
+            cached.to_string_lossy().into_owned()
+        }
+    };
+
+    let font = resolve_font(None).await?;
+    let filters =
+        contact_sheet_filters(interval, (tile_width, tile_height), (columns, rows), &font);
+    let mut command = tokio::process::Command::new(binaries::ffmpeg());
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-i", &input_path])
+        .args(["-vf", &filters, "-frames:v", "1"]);
+    if format_of(&output) == ImageFormat::Jpeg {
+        command.args(["-q:v", "3"]);
+    }
+    let result = command
+        .args(["-y", &output])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+    if !result.status.success() || !Path::new(&output).exists() {
+        return Err(format!(
+            "Could not generate the contact sheet: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    Ok(ContactSheet {
+        path: output,
+        timestamps,
+        columns,
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_frames_take_the_format_of_their_name() {
+        assert_eq!(format_of("/shots/frame.PNG"), ImageFormat::Png);
+        assert_eq!(format_of("/shots/frame.jpeg"), ImageFormat::Jpeg);
+        assert_eq!(format_of("/shots/frame"), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn contact_sheets_burn_in_the_frame_times() {
+        let filters = contact_sheet_filters(60.0, (320, 180), (4, 3), "/fonts/Sans.ttf");
+        assert_eq!(
+            filters,
+            "fps=1/60,scale=320:180,setsar=1,\
+             drawtext=fontfile=/fonts/Sans.ttf:text=%{pts\\\\:hms}:fontsize=18:\
+             fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=4:x=8:y=h-th-8,\
+             tile=4x3:padding=4:margin=4"
+        );
+    }
+}
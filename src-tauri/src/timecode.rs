@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ffmpeg::ClipSelection;
+
+/// A point of a `FrameRange`: a frame number counted from 0, or a SMPTE
+/// timecode `HH:MM:SS:FF` from the start of the file. Drop-frame timecodes
+/// of NTSC rates are written with a `;` before the frames, `HH:MM:SS;FF`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FramePosition {
+    Frame(u64),
+    Timecode(String),
+}
+
+/// Range of frames to keep, from `start` up to but not including `end`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameRange {
+    pub start: FramePosition,
+    pub end: FramePosition,
+}
+
+/// Frames a timecode second counts, 30 for 29.97 fps.
+fn nominal_rate(frame_rate: f64) -> u64 {
+    frame_rate.round() as u64
+}
+
+fn parse_timecode(timecode: &str, frame_rate: f64) -> Result<u64, String> {
+    let invalid = || format!("{} is not a HH:MM:SS:FF timecode", timecode);
+    let drop_frame = timecode.contains(';');
+    let fields: Vec<u64> = timecode
+        .split([':', ';'])
+        .map(|field| field.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let [hours, minutes, seconds, frames] = fields[..] else {
+        return Err(invalid());
+    };
+
+    let rate = nominal_rate(frame_rate);
+    if minutes >= 60 || seconds >= 60 || frames >= rate {
+        return Err(format!(
+            "{} does not exist at {:.3} fps",
+            timecode, frame_rate
+        ));
+    }
+    let total_minutes = hours * 60 + minutes;
+    let counted = (total_minutes * 60 + seconds) * rate + frames;
+    if !drop_frame {
+        return Ok(counted);
+    }
+
+    // Drop-frame timecode skips the first frame numbers of every minute but
+    // each tenth, 2 at 29.97 fps and 4 at 59.94
+    let ntsc = (frame_rate * 1001.0 / 1000.0 - rate as f64).abs() < 0.01;
+    if !ntsc || !rate.is_multiple_of(30) {
+        return Err(format!(
+            "Drop-frame timecodes need an NTSC frame rate, not {:.3} fps",
+            frame_rate
+        ));
+    }
+    let dropped = rate / 15;
+    if seconds == 0 && frames < dropped && minutes % 10 != 0 {
+        return Err(format!("{} is skipped by drop-frame counting", timecode));
+    }
+    Ok(counted - dropped * (total_minutes - total_minutes / 10))
+}
+
+impl FramePosition {
+    fn frame(&self, frame_rate: f64) -> Result<u64, String> {
+        match self {
+            FramePosition::Frame(frame) => Ok(*frame),
+            FramePosition::Timecode(timecode) => parse_timecode(timecode, frame_rate),
+        }
+    }
+}
+
+/// Converts frame ranges into seconds at the probed `frame_rate`. Each cut
+/// lands half a frame before its frame, so rounding never moves it.
+pub fn to_selection(ranges: &[FrameRange], frame_rate: f64) -> Result<Vec<ClipSelection>, String> {
+    if frame_rate <= 0.0 {
+        return Err("Cannot read the frame rate of the video".to_string());
+    }
+    let seconds = |frame: u64| ((frame as f64 - 0.5) / frame_rate).max(0.0);
+
+    ranges
+        .iter()
+        .map(|range| {
+            let start = range.start.frame(frame_rate)?;
+            let end = range.end.frame(frame_rate)?;
+            if end <= start {
+                return Err(format!("Frame {} does not come after frame {}", end, start));
+            }
+            Ok(ClipSelection {
+                start: seconds(start),
+                end: seconds(end),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NTSC: f64 = 30000.0 / 1001.0;
+
+    #[test]
+    fn timecodes_count_frames_at_the_probed_rate() {
+        assert_eq!(parse_timecode("00:01:00:12", 25.0), Ok(1512));
+        // Non-drop NTSC counts 30 frames per timecode second
+        assert_eq!(parse_timecode("00:01:00:00", NTSC), Ok(1800));
+        // Drop-frame skips 00 and 01 at every minute but the tenth
+        assert_eq!(parse_timecode("00:01:00;02", NTSC), Ok(1800));
+        assert_eq!(parse_timecode("00:10:00;00", NTSC), Ok(17982));
+        assert!(parse_timecode("00:01:00;00", NTSC).is_err());
+        assert!(parse_timecode("00:00:00:25", 25.0).is_err());
+
+        let ranges = [FrameRange {
+            start: FramePosition::Frame(30),
+            end: FramePosition::Timecode("00:00:02:00".to_string()),
+        }];
+        let selection = to_selection(&ranges, NTSC).unwrap();
+        // Frame 30 of 29.97 fps starts at 1.001s
+        assert!((selection[0].start - 29.5 * 1.001 / 30.0).abs() < 1e-9);
+        assert!((selection[0].end - 59.5 * 1.001 / 30.0).abs() < 1e-9);
+    }
+}
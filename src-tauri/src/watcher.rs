@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{async_runtime, State, Window};
+use tokio::fs;
+
+use crate::ffmpeg::ExportArgs;
+use crate::jobs::{emit_event, JobId};
+use crate::queue::{enqueue, is_video};
+
+// How often watched folders are listed
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A folder whose new videos are exported with a preset as they appear,
+/// such as the recordings folder of OBS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchFolder {
+    pub folder: String,
+    /// Name of a saved preset
+    pub preset: String,
+    /// Must contain `{stem}` or `{basename}` and lie outside `folder`
+    pub output_path: String,
+}
+
+/// Payload of `watch-folder-queued`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchFolderQueued {
+    pub folder: String,
+    pub input_path: String,
+    pub job_id: JobId,
+}
+
+struct Watch {
+    config: WatchFolder,
+    stop: Arc<AtomicBool>,
+}
+
+/// Managed state holding the running watch folders, keyed by folder.
+#[derive(Default)]
+pub struct FolderWatcher {
+    watches: Mutex<HashMap<String, Watch>>,
+}
+
+impl WatchFolder {
+    fn validate(&self) -> Result<(), String> {
+        if !Path::new(&self.folder).is_dir() {
+            return Err(format!("{} is not a folder", self.folder));
+        }
+        if self.preset.trim().is_empty() {
+            return Err("Choose the preset new videos are exported with".to_string());
+        }
+        let args = ExportArgs::whole_source("", &self.output_path, &self.preset);
+        if !args.names_output_after_input() {
+            return Err(
+                "Name the outputs of a watch folder after their input with {stem} or {basename}"
+                    .to_string(),
+            );
+        }
+        // Outputs landing in the folder would be picked up and exported again
+        let output_dir = Path::new(&self.output_path).parent();
+        if output_dir.is_some_and(|dir| same_dir(dir, Path::new(&self.folder))) {
+            return Err("Write the outputs of a watch folder to another folder".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Sizes of the videos directly in `folder`.
+async fn list_videos(folder: &Path) -> HashMap<PathBuf, u64> {
+    let mut videos = HashMap::new();
+    let Ok(mut entries) = fs::read_dir(folder).await else {
+        return videos;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if !is_video(&path) {
+            continue;
+        }
+        if let Ok(metadata) = entry.metadata().await {
+            if metadata.is_file() {
+                videos.insert(path, metadata.len());
+            }
+        }
+    }
+    videos
+}
+
+/// Polls the folder until `stop` is set. A new video is queued once its size
+/// stayed the same between two polls, as recorders keep writing to it for as
+/// long as the recording runs. Videos already there when watching starts are
+/// left alone.
+async fn watch(window: Window, config: WatchFolder, stop: Arc<AtomicBool>) {
+    let folder = PathBuf::from(&config.folder);
+    let mut seen: HashSet<PathBuf> = list_videos(&folder).await.into_keys().collect();
+    let mut growing: HashMap<PathBuf, u64> = HashMap::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        for (path, size) in list_videos(&folder).await {
+            if seen.contains(&path) {
+                continue;
+            }
+            if size == 0 || growing.insert(path.clone(), size) != Some(size) {
+                continue;
+            }
+
+            growing.remove(&path);
+            seen.insert(path.clone());
+            let input_path = path.to_string_lossy().into_owned();
+            let args = ExportArgs::whole_source(&input_path, &config.output_path, &config.preset);
+            let job_id = enqueue(window.clone(), args);
+            println!("Watch folder {} queued {}", config.folder, input_path);
+            emit_event(
+                &window,
+                "watch-folder-queued",
+                WatchFolderQueued {
+                    folder: config.folder.clone(),
+                    input_path,
+                    job_id,
+                },
+            );
+        }
+    }
+}
+
+/// Starts exporting the new videos of `config.folder`, replacing an earlier
+/// watch of the same folder.
+#[tauri::command]
+pub fn start_watch_folder(
+    window: Window,
+    watcher: State<'_, FolderWatcher>,
+    config: WatchFolder,
+) -> Result<(), String> {
+    config.validate()?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let previous = watcher.watches.lock().unwrap().insert(
+        config.folder.clone(),
+        Watch {
+            config: config.clone(),
+            stop: stop.clone(),
+        },
+    );
+    if let Some(previous) = previous {
+        previous.stop.store(true, Ordering::SeqCst);
+    }
+
+    println!("Watching {} with preset {}", config.folder, config.preset);
+    async_runtime::spawn(watch(window, config, stop));
+    Ok(())
+}
+
+/// Stops watching the folder. Videos it already queued keep their place in
+/// the queue.
+#[tauri::command]
+pub fn stop_watch_folder(watcher: State<'_, FolderWatcher>, folder: String) -> Result<(), String> {
+    let watch = watcher
+        .watches
+        .lock()
+        .unwrap()
+        .remove(&folder)
+        .ok_or(format!("{} is not being watched", folder))?;
+    watch.stop.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_watch_folders(watcher: State<'_, FolderWatcher>) -> Vec<WatchFolder> {
+    watcher
+        .watches
+        .lock()
+        .unwrap()
+        .values()
+        .map(|watch| watch.config.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(output_path: &str) -> WatchFolder {
+        let folder = std::env::temp_dir();
+        WatchFolder {
+            folder: folder.to_string_lossy().into_owned(),
+            preset: "Compress".to_string(),
+            output_path: output_path.to_string(),
+        }
+    }
+
+    #[test]
+    fn outputs_are_named_after_the_input_outside_the_folder() {
+        let elsewhere = std::env::temp_dir().join("compressed");
+        let elsewhere = elsewhere.to_string_lossy();
+        assert!(config(&format!("{}/{{stem}}.mp4", elsewhere))
+            .validate()
+            .is_ok());
+        assert!(config(&format!("{}/out.mp4", elsewhere))
+            .validate()
+            .is_err());
+
+        let inside = std::env::temp_dir().join("{stem}-small.mp4");
+        assert!(config(&inside.to_string_lossy()).validate().is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+
+use crate::codecs::VideoCodec;
+use crate::encoders::Encoder;
+
+/// H.264 profile of web optimized outputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum H264Profile {
+    /// For old phones and embedded players, at a larger size
+    Baseline,
+    #[default]
+    Main,
+}
+
+/// Outputs that start playing before they are fully downloaded and that
+/// every browser decodes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebOptions {
+    #[serde(default)]
+    pub profile: H264Profile,
+    /// Fragmented MP4, for players streaming the file in pieces
+    #[serde(default)]
+    pub fragmented: bool,
+}
+
+impl WebOptions {
+    /// Flags of the MP4 and QuickTime muxers.
+    pub fn movflags(&self) -> &'static str {
+        if self.fragmented {
+            // Fragments carry their own index, so there is no moov to move
+            "+frag_keyframe+empty_moov+default_base_moof"
+        } else {
+            "+faststart"
+        }
+    }
+
+    /// 8-bit 4:2:0 pictures and the profile, for re-encodes.
+    pub fn encoder_args(&self, encoder: Encoder, codec: VideoCodec) -> Vec<String> {
+        let mut args: Vec<String> = vec![];
+        // VAAPI frames are already uploaded as NV12
+        if encoder != Encoder::Vaapi {
+            args.extend(["-pix_fmt", "yuv420p"].map(String::from));
+        }
+        if codec == VideoCodec::H264 {
+            let profile = match (self.profile, encoder) {
+                (H264Profile::Main, _) => "main",
+                // These name it after the constrained variant they implement
+                (H264Profile::Baseline, Encoder::Amf | Encoder::Vaapi) => "constrained_baseline",
+                (H264Profile::Baseline, _) => "baseline",
+            };
+            args.extend(["-profile:v".to_string(), profile.to_string()]);
+        }
+        args
+    }
+}
+
+/// Joins repeated `-movflags` into the first, as FFmpeg only keeps the last.
+pub fn merge_movflags(ffmpeg_args: &mut Vec<String>) {
+    let mut first = None;
+    let mut flags = String::new();
+    let mut index = 0;
+    while index + 1 < ffmpeg_args.len() {
+        if ffmpeg_args[index] != "-movflags" {
+            index += 1;
+            continue;
+        }
+        let value = ffmpeg_args[index + 1].clone();
+        if !value.starts_with(['+', '-']) {
+            flags.push('+');
+        }
+        flags.push_str(&value);
+        if first.is_none() {
+            first = Some(index);
+            index += 2;
+        } else {
+            ffmpeg_args.drain(index..index + 2);
+        }
+    }
+    if let Some(first) = first {
+        ffmpeg_args[first + 1] = flags;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movflags_of_several_options_are_joined() {
+        let mut args = [
+            "-i",
+            "in.mp4",
+            "-movflags",
+            "+use_metadata_tags",
+            "-c:v",
+            "libx264",
+            "-movflags",
+            "faststart",
+            "-y",
+            "out.mp4",
+        ]
+        .map(String::from)
+        .to_vec();
+        merge_movflags(&mut args);
+        assert_eq!(
+            args,
+            [
+                "-i",
+                "in.mp4",
+                "-movflags",
+                "+use_metadata_tags+faststart",
+                "-c:v",
+                "libx264",
+                "-y",
+                "out.mp4"
+            ]
+        );
+
+        let web = WebOptions {
+            profile: H264Profile::Baseline,
+            fragmented: false,
+        };
+        assert_eq!(
+            web.encoder_args(Encoder::Vaapi, VideoCodec::H264),
+            ["-profile:v", "constrained_baseline"]
+        );
+    }
+}
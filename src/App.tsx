@@ -1,7 +1,7 @@
 import { useCallback, useEffect, useRef, useState } from "react";
 import { invoke, convertFileSrc } from "@tauri-apps/api/tauri";
 import { listen } from "@tauri-apps/api/event";
-import { open, save } from "@tauri-apps/api/dialog";
+import { ask, open, save } from "@tauri-apps/api/dialog";
 
 import { SmartVideoLoader } from "./components/smart-video-loader";
 // import VideoCropper from "./components/video-cropper";
@@ -9,8 +9,24 @@ import TimelineSelector from "./components/timeline-selector";
 import Icon from "./components/icon";
 import LogModal from "./components/log-modal";
 
-import { ClipSelection, CropArea, ExportArgs, LogEntry } from "./types";
-import { formatTime } from "./utils/format";
+import {
+  ClipSelection,
+  CopyOutputPaths,
+  CropAdjustedPayload,
+  CropArea,
+  DiskSpaceLowPayload,
+  ExportArgs,
+  FfmpegInfo,
+  JobError,
+  JobFinished,
+  JobMessage,
+  LogEntry,
+  ProgressPayload,
+  ReverseMemoryHighPayload,
+  SphericalLostPayload,
+} from "./types";
+import { formatExportError, formatTime } from "./utils/format";
+import { notifyInBackground } from "./utils/notify";
 
 const App: React.FC = () => {
   const [videoPath, setVideoPath] = useState<string | null>(null);
@@ -38,6 +54,7 @@ const App: React.FC = () => {
   // Logs & Status
   const [logs, setLogs] = useState<LogEntry[]>([]);
   const [isProcessing, setIsProcessing] = useState(false);
+  const [jobId, setJobId] = useState<string | null>(null);
   const [isLogModalOpen, setIsLogModalOpen] = useState(false);
 
   // Metadata loading state
@@ -59,6 +76,18 @@ const App: React.FC = () => {
     ]);
   }, []);
 
+  // --- FFmpeg Check ---
+  useEffect(() => {
+    invoke<FfmpegInfo>("check_ffmpeg")
+      .then(({ ffmpeg }) =>
+        addLog(
+          `Using FFmpeg ${ffmpeg.version} (${ffmpeg.bundled ? "bundled" : ffmpeg.path})`,
+          "info",
+        ),
+      )
+      .catch((e) => addLog(`${e}`, "error"));
+  }, [addLog]);
+
   // --- Layout Resize Observer ---
   useEffect(() => {
     const updateSize = () => {
@@ -86,6 +115,9 @@ const App: React.FC = () => {
         setVideoUrl(convertFileSrc(selected));
         setLogs([]); // Clear logs on new file
         addLog("Loaded file: " + selected, "info");
+        invoke("add_recent_file", { path: selected }).catch((err) =>
+          addLog(`Could not add to recent files: ${err}`, "error"),
+        );
         // Set loading state when starting to load a new video
         setIsLoadingMetadata(true);
       }
@@ -153,16 +185,32 @@ const App: React.FC = () => {
           width: Math.round(currentCrop.width),
           height: Math.round(currentCrop.height),
         },
-        selection: {
-          start: parseFloat(currentSelection.start.toFixed(3)),
-          end: parseFloat(currentSelection.end.toFixed(3)),
-        },
+        // Lets the backend tell if the preview ignored the rotation tag
+        crop_frame: videoMeta,
+        selection: [
+          {
+            start: parseFloat(currentSelection.start.toFixed(3)),
+            end: parseFloat(currentSelection.end.toFixed(3)),
+          },
+        ],
+        // The save dialog already asked before replacing a file
+        on_conflict: "overwrite",
       };
 
-      await invoke("process_video", { args });
+      setJobId(await invoke<string>("process_video", { args }));
     } catch (e) {
       setIsProcessing(false);
-      addLog(`Export start failed: ${e}`, "error");
+      addLog(`Export start failed: ${formatExportError(e)}`, "error");
+    }
+  };
+
+  const handleCancel = async () => {
+    if (jobId === null) return;
+
+    try {
+      await invoke("cancel_export", { jobId });
+    } catch (e) {
+      addLog(`Cancel failed: ${formatExportError(e)}`, "error");
     }
   };
 
@@ -184,14 +232,109 @@ const App: React.FC = () => {
   // --- Listeners ---
   useEffect(() => {
     const unlisten = [
-      listen<string>("ffmpeg-progress", (e) => addLog(e.payload, "progress")),
-      listen<string>("ffmpeg-finished", () => {
-        addLog("Export completed successfully!", "success");
+      listen<ProgressPayload>("ffmpeg-progress", (e) => {
+        const {
+          percent,
+          speed,
+          eta_seconds,
+          segment,
+          segment_count,
+          pass,
+          pass_count,
+          slow,
+        } = e.payload;
+        const details = [
+          slow ? "slow filters, expect a low speed" : null,
+          pass !== null && pass_count !== null
+            ? `pass ${pass}/${pass_count}`
+            : null,
+          segment !== null && segment_count !== null
+            ? `segment ${segment + 1}/${segment_count}`
+            : null,
+          speed !== null ? `${speed.toFixed(2)}x` : null,
+          eta_seconds !== null ? `ETA ${formatTime(eta_seconds)}` : null,
+        ].filter(Boolean);
+        addLog(
+          `Exporting ${percent.toFixed(1)}%` +
+            (details.length ? ` (${details.join(", ")})` : ""),
+          "progress",
+        );
+      }),
+      listen<CropAdjustedPayload>("crop-adjusted", (e) => {
+        addLog("Crop adjusted: " + e.payload.reasons.join(", "), "info");
+      }),
+      listen<DiskSpaceLowPayload>("disk-space-low", (e) => {
+        const { estimated_bytes, available_bytes } = e.payload;
+        addLog(
+          `The output may not fit: about ${Math.round(estimated_bytes / 1e6)} MB expected, ${Math.round(available_bytes / 1e6)} MB free`,
+          "error",
+        );
+      }),
+      listen<ReverseMemoryHighPayload>("reverse-memory-high", (e) => {
+        const { duration, estimated_bytes } = e.payload;
+        addLog(
+          `Reversing ${duration.toFixed(1)}s may need about ${Math.round(estimated_bytes / 1e6)} MB of memory, consider a shorter selection`,
+          "error",
+        );
+      }),
+      listen<SphericalLostPayload>("spherical-lost", (e) => {
+        addLog(
+          `The output is cropped, so it loses its ${e.payload.projection} 360° projection and plays as a flat video`,
+          "info",
+        );
+      }),
+      listen<JobFinished>("ffmpeg-finished", (e) => {
+        addLog(
+          `Export completed successfully! Saved ${e.payload.output_paths.join(", ")}`,
+          "success",
+        );
+        notifyInBackground("Export finished", e.payload.output_paths.join("\n"));
         setIsProcessing(false);
+        setJobId(null);
       }),
-      listen<string>("ffmpeg-error", (e) => {
-        addLog("FFmpeg Error: " + e.payload, "error");
+      listen<JobError>("ffmpeg-error", (e) => {
+        addLog("FFmpeg Error: " + e.payload.message, "error");
+        notifyInBackground("Export failed", e.payload.message);
+        setIsProcessing(false);
+        setJobId(null);
+        // Show what FFmpeg actually complained about
+        invoke<string>("get_job_log", { jobId: e.payload.job_id })
+          .then((log) =>
+            log
+              .split("\n")
+              .filter((line) => line && !line.startsWith("$ "))
+              .forEach((line) => addLog(line, "error")),
+          )
+          .catch((err) => addLog(`Could not load FFmpeg log: ${err}`, "error"));
+      }),
+      listen("exit-requested", async () => {
+        const finishCurrent = await ask(
+          "An export is still running. Quitting now deletes the unfinished file.",
+          { title: "Quit", okLabel: "Finish first", cancelLabel: "Quit now" },
+        );
+        if (finishCurrent) addLog("Quitting once the export finishes", "info");
+        await invoke("confirm_exit", { finishCurrent });
+      }),
+      listen<CopyOutputPaths>("copy-output-paths", (e) => {
+        navigator.clipboard
+          .writeText(e.payload.text)
+          .then(() => addLog("Output path copied", "info"))
+          .catch((err) => addLog(`Could not copy the path: ${err}`, "error"));
+      }),
+      listen<JobMessage>("post-action-failed", (e) => {
+        addLog("After export: " + e.payload.message, "error");
+      }),
+      listen<{ delay_seconds: number }>("shutdown-scheduled", async (e) => {
+        const cancel = await ask(
+          `The queue is done, shutting down in ${e.payload.delay_seconds} seconds.`,
+          { title: "Shut down", okLabel: "Keep running", cancelLabel: "Shut down" },
+        );
+        if (cancel) await invoke("cancel_shutdown");
+      }),
+      listen<JobMessage>("ffmpeg-cancelled", () => {
+        addLog("Export cancelled", "info");
         setIsProcessing(false);
+        setJobId(null);
       }),
     ];
     return () => {
@@ -306,6 +449,11 @@ const App: React.FC = () => {
                     )}
                     {isProcessing ? "Exporting..." : "Export"}
                   </button>
+                  {isProcessing && jobId !== null && (
+                    <button className="btn btn-secondary" onClick={handleCancel}>
+                      Cancel
+                    </button>
+                  )}
                 </div>
               </>
             ) : (
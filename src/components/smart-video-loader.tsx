@@ -1,7 +1,8 @@
 import { useState, useEffect, useCallback } from "react";
 import { invoke, convertFileSrc } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
 import VideoCropper from "./video-cropper";
-import { CropArea } from "../types";
+import { CropArea, PreviewSource, ProxyProgress } from "../types";
 
 interface SmartVideoLoaderProps {
   originalVideoPath: string | null; // The actual GoPro HEVC file path
@@ -18,6 +19,7 @@ export const SmartVideoLoader: React.FC<SmartVideoLoaderProps> = ({
 }) => {
   const [displayUrl, setDisplayUrl] = useState<string | null>(null);
   const [isTranscoding, setIsTranscoding] = useState(false);
+  const [proxyPercent, setProxyPercent] = useState<number | null>(null);
   const [error, setError] = useState<string | null>(null);
 
   const loadVideo = useCallback(async (path: string) => {
@@ -25,26 +27,19 @@ export const SmartVideoLoader: React.FC<SmartVideoLoaderProps> = ({
     setError(null);
 
     try {
-      // Check if it's HEVC/H.265 that needs transcoding
-      const codec = await invoke<string>("get_video_codec", {
+      // The backend remuxes or transcodes what the webview cannot play,
+      // exports keep reading the original
+      setProxyPercent(null);
+      const preview = await invoke<PreviewSource>("prepare_preview", {
         inputPath: path,
       });
-      console.log(`Codec: ${codec}`);
-      const needsProxy =
-        codec.toLowerCase().includes("hevc") ||
-        codec.toLowerCase().includes("h265") ||
-        codec.toLowerCase().includes("265");
+      console.log(`Previewing ${path} (${preview.mode})`);
 
-      if (needsProxy) {
-        console.log(`Transcoding ${codec} video to H.264 for preview...`);
-        const proxyPath = await invoke<string>("generate_video_proxy", {
-          inputPath: path,
-        });
-        // Tauri returns paths without file:// prefix, add it for the video tag
-        setDisplayUrl(convertFileSrc(proxyPath));
-      } else {
-        // Native H.264, use directly
+      if (preview.mode === "original") {
         setDisplayUrl(`file://${path}`);
+      } else {
+        // Crops drawn on a proxy are scaled back through crop_frame
+        setDisplayUrl(convertFileSrc(preview.path));
       }
     } catch (err) {
       console.error("Failed to prepare video:", err);
@@ -54,6 +49,17 @@ export const SmartVideoLoader: React.FC<SmartVideoLoaderProps> = ({
     }
   }, []);
 
+  useEffect(() => {
+    const unlisten = listen<ProxyProgress>("proxy-progress", (e) => {
+      if (e.payload.input_path === originalVideoPath) {
+        setProxyPercent(e.payload.percent);
+      }
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [originalVideoPath]);
+
   useEffect(() => {
     if (originalVideoPath) {
       loadVideo(originalVideoPath);
@@ -72,8 +78,12 @@ export const SmartVideoLoader: React.FC<SmartVideoLoaderProps> = ({
     return (
       <div className="transcoding-loader">
         <div className="spinner" />
-        <span>Converting GoPro video for preview...</span>
-        <small>This may take a few seconds</small>
+        <span>Converting video for preview...</span>
+        <small>
+          {proxyPercent === null
+            ? "This may take a few seconds"
+            : `${proxyPercent.toFixed(0)}%`}
+        </small>
       </div>
     );
   }
@@ -11,10 +11,483 @@ export interface CropArea {
 }
 
 export interface ExportArgs {
-  input_path: string;
-  output_path: string;
-  selection: ClipSelection;
+  input_path: string; // Left empty in the settings of process_batch
+  output_path: string; // May use {stem}, {width}, {height}, {start}, {end}, {ext}, ...
+  selection: ClipSelection[]; // Joined in this order
+  relative_selection?: boolean; // selection is in fractions 0 - 1 of the duration
+  frame_selection?: { start: number | string; end: number | string }[]; // Frame numbers or HH:MM:SS:FF (HH:MM:SS;FF drop-frame), end exclusive
+  split_chapters?: boolean; // One file per chapter of the input, needs output_template
+  split?: { by: "duration"; seconds: number } | { by: "size"; megabytes: number }; // Needs output_template
+  metadata?: {
+    mode?: "default" | "copy" | "strip"; // "strip" leaves every source tag behind
+    title?: string;
+    comment?: string;
+    creation_time?: string; // ISO 8601 or "now"
+    keep_modified_time?: boolean; // Outputs get the modification time of the source
+  };
+  chapter_markers?: boolean; // A chapter at the start of each joined range
+  web?: WebOptions; // Faststart MP4 with yuv420p and a main or baseline H.264 profile
+  streaming?: StreamingOptions; // output_path is then the folder receiving the package
+  editing?: EditingPreset; // ProRes or DNxHR with PCM audio, for a .mov or .mkv output
+  keep_alpha?: boolean; // ProRes 4444 in .mov or VP9 (yuva420p) in .webm/.mkv, for sources with transparency
+  trim_accuracy?: "seek" | "decode"; // "decode" reads everything before the cut, for broken indexes
+  crop?: CropArea; // Pixels, or leave it out for crop_fraction
+  crop_fraction?: CropArea; // Fractions 0 - 1 of the frame, for any source size
+  crop_frame?: { width: number; height: number }; // Preview video size the crop was drawn on
+  audio_tracks?: number[]; // AudioStream.track values, several are mixed down
+  audio_gain_db?: number;
+  mute?: boolean;
+  loudness?: Loudness;
+  fade_in?: number; // Seconds, picture and audio
+  fade_out?: number;
+  external_audio?: ExternalAudio;
+  speed?: number; // 0.5 for half speed, 4 for a timelapse
+  interpolation?: Interpolation;
+  fps?: number; // Output frame rate, 23.976 and 29.97 are taken as NTSC
+  denoise?: Denoise;
+  chroma_key?: ChromaKey;
+  blur_fill?: { aspect?: string; blur?: number }; // Blurred copy behind the picture, aspect "9:16" by default
+  aspect_ratio?: AspectRatio; // Exact output aspect, e.g. for vertical platforms
+  stabilize?: { strength?: "light" | "medium" | "strong" }; // vid.stab, adds an analysis pass
+  second_crop?: DualCrop; // Facecam + gameplay layouts
+  extra_crops?: CropOutput[]; // Written to files of their own by the same FFmpeg run
+  ladder?: LadderRung[]; // Smaller copies scaled from the same picture, e.g. 720p and 480p
+  masks?: MaskRegion[]; // Blurred or pixelated before the crop
+  deinterlace?: "auto" | "yadif" | "bwdif"; // auto checks the selection with idet
+  tonemap?: "hable" | "mobius" | "reinhard" | "clip" | "linear"; // HDR sources to SDR, hable by default
+  preserve_hdr?: boolean; // 10-bit HDR with the source colors and mastering metadata, for HEVC or AV1 output
+  color_range?: { input?: "limited" | "full"; output?: "limited" | "full" }; // input overrides a missing or wrong tag, output is limited by default
+  lut_path?: string; // .cube 3D LUT, applied before the color adjustments
+  color?: ColorAdjustment;
+  playback?: "forward" | "reverse" | "boomerang"; // Boomerang plays it forward, then back
+  video_bitrate?: number; // kbit/s, encoded in two passes on the CPU
+  watermark?: Watermark;
+  text_overlays?: TextOverlay[];
+  subtitle_path?: string; // SRT or ASS, timed against the source
+  extra_args?: string[]; // Raw FFmpeg flags, placed before the output format
+  preset?: string; // Name of a saved Preset
+  on_conflict?: "fail" | "overwrite" | "rename"; // When an output exists, "fail" by default
+  post_action?: PostAction;
+}
+
+// Run once the export finished
+export type PostAction =
+  | { action: "open" }
+  | { action: "reveal" } // Shown in the file manager
+  | { action: "copy_path" } // Sent back as "copy-output-paths"
+  | { action: "command"; command: string } // $VIDEO_CROPPER_OUTPUT holds the path
+  | { action: "shutdown" }; // Once nothing is queued, cancel_shutdown stops it
+
+// Payload of "copy-output-paths"
+export interface CopyOutputPaths {
+  job_id: string;
+  text: string;
+}
+
+// Entry of probe_video's audio_streams
+export interface AudioStream {
+  track: number;
+  codec: string | null;
+  channels: number | null;
+  language: string | null;
+  title: string | null; // "Mic", "Desktop", ... in OBS recordings
+  default: boolean;
+}
+
+// EBU R128 normalization, two_pass measures the clip first
+export interface Loudness {
+  target_lufs: number; // -14 for most streaming platforms
+  true_peak?: number; // dBTP, -1 by default
+  loudness_range?: number; // LU, 11 by default
+  two_pass?: boolean;
+}
+
+// eq and colortemperature, applied right after the crop
+export interface ColorAdjustment {
+  brightness?: number; // -1 - 1, 0 keeps it
+  contrast?: number; // 0 - 2, 1 keeps it
+  saturation?: number; // 0 - 3, 1 keeps it
+  gamma?: number; // 0.1 - 10, 1 keeps it
+  temperature?: number; // Kelvin, 6500 keeps it, lower is warmer
+}
+
+// Source area hidden from the export, in video pixel coordinates
+export interface MaskRegion {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+  effect?: "blur" | "pixelate";
+  strength?: number; // Blur radius or pixel size
+  keyframes?: { time: number; x: number; y: number }[]; // Source time, replaces x and y
+}
+
+// Green screen: the key color of the cropped clip shows the background
+export interface ChromaKey {
+  color: string; // "0x00ff00", "green", ...
+  similarity?: number; // 0.01 - 1, 0.1 by default
+  blend?: number; // 0 - 1, edge softness
+  background:
+    | { type: "color"; color: string }
+    | { type: "image"; path: string }
+    | { type: "video"; path: string }; // Looped
+}
+
+// nlmeans keeps more detail than hqdn3d but is very slow
+export interface Denoise {
+  filter?: "hqdn3d" | "nlmeans"; // hqdn3d by default
+  strength?: "light" | "medium" | "strong"; // medium by default
+}
+
+// minterpolate settings for smooth slow motion
+export interface Interpolation {
+  fps?: number; // 60 by default
+  mode?: "mci" | "blend" | "dup";
+  motion_estimation?: "bidir" | "bilat";
+  search_range?: number;
+  smooth?: boolean;
+}
+
+// Music bed or voiceover, starting with the clip
+export interface ExternalAudio {
+  path: string;
+  mode: "replace" | "mix";
+  gain_db?: number;
+  duck?: boolean; // Mix only: lower it while the original audio is loud
+  start?: number; // Seconds into the file
+}
+
+export type Anchor =
+  | "top_left"
+  | "top"
+  | "top_right"
+  | "left"
+  | "center"
+  | "right"
+  | "bottom_left"
+  | "bottom"
+  | "bottom_right";
+
+// Position of an overlay in output pixels
+export type OverlayPosition =
+  | { position: "anchor"; anchor: Anchor; margin?: number } // 16 px margin by default
+  | { position: "absolute"; x: number; y: number };
+
+// Second crop of the same source, stacked above the main crop at its width
+// or scaled down and laid over it
+export type DualCrop =
+  | { layout: "stacked"; crop: CropArea }
+  | (OverlayPosition & {
+      layout: "picture_in_picture";
+      crop: CropArea;
+      scale?: number; // Share of the main crop's width, 0.3 by default
+    });
+
+// "crop" narrows the crop around its center, "pad" and "blur" fill the
+// rest of the frame
+export type AspectRatio = { ratio: string } & ( // "9:16"
+  | { fit: "crop" }
+  | { fit: "pad"; color?: string } // Black by default
+  | { fit: "blur"; blur?: number }
+);
+
+// Another crop of the source exported alongside the main one, decoding the
+// source once
+export interface CropOutput {
   crop: CropArea;
+  output_path: string; // Same placeholders as the main output path
+}
+
+export interface LadderRung {
+  height: number;
+  video_bitrate?: number; // kbit/s cap on top of the export quality
+  output_path?: string; // Defaults to the output path with _720p appended
+}
+
+// Laid over the cropped and scaled picture
+export type Watermark = OverlayPosition & {
+  image_path: string;
+  scale?: number; // Width as a share of the output width
+  opacity?: number; // 0 - 1
+  start?: number; // Seconds into the exported clip
+  end?: number;
+};
+
+// Burned-in caption drawn with FFmpeg's drawtext
+export type TextOverlay = OverlayPosition & {
+  text: string;
+  font?: string; // Family name or font file, the system sans-serif by default
+  size?: number; // px, 48 by default
+  color?: string; // "white", "#ffcc00", "black@0.5", ...
+  box_color?: string;
+  start?: number;
+  end?: number;
+};
+
+// Returned by list_interrupted_jobs: queued or running when the app went
+// down, resume_queue queues them again
+export interface InterruptedJob {
+  id: string;
+  status: "queued" | "running";
+  args: ExportArgs;
+  batch_id: string | null;
+  partial_outputs: string[]; // Deleted on resume or discard
+}
+
+// Payload of "proxy-progress", sent while generate_proxy runs
+// Returned by get_chapters, in seconds
+export interface Chapter {
+  start: number;
+  end: number;
+  title: string | null;
+}
+
+// Returned by prepare_preview
+export interface PreviewSource {
+  path: string;
+  mode: "original" | "remux" | "proxy";
+}
+
+export interface ProxyProgress {
+  input_path: string;
+  percent: number;
+  eta_seconds: number | null;
+}
+
+// Returned by get_recent_files, latest first
+export interface RecentFile {
+  path: string;
+  opened_at: number; // Unix seconds
+  duration: number;
+  thumbnail: string | null; // Image path, for convertFileSrc
+}
+
+// Returned by list_history, latest first. rerun_job queues args again
+export interface HistoryEntry {
+  job_id: string;
+  args: ExportArgs; // As requested, before presets and settings
+  status: "finished" | "failed" | "cancelled";
+  output_paths: string[];
+  error: string | null;
+  started_at: number; // Unix seconds
+  duration: number; // Seconds
+}
+
+// Returned by load_project, save_project takes args and editor
+export interface Project {
+  version: number; // Older files are upgraded when loaded
+  args: ExportArgs;
+  editor: unknown; // Whatever the editor saved alongside
+}
+
+// get_settings / update_settings, fill in what an export leaves out
+export interface Settings {
+  output_dir: string | null; // For outputs given as a bare file name
+  default_preset: string | null;
+  encoder: string | null; // Encoder of exports that choose none
+  concurrency: number; // Queued jobs run at once
+  temp_dir: string | null; // System temp dir when null
+  temp_budget_mb: number | null; // Jobs needing temp files are refused above it
+  prevent_sleep: boolean;
+}
+
+// Saved with save_preset; unset fields keep the export's own value
+export interface Preset {
+  name: string;
+  encoder?: string;
+  video_codec?: string;
+  audio_codec?: string;
+  container?: string;
+  quality?: { crf?: number; preset?: string };
+  audio_bitrate?: number; // kbit/s
+  scale?: Record<string, unknown>;
+  crop_fraction?: CropArea; // Fractions 0 - 1 of the frame
+  web?: WebOptions;
+}
+
+// ProRes 4444 keeps the alpha channel of sources that have one
+export type EditingPreset =
+  | "prores_proxy"
+  | "prores_lt"
+  | "prores_422"
+  | "prores_hq"
+  | "prores_4444"
+  | "prores_4444_xq"
+  | "dnxhr_lb"
+  | "dnxhr_sq"
+  | "dnxhr_hq"
+  | "dnxhr_hqx"
+  | "dnxhr_444";
+
+export interface StreamingOptions {
+  format: "hls" | "dash"; // Writes master.m3u8 or manifest.mpd
+  segment_duration?: number; // Seconds, 6 by default
+  // Ladder of qualities, or none for a single one at the export size
+  renditions?: { height: number; video_bitrate: number; audio_bitrate?: number }[]; // kbit/s
+}
+
+export interface WebOptions {
+  profile?: "main" | "baseline";
+  fragmented?: boolean; // Fragmented MP4 instead of faststart
+}
+
+export interface ProgressPayload {
+  job_id: string;
+  percent: number;
+  current_time: number;
+  fps: number | null;
+  speed: number | null;
+  bitrate: number | null; // kbit/s
+  eta_seconds: number | null;
+  segment: number | null; // Index into ExportArgs.selection
+  segment_count: number | null;
+  pass: number | null; // Counted from 1
+  pass_count: number | null;
+  slow: boolean; // Frame interpolation or nlmeans is running, expect a low speed
+  output_paths: string[]; // Files the running pass writes, extra crops advance together
+}
+
+// Payload of "batch-progress", sent whenever a job of a process_batch ends
+export interface BatchProgress {
+  batch_id: string;
+  total: number;
+  finished: number;
+  failed: number;
+  cancelled: number;
+  percent: number; // Jobs ended, however they ended
+}
+
+// Payload of "batch-finished"
+export interface BatchReport extends BatchProgress {
+  entries: { job_id: string; input_path: string; status: string }[];
+}
+
+// Passed to start_watch_folder, new videos are exported whole with the preset
+export interface WatchFolder {
+  folder: string;
+  preset: string;
+  output_path: string; // Needs {stem} or {basename}, in another folder
+}
+
+// Payload of "watch-folder-queued"
+export interface WatchFolderQueued {
+  folder: string;
+  input_path: string;
+  job_id: string;
+}
+
+export interface JobMessage {
+  job_id: string;
+  message: string;
+}
+
+export type ExportError =
+  | { kind: "invalid_input"; message: string }
+  | { kind: "probe_failed"; message: string }
+  | { kind: "encoder_missing"; encoder: string }
+  | { kind: "spawn_failed"; message: string }
+  | { kind: "non_zero_exit"; code: number | null; stderr_tail: string[] }
+  | { kind: "io"; message: string }
+  | {
+      kind: "insufficient_disk_space";
+      path: string;
+      required_bytes: number;
+      available_bytes: number;
+    }
+  | { kind: "job_not_found"; job_id: string }
+  | { kind: "cancelled" };
+
+// Payload of "ffmpeg-finished"
+export interface JobFinished extends JobMessage {
+  output_paths: string[];
+}
+
+// Payload of "ffmpeg-error"
+export interface JobError extends JobMessage {
+  error: ExportError;
+}
+
+export interface DiskSpaceLowPayload {
+  job_id: string;
+  path: string;
+  estimated_bytes: number;
+  available_bytes: number;
+}
+
+// Reversed frames are held in memory, warned about above 2 GiB
+export interface ReverseMemoryHighPayload {
+  job_id: string;
+  duration: number; // Seconds of the source
+  estimated_bytes: number;
+}
+
+// A 360° source whose projection the crop or transforms drop
+export interface SphericalLostPayload {
+  job_id: string;
+  projection: string; // e.g. "equirectangular"
+}
+
+export interface CropAdjustedPayload {
+  job_id: string;
+  requested: CropArea;
+  adjusted: CropArea;
+  reasons: string[];
+}
+
+export interface BinaryInfo {
+  path: string;
+  bundled: boolean;
+  version: string;
+}
+
+export interface FfmpegInfo {
+  ffmpeg: BinaryInfo;
+  ffprobe: BinaryInfo;
+}
+
+// Returned by get_ffmpeg_capabilities
+export interface FfmpegCapabilities {
+  video_encoders: string[];
+  audio_encoders: string[];
+  filters: string[];
+  hwaccels: string[];
+}
+
+// Returned by preview_command
+export interface SizeEstimate {
+  bytes: number;
+  exact: boolean;
+}
+
+// Returned by detect_interlacing, counts of idet's multi frame detection
+export interface InterlaceReport {
+  interlaced: boolean;
+  top_field_first: number;
+  bottom_field_first: number;
+  progressive: number;
+  undetermined: number;
+}
+
+// Returned by list_subtitle_tracks
+export interface SubtitleTrack {
+  track: number; // Pass to extract_subtitles
+  codec: string | null;
+  language: string | null;
+  title: string | null;
+  default: boolean;
+  forced: boolean;
+  text: boolean; // Bitmap tracks cannot be extracted
+}
+
+export interface CommandPreview {
+  program: string;
+  passes: string[][];
+}
+
+// Returned by render_preview; before_path is only set for a single frame
+export interface FilterPreview {
+  path: string;
+  before_path: string | null;
 }
 
 export interface LogEntry {
@@ -1,3 +1,5 @@
+import { ExportError } from "../types";
+
 export const formatTime = (seconds: number): string => {
   if (isNaN(seconds) || seconds < 0) return "00:00.0";
   const min = Math.floor(seconds / 60);
@@ -12,3 +14,23 @@ export const formatTimeShort = (time: number): string => {
   const sec = Math.floor(time % 60);
   return `${min.toString().padStart(2, "0")}:${sec.toString().padStart(2, "0")}`;
 };
+
+// Rejections of export commands carry an ExportError
+export const formatExportError = (e: unknown): string => {
+  if (typeof e !== "object" || e === null || !("kind" in e)) return String(e);
+  const error = e as ExportError;
+  switch (error.kind) {
+    case "encoder_missing":
+      return `FFmpeg was built without the ${error.encoder} encoder`;
+    case "non_zero_exit":
+      return `FFmpeg exited with code ${error.code}: ${error.stderr_tail.join("\n")}`;
+    case "insufficient_disk_space":
+      return `Not enough disk space in ${error.path}: about ${Math.round(error.required_bytes / 1e6)} MB needed, ${Math.round(error.available_bytes / 1e6)} MB free`;
+    case "job_not_found":
+      return `No running export with id ${error.job_id}`;
+    case "cancelled":
+      return "Export cancelled";
+    default:
+      return error.message;
+  }
+};
@@ -0,0 +1,18 @@
+import { convertFileSrc } from "@tauri-apps/api/tauri";
+
+/**
+ * URL of the exact frame at `time` seconds, decoded by the backend's frame
+ * server. Use it for an `<img>` on top of the paused video while scrubbing,
+ * where seeking the `<video>` element only lands near the frame.
+ */
+export const frameUrl = (
+  inputPath: string,
+  time: number,
+  maxWidth?: number,
+): string => {
+  // Gives frame://localhost/ or https://frame.localhost/ on Windows
+  const base = convertFileSrc("", "frame");
+  const params = new URLSearchParams({ path: inputPath, t: time.toFixed(3) });
+  if (maxWidth) params.set("w", String(maxWidth));
+  return `${base}?${params}`;
+};
@@ -0,0 +1,13 @@
+/**
+ * Shows a system notification unless the app window has the focus. Uses the
+ * webview's Notification API, which hands it to the OS notification center.
+ */
+export const notifyInBackground = async (title: string, body: string) => {
+  if (document.hasFocus() || !("Notification" in window)) return;
+  if (Notification.permission === "default") {
+    await Notification.requestPermission();
+  }
+  if (Notification.permission === "granted") {
+    new Notification(title, { body });
+  }
+};